@@ -2,7 +2,7 @@ use std::{sync::Arc, time::Instant};
 
 use xsynth_core::{
     channel::{ChannelAudioEvent, ChannelConfigEvent, ChannelEvent, VoiceChannel},
-    soundfont::{Interpolator, SampleSoundfont, SoundfontBase, SoundfontInitOptions},
+    soundfont::{Interpolator, SampleFormat, SampleSoundfont, SoundfontBase, SoundfontInitOptions},
     AudioPipe, AudioStreamParams, ChannelCount,
 };
 
@@ -36,6 +36,9 @@ pub fn main() {
                 interpolator: Interpolator::Nearest,
                 linear_release: false,
                 use_effects: false,
+                sample_format: SampleFormat::F32,
+                streaming_preload: None,
+                streaming_cache_dir: None,
             },
         )
         .unwrap(),
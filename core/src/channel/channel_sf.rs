@@ -2,7 +2,7 @@ use std::{iter, ops::Deref, sync::Arc};
 
 use crate::{
     helpers::are_arc_vecs_equal,
-    soundfont::SoundfontBase,
+    soundfont::{ControllerVoiceSpawner, KeyswitchState, SoundfontBase, VoiceSelection},
     voice::{Voice, VoiceControlData},
 };
 
@@ -13,6 +13,8 @@ pub struct ChannelSoundfont {
     matrix: VoiceSpawnerMatrix,
     curr_bank: u8,
     curr_preset: u8,
+    keyswitch_keys: u128,
+    controller_spawners: Vec<ControllerVoiceSpawner>,
 }
 
 impl Deref for ChannelSoundfont {
@@ -31,6 +33,8 @@ impl ChannelSoundfont {
             matrix: VoiceSpawnerMatrix::new(),
             curr_bank: 0,
             curr_preset: 0,
+            keyswitch_keys: 0,
+            controller_spawners: Vec::new(),
         }
     }
 
@@ -57,6 +61,7 @@ impl ChannelSoundfont {
 
         let bank = self.curr_bank;
         let preset = self.curr_preset;
+        let mut keyswitch_keys = 0;
 
         for k in 0..128u8 {
             for v in 0..128u8 {
@@ -104,10 +109,79 @@ impl ChannelSoundfont {
                     .find(|vec| !vec.is_empty())
                     .unwrap_or_default();
 
+                keyswitch_keys |= attack_spawners
+                    .iter()
+                    .chain(release_spawners.iter())
+                    .fold(0, |keys, spawner| keys | spawner.keyswitch_keys());
+
                 self.matrix.set_spawners_attack(k, v, attack_spawners);
                 self.matrix.set_spawners_release(k, v, release_spawners);
             }
         }
+
+        self.keyswitch_keys = keyswitch_keys;
+
+        self.controller_spawners = self
+            .soundfonts
+            .iter()
+            .map(|sf| sf.get_controller_voice_spawners(bank, preset))
+            .find(|vec| !vec.is_empty())
+            .unwrap_or_default();
+    }
+
+    /// Returns true if the key acts as a keyswitch in the current preset.
+    pub fn is_keyswitch(&self, key: u8) -> bool {
+        key < 128 && self.keyswitch_keys & (1 << key) != 0
+    }
+
+    /// Returns the indexes and keys of the controller voice spawners that are
+    /// triggered by a controller changing from `old` to `new`.
+    pub fn triggered_controller_spawners(
+        &self,
+        controller: u8,
+        old: u8,
+        new: u8,
+    ) -> impl Iterator<Item = (usize, u8)> + '_ {
+        self.controller_spawners
+            .iter()
+            .enumerate()
+            .filter(move |(_, spawner)| {
+                spawner.triggers.iter().any(|range| {
+                    range.cc == controller && !range.contains(old) && range.contains(new)
+                })
+            })
+            .map(|(i, spawner)| (i, spawner.key))
+    }
+
+    /// Spawns the voices of a controller voice spawner, if its conditions are met.
+    pub fn spawn_voices_controller<'a>(
+        &'a self,
+        control: &'a VoiceControlData,
+        index: usize,
+        selection: VoiceSelection,
+    ) -> impl Iterator<Item = Box<dyn Voice>> + 'a {
+        self.controller_spawners
+            .get(index)
+            .map(|spawner| &spawner.spawner)
+            .filter(|spawner| {
+                spawner.is_selected(selection)
+                    && spawner.matches_keyswitch(selection.keyswitch)
+                    && spawner.matches_controllers(&control.cc)
+            })
+            .map(|spawner| spawner.spawn_voice(control))
+            .into_iter()
+    }
+
+    pub fn exclusive_groups_attack(
+        &self,
+        key: u8,
+        vel: u8,
+        legato: bool,
+        keyswitch: KeyswitchState,
+        cc: &[u8; 128],
+    ) -> Vec<u32> {
+        self.matrix
+            .exclusive_groups_attack(key, vel, legato, keyswitch, cc)
     }
 
     pub fn spawn_voices_attack<'a>(
@@ -115,8 +189,11 @@ impl ChannelSoundfont {
         control: &'a VoiceControlData,
         key: u8,
         vel: u8,
+        legato: bool,
+        selection: VoiceSelection,
     ) -> impl Iterator<Item = Box<dyn Voice>> + 'a {
-        self.matrix.spawn_voices_attack(control, key, vel)
+        self.matrix
+            .spawn_voices_attack(control, key, vel, legato, selection)
     }
 
     pub fn spawn_voices_release<'a>(
@@ -124,7 +201,11 @@ impl ChannelSoundfont {
         control: &'a VoiceControlData,
         key: u8,
         vel: u8,
+        key_off: bool,
+        released: bool,
+        selection: VoiceSelection,
     ) -> impl Iterator<Item = Box<dyn Voice>> + 'a {
-        self.matrix.spawn_voices_release(control, key, vel)
+        self.matrix
+            .spawn_voices_release(control, key, vel, key_off, released, selection)
     }
 }
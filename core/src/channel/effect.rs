@@ -0,0 +1,53 @@
+use std::{fmt, sync::Arc};
+
+use crate::AudioStreamParams;
+
+use super::ControlEvent;
+
+/// An insert effect that processes the audio of a single channel.
+///
+/// The effects of a channel are installed with the
+/// `ChannelConfigEvent::SetEffects` event, and run in order at the end of
+/// the channel, after the channel volume and pan and the CC 74 cutoff filter.
+pub trait ChannelEffect: Send + Sync {
+    /// Processes a block of the channel's audio in place. The samples are
+    /// interleaved in the channel count of the audio stream.
+    fn process(&mut self, buffer: &mut [f32]);
+
+    /// Receives the control events of the channel, after the channel
+    /// itself has applied them.
+    fn process_control(&mut self, _event: &ControlEvent) {}
+
+    /// Clears the internal state of the effect, such as delay lines or
+    /// filter history. Called when all sounds of the channel are killed
+    /// and on system resets.
+    fn reset(&mut self) {}
+}
+
+type CreateChannelEffect = dyn Fn(AudioStreamParams) -> Box<dyn ChannelEffect> + Send + Sync;
+
+/// Creates the instances of a channel effect. Each channel that the
+/// effect is installed on gets its own instance.
+#[derive(Clone)]
+pub struct ChannelEffectFactory(Arc<CreateChannelEffect>);
+
+impl ChannelEffectFactory {
+    /// Creates a new factory from a function that receives the parameters
+    /// of the output audio and returns a new instance of the effect.
+    pub fn new(
+        create: impl Fn(AudioStreamParams) -> Box<dyn ChannelEffect> + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(create))
+    }
+
+    /// Creates a new instance of the effect.
+    pub fn create(&self, stream_params: AudioStreamParams) -> Box<dyn ChannelEffect> {
+        (self.0)(stream_params)
+    }
+}
+
+impl fmt::Debug for ChannelEffectFactory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ChannelEffectFactory")
+    }
+}
@@ -1,12 +1,29 @@
 use std::sync::Arc;
 
-use crate::soundfont::SoundfontBase;
+use super::ChannelEffectFactory;
+use crate::{
+    soundfont::{KeyswitchState, SoundfontBase},
+    tuning::Tuning,
+    voice::GlideControlData,
+};
 
 /// MIDI events for a single key in a channel.
 #[derive(Debug, Clone)]
 pub enum KeyNoteEvent {
-    /// Starts a new note voice with a velocity
-    On(u8),
+    /// Starts a new note voice with a velocity. `legato` is true if
+    /// other notes were held in the channel when the note started,
+    /// `keyswitch` is the keyswitch state of the channel and `glide`
+    /// the portamento of the note.
+    On {
+        vel: u8,
+        legato: bool,
+        keyswitch: KeyswitchState,
+        glide: GlideControlData,
+    },
+
+    /// Changes the pitch glide of the voices of the key, used to
+    /// move a voice to another note in mono mode
+    Glide(GlideControlData),
 
     /// Signals off to a note voice
     Off,
@@ -16,6 +33,62 @@ pub enum KeyNoteEvent {
 
     /// Kills all note voices without decay
     AllKilled,
+
+    /// Stops the voices that are turned off by the given
+    /// exclusive class group
+    GroupOff(u32),
+
+    /// Spawns the voices of the controller voice spawner with
+    /// the given index
+    ControllerTrigger(usize),
+
+    /// Sets the polyphonic aftertouch value of the key
+    Pressure(u8),
+
+    /// Sets the per-note parameters of the key
+    Params(KeyParams),
+
+    /// Sets the offset of the key from its 12-TET pitch in semitones,
+    /// from the tuning of the channel
+    Tuning(f32),
+
+    /// Sets the state of the damper pedal
+    Damper(bool),
+
+    /// Sets the state of the sostenuto pedal. It is only pressed on
+    /// the keys that are held, and lifted on all keys.
+    Sostenuto(bool),
+
+    /// Sets the state of the soft pedal
+    SoftPedal(bool),
+}
+
+/// Parameters of a single key that are applied on top of the
+/// channel, set by the GS/XG drum note NRPNs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyParams {
+    /// Pitch offset in semitones
+    pub coarse_tune: f32,
+
+    /// Pitch offset in cents
+    pub fine_tune: f32,
+
+    /// Amplitude multiplier
+    pub level: f32,
+
+    /// Pan, from 0 (left) to 1 (right)
+    pub pan: f32,
+}
+
+impl Default for KeyParams {
+    fn default() -> Self {
+        KeyParams {
+            coarse_tune: 0.0,
+            fine_tune: 0.0,
+            level: 1.0,
+            pan: 0.5,
+        }
+    }
 }
 
 /// Events to modify parameters of a channel.
@@ -26,6 +99,11 @@ pub enum ChannelConfigEvent {
 
     /// Sets the layer count for the soundfont
     SetLayerCount(Option<usize>),
+
+    /// Replaces the insert effect chain of the channel. The effects are
+    /// applied in order, and an empty list removes all of them.
+    /// See `ChannelEffect` documentation for more information.
+    SetEffects(Vec<ChannelEffectFactory>),
 }
 
 /// MIDI events for a channel.
@@ -46,11 +124,38 @@ pub enum ChannelAudioEvent {
     /// Resets all CC to their default values
     ResetControl,
 
+    /// Resets the channel to its initial state, including the master
+    /// parameters and the drum mode. Sent by the system reset SysEx messages.
+    SystemReset,
+
+    /// Sets if the channel is used for drums, using the drum
+    /// patches of bank 128
+    SetDrums(bool),
+
     /// Control event for the channel
     Control(ControlEvent),
 
     /// Program change event
     ProgramChange(u8),
+
+    /// Polyphonic aftertouch of a key
+    PolyAftertouch { key: u8, value: u8 },
+
+    /// Channel aftertouch, applied to all keys
+    ChannelPressure(u8),
+
+    /// Sets the tuning of the channel, `None` for 12-TET. A MIDI Tuning
+    /// Standard program selected with RPN 3 and 4 takes priority over it.
+    SetTuning(Option<Arc<Tuning>>),
+
+    /// Changes the pitch of keys of a MIDI Tuning Standard tuning program,
+    /// as `(key, pitch)` pairs with the pitch in semitones.
+    /// Programs that weren't changed before start from 12-TET.
+    TuningChange {
+        bank: u8,
+        program: u8,
+        changes: Vec<(u8, f32)>,
+    },
 }
 
 /// Wrapper enum for various events for a channel.
@@ -78,9 +183,25 @@ pub enum ControlEvent {
     /// The pitch bend, product of value * sensitivity
     PitchBend(f32),
 
+    /// The pitch bend of the MPE zone master channel in semitones,
+    /// added to the pitch bend of the channel
+    ZonePitchBend(f32),
+
     /// Fine tune value in cents
     FineTune(f32),
 
     /// Coarse tune value in semitones
     CoarseTune(f32),
+
+    /// The master volume, between 0 and 1
+    MasterVolume(f32),
+
+    /// The master balance, between 0 (left) and 1 (right)
+    MasterBalance(f32),
+
+    /// Master fine tune value in cents
+    MasterFineTune(f32),
+
+    /// Master coarse tune value in semitones
+    MasterCoarseTune(f32),
 }
@@ -3,16 +3,51 @@ use std::sync::{
     Arc,
 };
 
+use crate::{
+    helpers::Xorshift32,
+    soundfont::{KeyswitchState, VoiceSelection},
+    voice::GlideControlData,
+    ChannelCount,
+};
+
 use super::{
-    channel_sf::ChannelSoundfont, event::KeyNoteEvent, voice_buffer::VoiceBuffer,
+    channel_sf::ChannelSoundfont,
+    event::{KeyNoteEvent, KeyParams},
+    voice_buffer::VoiceBuffer,
     ChannelInitOptions, VoiceControlData,
 };
 
+/// The velocity multiplier of the notes started while the soft pedal
+/// is held, making them quieter and less bright.
+const SOFT_PEDAL_VELOCITY: f32 = 0.75;
+
 pub struct KeyData {
     key: u8,
     voices: VoiceBuffer,
     last_voice_count: usize,
     shared_voice_counter: Arc<AtomicU64>,
+    attack_sequence: u32,
+    release_sequence: u32,
+    rng: Xorshift32,
+
+    /// The keyswitch state of the last note, also used for its release voices
+    keyswitch: KeyswitchState,
+
+    /// The pitch glide of the voices of the key
+    glide: GlideControlData,
+
+    /// The polyphonic aftertouch value of the key
+    pressure: u8,
+
+    /// The per-note pitch, level and pan of the key
+    params: KeyParams,
+    channels: ChannelCount,
+
+    /// The offset of the key from its 12-TET pitch in semitones
+    tuning: f32,
+
+    /// If the soft pedal (una corda) is held
+    soft_pedal: bool,
 }
 
 impl KeyData {
@@ -20,12 +55,23 @@ impl KeyData {
         key: u8,
         shared_voice_counter: Arc<AtomicU64>,
         options: ChannelInitOptions,
+        channels: ChannelCount,
     ) -> KeyData {
         KeyData {
             key,
             voices: VoiceBuffer::new(options),
             last_voice_count: 0,
             shared_voice_counter,
+            attack_sequence: 0,
+            release_sequence: 0,
+            rng: Xorshift32::new(key as u32 + 1),
+            keyswitch: KeyswitchState::default(),
+            glide: GlideControlData::default(),
+            pressure: 0,
+            params: KeyParams::default(),
+            channels,
+            tuning: 0.0,
+            soft_pedal: false,
         }
     }
 
@@ -37,30 +83,128 @@ impl KeyData {
         max_layers: Option<usize>,
     ) {
         match event {
-            KeyNoteEvent::On(vel) => {
-                let voices = channel_sf.spawn_voices_attack(control, self.key, vel);
+            KeyNoteEvent::On { glide, .. } => {
+                self.glide = glide;
+                self.pressure = 0;
+            }
+            KeyNoteEvent::Glide(glide) => self.glide = glide,
+            KeyNoteEvent::Pressure(pressure) => self.pressure = pressure,
+            KeyNoteEvent::Params(params) => self.params = params,
+            KeyNoteEvent::Tuning(tuning) => self.tuning = tuning,
+            KeyNoteEvent::SoftPedal(soft_pedal) => self.soft_pedal = soft_pedal,
+            _ => {}
+        }
+        let control = &self.voice_control(control);
+
+        match event {
+            KeyNoteEvent::On {
+                vel,
+                legato,
+                keyswitch,
+                ..
+            } => {
+                self.keyswitch = keyswitch;
+                let vel = if self.soft_pedal {
+                    ((vel as f32 * SOFT_PEDAL_VELOCITY) as u8).max(1)
+                } else {
+                    vel
+                };
+                let selection = self.next_selection(false);
+                let voices =
+                    channel_sf.spawn_voices_attack(control, self.key, vel, legato, selection);
                 self.voices.push_voices(voices, max_layers);
+
+                // Only the voices of the note start from the glide start
+                self.glide.start = self.glide.target;
             }
-            KeyNoteEvent::Off => {
-                let vel = self.voices.release_next_voice();
-                if let Some(vel) = vel {
-                    let voices = channel_sf.spawn_voices_release(control, self.key, vel);
-                    self.voices.push_voices(voices, max_layers);
+            KeyNoteEvent::Glide(_)
+            | KeyNoteEvent::Pressure(_)
+            | KeyNoteEvent::Params(_)
+            | KeyNoteEvent::Tuning(_) => {
+                for voice in &mut self.voices.iter_voices_mut() {
+                    voice.process_controls(control);
                 }
             }
+            KeyNoteEvent::Off => {
+                self.release_next_voice(control, channel_sf, max_layers);
+            }
             KeyNoteEvent::AllOff => {
-                while let Some(vel) = self.voices.release_next_voice() {
-                    let voices = channel_sf.spawn_voices_release(control, self.key, vel);
-                    self.voices.push_voices(voices, max_layers);
-                }
+                while self.release_next_voice(control, channel_sf, max_layers) {}
             }
             KeyNoteEvent::AllKilled => {
                 self.voices.kill_all_voices();
             }
+            KeyNoteEvent::GroupOff(group) => {
+                self.voices.turn_off_group(group);
+            }
+            KeyNoteEvent::Damper(damper) => {
+                let released = self.voices.set_damper(damper);
+                self.spawn_pedal_release_voices(released, control, channel_sf, max_layers);
+            }
+            KeyNoteEvent::Sostenuto(sostenuto) => {
+                let released = self.voices.set_sostenuto(sostenuto);
+                self.spawn_pedal_release_voices(released, control, channel_sf, max_layers);
+            }
+            KeyNoteEvent::SoftPedal(_) => {}
+            KeyNoteEvent::ControllerTrigger(index) => {
+                let selection = self.next_selection(false);
+                let voices = channel_sf.spawn_voices_controller(control, index, selection);
+                self.voices.push_release_voices(voices, max_layers);
+            }
+        }
+    }
+
+    /// Returns the round robin position and a random value for the next
+    /// attack or release voices, and advances the round robin counter.
+    fn next_selection(&mut self, release: bool) -> VoiceSelection {
+        let sequence = if release {
+            &mut self.release_sequence
+        } else {
+            &mut self.attack_sequence
+        };
+        let selection = VoiceSelection {
+            sequence: *sequence,
+            random: self.rng.next_f32(),
+            keyswitch: self.keyswitch,
+        };
+        *sequence = sequence.wrapping_add(1);
+        selection
+    }
+
+    /// Releases the next voice and spawns its release voices.
+    /// Returns false if there were no voices to release.
+    fn release_next_voice(
+        &mut self,
+        control: &VoiceControlData,
+        channel_sf: &ChannelSoundfont,
+        max_layers: Option<usize>,
+    ) -> bool {
+        match self.voices.release_next_voice() {
+            Some((vel, released)) => {
+                let selection = self.next_selection(true);
+                let voices = channel_sf
+                    .spawn_voices_release(control, self.key, vel, true, released, selection);
+                self.voices.push_release_voices(voices, max_layers);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the voice controls with the pitch glide, aftertouch
+    /// and tuning of the key.
+    fn voice_control(&self, control: &VoiceControlData) -> VoiceControlData {
+        let semitones = self.tuning + self.params.coarse_tune + self.params.fine_tune / 100.0;
+        VoiceControlData {
+            voice_pitch_multiplier: control.voice_pitch_multiplier * 2.0f32.powf(semitones / 12.0),
+            glide: self.glide,
+            poly_pressure: self.pressure,
+            ..*control
         }
     }
 
     pub fn process_controls(&mut self, control: &VoiceControlData) {
+        let control = &self.voice_control(control);
         for voice in &mut self.voices.iter_voices_mut() {
             voice.process_controls(control);
         }
@@ -75,6 +219,7 @@ impl KeyData {
             voice.render_to(out);
         }
         self.voices.remove_ended_voices();
+        self.apply_params(out);
 
         let voice_count = self.voices.voice_count();
         let change = voice_count as i64 - self.last_voice_count as i64;
@@ -88,11 +233,46 @@ impl KeyData {
         self.last_voice_count = voice_count;
     }
 
+    /// Applies the level and pan of the key to the rendered audio.
+    fn apply_params(&self, out: &mut [f32]) {
+        let KeyParams { level, pan, .. } = self.params;
+        match self.channels {
+            ChannelCount::Mono => {
+                if level != 1.0 {
+                    out.iter_mut().for_each(|s| *s *= level);
+                }
+            }
+            ChannelCount::Stereo => {
+                let left = level * (2.0 - pan * 2.0).min(1.0);
+                let right = level * (pan * 2.0).min(1.0);
+                if left != 1.0 || right != 1.0 {
+                    for sample in out.chunks_mut(2) {
+                        sample[0] *= left;
+                        sample[1] *= right;
+                    }
+                }
+            }
+        }
+    }
+
     pub fn has_voices(&self) -> bool {
         self.voices.has_voices()
     }
 
-    pub fn set_damper(&mut self, damper: bool) {
-        self.voices.set_damper(damper);
+    /// Spawns the release voices of the notes that were released by
+    /// lifting a pedal.
+    fn spawn_pedal_release_voices(
+        &mut self,
+        released: Vec<u8>,
+        control: &VoiceControlData,
+        channel_sf: &ChannelSoundfont,
+        max_layers: Option<usize>,
+    ) {
+        for vel in released {
+            let selection = self.next_selection(true);
+            let voices =
+                channel_sf.spawn_voices_release(control, self.key, vel, false, true, selection);
+            self.voices.push_release_voices(voices, max_layers);
+        }
     }
 }
@@ -1,9 +1,15 @@
-use std::sync::{atomic::AtomicU64, Arc};
+use std::{
+    collections::HashMap,
+    sync::{atomic::AtomicU64, Arc},
+};
 
 use crate::{
+    control::{CCChangeType, CCTypes, ControllerState, MSBLSBControl},
     effects::MultiChannelBiQuad,
     helpers::{db_to_amp, prepapre_cache_vec, sum_simd, FREQS},
-    voice::VoiceControlData,
+    soundfont::KeyswitchState,
+    tuning::Tuning,
+    voice::{GlideControlData, VoiceControlData},
     AudioStreamParams, ChannelCount,
 };
 
@@ -23,11 +29,34 @@ mod params;
 mod voice_buffer;
 mod voice_spawner;
 
+mod effect;
 mod event;
+pub use effect::*;
 pub use event::*;
 
 pub use params::VoiceChannelStatsReader;
 
+/// The release time multiplier of a damper pedal that is almost half
+/// pressed. Lower values scale the release time linearly.
+const HALF_PEDAL_RELEASE_SCALE: f32 = 8.0;
+
+/// The default reverb send level, CC 91 value 40 as defined by GM2.
+const DEFAULT_REVERB_SEND: f32 = 40.0 / 127.0;
+
+/// The reverb and chorus send levels of a channel, set by CC 91 and CC 93,
+/// from a sample offset (per audio channel) of a rendered block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SendLevels {
+    /// The offset where the levels start
+    pub offset: usize,
+
+    /// The level (0 to 1) of the audio sent to the reverb
+    pub reverb: f32,
+
+    /// The level (0 to 1) of the audio sent to the chorus
+    pub chorus: f32,
+}
+
 pub(crate) struct ValueLerp {
     lerp_length: f32,
     step: f32,
@@ -64,27 +93,40 @@ struct Key {
     data: KeyData,
     audio_cache: Vec<f32>,
     event_cache: Vec<KeyNoteEvent>,
+
+    /// The number of note on events that haven't received a note off yet
+    held_notes: usize,
+
+    /// The per-note parameters set by the drum NRPNs
+    params: KeyParams,
+
+    /// The offset from the 12-TET pitch last sent to the key
+    tuning: f32,
 }
 
 impl Key {
-    pub fn new(key: u8, shared_voice_counter: Arc<AtomicU64>, options: ChannelInitOptions) -> Self {
+    pub fn new(
+        key: u8,
+        shared_voice_counter: Arc<AtomicU64>,
+        options: ChannelInitOptions,
+        channels: ChannelCount,
+    ) -> Self {
         Key {
-            data: KeyData::new(key, shared_voice_counter, options),
+            data: KeyData::new(key, shared_voice_counter, options, channels),
             audio_cache: Vec::new(),
             event_cache: Vec::new(),
+            held_notes: 0,
+            params: KeyParams::default(),
+            tuning: 0.0,
         }
     }
 }
 
 struct ControlEventData {
-    selected_lsb: i8,
-    selected_msb: i8,
-    pitch_bend_sensitivity_lsb: u8,
-    pitch_bend_sensitivity_msb: u8,
+    controllers: ControllerState,
     pitch_bend_sensitivity: f32,
     pitch_bend_value: f32,
-    fine_tune_lsb: u8,
-    fine_tune_msb: u8,
+    zone_pitch_bend: f32,
     fine_tune_value: f32,
     coarse_tune_value: f32,
     volume: ValueLerp, // 0.0 = silent, 1.0 = max volume
@@ -94,19 +136,20 @@ struct ControlEventData {
     expression: ValueLerp,
     preset: u8,
     bank: u8,
+    portamento: bool,
+    portamento_control: Option<u8>,
+    damper: bool,
+    sostenuto: bool,
+    soft_pedal: bool,
 }
 
 impl ControlEventData {
     pub fn new_defaults(sample_rate: u32, drums_only: bool) -> Self {
         ControlEventData {
-            selected_lsb: -1,
-            selected_msb: -1,
-            pitch_bend_sensitivity_lsb: 0,
-            pitch_bend_sensitivity_msb: 2,
+            controllers: ControllerState::new(),
             pitch_bend_sensitivity: 2.0,
             pitch_bend_value: 0.0,
-            fine_tune_lsb: 0,
-            fine_tune_msb: 0,
+            zone_pitch_bend: 0.0,
             fine_tune_value: 0.0,
             coarse_tune_value: 0.0,
             volume: ValueLerp::new(1.0, sample_rate),
@@ -116,6 +159,31 @@ impl ControlEventData {
             expression: ValueLerp::new(1.0, sample_rate),
             preset: 0,
             bank: if drums_only { 128 } else { 0 },
+            portamento: false,
+            portamento_control: None,
+            damper: false,
+            sostenuto: false,
+            soft_pedal: false,
+        }
+    }
+}
+
+/// The synthesizer wide parameters set by the universal SysEx messages.
+/// Unlike the controllers, they aren't reset by "Reset All Controllers".
+struct MasterControlData {
+    volume: ValueLerp,  // 0.0 = silent, 1.0 = max volume
+    balance: ValueLerp, // 0.0 = left, 0.5 = center, 1.0 = right
+    fine_tune: f32,     // Cents
+    coarse_tune: f32,   // Semitones
+}
+
+impl MasterControlData {
+    pub fn new_defaults(sample_rate: u32) -> Self {
+        MasterControlData {
+            volume: ValueLerp::new(1.0, sample_rate),
+            balance: ValueLerp::new(0.5, sample_rate),
+            fine_tune: 0.0,
+            coarse_tune: 0.0,
         }
     }
 }
@@ -164,8 +232,46 @@ pub struct VoiceChannel {
     /// Processed control data, ready to feed to voices
     voice_control_data: VoiceControlData,
 
+    /// The master volume, balance and tuning
+    master_control_data: MasterControlData,
+
+    /// If the channel is used for drums, can be changed by SysEx messages
+    drums: bool,
+
+    /// The last keyswitch key that was pressed
+    last_keyswitch: Option<u8>,
+
+    /// The last note that was started, used as the portamento source
+    last_note: Option<u8>,
+
+    /// Mono mode state. The held notes are in the order they were pressed,
+    /// the voice key is the key of the voice that plays them.
+    mono: bool,
+    mono_notes: Vec<u8>,
+    mono_voice_key: Option<u8>,
+
+    /// The tuning of the channel, and the MIDI Tuning Standard programs
+    /// by bank and program number
+    tuning: Option<Arc<Tuning>>,
+    tuning_programs: HashMap<(u8, u8), Tuning>,
+
+    /// Events to be applied at a sample offset of the next rendered block
+    timed_events: Vec<(u32, ChannelEvent)>,
+
+    /// The reverb and chorus send levels set by CC 91 and CC 93. Like the
+    /// other effect depths, they aren't reset by "Reset All Controllers".
+    reverb_send: f32,
+    chorus_send: f32,
+
+    /// The send levels of the parts of the last rendered block, which
+    /// change at the offsets of timed events
+    send_levels: Vec<SendLevels>,
+
     /// Effects
     cutoff: MultiChannelBiQuad,
+
+    /// The insert effects installed by `ChannelConfigEvent::SetEffects`
+    effects: Vec<Box<dyn ChannelEffect>>,
 }
 
 impl VoiceChannel {
@@ -194,7 +300,14 @@ impl VoiceChannel {
 
         VoiceChannel {
             params,
-            key_voices: fill_key_array(|i| Key::new(i, shared_voice_counter.clone(), options)),
+            key_voices: fill_key_array(|i| {
+                Key::new(
+                    i,
+                    shared_voice_counter.clone(),
+                    options,
+                    stream_params.channels,
+                )
+            }),
 
             threadpool,
 
@@ -206,6 +319,25 @@ impl VoiceChannel {
                 options.drums_only,
             ),
             voice_control_data: VoiceControlData::new_defaults(),
+            master_control_data: MasterControlData::new_defaults(stream_params.sample_rate),
+            drums: options.drums_only,
+
+            last_keyswitch: None,
+
+            last_note: None,
+
+            mono: false,
+            mono_notes: Vec::new(),
+            mono_voice_key: None,
+
+            tuning: None,
+            tuning_programs: HashMap::new(),
+
+            timed_events: Vec::new(),
+
+            reverb_send: DEFAULT_REVERB_SEND,
+            chorus_send: 0.0,
+            send_levels: Vec::new(),
 
             cutoff: MultiChannelBiQuad::new(
                 stream_params.channels.count() as usize,
@@ -214,17 +346,22 @@ impl VoiceChannel {
                 stream_params.sample_rate as f32,
                 None,
             ),
+
+            effects: Vec::new(),
         }
     }
 
     fn apply_channel_effects(&mut self, out: &mut [f32]) {
         let control = &mut self.control_event_data;
+        let master = &mut self.master_control_data;
 
         match self.stream_params.channels {
             ChannelCount::Mono => {
                 // Volume
                 for sample in out.iter_mut() {
-                    let vol = control.volume.get_next() * control.expression.get_next();
+                    let vol = control.volume.get_next()
+                        * control.expression.get_next()
+                        * master.volume.get_next();
                     let vol = vol.powi(2);
                     *sample *= vol;
                 }
@@ -232,7 +369,9 @@ impl VoiceChannel {
             ChannelCount::Stereo => {
                 // Volume
                 for sample in out.chunks_mut(2) {
-                    let vol = control.volume.get_next() * control.expression.get_next();
+                    let vol = control.volume.get_next()
+                        * control.expression.get_next()
+                        * master.volume.get_next();
                     let vol = vol.powi(2);
                     sample[0] *= vol;
                     sample[1] *= vol;
@@ -244,6 +383,13 @@ impl VoiceChannel {
                     sample[0] *= ((pan * std::f32::consts::PI / 2.0).cos()).min(1.0);
                     sample[1] *= ((pan * std::f32::consts::PI / 2.0).sin()).min(1.0);
                 }
+
+                // Balance
+                for sample in out.chunks_mut(2) {
+                    let balance = master.balance.get_next();
+                    sample[0] *= (2.0 - balance * 2.0).min(1.0);
+                    sample[1] *= (balance * 2.0).min(1.0);
+                }
             }
         }
 
@@ -253,6 +399,11 @@ impl VoiceChannel {
                 .set_filter_type(FilterType::LowPass, cutoff, control.resonance);
             self.cutoff.process(out);
         }
+
+        // Insert effects
+        for effect in self.effects.iter_mut() {
+            effect.process(out);
+        }
     }
 
     fn push_key_events_and_render(&mut self, out: &mut [f32]) {
@@ -315,166 +466,138 @@ impl VoiceChannel {
     /// See the `ControlEvent` documentation for more information.
     pub fn process_control_event(&mut self, event: ControlEvent) {
         match event {
-            ControlEvent::Raw(controller, value) => match controller {
-                0x00 => {
-                    // Bank select
-                    if !self.options.drums_only {
-                        self.control_event_data.bank = value;
-                        self.params.channel_sf.change_program(
-                            self.control_event_data.bank,
-                            self.control_event_data.preset,
-                        );
+            ControlEvent::Raw(controller, value) => {
+                // Keep track of the controller values for the voice modulators
+                if let Some(cc) = self.voice_control_data.cc.get_mut(controller as usize) {
+                    if *cc != value {
+                        let old = *cc;
+                        *cc = value;
+                        self.propagate_voice_controls();
+                        self.trigger_controller_voices(controller, old, value);
                     }
                 }
-                0x64 => {
-                    self.control_event_data.selected_lsb = value as i8;
-                }
-                0x65 => {
-                    self.control_event_data.selected_msb = value as i8;
+
+                match self
+                    .control_event_data
+                    .controllers
+                    .process(controller, value)
+                {
+                    Some(CCChangeType::Rpn(parameter, data)) => self.process_rpn(parameter, data),
+                    Some(CCChangeType::Nrpn(parameter, data)) => self.process_nrpn(parameter, data),
+                    None => {}
                 }
-                0x06 | 0x26 => {
-                    let (lsb, msb) = {
-                        let data = &self.control_event_data;
-                        (data.selected_lsb, data.selected_msb)
-                    };
-                    if msb == 0 {
-                        match lsb {
-                            0 => {
-                                // Pitch
-                                match controller {
-                                    0x06 => {
-                                        self.control_event_data.pitch_bend_sensitivity_msb = value
-                                    }
-                                    0x26 => {
-                                        self.control_event_data.pitch_bend_sensitivity_lsb = value
-                                    }
-                                    _ => (),
-                                }
-
-                                let sensitivity = {
-                                    let data = &self.control_event_data;
-                                    (data.pitch_bend_sensitivity_msb as f32)
-                                        + (data.pitch_bend_sensitivity_lsb as f32) / 100.0
-                                };
-
-                                self.process_control_event(ControlEvent::PitchBendSensitivity(
-                                    sensitivity,
-                                ))
-                            }
-                            1 => {
-                                // Fine tune
-                                match controller {
-                                    0x06 => self.control_event_data.fine_tune_msb = value,
-                                    0x26 => self.control_event_data.fine_tune_lsb = value,
-                                    _ => (),
-                                }
-                                let val: u16 = ((self.control_event_data.fine_tune_msb as u16)
-                                    << 6)
-                                    + self.control_event_data.fine_tune_lsb as u16;
-                                let val = (val as f32 - 4096.0) / 4096.0 * 100.0;
-                                self.process_control_event(ControlEvent::FineTune(val));
-                            }
-                            2 => {
-                                // Coarse tune
-                                if controller == 0x06 {
-                                    self.process_control_event(ControlEvent::CoarseTune(
-                                        value as f32 - 64.0,
-                                    ))
-                                }
-                            }
-                            _ => {}
+                let controllers = &self.control_event_data.controllers;
+
+                match controller {
+                    0x00 => {
+                        // Bank select
+                        if !self.drums {
+                            self.control_event_data.bank = value;
+                            self.params.channel_sf.change_program(
+                                self.control_event_data.bank,
+                                self.control_event_data.preset,
+                            );
                         }
                     }
-                }
-                0x07 => {
-                    // Volume
-                    let vol: f32 = value as f32 / 128.0;
-                    self.control_event_data.volume.set_end(vol);
-                }
-                0x0A => {
-                    // Pan
-                    let pan: f32 = value as f32 / 128.0;
-                    self.control_event_data.pan.set_end(pan);
-                }
-                0x0B => {
-                    // Expression
-                    let expr = value as f32 / 128.0;
-                    self.control_event_data.expression.set_end(expr);
-                }
-                0x40 => {
-                    // Damper / Sustain
-                    let damper = match value {
-                        0..=63 => false,
-                        64..=127 => true,
-                        _ => false,
-                    };
-
-                    for key in self.key_voices.iter_mut() {
-                        key.data.set_damper(damper);
+                    0x07 | 0x27 => {
+                        // Volume
+                        let vol = controllers.controller(0x07).value() as f32 / 16384.0;
+                        self.control_event_data.volume.set_end(vol);
                     }
-                }
-                0x47 => {
-                    // Resonance
-                    if value > 64 {
-                        let db = (value as f32 - 64.0) / 2.4;
-                        let value = db_to_amp(db) * Q_BUTTERWORTH_F32;
-                        self.control_event_data.resonance = Some(value);
-                    } else {
-                        self.control_event_data.resonance = None;
+                    0x0A | 0x2A => {
+                        // Pan
+                        let pan = controllers.controller(0x0A).value() as f32 / 16384.0;
+                        self.control_event_data.pan.set_end(pan);
                     }
-                }
-                0x48 => {
-                    // Release
-                    self.voice_control_data.envelope.release = Some(value);
-                    self.propagate_voice_controls();
-                }
-                0x49 => {
-                    // Attack
-                    self.voice_control_data.envelope.attack = Some(value);
-                    self.propagate_voice_controls();
-                }
-                0x4A => {
-                    // Cutoff
-                    if value < 64 {
-                        let value = value as usize + 64;
-                        let mut freq = FREQS[value];
-                        if freq > 7000.0 {
-                            // I hate BASS
-                            let mult = freq / 7000.0 - 1.0;
-                            let mult = mult * 2.36 + 1.0;
-                            freq = mult * 7000.0;
+                    0x0B | 0x2B => {
+                        // Expression
+                        let expr = controllers.controller(0x0B).value() as f32 / 16384.0;
+                        self.control_event_data.expression.set_end(expr);
+                    }
+                    0x40 => {
+                        // Damper / Sustain
+                        self.set_damper(value);
+                    }
+                    0x41 => {
+                        // Portamento
+                        self.control_event_data.portamento = value >= 64;
+                    }
+                    0x42 => {
+                        // Sostenuto
+                        self.set_sostenuto(value >= 64);
+                    }
+                    0x43 => {
+                        // Soft pedal
+                        self.set_soft_pedal(value >= 64);
+                    }
+                    0x47 => {
+                        // Resonance
+                        self.set_resonance(value);
+                    }
+                    0x48 => {
+                        // Release
+                        self.voice_control_data.envelope.release = Some(value);
+                        self.propagate_voice_controls();
+                    }
+                    0x49 => {
+                        // Attack
+                        self.voice_control_data.envelope.attack = Some(value);
+                        self.propagate_voice_controls();
+                    }
+                    0x4A => {
+                        // Cutoff
+                        self.set_cutoff(value);
+                    }
+                    0x54 => {
+                        // Portamento control
+                        self.control_event_data.portamento_control = Some(value);
+                    }
+                    0x5B => {
+                        // Reverb send
+                        self.reverb_send = value as f32 / 127.0;
+                    }
+                    0x5D => {
+                        // Chorus send
+                        self.chorus_send = value as f32 / 127.0;
+                    }
+                    0x78 => {
+                        // All Sounds Off
+                        if value == 0 {
+                            self.process_event(ChannelEvent::Audio(
+                                ChannelAudioEvent::AllNotesKilled,
+                            ));
                         }
-                        self.control_event_data.cutoff = Some(freq);
-                    } else {
-                        self.control_event_data.cutoff = None;
                     }
-                }
-                0x78 => {
-                    // All Sounds Off
-                    if value == 0 {
-                        self.process_event(ChannelEvent::Audio(ChannelAudioEvent::AllNotesKilled));
+                    0x79 => {
+                        // Reset All Controllers
+                        if value == 0 {
+                            self.reset_control();
+                        }
                     }
-                }
-                0x79 => {
-                    // Reset All Controllers
-                    if value == 0 {
-                        self.reset_control();
+                    0x7B => {
+                        // All Notes Off
+                        if value == 0 {
+                            self.process_event(ChannelEvent::Audio(ChannelAudioEvent::AllNotesOff));
+                        }
                     }
-                }
-                0x7B => {
-                    // All Notes Off
-                    if value == 0 {
-                        self.process_event(ChannelEvent::Audio(ChannelAudioEvent::AllNotesOff));
+                    0x7E => {
+                        // Mono mode on
+                        self.set_mono(true);
+                    }
+                    0x7F => {
+                        // Poly mode on
+                        self.set_mono(false);
                     }
+                    _ => {}
                 }
-                _ => {}
-            },
+            }
             ControlEvent::PitchBendSensitivity(sensitivity) => {
                 let pitch_bend = {
                     let data = &mut self.control_event_data;
                     data.pitch_bend_sensitivity = sensitivity;
                     data.pitch_bend_sensitivity * data.pitch_bend_value
                 };
+                self.voice_control_data.pitch_wheel_sensitivity = sensitivity;
                 self.process_control_event(ControlEvent::PitchBend(pitch_bend));
             }
             ControlEvent::PitchBendValue(value) => {
@@ -483,12 +606,17 @@ impl VoiceChannel {
                     data.pitch_bend_value = value;
                     data.pitch_bend_sensitivity * data.pitch_bend_value
                 };
+                self.voice_control_data.pitch_wheel = value;
                 self.process_control_event(ControlEvent::PitchBend(pitch_bend));
             }
             ControlEvent::PitchBend(value) => {
                 self.control_event_data.pitch_bend_value = value;
                 self.process_pitch();
             }
+            ControlEvent::ZonePitchBend(value) => {
+                self.control_event_data.zone_pitch_bend = value;
+                self.process_pitch();
+            }
             ControlEvent::FineTune(value) => {
                 self.control_event_data.fine_tune_value = value;
                 self.process_pitch();
@@ -497,60 +625,327 @@ impl VoiceChannel {
                 self.control_event_data.coarse_tune_value = value;
                 self.process_pitch();
             }
+            ControlEvent::MasterVolume(value) => {
+                self.master_control_data.volume.set_end(value);
+            }
+            ControlEvent::MasterBalance(value) => {
+                self.master_control_data.balance.set_end(value);
+            }
+            ControlEvent::MasterFineTune(value) => {
+                self.master_control_data.fine_tune = value;
+                self.process_pitch();
+            }
+            ControlEvent::MasterCoarseTune(value) => {
+                self.master_control_data.coarse_tune = value;
+                self.process_pitch();
+            }
+        }
+    }
+
+    /// Applies a change of a registered parameter.
+    fn process_rpn(&mut self, parameter: u16, data: MSBLSBControl) {
+        match parameter {
+            0 => {
+                // Pitch bend sensitivity
+                let sensitivity = data.msb() as f32 + data.lsb() as f32 / 100.0;
+                self.process_control_event(ControlEvent::PitchBendSensitivity(sensitivity));
+            }
+            1 => {
+                // Fine tune
+                let value = (data.value() as f32 - 8192.0) / 8192.0 * 100.0;
+                self.process_control_event(ControlEvent::FineTune(value));
+            }
+            2 => {
+                // Coarse tune
+                self.process_control_event(ControlEvent::CoarseTune(data.msb() as f32 - 64.0));
+            }
+            5 => {
+                // Modulation depth range
+                let cents = data.msb() as f32 * 100.0 + data.lsb() as f32 * 100.0 / 128.0;
+                self.voice_control_data.modulation_depth_range = cents;
+                self.propagate_voice_controls();
+            }
+            3 | 4 => {
+                // Tuning program and bank select
+                self.apply_tuning();
+            }
+            _ => {}
+        }
+    }
+
+    /// Applies a change of a non-registered parameter. The GS and XG
+    /// parameters of the vibrato, filter, envelope and drum notes are
+    /// supported.
+    fn process_nrpn(&mut self, parameter: u16, data: MSBLSBControl) {
+        let msb = (parameter >> 7) as u8;
+        let lsb = (parameter & 0x7F) as u8;
+        let value = data.msb();
+        let relative = value as f32 - 64.0;
+
+        match (msb, lsb) {
+            (0x01, 0x08) => {
+                // Vibrato rate
+                self.voice_control_data.vibrato.rate = 2.0f32.powf(relative / 32.0);
+                self.propagate_voice_controls();
+            }
+            (0x01, 0x09) => {
+                // Vibrato depth
+                self.voice_control_data.vibrato.depth = value as f32 / 64.0;
+                self.propagate_voice_controls();
+            }
+            (0x01, 0x0A) => {
+                // Vibrato delay
+                self.voice_control_data.vibrato.delay = 2.0f32.powf(relative / 32.0);
+                self.propagate_voice_controls();
+            }
+            (0x01, 0x20) => self.set_cutoff(value),
+            (0x01, 0x21) => self.set_resonance(value),
+            (0x01, 0x63) => {
+                // Attack time
+                self.voice_control_data.envelope.attack = Some(value);
+                self.propagate_voice_controls();
+            }
+            (0x01, 0x64) => {
+                // Decay time
+                self.voice_control_data.envelope.decay = Some(value);
+                self.propagate_voice_controls();
+            }
+            (0x01, 0x66) => {
+                // Release time
+                self.voice_control_data.envelope.release = Some(value);
+                self.propagate_voice_controls();
+            }
+            (0x18..=0x1C, key) => {
+                // Drum note pitch, level and pan
+                let key = &mut self.key_voices[key as usize];
+                match msb {
+                    0x18 => key.params.coarse_tune = relative,
+                    0x19 => key.params.fine_tune = relative,
+                    0x1A => key.params.level = (value as f32 / 127.0).powi(2),
+                    0x1C => key.params.pan = (value.max(1) - 1) as f32 / 126.0,
+                    _ => return,
+                }
+                key.event_cache.push(KeyNoteEvent::Params(key.params));
+            }
+            _ => {}
+        }
+    }
+
+    /// Sets the damper pedal from a controller value. Values of 64 and
+    /// above hold the released notes, lower values make them release
+    /// slower, for half-pedalling.
+    fn set_damper(&mut self, value: u8) {
+        let release_scale = match value {
+            1..=63 => 1.0 + value as f32 / 64.0 * (HALF_PEDAL_RELEASE_SCALE - 1.0),
+            _ => 1.0,
+        };
+        if self.voice_control_data.envelope.release_scale != release_scale {
+            self.voice_control_data.envelope.release_scale = release_scale;
+            self.propagate_voice_controls();
+        }
+
+        let damper = value >= 64;
+        if self.control_event_data.damper != damper {
+            self.control_event_data.damper = damper;
+            for key in self.key_voices.iter_mut() {
+                key.event_cache.push(KeyNoteEvent::Damper(damper));
+            }
+        }
+    }
+
+    /// Sets the sostenuto pedal, which only holds the notes of the keys
+    /// that are held when it is pressed.
+    fn set_sostenuto(&mut self, sostenuto: bool) {
+        if self.control_event_data.sostenuto == sostenuto {
+            return;
+        }
+        self.control_event_data.sostenuto = sostenuto;
+        for key in self.key_voices.iter_mut() {
+            if !sostenuto || key.held_notes > 0 {
+                key.event_cache.push(KeyNoteEvent::Sostenuto(sostenuto));
+            }
+        }
+    }
+
+    /// Sets the soft pedal, which makes the following notes softer.
+    fn set_soft_pedal(&mut self, soft_pedal: bool) {
+        if self.control_event_data.soft_pedal == soft_pedal {
+            return;
+        }
+        self.control_event_data.soft_pedal = soft_pedal;
+        for key in self.key_voices.iter_mut() {
+            key.event_cache.push(KeyNoteEvent::SoftPedal(soft_pedal));
+        }
+    }
+
+    /// Sets the cutoff of the channel filter from a controller value,
+    /// values of 64 and above disable the filter.
+    fn set_cutoff(&mut self, value: u8) {
+        if value < 64 {
+            let value = value as usize + 64;
+            let mut freq = FREQS[value];
+            if freq > 7000.0 {
+                // I hate BASS
+                let mult = freq / 7000.0 - 1.0;
+                let mult = mult * 2.36 + 1.0;
+                freq = mult * 7000.0;
+            }
+            self.control_event_data.cutoff = Some(freq);
+        } else {
+            self.control_event_data.cutoff = None;
+        }
+    }
+
+    /// Sets the resonance of the channel filter from a controller value.
+    fn set_resonance(&mut self, value: u8) {
+        if value > 64 {
+            let db = (value as f32 - 64.0) / 2.4;
+            let value = db_to_amp(db) * Q_BUTTERWORTH_F32;
+            self.control_event_data.resonance = Some(value);
+        } else {
+            self.control_event_data.resonance = None;
         }
     }
 
     fn process_pitch(&mut self) {
         let data = &mut self.control_event_data;
-        let pitch_bend = data.pitch_bend_value;
-        let fine_tune = data.fine_tune_value;
-        let coarse_tune = data.coarse_tune_value;
+        let master = &self.master_control_data;
+        let pitch_bend = data.pitch_bend_value + data.zone_pitch_bend;
+        let fine_tune = data.fine_tune_value + master.fine_tune;
+        let coarse_tune = data.coarse_tune_value + master.coarse_tune;
         let combined = pitch_bend + coarse_tune + fine_tune / 100.0;
 
         self.voice_control_data.voice_pitch_multiplier = 2.0f32.powf(combined / 12.0);
         self.propagate_voice_controls();
     }
 
+    /// Sends the pitch offsets of the active tuning to the keys. The MTS
+    /// program selected with RPN 3 and 4 is used if it was defined,
+    /// otherwise the tuning set for the channel.
+    fn apply_tuning(&mut self) {
+        let controllers = &self.control_event_data.controllers;
+        let program = controllers.parameter(CCTypes::Rpn(3)).msb();
+        let bank = controllers.parameter(CCTypes::Rpn(4)).msb();
+
+        let tuning = self
+            .tuning_programs
+            .get(&(bank, program))
+            .or(self.tuning.as_deref());
+
+        for (i, key) in self.key_voices.iter_mut().enumerate() {
+            let offset = tuning.map(|t| t.offset(i as u8)).unwrap_or(0.0);
+            if key.tuning != offset {
+                key.tuning = offset;
+                key.event_cache.push(KeyNoteEvent::Tuning(offset));
+            }
+        }
+    }
+
     /// Sends a ChannelEvent to the channel.
     /// See the `ChannelEvent` documentation for more information.
     pub fn process_event(&mut self, event: ChannelEvent) {
         self.push_events_iter(std::iter::once(event));
     }
 
+    /// Sends a ChannelEvent to the channel, to be applied `offset` samples
+    /// (per audio channel) into the next rendered block. Rendering is split
+    /// at the offsets of the events, events past the end of the block are
+    /// applied in the following blocks.
+    ///
+    /// Events sent without an offset are applied before the timed events.
+    pub fn process_event_at(&mut self, event: ChannelEvent, offset: u32) {
+        self.timed_events.push((offset, event));
+    }
+
+    /// Renders the block in parts, applying the timed events between them.
+    fn render_timed_events(&mut self, out: &mut [f32]) {
+        let channels = self.stream_params.channels.count() as usize;
+        let len = out.len() / channels;
+
+        let mut events = std::mem::take(&mut self.timed_events);
+        events.sort_by_key(|(offset, _)| *offset);
+        let count = events.partition_point(|(offset, _)| (*offset as usize) < len);
+
+        let mut pos = 0;
+        for (offset, event) in events.drain(..count) {
+            let offset = offset as usize;
+            if offset > pos {
+                self.push_key_events_and_render(&mut out[pos * channels..offset * channels]);
+                pos = offset;
+            }
+            self.process_event(event);
+            self.push_send_levels(offset);
+        }
+        self.push_key_events_and_render(&mut out[pos * channels..]);
+
+        // The events past the end of the block are kept for the next blocks
+        for (offset, _) in events.iter_mut() {
+            *offset -= len as u32;
+        }
+        self.timed_events = events;
+    }
+
     /// Sends multiple ChannelEvent items to the channel as an iterator.
     pub fn push_events_iter<T: Iterator<Item = ChannelEvent>>(&mut self, iter: T) {
         for e in iter {
             match e {
                 ChannelEvent::Audio(audio) => match audio {
                     ChannelAudioEvent::NoteOn { key, vel } => {
-                        if let Some(key) = self.key_voices.get_mut(key as usize) {
-                            let ev = KeyNoteEvent::On(vel);
-                            key.event_cache.push(ev);
-                        }
+                        self.note_on(key, vel);
                     }
                     ChannelAudioEvent::NoteOff { key } => {
-                        if let Some(key) = self.key_voices.get_mut(key as usize) {
-                            let ev = KeyNoteEvent::Off;
-                            key.event_cache.push(ev);
-                        }
+                        self.note_off(key);
                     }
                     ChannelAudioEvent::AllNotesOff => {
                         for key in self.key_voices.iter_mut() {
                             let ev = KeyNoteEvent::AllOff;
                             key.event_cache.push(ev);
+                            key.held_notes = 0;
                         }
+                        self.mono_notes.clear();
+                        self.mono_voice_key = None;
                     }
                     ChannelAudioEvent::AllNotesKilled => {
                         for key in self.key_voices.iter_mut() {
                             let ev = KeyNoteEvent::AllKilled;
                             key.event_cache.push(ev);
                         }
+                        self.mono_notes.clear();
+                        self.mono_voice_key = None;
+                        for effect in self.effects.iter_mut() {
+                            effect.reset();
+                        }
                     }
                     ChannelAudioEvent::ResetControl => {
                         self.reset_control();
                     }
+                    ChannelAudioEvent::SystemReset => {
+                        self.system_reset();
+                    }
+                    ChannelAudioEvent::SetDrums(drums) => {
+                        self.drums = drums;
+                        self.control_event_data.bank = if drums { 128 } else { 0 };
+                        self.params.channel_sf.change_program(
+                            self.control_event_data.bank,
+                            self.control_event_data.preset,
+                        );
+                    }
                     ChannelAudioEvent::Control(control) => {
-                        self.process_control_event(control);
+                        self.process_control_event(control.clone());
+                        for effect in self.effects.iter_mut() {
+                            effect.process_control(&control);
+                        }
+                    }
+                    ChannelAudioEvent::PolyAftertouch { key, value } => {
+                        if let Some(key) = self.key_voices.get_mut(key as usize) {
+                            key.event_cache.push(KeyNoteEvent::Pressure(value));
+                        }
+                    }
+                    ChannelAudioEvent::ChannelPressure(value) => {
+                        if self.voice_control_data.channel_pressure != value {
+                            self.voice_control_data.channel_pressure = value;
+                            self.propagate_voice_controls();
+                        }
                     }
                     ChannelAudioEvent::ProgramChange(preset) => {
                         self.control_event_data.preset = preset;
@@ -559,12 +954,227 @@ impl VoiceChannel {
                             self.control_event_data.preset,
                         );
                     }
+                    ChannelAudioEvent::SetTuning(tuning) => {
+                        self.tuning = tuning;
+                        self.apply_tuning();
+                    }
+                    ChannelAudioEvent::TuningChange {
+                        bank,
+                        program,
+                        changes,
+                    } => {
+                        let tuning = self.tuning_programs.entry((bank, program)).or_default();
+                        for (key, pitch) in changes {
+                            tuning.set_pitch(key, pitch);
+                        }
+                        self.apply_tuning();
+                    }
                 },
+                ChannelEvent::Config(ChannelConfigEvent::SetEffects(effects)) => {
+                    self.effects = effects
+                        .iter()
+                        .map(|effect| effect.create(self.stream_params))
+                        .collect();
+                }
                 ChannelEvent::Config(config) => self.params.process_config_event(config),
             }
         }
     }
 
+    fn note_on(&mut self, key: u8, vel: u8) {
+        if key >= 128 {
+            return;
+        }
+
+        if self.params.channel_sf.is_keyswitch(key) {
+            self.last_keyswitch = Some(key);
+
+            // A keyswitch only changes the articulation of the mono voice
+            if self.mono {
+                self.key_voices[key as usize].held_notes += 1;
+                return;
+            }
+        }
+
+        let glide = self.portamento_glide(key);
+        self.last_note = Some(key);
+
+        if self.mono {
+            self.mono_notes.retain(|&k| k != key);
+            self.mono_notes.push(key);
+
+            // Move the sounding voice to the new note instead of starting a new one
+            if self.mono_notes.len() > 1 {
+                if let Some(voice_key) = self.mono_voice_key {
+                    self.key_voices[key as usize].held_notes += 1;
+                    self.glide_mono_voice(voice_key, key, glide.time);
+                    return;
+                }
+            }
+            self.mono_voice_key = Some(key);
+        }
+
+        let keyswitch = KeyswitchState {
+            last: self.last_keyswitch,
+            held: self.held_keys(),
+        };
+        let legato = keyswitch.held != 0;
+        self.turn_off_exclusive_groups(key, vel, legato, keyswitch);
+
+        let key = &mut self.key_voices[key as usize];
+        key.event_cache.push(KeyNoteEvent::On {
+            vel,
+            legato,
+            keyswitch,
+            glide,
+        });
+        key.held_notes += 1;
+    }
+
+    fn note_off(&mut self, key: u8) {
+        if key >= 128 {
+            return;
+        }
+
+        let held_key = &mut self.key_voices[key as usize];
+        held_key.held_notes = held_key.held_notes.saturating_sub(1);
+
+        if self.mono && self.mono_notes.contains(&key) {
+            let was_last = self.mono_notes.last() == Some(&key);
+            self.mono_notes.retain(|&k| k != key);
+
+            if let Some(voice_key) = self.mono_voice_key {
+                match self.mono_notes.last() {
+                    Some(&last) => {
+                        // Return to the last held note
+                        if was_last {
+                            let time = self.portamento_time();
+                            self.glide_mono_voice(voice_key, last, time);
+                        }
+                    }
+                    None => {
+                        self.key_voices[voice_key as usize]
+                            .event_cache
+                            .push(KeyNoteEvent::AllOff);
+                        self.mono_voice_key = None;
+                    }
+                }
+                return;
+            }
+        }
+
+        self.key_voices[key as usize]
+            .event_cache
+            .push(KeyNoteEvent::Off);
+    }
+
+    /// Returns the length of the portamento glide in seconds,
+    /// or 0 if portamento is disabled.
+    fn portamento_time(&self) -> f32 {
+        let data = &self.control_event_data;
+        if data.portamento {
+            let millis = data.controllers.controller(0x05).value();
+            millis as f32 / 1000.0
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the portamento glide of a new note. The glide starts from the
+    /// portamento control key if it was set, otherwise from the last note.
+    fn portamento_glide(&mut self, key: u8) -> GlideControlData {
+        let from = match self.control_event_data.portamento_control.take() {
+            Some(from) => Some(from),
+            None if self.control_event_data.portamento => self.last_note,
+            None => None,
+        };
+
+        match from {
+            Some(from) => GlideControlData {
+                start: from as f32 - key as f32,
+                target: 0.0,
+                time: self.portamento_time(),
+            },
+            None => GlideControlData::default(),
+        }
+    }
+
+    /// Moves the pitch of the voice of a mono mode channel to another note.
+    fn glide_mono_voice(&mut self, voice_key: u8, key: u8, time: f32) {
+        let offset = key as f32 - voice_key as f32;
+        self.key_voices[voice_key as usize]
+            .event_cache
+            .push(KeyNoteEvent::Glide(GlideControlData {
+                start: offset,
+                target: offset,
+                time,
+            }));
+    }
+
+    /// Sets the mono or poly mode of the channel, turning off all notes.
+    fn set_mono(&mut self, mono: bool) {
+        self.mono = mono;
+        self.process_event(ChannelEvent::Audio(ChannelAudioEvent::AllNotesOff));
+    }
+
+    /// Queues the voices that are triggered by a controller moving
+    /// from `old` to `new`.
+    fn trigger_controller_voices(&mut self, controller: u8, old: u8, new: u8) {
+        for (index, key) in self
+            .params
+            .channel_sf
+            .triggered_controller_spawners(controller, old, new)
+        {
+            if let Some(key) = self.key_voices.get_mut(key as usize) {
+                key.event_cache.push(KeyNoteEvent::ControllerTrigger(index));
+            }
+        }
+    }
+
+    /// Returns a bitmask of the keys that have notes held.
+    fn held_keys(&self) -> u128 {
+        self.key_voices
+            .iter()
+            .enumerate()
+            .filter(|(_, key)| key.held_notes > 0)
+            .fold(0, |held, (i, _)| held | 1 << i)
+    }
+
+    /// Stops the voices of all keys that are turned off by the exclusive
+    /// class groups of a new note. The events are queued before the note
+    /// itself, so the new voices aren't affected.
+    fn turn_off_exclusive_groups(
+        &mut self,
+        key: u8,
+        vel: u8,
+        legato: bool,
+        keyswitch: KeyswitchState,
+    ) {
+        if key >= 128 || vel >= 128 {
+            return;
+        }
+
+        let groups = self.params.channel_sf.exclusive_groups_attack(
+            key,
+            vel,
+            legato,
+            keyswitch,
+            &self.voice_control_data.cc,
+        );
+        if groups.is_empty() {
+            return;
+        }
+
+        for key in self.key_voices.iter_mut() {
+            if !key.data.has_voices() && key.event_cache.is_empty() {
+                continue;
+            }
+            for &group in &groups {
+                key.event_cache.push(KeyNoteEvent::GroupOff(group));
+            }
+        }
+    }
+
     /// Returns a reader for the VoiceChannel statistics.
     /// See the `VoiceChannelStatsReader` documentation for more information.
     pub fn get_channel_stats(&self) -> VoiceChannelStatsReader {
@@ -572,17 +1182,73 @@ impl VoiceChannel {
         VoiceChannelStatsReader::new(stats)
     }
 
+    /// Returns the reverb and chorus send levels of the last rendered block,
+    /// in order of their offsets. The first levels start at offset 0, and
+    /// new levels are added where timed events change them.
+    pub fn send_levels(&self) -> &[SendLevels] {
+        &self.send_levels
+    }
+
+    /// Records the current send levels from an offset of the block.
+    fn push_send_levels(&mut self, offset: usize) {
+        let levels = SendLevels {
+            offset,
+            reverb: self.reverb_send,
+            chorus: self.chorus_send,
+        };
+        match self.send_levels.last_mut() {
+            Some(last) if last.offset == offset => *last = levels,
+            Some(last) if (last.reverb, last.chorus) == (levels.reverb, levels.chorus) => {}
+            _ => self.send_levels.push(levels),
+        }
+    }
+
+    /// Returns the channel to its initial state, as done by the
+    /// GM, GS and XG system reset messages.
+    fn system_reset(&mut self) {
+        self.process_event(ChannelEvent::Audio(ChannelAudioEvent::AllNotesOff));
+        self.mono = false;
+        self.mono_notes.clear();
+        self.mono_voice_key = None;
+        self.drums = self.options.drums_only;
+        self.master_control_data = MasterControlData::new_defaults(self.stream_params.sample_rate);
+        self.reverb_send = DEFAULT_REVERB_SEND;
+        self.chorus_send = 0.0;
+
+        // The selected articulation isn't a controller, so it's only
+        // cleared here and not by Reset All Controllers
+        self.last_keyswitch = None;
+
+        for effect in self.effects.iter_mut() {
+            effect.reset();
+        }
+
+        for key in self.key_voices.iter_mut() {
+            key.params = KeyParams::default();
+            key.event_cache.push(KeyNoteEvent::Params(key.params));
+        }
+
+        self.reset_control();
+    }
+
     fn reset_control(&mut self) {
         self.control_event_data =
-            ControlEventData::new_defaults(self.stream_params.sample_rate, self.options.drums_only);
+            ControlEventData::new_defaults(self.stream_params.sample_rate, self.drums);
         self.voice_control_data = VoiceControlData::new_defaults();
         self.process_event(ChannelEvent::Audio(ChannelAudioEvent::ProgramChange(0)));
-        self.propagate_voice_controls();
+        // Keeps the master tuning
+        self.process_pitch();
 
         self.control_event_data.cutoff = None;
 
+        // The tuning program selection (RPN 3 and 4) is reset with the controllers
+        self.apply_tuning();
+
         for key in self.key_voices.iter_mut() {
-            key.data.set_damper(false);
+            key.event_cache.push(KeyNoteEvent::Pressure(0));
+            key.event_cache.push(KeyNoteEvent::Damper(false));
+            key.event_cache.push(KeyNoteEvent::Sostenuto(false));
+            key.event_cache.push(KeyNoteEvent::SoftPedal(false));
         }
     }
 }
@@ -593,6 +1259,16 @@ impl AudioPipe for VoiceChannel {
     }
 
     fn read_samples_unchecked(&mut self, out: &mut [f32]) {
-        self.push_key_events_and_render(out);
+        self.send_levels.clear();
+        self.push_send_levels(0);
+
+        if self.timed_events.is_empty() {
+            self.push_key_events_and_render(out);
+        } else {
+            self.render_timed_events(out);
+        }
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests;
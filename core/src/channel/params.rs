@@ -60,6 +60,8 @@ impl VoiceChannelParams {
             ChannelConfigEvent::SetLayerCount(count) => {
                 self.layers = count;
             }
+            // The effects are created by the channel
+            ChannelConfigEvent::SetEffects(_) => {}
         }
     }
 }
@@ -0,0 +1,377 @@
+use std::sync::Arc;
+
+use super::*;
+use crate::{
+    soundfont::{SoundfontBase, VoiceSpawner},
+    voice::{ExclusiveClass, ReleaseType, Voice, VoiceGeneratorBase, VoiceSampleGenerator},
+};
+
+/// A voice that outputs a constant level until it is released.
+struct TestVoice {
+    ended: bool,
+}
+
+impl VoiceGeneratorBase for TestVoice {
+    fn ended(&self) -> bool {
+        self.ended
+    }
+
+    fn signal_release(&mut self, _rel_type: ReleaseType) {
+        self.ended = true;
+    }
+
+    fn process_controls(&mut self, _control: &VoiceControlData) {}
+}
+
+impl VoiceSampleGenerator for TestVoice {
+    fn render_to(&mut self, buffer: &mut [f32]) {
+        if !self.ended {
+            for sample in buffer.iter_mut() {
+                *sample += 1.0;
+            }
+        }
+    }
+}
+
+impl Voice for TestVoice {
+    fn is_releasing(&self) -> bool {
+        self.ended
+    }
+
+    fn is_killed(&self) -> bool {
+        self.ended
+    }
+
+    fn velocity(&self) -> u8 {
+        127
+    }
+
+    fn exclusive_class(&self) -> ExclusiveClass {
+        ExclusiveClass::NONE
+    }
+}
+
+/// The key that acts as a keyswitch in the test soundfont.
+const TEST_KEYSWITCH: u8 = 24;
+
+struct TestVoiceSpawner;
+
+impl VoiceSpawner for TestVoiceSpawner {
+    fn spawn_voice(&self, _control: &VoiceControlData) -> Box<dyn Voice> {
+        Box::new(TestVoice { ended: false })
+    }
+
+    fn keyswitch_keys(&self) -> u128 {
+        1 << TEST_KEYSWITCH
+    }
+}
+
+/// A soundfont that plays a constant level on every key.
+#[derive(Debug)]
+struct TestSoundfont(AudioStreamParams);
+
+pub(crate) fn test_soundfont(stream_params: AudioStreamParams) -> Arc<dyn SoundfontBase> {
+    Arc::new(TestSoundfont(stream_params))
+}
+
+impl SoundfontBase for TestSoundfont {
+    fn stream_params(&self) -> &'_ AudioStreamParams {
+        &self.0
+    }
+
+    fn get_attack_voice_spawners_at(
+        &self,
+        _bank: u8,
+        _preset: u8,
+        _key: u8,
+        _vel: u8,
+    ) -> Vec<Box<dyn VoiceSpawner>> {
+        vec![Box::new(TestVoiceSpawner)]
+    }
+
+    fn get_release_voice_spawners_at(
+        &self,
+        _bank: u8,
+        _preset: u8,
+        _key: u8,
+        _vel: u8,
+    ) -> Vec<Box<dyn VoiceSpawner>> {
+        Vec::new()
+    }
+}
+
+pub(crate) fn test_channel() -> VoiceChannel {
+    let stream_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+    let mut channel = VoiceChannel::new(Default::default(), stream_params, None);
+    channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
+        vec![test_soundfont(stream_params)],
+    )));
+    channel
+}
+
+fn send(channel: &mut VoiceChannel, event: ChannelAudioEvent) {
+    channel.process_event(ChannelEvent::Audio(event));
+}
+
+fn render(channel: &mut VoiceChannel) -> Vec<f32> {
+    let mut out = vec![0.0; 256];
+    channel.read_samples(&mut out);
+    out
+}
+
+fn is_silent(samples: &[f32]) -> bool {
+    samples.iter().all(|&s| s == 0.0)
+}
+
+#[test]
+fn test_mono_note_after_kill() {
+    let mut channel = test_channel();
+    send(
+        &mut channel,
+        ChannelAudioEvent::Control(ControlEvent::Raw(0x7E, 1)),
+    );
+
+    send(
+        &mut channel,
+        ChannelAudioEvent::NoteOn { key: 60, vel: 100 },
+    );
+    assert!(!is_silent(&render(&mut channel)));
+
+    send(&mut channel, ChannelAudioEvent::AllNotesKilled);
+    assert!(is_silent(&render(&mut channel)));
+
+    // Starts a new voice instead of moving the killed one
+    send(
+        &mut channel,
+        ChannelAudioEvent::NoteOn { key: 62, vel: 100 },
+    );
+    assert!(!is_silent(&render(&mut channel)));
+    assert_eq!(channel.mono_notes, vec![62]);
+    assert_eq!(channel.mono_voice_key, Some(62));
+}
+
+fn key_tunings(channel: &VoiceChannel) -> Vec<f32> {
+    channel.key_voices.iter().map(|key| key.tuning).collect()
+}
+
+/// Selects a tuning program with RPN 3.
+fn select_tuning_program(channel: &mut VoiceChannel, program: u8) {
+    for (controller, value) in [(0x65, 0), (0x64, 3), (0x06, program)] {
+        send(
+            channel,
+            ChannelAudioEvent::Control(ControlEvent::Raw(controller, value)),
+        );
+    }
+}
+
+#[test]
+fn test_tuning_program_reset() {
+    let mut channel = test_channel();
+    send(
+        &mut channel,
+        ChannelAudioEvent::TuningChange {
+            bank: 0,
+            program: 1,
+            changes: vec![(60, 60.5)],
+        },
+    );
+    assert!(key_tunings(&channel).iter().all(|&tuning| tuning == 0.0));
+
+    select_tuning_program(&mut channel, 1);
+    assert_eq!(key_tunings(&channel)[60], 0.5);
+
+    // The brightness controller doesn't change the tuning
+    send(
+        &mut channel,
+        ChannelAudioEvent::Control(ControlEvent::Raw(0x4A, 100)),
+    );
+    assert_eq!(key_tunings(&channel)[60], 0.5);
+
+    // Reset All Controllers selects the default program again
+    send(
+        &mut channel,
+        ChannelAudioEvent::Control(ControlEvent::Raw(0x79, 0)),
+    );
+    assert!(key_tunings(&channel).iter().all(|&tuning| tuning == 0.0));
+
+    select_tuning_program(&mut channel, 1);
+    assert_eq!(key_tunings(&channel)[60], 0.5);
+    send(&mut channel, ChannelAudioEvent::SystemReset);
+    assert!(key_tunings(&channel).iter().all(|&tuning| tuning == 0.0));
+}
+
+#[test]
+fn test_event_offset() {
+    let mut channel = test_channel();
+    channel.process_event_at(
+        ChannelEvent::Audio(ChannelAudioEvent::NoteOn { key: 60, vel: 100 }),
+        100,
+    );
+
+    // The samples of both audio channels before the offset are silent
+    let out = render(&mut channel);
+    assert!(is_silent(&out[..200]));
+    assert!(out[200..].iter().all(|&s| s != 0.0));
+
+    channel.process_event_at(
+        ChannelEvent::Audio(ChannelAudioEvent::NoteOff { key: 60 }),
+        50,
+    );
+    let out = render(&mut channel);
+    assert!(out[..100].iter().all(|&s| s != 0.0));
+    assert!(is_silent(&out[100..]));
+}
+
+#[test]
+fn test_event_offset_past_block() {
+    let mut channel = test_channel();
+
+    // The blocks are 128 samples long, the note starts 20 samples into
+    // the second block
+    channel.process_event_at(
+        ChannelEvent::Audio(ChannelAudioEvent::NoteOn { key: 60, vel: 100 }),
+        148,
+    );
+
+    assert!(is_silent(&render(&mut channel)));
+    let out = render(&mut channel);
+    assert!(is_silent(&out[..40]));
+    assert!(out[40..].iter().all(|&s| s != 0.0));
+}
+
+#[derive(Default)]
+struct EffectCalls {
+    process: usize,
+    controls: Vec<ControlEvent>,
+    reset: usize,
+}
+
+/// An insert effect that records its calls and mutes the channel.
+struct RecordingEffect(Arc<std::sync::Mutex<EffectCalls>>);
+
+impl ChannelEffect for RecordingEffect {
+    fn process(&mut self, buffer: &mut [f32]) {
+        self.0.lock().unwrap().process += 1;
+        buffer.fill(0.0);
+    }
+
+    fn process_control(&mut self, event: &ControlEvent) {
+        self.0.lock().unwrap().controls.push(event.clone());
+    }
+
+    fn reset(&mut self) {
+        self.0.lock().unwrap().reset += 1;
+    }
+}
+
+#[test]
+fn test_channel_effect_calls() {
+    let mut channel = test_channel();
+    let calls = Arc::new(std::sync::Mutex::new(EffectCalls::default()));
+    let effect_calls = calls.clone();
+    channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetEffects(vec![
+        ChannelEffectFactory::new(move |_| Box::new(RecordingEffect(effect_calls.clone()))),
+    ])));
+
+    // The effect runs at the end of the channel, so its output is final
+    send(
+        &mut channel,
+        ChannelAudioEvent::NoteOn { key: 60, vel: 100 },
+    );
+    assert!(is_silent(&render(&mut channel)));
+    assert!(calls.lock().unwrap().process > 0);
+
+    send(
+        &mut channel,
+        ChannelAudioEvent::Control(ControlEvent::Raw(0x4A, 100)),
+    );
+    assert!(matches!(
+        calls.lock().unwrap().controls[..],
+        [ControlEvent::Raw(0x4A, 100)]
+    ));
+
+    send(&mut channel, ChannelAudioEvent::AllNotesKilled);
+    assert_eq!(calls.lock().unwrap().reset, 1);
+    send(&mut channel, ChannelAudioEvent::SystemReset);
+    assert_eq!(calls.lock().unwrap().reset, 2);
+
+    // An empty list removes the effect
+    channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetEffects(
+        Vec::new(),
+    )));
+    send(
+        &mut channel,
+        ChannelAudioEvent::NoteOn { key: 60, vel: 100 },
+    );
+    let process = calls.lock().unwrap().process;
+    assert!(!is_silent(&render(&mut channel)));
+    assert_eq!(calls.lock().unwrap().process, process);
+}
+
+#[test]
+fn test_keyswitch_reset() {
+    let mut channel = test_channel();
+    send(
+        &mut channel,
+        ChannelAudioEvent::NoteOn {
+            key: TEST_KEYSWITCH,
+            vel: 100,
+        },
+    );
+    send(
+        &mut channel,
+        ChannelAudioEvent::NoteOff {
+            key: TEST_KEYSWITCH,
+        },
+    );
+    assert_eq!(channel.last_keyswitch, Some(TEST_KEYSWITCH));
+
+    // Reset All Controllers keeps the selected articulation
+    send(
+        &mut channel,
+        ChannelAudioEvent::Control(ControlEvent::Raw(0x79, 0)),
+    );
+    assert_eq!(channel.last_keyswitch, Some(TEST_KEYSWITCH));
+
+    send(&mut channel, ChannelAudioEvent::SystemReset);
+    assert_eq!(channel.last_keyswitch, None);
+}
+
+#[test]
+fn test_mono_keyswitch() {
+    let mut channel = test_channel();
+    send(
+        &mut channel,
+        ChannelAudioEvent::Control(ControlEvent::Raw(0x7E, 1)),
+    );
+    send(
+        &mut channel,
+        ChannelAudioEvent::NoteOn { key: 60, vel: 100 },
+    );
+    send(
+        &mut channel,
+        ChannelAudioEvent::NoteOn { key: 62, vel: 100 },
+    );
+    let voice_key = channel.mono_voice_key;
+
+    // The keyswitch is selected without moving the voice to it
+    send(
+        &mut channel,
+        ChannelAudioEvent::NoteOn {
+            key: TEST_KEYSWITCH,
+            vel: 100,
+        },
+    );
+    assert_eq!(channel.last_keyswitch, Some(TEST_KEYSWITCH));
+    assert_eq!(channel.mono_notes, vec![60, 62]);
+    assert_eq!(channel.mono_voice_key, voice_key);
+
+    send(
+        &mut channel,
+        ChannelAudioEvent::NoteOff {
+            key: TEST_KEYSWITCH,
+        },
+    );
+    assert_eq!(channel.mono_notes, vec![60, 62]);
+    assert!(!is_silent(&render(&mut channel)));
+}
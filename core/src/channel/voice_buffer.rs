@@ -9,6 +9,9 @@ use std::{
 struct GroupVoice {
     pub id: usize,
     pub voice: Box<dyn Voice>,
+
+    /// Voices spawned by a note off event aren't released by note off events
+    pub release_triggered: bool,
 }
 
 impl Deref for GroupVoice {
@@ -42,7 +45,13 @@ pub struct VoiceBuffer {
     id_counter: usize,
     buffer: VecDeque<GroupVoice>,
     damper_held: bool,
-    held_by_damper: Vec<usize>,
+
+    /// The voice groups that were playing when the sostenuto pedal was pressed
+    sostenuto_held: Vec<usize>,
+
+    /// The voice groups that received a note off, but are held by the
+    /// damper or sostenuto pedal
+    held_by_pedal: Vec<usize>,
 }
 
 impl VoiceBuffer {
@@ -52,7 +61,8 @@ impl VoiceBuffer {
             id_counter: 0,
             buffer: VecDeque::new(),
             damper_held: false,
-            held_by_damper: Vec::new(),
+            sostenuto_held: Vec::new(),
+            held_by_pedal: Vec::new(),
         }
     }
 
@@ -97,9 +107,7 @@ impl VoiceBuffer {
                 self.buffer.drain(quietest_index..(quietest_index + count));
             }
 
-            if let Some(index) = self.held_by_damper.iter().position(|&x| x == quietest_id) {
-                self.held_by_damper.remove(index);
-            }
+            self.forget_held_group(quietest_id);
         }
     }
 
@@ -118,6 +126,16 @@ impl VoiceBuffer {
         } else {
             self.buffer.clear();
         }
+
+        // The IDs can be reused after this, so the pedals can't hold them
+        self.sostenuto_held.clear();
+        self.held_by_pedal.clear();
+    }
+
+    /// Removes a voice group from the groups held by the pedals.
+    fn forget_held_group(&mut self, id: usize) {
+        self.sostenuto_held.retain(|&x| x != id);
+        self.held_by_pedal.retain(|&x| x != id);
     }
 
     fn get_active_count(&mut self) -> usize {
@@ -136,12 +154,35 @@ impl VoiceBuffer {
         &mut self,
         voices: impl Iterator<Item = Box<dyn Voice>>,
         max_voices: Option<usize>,
+    ) {
+        self.push_voice_group(voices, max_voices, false);
+    }
+
+    /// Pushes a new set of voices for a single note off event. These voices
+    /// play until they end on their own.
+    pub fn push_release_voices(
+        &mut self,
+        voices: impl Iterator<Item = Box<dyn Voice>>,
+        max_voices: Option<usize>,
+    ) {
+        self.push_voice_group(voices, max_voices, true);
+    }
+
+    fn push_voice_group(
+        &mut self,
+        voices: impl Iterator<Item = Box<dyn Voice>>,
+        max_voices: Option<usize>,
+        release_triggered: bool,
     ) {
         let mut len = 0;
 
         let id = self.get_id();
         for voice in voices {
-            self.buffer.push_back(GroupVoice { id, voice });
+            self.buffer.push_back(GroupVoice {
+                id,
+                voice,
+                release_triggered,
+            });
             len += 1;
         }
 
@@ -161,46 +202,59 @@ impl VoiceBuffer {
     }
 
     /// Releases the next voice, and all subsequent voices that have the same ID.
-    pub fn release_next_voice(&mut self) -> Option<u8> {
-        if !self.damper_held {
-            let mut id: Option<usize> = None;
-            let mut vel = None;
-
-            // Find the first non releasing voice, get its id and release all voices with that id
-            for voice in self.buffer.iter_mut() {
-                if voice.is_releasing() {
-                    continue;
-                }
-
-                if id.is_none() {
-                    id = Some(voice.id);
-                    vel = Some(voice.velocity())
-                }
+    /// Returns the velocity of the voice, and false if it is held by a pedal
+    /// instead of being released.
+    pub fn release_next_voice(&mut self) -> Option<(u8, bool)> {
+        // Find the first non releasing voice which also isn't being held by a pedal
+        let index = self.buffer.iter().position(|voice| {
+            !(voice.is_releasing()
+                || voice.is_killed()
+                || voice.release_triggered
+                || self.held_by_pedal.contains(&voice.id))
+        })?;
+        let id = self.buffer[index].id;
+        let vel = self.buffer[index].velocity();
+
+        if self.damper_held || self.sostenuto_held.contains(&id) {
+            self.held_by_pedal.push(id);
+            return Some((vel, false));
+        }
 
-                if id != Some(voice.id) {
-                    break;
-                }
+        // Release all voices with the same id
+        for voice in self.buffer.iter_mut().skip(index) {
+            if voice.is_releasing() || voice.is_killed() || voice.release_triggered {
+                continue;
+            }
 
-                voice.signal_release(ReleaseType::Standard);
+            if voice.id != id {
+                break;
             }
 
-            vel
-        } else {
-            // Find the first non releasing voice which also isn't being held in the release buffer, and add it to the release buffer
-            for voice in self.buffer.iter_mut() {
-                if voice.is_releasing() {
-                    continue;
-                }
+            voice.signal_release(ReleaseType::Standard);
+        }
 
-                if self.held_by_damper.contains(&voice.id) {
-                    continue;
-                }
+        Some((vel, true))
+    }
 
-                self.held_by_damper.push(voice.id);
-                break;
+    /// Stops all voices that are turned off by the given exclusive class group.
+    pub fn turn_off_group(&mut self, group: u32) {
+        for voice in self.buffer.iter_mut() {
+            let class = voice.exclusive_class();
+            if class.off_by != group || voice.is_killed() {
+                continue;
             }
 
-            None
+            if class.off_release == ReleaseType::Standard {
+                if !voice.is_releasing() {
+                    voice.signal_release(ReleaseType::Standard);
+                }
+            } else {
+                voice.signal_release(class.off_release);
+            }
+
+            let id = voice.id;
+            self.sostenuto_held.retain(|&x| x != id);
+            self.held_by_pedal.retain(|&x| x != id);
         }
     }
 
@@ -231,16 +285,59 @@ impl VoiceBuffer {
         self.buffer.len()
     }
 
-    pub fn set_damper(&mut self, damper: bool) {
-        if self.damper_held && !damper {
-            // Release all voices that are held by the damper
-            for voice in self.buffer.iter_mut() {
-                if self.held_by_damper.contains(&voice.id) {
-                    voice.signal_release(ReleaseType::Standard);
+    /// Sets the state of the damper. Returns the velocities of the
+    /// voices that were released by lifting it.
+    pub fn set_damper(&mut self, damper: bool) -> Vec<u8> {
+        self.damper_held = damper;
+        self.release_held_voices()
+    }
+
+    /// Sets the state of the sostenuto pedal. Pressing it holds the voices
+    /// that are currently playing, until it is lifted. Returns the velocities
+    /// of the voices that were released by lifting it.
+    pub fn set_sostenuto(&mut self, sostenuto: bool) -> Vec<u8> {
+        if sostenuto {
+            for voice in self.buffer.iter() {
+                if voice.is_releasing()
+                    || voice.is_killed()
+                    || voice.release_triggered
+                    || self.held_by_pedal.contains(&voice.id)
+                    || self.sostenuto_held.contains(&voice.id)
+                {
+                    continue;
                 }
+                self.sostenuto_held.push(voice.id);
             }
-            self.held_by_damper.clear();
+            Vec::new()
+        } else {
+            self.sostenuto_held.clear();
+            self.release_held_voices()
         }
-        self.damper_held = damper;
+    }
+
+    /// Releases the voices that received a note off and aren't held by
+    /// any pedal anymore. Returns the velocities of the released voices.
+    fn release_held_voices(&mut self) -> Vec<u8> {
+        let mut released = Vec::new();
+        if self.damper_held || self.held_by_pedal.is_empty() {
+            return released;
+        }
+
+        let sostenuto_held = &self.sostenuto_held;
+        let mut to_release = self.held_by_pedal.clone();
+        to_release.retain(|id| !sostenuto_held.contains(id));
+        self.held_by_pedal.retain(|id| sostenuto_held.contains(id));
+
+        let mut last_id = None;
+        for voice in self.buffer.iter_mut() {
+            if to_release.contains(&voice.id) {
+                voice.signal_release(ReleaseType::Standard);
+                if last_id != Some(voice.id) {
+                    last_id = Some(voice.id);
+                    released.push(voice.velocity());
+                }
+            }
+        }
+        released
     }
 }
@@ -1,4 +1,4 @@
-use crate::soundfont::VoiceSpawner;
+use crate::soundfont::{KeyswitchState, TriggerMode, VoiceSelection, VoiceSpawner};
 
 use crate::voice::{Voice, VoiceControlData};
 
@@ -10,8 +10,25 @@ pub struct VoiceSpawnerMatrix {
 fn voice_iter_from_vec<'a>(
     vec: &'a [Box<dyn VoiceSpawner>],
     control: &'a VoiceControlData,
+    trigger: impl Fn(TriggerMode) -> bool + 'a,
+    selection: VoiceSelection,
 ) -> impl Iterator<Item = Box<dyn Voice>> + 'a {
-    vec.iter().map(move |voice| voice.spawn_voice(control))
+    vec.iter()
+        .filter(move |voice| {
+            trigger(voice.trigger())
+                && voice.is_selected(selection)
+                && voice.matches_keyswitch(selection.keyswitch)
+                && voice.matches_controllers(&control.cc)
+        })
+        .map(move |voice| voice.spawn_voice(control))
+}
+
+fn attack_trigger(legato: bool) -> impl Fn(TriggerMode) -> bool {
+    move |trigger| match trigger {
+        TriggerMode::First => !legato,
+        TriggerMode::Legato => legato,
+        _ => true,
+    }
 }
 
 impl VoiceSpawnerMatrix {
@@ -53,24 +70,75 @@ impl VoiceSpawnerMatrix {
         &self.voice_spawners_release[self.get_spawners_index_at_release(key, vel)]
     }
 
+    /// Returns the exclusive class groups of the attack voices that can be
+    /// spawned by a note on event, without duplicates.
+    pub fn exclusive_groups_attack(
+        &self,
+        key: u8,
+        vel: u8,
+        legato: bool,
+        keyswitch: KeyswitchState,
+        cc: &[u8; 128],
+    ) -> Vec<u32> {
+        let trigger = attack_trigger(legato);
+        let mut groups = Vec::new();
+        for spawner in self.get_attack_spawners_vec_at(key, vel) {
+            let group = spawner.exclusive_class().group;
+            if group != 0
+                && trigger(spawner.trigger())
+                && spawner.matches_keyswitch(keyswitch)
+                && spawner.matches_controllers(cc)
+                && !groups.contains(&group)
+            {
+                groups.push(group);
+            }
+        }
+        groups
+    }
+
+    /// Spawns the attack voices of a note on event. `legato` should be true
+    /// if other notes are held in the channel. Only the voices matching
+    /// `selection` are spawned.
     #[inline(always)]
     pub fn spawn_voices_attack<'a>(
         &'a self,
         control: &'a VoiceControlData,
         key: u8,
         vel: u8,
+        legato: bool,
+        selection: VoiceSelection,
     ) -> impl Iterator<Item = Box<dyn Voice>> + 'a {
-        voice_iter_from_vec(self.get_attack_spawners_vec_at(key, vel), control)
+        voice_iter_from_vec(
+            self.get_attack_spawners_vec_at(key, vel),
+            control,
+            attack_trigger(legato),
+            selection,
+        )
     }
 
+    /// Spawns the release voices of a note. `key_off` should be true if the voices
+    /// are spawned by a note off event, `released` if the note was released.
+    /// They can differ if the note is held by the sustain pedal.
     #[inline(always)]
     pub fn spawn_voices_release<'a>(
         &'a self,
         control: &'a VoiceControlData,
         key: u8,
         vel: u8,
+        key_off: bool,
+        released: bool,
+        selection: VoiceSelection,
     ) -> impl Iterator<Item = Box<dyn Voice>> + 'a {
-        voice_iter_from_vec(self.get_release_spawners_vec_at(key, vel), control)
+        let trigger = move |trigger| match trigger {
+            TriggerMode::ReleaseKey => key_off,
+            _ => released,
+        };
+        voice_iter_from_vec(
+            self.get_release_spawners_vec_at(key, vel),
+            control,
+            trigger,
+            selection,
+        )
     }
 
     #[inline(always)]
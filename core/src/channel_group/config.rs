@@ -1,4 +1,8 @@
-use crate::{channel::ChannelInitOptions, AudioStreamParams};
+use crate::{
+    channel::ChannelInitOptions,
+    effects::{ChorusParams, MasterEffect, ReverbParams},
+    AudioStreamParams,
+};
 
 /// Defines the multithreading options for each task that supports it.
 #[derive(Clone)]
@@ -84,4 +88,19 @@ pub struct ChannelGroupConfig {
     /// Options about the `ChannelGroup` instance's parallelism. See the `ParallelismOptions`
     /// documentation for more information.
     pub parallelism: ParallelismOptions,
+
+    /// Parameters of the reverb send effect, which the channels are sent to
+    /// by their CC 91 value. `None` disables the reverb.
+    /// See the `ReverbParams` documentation for more information.
+    pub reverb: Option<ReverbParams>,
+
+    /// Parameters of the chorus send effect, which the channels are sent to
+    /// by their CC 93 value. `None` disables the chorus.
+    /// See the `ChorusParams` documentation for more information.
+    pub chorus: Option<ChorusParams>,
+
+    /// The master bus effects, applied in order to the final output after
+    /// the send effects. See the `MasterEffect` documentation for the
+    /// available effects.
+    pub master_effects: Vec<MasterEffect>,
 }
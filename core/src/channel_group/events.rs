@@ -1,4 +1,7 @@
-use crate::channel::{ChannelAudioEvent, ChannelConfigEvent};
+use crate::{
+    channel::{ChannelAudioEvent, ChannelConfigEvent},
+    effects::{ChorusParameter, ChorusType, ReverbType},
+};
 
 /// Wrapper enum for various events to be sent to a MIDI synthesizer.
 pub enum SynthEvent {
@@ -13,4 +16,27 @@ pub enum SynthEvent {
     /// Configuration event for all channels.
     /// See `ChannelConfigEvent` documentation for more information.
     ChannelConfig(ChannelConfigEvent),
+
+    /// Configuration event for the specified channel, such as its
+    /// insert effects. Ignored if the channel doesn't exist.
+    /// See `ChannelConfigEvent` documentation for more information.
+    SingleChannelConfig(u32, ChannelConfigEvent),
+
+    /// Changes the type of the reverb send effect, as done by the GS and
+    /// XG reverb SysEx messages. See `ReverbType` documentation for more
+    /// information.
+    ReverbType(ReverbType),
+
+    /// Changes the type of the chorus send effect, as done by the GS chorus
+    /// macro SysEx message. See `ChorusType` documentation for more information.
+    ChorusType(ChorusType),
+
+    /// Changes a parameter of the chorus send effect, as done by the GS
+    /// chorus SysEx messages. See `ChorusParameter` documentation for more
+    /// information.
+    ChorusParameter(ChorusParameter),
+
+    /// A System Exclusive message, with or without its F0 and F7 bytes.
+    /// See the `parse_sysex` documentation for the supported messages.
+    SysEx(Vec<u8>),
 }
@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use crate::{
     channel::{ChannelAudioEvent, ChannelEvent, VoiceChannel},
-    helpers::sum_simd,
+    effects::EffectMixer,
     AudioPipe, AudioStreamParams,
 };
 
@@ -10,13 +10,22 @@ mod config;
 pub use config::*;
 mod events;
 pub use events::*;
+mod mpe;
+pub use mpe::*;
+mod sysex;
 use rayon::prelude::*;
+pub use sysex::*;
 
 const MAX_EVENT_CACHE_SIZE: u32 = 1024 * 1024;
 
 /// Represents a MIDI synthesizer within XSynth.
 ///
-/// Manages multiple VoiceChannel objects at once.
+/// Manages multiple VoiceChannel objects at once. MPE zones are supported
+/// on the channels, see the `MpeZones` documentation for more information.
+///
+/// The channels are sent to a shared reverb and chorus by their CC 91 and
+/// CC 93 values, which are mixed into the output after the channels.
+/// The output then goes through the master bus effects of the config.
 pub struct ChannelGroup {
     thread_pool: Option<rayon::ThreadPool>,
     cached_event_count: u32,
@@ -24,6 +33,10 @@ pub struct ChannelGroup {
     sample_cache_vecs: Box<[Vec<f32>]>,
     channels: Box<[VoiceChannel]>,
     audio_params: AudioStreamParams,
+    mpe: MpeZones,
+
+    /// The send effects and the master bus
+    effects: EffectMixer,
 }
 
 impl ChannelGroup {
@@ -78,6 +91,13 @@ impl ChannelGroup {
             channels: channels.into_boxed_slice(),
             sample_cache_vecs: sample_cache_vecs.into_boxed_slice(),
             audio_params: config.audio_params,
+            mpe: MpeZones::new(config.channel_count),
+            effects: EffectMixer::new(
+                config.reverb,
+                config.chorus,
+                &config.master_effects,
+                config.audio_params,
+            ),
         }
     }
 
@@ -86,13 +106,22 @@ impl ChannelGroup {
     pub fn send_event(&mut self, event: SynthEvent) {
         match event {
             SynthEvent::Channel(channel, event) => {
-                self.channel_events_cache[channel as usize].push(event);
-                self.cached_event_count += 1;
+                let cache = &mut self.channel_events_cache;
+                let count = &mut self.cached_event_count;
+                self.mpe.process_event(channel, event, |channel, event| {
+                    cache[channel as usize].push(event);
+                    *count += 1;
+                });
                 if self.cached_event_count > MAX_EVENT_CACHE_SIZE {
                     self.flush_events();
                 }
             }
             SynthEvent::AllChannels(event) => {
+                self.mpe.process_all_channels_event(&event);
+                if let ChannelAudioEvent::SystemReset = event {
+                    self.effects
+                        .process_event(SynthEvent::AllChannels(ChannelAudioEvent::SystemReset));
+                }
                 for channel in self.channel_events_cache.iter_mut() {
                     channel.push(event.clone());
                 }
@@ -106,6 +135,69 @@ impl ChannelGroup {
                     channel.process_event(ChannelEvent::Config(config.clone()));
                 }
             }
+            SynthEvent::SingleChannelConfig(channel, config) => {
+                if let Some(channel) = self.channels.get_mut(channel as usize) {
+                    channel.process_event(ChannelEvent::Config(config));
+                }
+            }
+            event @ (SynthEvent::ReverbType(_)
+            | SynthEvent::ChorusType(_)
+            | SynthEvent::ChorusParameter(_)) => self.effects.process_event(event),
+            SynthEvent::SysEx(data) => {
+                for event in parse_sysex(&data, self.channels.len() as u32) {
+                    self.send_event(event);
+                }
+            }
+        }
+    }
+
+    /// Sends a SynthEvent to the ChannelGroup, to be applied `offset`
+    /// samples (per audio channel) into the next block read from it.
+    /// The channels and effects split their rendering at the event offsets,
+    /// so the timing of the events doesn't depend on the block size.
+    /// Events past the end of the next block are applied in the following
+    /// blocks.
+    ///
+    /// Events sent with `send_event` are applied at the start of the block,
+    /// before the timed events.
+    pub fn send_event_at(&mut self, event: SynthEvent, offset: u32) {
+        match event {
+            SynthEvent::Channel(channel, event) => {
+                let channels = &mut self.channels;
+                self.mpe.process_event(channel, event, |channel, event| {
+                    channels[channel as usize].process_event_at(ChannelEvent::Audio(event), offset);
+                });
+            }
+            SynthEvent::AllChannels(event) => {
+                self.mpe.process_all_channels_event(&event);
+                if let ChannelAudioEvent::SystemReset = event {
+                    self.effects.process_event_at(
+                        SynthEvent::AllChannels(ChannelAudioEvent::SystemReset),
+                        offset,
+                    );
+                }
+                for channel in self.channels.iter_mut() {
+                    channel.process_event_at(ChannelEvent::Audio(event.clone()), offset);
+                }
+            }
+            SynthEvent::ChannelConfig(config) => {
+                for channel in self.channels.iter_mut() {
+                    channel.process_event_at(ChannelEvent::Config(config.clone()), offset);
+                }
+            }
+            SynthEvent::SingleChannelConfig(channel, config) => {
+                if let Some(channel) = self.channels.get_mut(channel as usize) {
+                    channel.process_event_at(ChannelEvent::Config(config), offset);
+                }
+            }
+            event @ (SynthEvent::ReverbType(_)
+            | SynthEvent::ChorusType(_)
+            | SynthEvent::ChorusParameter(_)) => self.effects.process_event_at(event, offset),
+            SynthEvent::SysEx(data) => {
+                for event in parse_sysex(&data, self.channels.len() as u32) {
+                    self.send_event_at(event, offset);
+                }
+            }
         }
     }
 
@@ -150,19 +242,15 @@ impl ChannelGroup {
             Some(pool) => {
                 let channels = &mut self.channels;
                 let sample_cache_vecs = &mut self.sample_cache_vecs;
+                let len = buffer.len();
                 pool.install(move || {
                     channels
                         .par_iter_mut()
                         .zip(sample_cache_vecs.par_iter_mut())
                         .for_each(|(channel, samples)| {
-                            samples.resize(buffer.len(), 0.0);
+                            samples.resize(len, 0.0);
                             channel.read_samples(samples.as_mut_slice());
                         });
-
-                    for vec in sample_cache_vecs.iter_mut() {
-                        sum_simd(vec, buffer);
-                        vec.clear();
-                    }
                 });
             }
             None => {
@@ -176,13 +264,21 @@ impl ChannelGroup {
                     samples.resize(len, 0.0);
                     channel.read_samples(samples.as_mut_slice());
                 }
-
-                for vec in self.sample_cache_vecs.iter_mut() {
-                    sum_simd(vec, buffer);
-                    vec.clear();
-                }
             }
         }
+
+        for (channel, vec) in self.channels.iter().zip(self.sample_cache_vecs.iter_mut()) {
+            self.effects.mix_channel(buffer, vec, channel.send_levels());
+            vec.clear();
+        }
+
+        self.effects.process(buffer);
+    }
+
+    /// Returns the delay of the output caused by the master bus effects,
+    /// in samples per channel. See `MasterBus::latency` for more information.
+    pub fn latency(&self) -> usize {
+        self.effects.latency()
     }
 
     /// Returns the active voice count of the synthesizer.
@@ -203,3 +299,156 @@ impl AudioPipe for ChannelGroup {
         self.render_to(to);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        channel::{tests::test_soundfont, ChannelConfigEvent, ControlEvent},
+        effects::{ChorusParameter, ChorusParams, MasterEffect},
+        ChannelCount,
+    };
+
+    fn test_group(chorus: Option<ChorusParams>, master_effects: Vec<MasterEffect>) -> ChannelGroup {
+        let audio_params = AudioStreamParams::new(48000, ChannelCount::Stereo);
+        let mut group = ChannelGroup::new(ChannelGroupConfig {
+            channel_init_options: Default::default(),
+            channel_count: 16,
+            drums_channels: Vec::new(),
+            audio_params,
+            parallelism: ParallelismOptions {
+                channel: ThreadCount::None,
+                key: ThreadCount::None,
+            },
+            reverb: None,
+            chorus,
+            master_effects,
+        });
+        group.send_event(SynthEvent::ChannelConfig(
+            ChannelConfigEvent::SetSoundfonts(vec![test_soundfont(audio_params)]),
+        ));
+        group
+    }
+
+    fn render(group: &mut ChannelGroup, len: usize) -> Vec<f32> {
+        let mut out = vec![0.0; len];
+        group.read_samples(&mut out);
+        out
+    }
+
+    #[test]
+    fn test_event_offset() {
+        let mut group = test_group(None, Vec::new());
+        group.send_event_at(
+            SynthEvent::Channel(0, ChannelAudioEvent::NoteOn { key: 60, vel: 100 }),
+            100,
+        );
+        group.send_event_at(
+            SynthEvent::Channel(1, ChannelAudioEvent::NoteOn { key: 60, vel: 100 }),
+            300,
+        );
+
+        let out = render(&mut group, 512);
+        assert!(out[..200].iter().all(|&s| s == 0.0));
+        assert!(out[200..].iter().all(|&s| s != 0.0));
+
+        // The second note starts 44 samples into the next block
+        let out = render(&mut group, 512);
+        assert_ne!(out[87], out[88]);
+        assert_eq!(out[88], out[89]);
+    }
+
+    #[test]
+    fn test_effect_event_offset() {
+        let chorus = ChorusParams {
+            wet: 0.0,
+            ..Default::default()
+        };
+        let mut group = test_group(Some(chorus), Vec::new());
+        group.send_event(SynthEvent::Channel(
+            0,
+            ChannelAudioEvent::Control(ControlEvent::Raw(0x5D, 127)),
+        ));
+        group.send_event(SynthEvent::Channel(
+            0,
+            ChannelAudioEvent::NoteOn { key: 60, vel: 100 },
+        ));
+
+        let out = render(&mut group, 8192);
+        let dry = out[8191];
+
+        // The chorus is only heard after the offset
+        group.send_event_at(SynthEvent::ChorusParameter(ChorusParameter::Wet(1.0)), 1000);
+        let out = render(&mut group, 4096);
+        assert!(out[..2000].iter().all(|&s| s == dry));
+        assert!(out[2000..].iter().any(|&s| s != dry));
+
+        // The system reset returns the chorus to its parameters at the offset
+        group.send_event_at(SynthEvent::AllChannels(ChannelAudioEvent::SystemReset), 500);
+        let out = render(&mut group, 4096);
+        assert!(out[..1000].iter().any(|&s| s != dry));
+        assert!(out[1000..].iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_send_level_offset() {
+        let mut group = test_group(Some(Default::default()), Vec::new());
+        group.send_event(SynthEvent::Channel(
+            0,
+            ChannelAudioEvent::NoteOn { key: 60, vel: 100 },
+        ));
+
+        let out = render(&mut group, 8192);
+        let dry = out[8191];
+
+        // The channel is only sent to the chorus after the offset
+        group.send_event_at(
+            SynthEvent::Channel(0, ChannelAudioEvent::Control(ControlEvent::Raw(0x5D, 127))),
+            1000,
+        );
+        let out = render(&mut group, 4096);
+        assert!(out[..2000].iter().all(|&s| s == dry));
+        assert!(out[2000..].iter().any(|&s| s != dry));
+    }
+
+    #[test]
+    fn test_system_reset_master_bus() {
+        let mut group = test_group(None, vec![MasterEffect::DcBlocker]);
+        let note_on = || SynthEvent::Channel(0, ChannelAudioEvent::NoteOn { key: 60, vel: 100 });
+        let reset = || SynthEvent::AllChannels(ChannelAudioEvent::SystemReset);
+
+        // Without a reset, the DC blocker swings back after the note stops
+        group.send_event(note_on());
+        render(&mut group, 4800);
+        group.send_event(SynthEvent::Channel(0, ChannelAudioEvent::AllNotesKilled));
+        assert!(render(&mut group, 4800).iter().any(|&s| s < 0.0));
+
+        // The reset clears the state of the master bus, whether it's timed
+        // or not
+        group.send_event(note_on());
+        render(&mut group, 4800);
+        group.send_event(reset());
+        assert!(render(&mut group, 4800).iter().all(|&s| s == 0.0));
+
+        group.send_event(note_on());
+        render(&mut group, 4800);
+        group.send_event_at(reset(), 100);
+        let out = render(&mut group, 4800);
+        assert!(out[..200].iter().all(|&s| s != 0.0));
+        assert!(out[200..].iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_single_channel_config_out_of_range() {
+        let mut group = test_group(None, Vec::new());
+        group.send_event(SynthEvent::SingleChannelConfig(
+            16,
+            ChannelConfigEvent::SetLayerCount(Some(1)),
+        ));
+        group.send_event_at(
+            SynthEvent::SingleChannelConfig(u32::MAX, ChannelConfigEvent::SetLayerCount(None)),
+            100,
+        );
+        assert!(render(&mut group, 512).iter().all(|&s| s == 0.0));
+    }
+}
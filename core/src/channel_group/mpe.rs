@@ -0,0 +1,374 @@
+use std::ops::RangeInclusive;
+
+use crate::{
+    channel::{ChannelAudioEvent, ControlEvent},
+    control::{CCChangeType, ControllerState},
+};
+
+/// The master channels of the lower and upper zones.
+const MASTER_CHANNELS: [u32; 2] = [0, 15];
+
+/// The highest number of member channels of a zone.
+const MAX_MEMBERS: u8 = 15;
+
+/// The pitch bend sensitivity that the MPE Configuration Message sets on
+/// the master and member channels, in semitones.
+const MASTER_PITCH_BEND_SENSITIVITY: f32 = 2.0;
+const MEMBER_PITCH_BEND_SENSITIVITY: f32 = 48.0;
+
+/// The state of a zone master channel.
+#[derive(Debug, Clone)]
+struct MpeMaster {
+    /// The RPN state of the channel, used to receive the MPE
+    /// Configuration Message and the pitch bend sensitivity
+    controllers: ControllerState,
+    pitch_bend_sensitivity: f32,
+
+    /// The number of member channels, zero if the zone is disabled
+    members: u8,
+}
+
+impl MpeMaster {
+    fn new() -> Self {
+        MpeMaster {
+            controllers: ControllerState::new(),
+            pitch_bend_sensitivity: MASTER_PITCH_BEND_SENSITIVITY,
+            members: 0,
+        }
+    }
+
+    fn reset_control(&mut self) {
+        self.controllers = ControllerState::new();
+        self.pitch_bend_sensitivity = MASTER_PITCH_BEND_SENSITIVITY;
+    }
+}
+
+/// Keeps track of the MPE (MIDI Polyphonic Expression) zones and routes
+/// the channel events of their master channels.
+///
+/// Zones are configured with the MPE Configuration Message (RPN 6) sent on
+/// MIDI channel 1 for the lower zone or 16 for the upper zone. The member
+/// channels of a zone each play their own notes, so their pitch bend,
+/// pressure and timbre (CC 74) only change those notes. The controls of
+/// the master channel are also applied to all of its member channels,
+/// with the master pitch bend added on top of the member pitch bend.
+///
+/// Only the channels below the channel count of the synthesizer are used,
+/// the events of the other channels are ignored.
+#[derive(Debug, Clone)]
+pub struct MpeZones {
+    masters: [MpeMaster; 2],
+    channel_count: u32,
+}
+
+impl MpeZones {
+    pub fn new(channel_count: u32) -> Self {
+        MpeZones {
+            masters: [MpeMaster::new(), MpeMaster::new()],
+            channel_count,
+        }
+    }
+
+    /// Returns the member channels of the lower (`0`) or upper (`1`) zone.
+    fn members(&self, zone: usize) -> Option<RangeInclusive<u32>> {
+        let members = self.masters[zone].members as u32;
+        let (first, last) = match (zone, members) {
+            (_, 0) => return None,
+            (0, members) => (1, members),
+            (_, members) => (MASTER_CHANNELS[1] - members, MASTER_CHANNELS[1] - 1),
+        };
+
+        let last = last.min(self.channel_count.saturating_sub(1));
+        (first <= last).then_some(first..=last)
+    }
+
+    /// Applies an event sent to all channels.
+    pub fn process_all_channels_event(&mut self, event: &ChannelAudioEvent) {
+        match event {
+            ChannelAudioEvent::SystemReset => *self = MpeZones::new(self.channel_count),
+            ChannelAudioEvent::ResetControl => {
+                for master in self.masters.iter_mut() {
+                    master.reset_control();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Processes an event of a channel, and calls `send` with the events
+    /// that need to be sent to each channel.
+    pub fn process_event(
+        &mut self,
+        channel: u32,
+        event: ChannelAudioEvent,
+        mut send: impl FnMut(u32, ChannelAudioEvent),
+    ) {
+        if channel >= self.channel_count {
+            return;
+        }
+
+        let Some(zone) = MASTER_CHANNELS.iter().position(|&c| c == channel) else {
+            send(channel, event);
+            return;
+        };
+
+        let master = &mut self.masters[zone];
+        match event {
+            ChannelAudioEvent::Control(ControlEvent::Raw(controller, value)) => {
+                match master.controllers.process(controller, value) {
+                    Some(CCChangeType::Rpn(0, data)) => {
+                        master.pitch_bend_sensitivity =
+                            data.msb() as f32 + data.lsb() as f32 / 100.0;
+                    }
+                    Some(CCChangeType::Rpn(6, data)) => {
+                        send(channel, event);
+                        self.configure_zone(zone, data.msb(), send);
+                        return;
+                    }
+                    _ => {}
+                }
+                if controller == 0x79 && value == 0 {
+                    master.reset_control();
+                }
+            }
+            ChannelAudioEvent::Control(ControlEvent::PitchBendSensitivity(sensitivity)) => {
+                master.pitch_bend_sensitivity = sensitivity;
+            }
+            ChannelAudioEvent::ResetControl => master.reset_control(),
+            _ => {}
+        }
+
+        let Some(members) = self.members(zone) else {
+            send(channel, event);
+            return;
+        };
+
+        let sensitivity = self.masters[zone].pitch_bend_sensitivity;
+        let zone_event = match &event {
+            // Notes of the master channel only play on it
+            ChannelAudioEvent::NoteOn { .. }
+            | ChannelAudioEvent::NoteOff { .. }
+            | ChannelAudioEvent::PolyAftertouch { .. } => None,
+
+            // The parameters of the master channel don't apply to the members
+            ChannelAudioEvent::Control(ControlEvent::Raw(0x06 | 0x26 | 0x60..=0x65, _))
+            | ChannelAudioEvent::Control(ControlEvent::PitchBendSensitivity(_)) => None,
+
+            ChannelAudioEvent::Control(ControlEvent::PitchBendValue(value)) => Some(
+                ChannelAudioEvent::Control(ControlEvent::ZonePitchBend(value * sensitivity)),
+            ),
+            ChannelAudioEvent::Control(ControlEvent::PitchBend(value)) => Some(
+                ChannelAudioEvent::Control(ControlEvent::ZonePitchBend(*value)),
+            ),
+            event => Some(event.clone()),
+        };
+
+        if let Some(zone_event) = zone_event {
+            for member in members {
+                send(member, zone_event.clone());
+            }
+        }
+        send(channel, event);
+    }
+
+    /// Applies an MPE Configuration Message. A zone with no members is
+    /// disabled, and the other zone shrinks if the two zones overlap.
+    fn configure_zone(
+        &mut self,
+        zone: usize,
+        members: u8,
+        mut send: impl FnMut(u32, ChannelAudioEvent),
+    ) {
+        let members = members.min(MAX_MEMBERS);
+        self.masters[zone].members = members;
+        self.masters[zone].pitch_bend_sensitivity = MASTER_PITCH_BEND_SENSITIVITY;
+
+        let other = &mut self.masters[1 - zone];
+        other.members = other.members.min((MAX_MEMBERS - 1).saturating_sub(members));
+
+        let pitch_bend_sensitivity =
+            |value| ChannelAudioEvent::Control(ControlEvent::PitchBendSensitivity(value));
+
+        send(
+            MASTER_CHANNELS[zone],
+            pitch_bend_sensitivity(MASTER_PITCH_BEND_SENSITIVITY),
+        );
+        for member in self.members(zone).into_iter().flatten() {
+            send(
+                member,
+                pitch_bend_sensitivity(MEMBER_PITCH_BEND_SENSITIVITY),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Processes an event and returns the events sent to the channels.
+    fn process(
+        zones: &mut MpeZones,
+        channel: u32,
+        event: ChannelAudioEvent,
+    ) -> Vec<(u32, ChannelAudioEvent)> {
+        let mut sent = Vec::new();
+        zones.process_event(channel, event, |channel, event| sent.push((channel, event)));
+        sent
+    }
+
+    /// Sends the MPE Configuration Message on a master channel.
+    fn configure(zones: &mut MpeZones, channel: u32, members: u8) -> Vec<(u32, ChannelAudioEvent)> {
+        let mut sent = Vec::new();
+        for (controller, value) in [(0x65, 0), (0x64, 6), (0x06, members)] {
+            sent = process(
+                zones,
+                channel,
+                ChannelAudioEvent::Control(ControlEvent::Raw(controller, value)),
+            );
+        }
+        sent
+    }
+
+    fn channels(sent: &[(u32, ChannelAudioEvent)]) -> Vec<u32> {
+        sent.iter().map(|(channel, _)| *channel).collect()
+    }
+
+    fn sensitivity(sent: &[(u32, ChannelAudioEvent)], channel: u32) -> Option<f32> {
+        sent.iter().find_map(|(c, event)| match event {
+            ChannelAudioEvent::Control(ControlEvent::PitchBendSensitivity(value))
+                if *c == channel =>
+            {
+                Some(*value)
+            }
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_configuration_message() {
+        let mut zones = MpeZones::new(16);
+        assert_eq!(zones.members(0), None);
+
+        let sent = configure(&mut zones, 0, 3);
+        assert_eq!(zones.members(0), Some(1..=3));
+        assert_eq!(sensitivity(&sent, 0), Some(MASTER_PITCH_BEND_SENSITIVITY));
+        for member in 1..=3 {
+            assert_eq!(
+                sensitivity(&sent, member),
+                Some(MEMBER_PITCH_BEND_SENSITIVITY)
+            );
+        }
+        assert_eq!(sensitivity(&sent, 4), None);
+
+        configure(&mut zones, 15, 2);
+        assert_eq!(zones.members(1), Some(13..=14));
+
+        // No members disables the zone
+        configure(&mut zones, 0, 0);
+        assert_eq!(zones.members(0), None);
+        assert_eq!(zones.members(1), Some(13..=14));
+
+        // Other channels don't configure zones
+        configure(&mut zones, 5, 3);
+        assert_eq!(zones.members(0), None);
+
+        zones.process_all_channels_event(&ChannelAudioEvent::SystemReset);
+        assert_eq!(zones.members(1), None);
+    }
+
+    #[test]
+    fn test_zone_overlap() {
+        let mut zones = MpeZones::new(16);
+        configure(&mut zones, 0, 10);
+        assert_eq!(zones.members(0), Some(1..=10));
+
+        // The lower zone shrinks to make room for the upper zone
+        configure(&mut zones, 15, 8);
+        assert_eq!(zones.members(1), Some(7..=14));
+        assert_eq!(zones.members(0), Some(1..=6));
+
+        // All member channels disable the other zone
+        configure(&mut zones, 0, 15);
+        assert_eq!(zones.members(0), Some(1..=15));
+        assert_eq!(zones.members(1), None);
+    }
+
+    #[test]
+    fn test_member_routing() {
+        let mut zones = MpeZones::new(16);
+        configure(&mut zones, 0, 3);
+
+        // Controls of the master channel are sent to the members too
+        let sent = process(
+            &mut zones,
+            0,
+            ChannelAudioEvent::Control(ControlEvent::Raw(0x01, 100)),
+        );
+        assert_eq!(channels(&sent), vec![1, 2, 3, 0]);
+
+        // Notes of the master channel only play on it
+        let sent = process(
+            &mut zones,
+            0,
+            ChannelAudioEvent::NoteOn { key: 60, vel: 100 },
+        );
+        assert_eq!(channels(&sent), vec![0]);
+
+        // The master pitch bend is added to the member pitch bend
+        let sent = process(
+            &mut zones,
+            0,
+            ChannelAudioEvent::Control(ControlEvent::PitchBendValue(0.5)),
+        );
+        assert!(matches!(
+            sent[0],
+            (1, ChannelAudioEvent::Control(ControlEvent::ZonePitchBend(value))) if value == 1.0
+        ));
+
+        // Events of the member channels only change them
+        let sent = process(
+            &mut zones,
+            2,
+            ChannelAudioEvent::Control(ControlEvent::PitchBendValue(0.5)),
+        );
+        assert!(matches!(
+            sent[..],
+            [(
+                2,
+                ChannelAudioEvent::Control(ControlEvent::PitchBendValue(_))
+            )]
+        ));
+    }
+
+    #[test]
+    fn test_fewer_channels() {
+        let mut zones = MpeZones::new(8);
+
+        // The members are limited to the channels of the synthesizer
+        let sent = configure(&mut zones, 0, 15);
+        assert_eq!(zones.members(0), Some(1..=7));
+        assert!(channels(&sent).iter().all(|&channel| channel < 8));
+
+        let sent = process(
+            &mut zones,
+            0,
+            ChannelAudioEvent::Control(ControlEvent::Raw(0x01, 100)),
+        );
+        assert_eq!(channels(&sent), vec![1, 2, 3, 4, 5, 6, 7, 0]);
+
+        // The upper zone master channel doesn't exist
+        assert!(configure(&mut zones, 15, 2).is_empty());
+        assert_eq!(zones.members(1), None);
+        assert!(process(
+            &mut zones,
+            12,
+            ChannelAudioEvent::NoteOn { key: 60, vel: 100 }
+        )
+        .is_empty());
+
+        let mut zones = MpeZones::new(1);
+        configure(&mut zones, 0, 3);
+        assert_eq!(zones.members(0), None);
+    }
+}
@@ -0,0 +1,419 @@
+use crate::{
+    channel::{ChannelAudioEvent, ControlEvent},
+    effects::{ChorusParameter, ChorusType, ReverbType},
+};
+
+use super::SynthEvent;
+
+/// Converts a System Exclusive message to the events that apply it to the
+/// channels of a synthesizer with `channel_count` channels. The message can
+/// be given with or without its F0 and F7 status bytes.
+///
+/// Supported messages:
+/// - GM System On/Off, GM2 System On, GS Reset and XG System On
+/// - Universal master volume, balance, fine tuning and coarse tuning
+/// - GS "use for rhythm part"
+/// - GS reverb macro and XG reverb type
+/// - GS chorus macro, level, feedback, delay, rate and depth
+/// - MIDI Tuning Standard bulk tuning dumps and single note tuning changes
+///
+/// Unsupported messages and GS messages with an invalid checksum
+/// return no events.
+pub fn parse_sysex(data: &[u8], channel_count: u32) -> Vec<SynthEvent> {
+    let data = data.strip_prefix(&[0xF0]).unwrap_or(data);
+    let data = data.strip_suffix(&[0xF7]).unwrap_or(data);
+
+    if let [0x41, _, 0x42, 0x12, body @ ..] = data {
+        if !is_roland_checksum_valid(body) {
+            return Vec::new();
+        }
+    }
+
+    let all_channels = |event: ChannelAudioEvent| vec![SynthEvent::AllChannels(event)];
+    let master_control = |event: ControlEvent| all_channels(ChannelAudioEvent::Control(event));
+
+    match data {
+        // GM System On, GM System Off and GM2 System On
+        [0x7E, _, 0x09, 0x01..=0x03] => all_channels(ChannelAudioEvent::SystemReset),
+
+        // GS Reset
+        [0x41, _, 0x42, 0x12, 0x40, 0x00, 0x7F, 0x00, _] => {
+            all_channels(ChannelAudioEvent::SystemReset)
+        }
+
+        // XG System On
+        [0x43, 0x10..=0x1F, 0x4C, 0x00, 0x00, 0x7E, 0x00] => {
+            all_channels(ChannelAudioEvent::SystemReset)
+        }
+
+        // Universal realtime device control
+        [0x7F, _, 0x04, control, lsb, msb] => {
+            let value = ((*msb as u16) << 7) | *lsb as u16;
+            match control {
+                0x01 => master_control(ControlEvent::MasterVolume(value as f32 / 16383.0)),
+                0x02 => master_control(ControlEvent::MasterBalance(value as f32 / 16383.0)),
+                0x03 => master_control(ControlEvent::MasterFineTune(
+                    (value as f32 - 8192.0) / 8192.0 * 100.0,
+                )),
+                0x04 => master_control(ControlEvent::MasterCoarseTune(*msb as f32 - 64.0)),
+                _ => Vec::new(),
+            }
+        }
+
+        // MTS bulk tuning dump, without and with a bank
+        [0x7E, _, 0x08, 0x01, program, data @ ..] => bulk_tuning_dump(0, *program, data),
+        [0x7E, _, 0x08, 0x04, bank, program, data @ ..] => bulk_tuning_dump(*bank, *program, data),
+
+        // MTS single note tuning change, without and with a bank
+        [0x7F, _, 0x08, 0x02, program, count, data @ ..] => {
+            note_tuning_change(0, *program, *count, data)
+        }
+        [0x7E | 0x7F, _, 0x08, 0x07, bank, program, count, data @ ..] => {
+            note_tuning_change(*bank, *program, *count, data)
+        }
+
+        // GS use for rhythm part
+        [0x41, _, 0x42, 0x12, 0x40, block @ 0x10..=0x1F, 0x15, map, _] => {
+            let channel = gs_part_channel(block & 0x0F);
+            if channel < channel_count {
+                vec![SynthEvent::Channel(
+                    channel,
+                    ChannelAudioEvent::SetDrums(*map != 0),
+                )]
+            } else {
+                Vec::new()
+            }
+        }
+
+        // GS reverb macro
+        [0x41, _, 0x42, 0x12, 0x40, 0x01, 0x30, macro_type, _] => {
+            let reverb_type = match macro_type {
+                0x00 => ReverbType::Room1,
+                0x01 => ReverbType::Room2,
+                0x02 => ReverbType::Room3,
+                0x03 => ReverbType::Hall1,
+                0x04 => ReverbType::Hall2,
+                0x05 => ReverbType::Plate,
+                0x06 => ReverbType::Delay,
+                0x07 => ReverbType::PanningDelay,
+                _ => return Vec::new(),
+            };
+            vec![SynthEvent::ReverbType(reverb_type)]
+        }
+
+        // GS chorus macro
+        [0x41, _, 0x42, 0x12, 0x40, 0x01, 0x38, macro_type, _] => {
+            let chorus_type = match macro_type {
+                0x00 => ChorusType::Chorus1,
+                0x01 => ChorusType::Chorus2,
+                0x02 => ChorusType::Chorus3,
+                0x03 => ChorusType::Chorus4,
+                0x04 => ChorusType::FeedbackChorus,
+                0x05 => ChorusType::Flanger,
+                0x06 => ChorusType::ShortDelay,
+                0x07 => ChorusType::ShortDelayFeedback,
+                _ => return Vec::new(),
+            };
+            vec![SynthEvent::ChorusType(chorus_type)]
+        }
+
+        // GS chorus parameters. The values are converted with approximate
+        // linear mappings of the ranges of the Sound Canvas.
+        [0x41, _, 0x42, 0x12, 0x40, 0x01, parameter @ 0x3A..=0x3E, value, _] => {
+            let value = *value.min(&0x7F) as f32 / 127.0;
+            let parameter = match parameter {
+                0x3A => ChorusParameter::Wet(value),
+                0x3B => ChorusParameter::Feedback(value * 0.95),
+                0x3C => ChorusParameter::Delay(value * 0.03),
+                0x3D => ChorusParameter::Rate(value * 10.0),
+                _ => ChorusParameter::Depth(value * 0.01),
+            };
+            vec![SynthEvent::ChorusParameter(parameter)]
+        }
+
+        // XG reverb type, the variation (LSB) is optional
+        [0x43, 0x10..=0x1F, 0x4C, 0x02, 0x01, 0x00, msb, lsb @ ..] => {
+            let reverb_type = match (msb, lsb.first().unwrap_or(&0)) {
+                (0x01, 0x00) => ReverbType::Hall1,
+                (0x01, 0x01) => ReverbType::Hall2,
+                (0x02, 0x00) => ReverbType::Room1,
+                (0x02, 0x01) => ReverbType::Room2,
+                (0x02, 0x02) => ReverbType::Room3,
+                (0x03, 0x00) => ReverbType::Stage1,
+                (0x03, 0x01) => ReverbType::Stage2,
+                (0x04, 0x00) => ReverbType::Plate,
+                _ => return Vec::new(),
+            };
+            vec![SynthEvent::ReverbType(reverb_type)]
+        }
+
+        _ => Vec::new(),
+    }
+}
+
+/// Returns true if the address, data and checksum of a Roland data set
+/// message add up to a multiple of 128.
+fn is_roland_checksum_valid(body: &[u8]) -> bool {
+    body.len() > 3 && body.iter().map(|&byte| byte as u32).sum::<u32>() % 128 == 0
+}
+
+/// Decodes an MTS frequency, made of the key below the frequency and a
+/// 14-bit fraction of a semitone above it. Returns `None` for the
+/// reserved "no change" value.
+fn mts_pitch(data: &[u8]) -> Option<f32> {
+    match data {
+        [0x7F, 0x7F, 0x7F] => None,
+        [key, msb, lsb] => {
+            let fraction = ((*msb as u16) << 7) | *lsb as u16;
+            Some(*key as f32 + fraction as f32 / 16384.0)
+        }
+        _ => None,
+    }
+}
+
+/// Parses the data of a bulk tuning dump: a 16 character name followed
+/// by the frequencies of all 128 keys and a checksum.
+fn bulk_tuning_dump(bank: u8, program: u8, data: &[u8]) -> Vec<SynthEvent> {
+    let Some(frequencies) = data.get(16..16 + 128 * 3) else {
+        return Vec::new();
+    };
+
+    let changes = frequencies
+        .chunks_exact(3)
+        .enumerate()
+        .filter_map(|(key, data)| Some((key as u8, mts_pitch(data)?)))
+        .collect();
+
+    vec![SynthEvent::AllChannels(ChannelAudioEvent::TuningChange {
+        bank,
+        program,
+        changes,
+    })]
+}
+
+/// Parses the data of a single note tuning change: `count` entries of
+/// a key and its frequency.
+fn note_tuning_change(bank: u8, program: u8, count: u8, data: &[u8]) -> Vec<SynthEvent> {
+    let changes = data
+        .chunks_exact(4)
+        .take(count as usize)
+        .filter_map(|data| Some((data[0], mts_pitch(&data[1..])?)))
+        .collect();
+
+    vec![SynthEvent::AllChannels(ChannelAudioEvent::TuningChange {
+        bank,
+        program,
+        changes,
+    })]
+}
+
+/// Returns the MIDI channel of a GS part. Part blocks are numbered with the
+/// drum part first, followed by the rest of the channels.
+fn gs_part_channel(block: u8) -> u32 {
+    match block {
+        0 => 9,
+        1..=9 => block as u32 - 1,
+        _ => block as u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a GS data set message with a valid checksum.
+    fn gs_message(address: [u8; 3], value: u8) -> Vec<u8> {
+        let sum = address.iter().sum::<u8>() as u32 + value as u32;
+        let checksum = ((128 - sum % 128) % 128) as u8;
+
+        let mut message = vec![0xF0, 0x41, 0x10, 0x42, 0x12];
+        message.extend_from_slice(&address);
+        message.extend_from_slice(&[value, checksum, 0xF7]);
+        message
+    }
+
+    fn is_reset(events: &[SynthEvent]) -> bool {
+        matches!(
+            events,
+            [SynthEvent::AllChannels(ChannelAudioEvent::SystemReset)]
+        )
+    }
+
+    fn master_control(events: &[SynthEvent]) -> Option<&ControlEvent> {
+        match events {
+            [SynthEvent::AllChannels(ChannelAudioEvent::Control(event))] => Some(event),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_resets() {
+        // GM System On, GM System Off and GM2 System On
+        assert!(is_reset(&parse_sysex(
+            &[0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7],
+            16
+        )));
+        assert!(is_reset(&parse_sysex(
+            &[0xF0, 0x7E, 0x7F, 0x09, 0x02, 0xF7],
+            16
+        )));
+        assert!(is_reset(&parse_sysex(
+            &[0xF0, 0x7E, 0x7F, 0x09, 0x03, 0xF7],
+            16
+        )));
+
+        // GS Reset, with and without the status bytes
+        let gs_reset = [
+            0xF0, 0x41, 0x10, 0x42, 0x12, 0x40, 0x00, 0x7F, 0x00, 0x41, 0xF7,
+        ];
+        assert!(is_reset(&parse_sysex(&gs_reset, 16)));
+        assert!(is_reset(&parse_sysex(&gs_reset[1..10], 16)));
+
+        // XG System On
+        let xg_reset = [0xF0, 0x43, 0x10, 0x4C, 0x00, 0x00, 0x7E, 0x00, 0xF7];
+        assert!(is_reset(&parse_sysex(&xg_reset, 16)));
+    }
+
+    #[test]
+    fn test_gs_checksum() {
+        let mut gs_reset = gs_message([0x40, 0x00, 0x7F], 0x00);
+        let checksum = gs_reset.len() - 2;
+        assert_eq!(gs_reset[checksum], 0x41);
+
+        gs_reset[checksum] = 0x42;
+        assert!(parse_sysex(&gs_reset, 16).is_empty());
+
+        // The checksum can't be missing
+        let truncated = [0xF0, 0x41, 0x10, 0x42, 0x12, 0x40, 0x00, 0x7F, 0x00, 0xF7];
+        assert!(parse_sysex(&truncated, 16).is_empty());
+    }
+
+    #[test]
+    fn test_master_controls() {
+        let events = parse_sysex(&[0xF0, 0x7F, 0x7F, 0x04, 0x01, 0x7F, 0x7F, 0xF7], 16);
+        assert!(matches!(
+            master_control(&events),
+            Some(ControlEvent::MasterVolume(volume)) if *volume == 1.0
+        ));
+
+        let events = parse_sysex(&[0xF0, 0x7F, 0x7F, 0x04, 0x02, 0x00, 0x00, 0xF7], 16);
+        assert!(matches!(
+            master_control(&events),
+            Some(ControlEvent::MasterBalance(balance)) if *balance == 0.0
+        ));
+
+        // A quarter of a semitone up
+        let events = parse_sysex(&[0xF0, 0x7F, 0x7F, 0x04, 0x03, 0x00, 0x50, 0xF7], 16);
+        assert!(matches!(
+            master_control(&events),
+            Some(ControlEvent::MasterFineTune(cents)) if *cents == 25.0
+        ));
+
+        // Two semitones down
+        let events = parse_sysex(&[0xF0, 0x7F, 0x7F, 0x04, 0x04, 0x00, 0x3E, 0xF7], 16);
+        assert!(matches!(
+            master_control(&events),
+            Some(ControlEvent::MasterCoarseTune(semitones)) if *semitones == -2.0
+        ));
+    }
+
+    #[test]
+    fn test_gs_part_channels() {
+        let drums_channel = |block: u8, channel_count: u32| match parse_sysex(
+            &gs_message([0x40, block, 0x15], 0x01),
+            channel_count,
+        )[..]
+        {
+            [SynthEvent::Channel(channel, ChannelAudioEvent::SetDrums(true))] => Some(channel),
+            _ => None,
+        };
+
+        assert_eq!(drums_channel(0x10, 16), Some(9));
+        assert_eq!(drums_channel(0x11, 16), Some(0));
+        assert_eq!(drums_channel(0x19, 16), Some(8));
+        assert_eq!(drums_channel(0x1A, 16), Some(10));
+        assert_eq!(drums_channel(0x1F, 16), Some(15));
+
+        // Parts without a channel are ignored
+        assert_eq!(drums_channel(0x1F, 8), None);
+
+        assert!(matches!(
+            parse_sysex(&gs_message([0x40, 0x10, 0x15], 0x00), 16)[..],
+            [SynthEvent::Channel(9, ChannelAudioEvent::SetDrums(false))]
+        ));
+    }
+
+    /// The bank, program and changes of a tuning change event.
+    type TuningChange<'a> = (u8, u8, &'a [(u8, f32)]);
+
+    fn tuning_change(events: &[SynthEvent]) -> Option<TuningChange<'_>> {
+        match events {
+            [SynthEvent::AllChannels(ChannelAudioEvent::TuningChange {
+                bank,
+                program,
+                changes,
+            })] => Some((*bank, *program, changes)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_mts_bulk_tuning_dump() {
+        let mut message = vec![0xF0, 0x7E, 0x7F, 0x08, 0x01, 0x05];
+        message.extend_from_slice(b"Quarter tone up ");
+        for key in 0..128 {
+            if key == 1 {
+                // No change
+                message.extend_from_slice(&[0x7F, 0x7F, 0x7F]);
+            } else {
+                message.extend_from_slice(&[key, 0x20, 0x00]);
+            }
+        }
+        message.extend_from_slice(&[0x00, 0xF7]);
+
+        let events = parse_sysex(&message, 16);
+        let (bank, program, changes) = tuning_change(&events).unwrap();
+        assert_eq!((bank, program), (0, 5));
+        assert_eq!(changes.len(), 127);
+        assert_eq!(changes[0], (0, 0.25));
+        assert_eq!(changes[1], (2, 2.25));
+
+        // With a bank
+        message.splice(4..5, [0x04, 0x02]);
+        let events = parse_sysex(&message, 16);
+        let (bank, program, _) = tuning_change(&events).unwrap();
+        assert_eq!((bank, program), (2, 5));
+
+        // Too short to hold all keys
+        assert!(parse_sysex(&message[..100], 16).is_empty());
+    }
+
+    #[test]
+    fn test_mts_note_tuning_change() {
+        let message = [
+            0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x03, 0x02, 60, 61, 0x00, 0x00, 62, 0x7F, 0x7F, 0x7F,
+            0xF7,
+        ];
+        let events = parse_sysex(&message, 16);
+        let (bank, program, changes) = tuning_change(&events).unwrap();
+        assert_eq!((bank, program), (0, 3));
+        assert_eq!(changes, [(60, 61.0)]);
+
+        // With a bank, the count limits the entries
+        let message = [
+            0xF0, 0x7E, 0x7F, 0x08, 0x07, 0x01, 0x03, 0x01, 60, 60, 0x40, 0x00, 61, 60, 0x00, 0x00,
+            0xF7,
+        ];
+        let events = parse_sysex(&message, 16);
+        let (bank, program, changes) = tuning_change(&events).unwrap();
+        assert_eq!((bank, program), (1, 3));
+        assert_eq!(changes, [(60, 60.5)]);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert!(parse_sysex(&[], 16).is_empty());
+        assert!(parse_sysex(&[0xF0, 0x7D, 0x00, 0xF7], 16).is_empty());
+        assert!(parse_sysex(&gs_message([0x40, 0x01, 0x30], 0x10), 16).is_empty());
+    }
+}
@@ -1,19 +1,402 @@
-pub enum CCTypes {
-    
-}
+/// The highest value of a 14-bit controller.
+const MAX_VALUE: u16 = 0x3FFF;
+
+/// The number of registered parameters defined by MIDI (RPN 0-6),
+/// whose values are always kept.
+const RPN_COUNT: usize = 7;
+
+/// The number of other parameters whose values are kept. When more of
+/// them are used, the value of the oldest one is forgotten.
+const PARAMETER_SLOTS: usize = 16;
 
+/// A 14-bit controller value made of a MSB and LSB pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct MSBLSBControl {
     msb: u8,
     lsb: u8,
-    value: f64,
 }
 
 impl MSBLSBControl {
-    
+    pub const fn new(msb: u8, lsb: u8) -> Self {
+        MSBLSBControl { msb, lsb }
+    }
+
+    pub fn msb(&self) -> u8 {
+        self.msb
+    }
+
+    pub fn lsb(&self) -> u8 {
+        self.lsb
+    }
+
+    /// Returns the combined 14-bit value.
+    pub fn value(&self) -> u16 {
+        ((self.msb as u16) << 7) | self.lsb as u16
+    }
+
+    fn set_value(&mut self, value: u16) {
+        let value = value.min(MAX_VALUE);
+        self.msb = (value >> 7) as u8;
+        self.lsb = (value & 0x7F) as u8;
+    }
 }
 
+/// A parameter that can be changed with the data entry controllers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CCTypes {
+    /// Registered parameter number (CC 101/100)
+    Rpn(u16),
+
+    /// Non-registered parameter number (CC 99/98)
+    Nrpn(u16),
+}
+
+impl CCTypes {
+    /// The parameter number that deselects the current parameter.
+    const NULL: u16 = MAX_VALUE;
+
+    /// The value of the parameter before any data entry was received.
+    fn default_value(&self) -> MSBLSBControl {
+        match self {
+            // Pitch bend sensitivity, 2 semitones
+            CCTypes::Rpn(0) => MSBLSBControl::new(2, 0),
+            // Modulation depth range, 50 cents
+            CCTypes::Rpn(5) => MSBLSBControl::new(0, 64),
+            CCTypes::Rpn(3) | CCTypes::Rpn(4) => MSBLSBControl::new(0, 0),
+            _ => MSBLSBControl::new(64, 0),
+        }
+    }
+
+    /// The amount that data increment and decrement change the value by.
+    /// Parameters that only use the data entry MSB are changed by one MSB step.
+    fn step(&self) -> u16 {
+        match self {
+            CCTypes::Rpn(2..=4) | CCTypes::Nrpn(_) => 0x80,
+            _ => 1,
+        }
+    }
+}
+
+/// A change of a parameter value that needs to be applied to the channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CCChangeType {
-    MSB(u8),
-    LSB(u8),
-    Value(f32),
+    /// The value of a registered parameter changed
+    Rpn(u16, MSBLSBControl),
+
+    /// The value of a non-registered parameter changed
+    Nrpn(u16, MSBLSBControl),
+}
+
+/// Keeps track of the MIDI controllers that are made of multiple messages:
+/// the 14-bit controller pairs, and the RPN and NRPN parameters changed
+/// with data entry, data increment and data decrement.
+#[derive(Debug, Clone)]
+pub struct ControllerState {
+    controllers: [MSBLSBControl; 32],
+    rpn: MSBLSBControl,
+    nrpn: MSBLSBControl,
+    selected: Option<CCTypes>,
+    rpns: [MSBLSBControl; RPN_COUNT],
+    parameters: [Option<(CCTypes, MSBLSBControl)>; PARAMETER_SLOTS],
+    next_slot: usize,
+}
+
+impl ControllerState {
+    pub fn new() -> Self {
+        let mut controllers = [MSBLSBControl::default(); 32];
+        // The channel starts at full volume and expression, so that
+        // a lone LSB only adjusts them finely
+        controllers[7] = MSBLSBControl::new(0x7F, 0x7F);
+        controllers[10] = MSBLSBControl::new(64, 0);
+        controllers[11] = MSBLSBControl::new(0x7F, 0x7F);
+
+        ControllerState {
+            controllers,
+            rpn: MSBLSBControl::new(0x7F, 0x7F),
+            nrpn: MSBLSBControl::new(0x7F, 0x7F),
+            selected: None,
+            rpns: std::array::from_fn(|rpn| CCTypes::Rpn(rpn as u16).default_value()),
+            parameters: [None; PARAMETER_SLOTS],
+            next_slot: 0,
+        }
+    }
+
+    /// Returns the 14-bit value of one of the controllers 0-31.
+    pub fn controller(&self, controller: u8) -> MSBLSBControl {
+        self.controllers[controller as usize & 0x1F]
+    }
+
+    /// Returns the current value of a registered or non-registered parameter.
+    pub fn parameter(&self, parameter: CCTypes) -> MSBLSBControl {
+        match parameter {
+            CCTypes::Rpn(rpn) if (rpn as usize) < RPN_COUNT => self.rpns[rpn as usize],
+            _ => self
+                .parameters
+                .iter()
+                .flatten()
+                .find(|(stored, _)| *stored == parameter)
+                .map(|(_, data)| *data)
+                .unwrap_or_else(|| parameter.default_value()),
+        }
+    }
+
+    fn store_parameter(&mut self, parameter: CCTypes, data: MSBLSBControl) {
+        if let CCTypes::Rpn(rpn) = parameter {
+            if (rpn as usize) < RPN_COUNT {
+                self.rpns[rpn as usize] = data;
+                return;
+            }
+        }
+
+        let stored = self
+            .parameters
+            .iter_mut()
+            .flatten()
+            .find(|(stored, _)| *stored == parameter);
+        if let Some(stored) = stored {
+            stored.1 = data;
+        } else {
+            self.parameters[self.next_slot] = Some((parameter, data));
+            self.next_slot = (self.next_slot + 1) % PARAMETER_SLOTS;
+        }
+    }
+
+    /// Processes a raw control change. Returns the parameter that was
+    /// changed by data entry, if any.
+    pub fn process(&mut self, controller: u8, value: u8) -> Option<CCChangeType> {
+        match controller {
+            0x06 => self.update_parameter(|data| data.msb = value),
+            0x26 => self.update_parameter(|data| data.lsb = value),
+            0x00..=0x1F => {
+                // A new MSB resets the fine adjustment of the previous value
+                self.controllers[controller as usize] = MSBLSBControl::new(value, 0);
+                None
+            }
+            0x20..=0x3F => {
+                self.controllers[controller as usize - 0x20].lsb = value;
+                None
+            }
+            0x60 | 0x61 => {
+                // Data increment/decrement
+                let step = self.selected?.step();
+                self.update_parameter(|data| {
+                    let value = if controller == 0x60 {
+                        data.value().saturating_add(step)
+                    } else {
+                        data.value().saturating_sub(step)
+                    };
+                    data.set_value(value);
+                })
+            }
+            0x62 => {
+                self.nrpn.lsb = value;
+                self.select(CCTypes::Nrpn(self.nrpn.value()));
+                None
+            }
+            0x63 => {
+                self.nrpn.msb = value;
+                self.select(CCTypes::Nrpn(self.nrpn.value()));
+                None
+            }
+            0x64 => {
+                self.rpn.lsb = value;
+                self.select(CCTypes::Rpn(self.rpn.value()));
+                None
+            }
+            0x65 => {
+                self.rpn.msb = value;
+                self.select(CCTypes::Rpn(self.rpn.value()));
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn select(&mut self, parameter: CCTypes) {
+        self.selected = match parameter {
+            CCTypes::Rpn(CCTypes::NULL) | CCTypes::Nrpn(CCTypes::NULL) => None,
+            parameter => Some(parameter),
+        };
+    }
+
+    fn update_parameter(
+        &mut self,
+        update: impl FnOnce(&mut MSBLSBControl),
+    ) -> Option<CCChangeType> {
+        let parameter = self.selected?;
+        let mut data = self.parameter(parameter);
+        update(&mut data);
+        self.store_parameter(parameter, data);
+        Some(match parameter {
+            CCTypes::Rpn(parameter) => CCChangeType::Rpn(parameter, data),
+            CCTypes::Nrpn(parameter) => CCChangeType::Nrpn(parameter, data),
+        })
+    }
+}
+
+impl Default for ControllerState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select_rpn(state: &mut ControllerState, rpn: u16) {
+        assert_eq!(state.process(0x65, (rpn >> 7) as u8), None);
+        assert_eq!(state.process(0x64, (rpn & 0x7F) as u8), None);
+    }
+
+    fn select_nrpn(state: &mut ControllerState, nrpn: u16) {
+        assert_eq!(state.process(0x63, (nrpn >> 7) as u8), None);
+        assert_eq!(state.process(0x62, (nrpn & 0x7F) as u8), None);
+    }
+
+    #[test]
+    fn test_msb_lsb_pairing() {
+        let mut state = ControllerState::new();
+
+        state.process(0x07, 100);
+        state.process(0x27, 64);
+        assert_eq!(state.controller(0x07), MSBLSBControl::new(100, 64));
+        assert_eq!(state.controller(0x07).value(), (100 << 7) | 64);
+
+        // A new MSB clears the LSB
+        state.process(0x07, 90);
+        assert_eq!(state.controller(0x07), MSBLSBControl::new(90, 0));
+
+        // Other controllers are unaffected
+        assert_eq!(state.controller(0x0A), MSBLSBControl::new(64, 0));
+    }
+
+    #[test]
+    fn test_lone_lsb_starts_at_full_level() {
+        let mut state = ControllerState::new();
+        state.process(0x27, 0);
+        state.process(0x2B, 0);
+        assert_eq!(state.controller(0x07), MSBLSBControl::new(0x7F, 0));
+        assert_eq!(state.controller(0x0B), MSBLSBControl::new(0x7F, 0));
+    }
+
+    #[test]
+    fn test_rpn_data_entry() {
+        let mut state = ControllerState::new();
+
+        // No parameter is selected at first
+        assert_eq!(state.process(0x06, 12), None);
+
+        select_rpn(&mut state, 0);
+        assert_eq!(
+            state.process(0x06, 12),
+            Some(CCChangeType::Rpn(0, MSBLSBControl::new(12, 0)))
+        );
+        assert_eq!(
+            state.process(0x26, 50),
+            Some(CCChangeType::Rpn(0, MSBLSBControl::new(12, 50)))
+        );
+
+        // The value is kept when another parameter is selected
+        select_rpn(&mut state, 2);
+        assert_eq!(
+            state.process(0x06, 70),
+            Some(CCChangeType::Rpn(2, MSBLSBControl::new(70, 0)))
+        );
+        assert_eq!(state.parameter(CCTypes::Rpn(0)), MSBLSBControl::new(12, 50));
+    }
+
+    #[test]
+    fn test_nrpn_data_entry() {
+        let mut state = ControllerState::new();
+
+        select_nrpn(&mut state, (0x01 << 7) | 0x08);
+        assert_eq!(
+            state.process(0x06, 80),
+            Some(CCChangeType::Nrpn(0x88, MSBLSBControl::new(80, 0)))
+        );
+        assert_eq!(
+            state.parameter(CCTypes::Nrpn(0x88)),
+            MSBLSBControl::new(80, 0)
+        );
+
+        // RPN and NRPN parameters with the same number are separate
+        assert_eq!(
+            state.parameter(CCTypes::Rpn(0x88)),
+            MSBLSBControl::new(64, 0)
+        );
+    }
+
+    #[test]
+    fn test_data_increment_decrement() {
+        let mut state = ControllerState::new();
+
+        // Pitch bend sensitivity changes by one LSB step
+        select_rpn(&mut state, 0);
+        assert_eq!(
+            state.process(0x60, 0),
+            Some(CCChangeType::Rpn(0, MSBLSBControl::new(2, 1)))
+        );
+        assert_eq!(
+            state.process(0x61, 0),
+            Some(CCChangeType::Rpn(0, MSBLSBControl::new(2, 0)))
+        );
+
+        // Coarse tune changes by one MSB step
+        select_rpn(&mut state, 2);
+        assert_eq!(
+            state.process(0x60, 0),
+            Some(CCChangeType::Rpn(2, MSBLSBControl::new(65, 0)))
+        );
+
+        // The value doesn't wrap around
+        select_rpn(&mut state, 3);
+        assert_eq!(
+            state.process(0x61, 0),
+            Some(CCChangeType::Rpn(3, MSBLSBControl::new(0, 0)))
+        );
+        state.process(0x06, 0x7F);
+        state.process(0x26, 0x7F);
+        assert_eq!(
+            state.process(0x60, 0),
+            Some(CCChangeType::Rpn(3, MSBLSBControl::new(0x7F, 0x7F)))
+        );
+    }
+
+    #[test]
+    fn test_null_rpn() {
+        let mut state = ControllerState::new();
+
+        select_rpn(&mut state, 0);
+        select_rpn(&mut state, CCTypes::NULL);
+        assert_eq!(state.process(0x06, 12), None);
+        assert_eq!(state.process(0x60, 0), None);
+        assert_eq!(state.parameter(CCTypes::Rpn(0)), MSBLSBControl::new(2, 0));
+
+        select_nrpn(&mut state, 0x88);
+        select_nrpn(&mut state, CCTypes::NULL);
+        assert_eq!(state.process(0x26, 12), None);
+    }
+
+    #[test]
+    fn test_oldest_parameter_forgotten() {
+        let mut state = ControllerState::new();
+
+        for nrpn in 0..=PARAMETER_SLOTS as u16 {
+            select_nrpn(&mut state, nrpn);
+            state.process(0x06, 1);
+        }
+
+        assert_eq!(state.parameter(CCTypes::Nrpn(0)), MSBLSBControl::new(64, 0));
+        assert_eq!(state.parameter(CCTypes::Nrpn(1)), MSBLSBControl::new(1, 0));
+
+        // The registered parameters are always kept
+        select_rpn(&mut state, 1);
+        state.process(0x06, 10);
+        for nrpn in 0..PARAMETER_SLOTS as u16 * 2 {
+            select_nrpn(&mut state, nrpn);
+            state.process(0x06, 1);
+        }
+        assert_eq!(state.parameter(CCTypes::Rpn(1)), MSBLSBControl::new(10, 0));
+    }
 }
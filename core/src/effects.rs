@@ -2,3 +2,14 @@ mod limiter;
 pub use limiter::*;
 mod filter;
 pub use filter::*;
+mod reverb;
+pub use reverb::*;
+mod chorus;
+pub use chorus::*;
+mod master;
+pub use master::*;
+mod mixer;
+pub use mixer::*;
+
+#[cfg(test)]
+mod test_helpers;
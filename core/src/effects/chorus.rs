@@ -0,0 +1,372 @@
+use std::f32::consts::PI;
+
+use crate::{AudioStreamParams, ChannelCount};
+
+/// The longest supported delay plus depth, in seconds.
+pub const MAX_CHORUS_DELAY: f32 = 0.05;
+
+/// The chorus types (macros) of the GS standard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChorusType {
+    Chorus1,
+    Chorus2,
+    Chorus3,
+    Chorus4,
+    FeedbackChorus,
+    Flanger,
+    ShortDelay,
+    ShortDelayFeedback,
+}
+
+/// Parameters of the chorus send effect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChorusParams {
+    /// The delay of the chorus voices, in seconds.
+    pub delay: f32,
+
+    /// How much the delay is modulated, in seconds.
+    pub depth: f32,
+
+    /// The frequency of the delay modulation, in Hz.
+    pub rate: f32,
+
+    /// How much of the output is fed back to the delay, from 0 to 1.
+    pub feedback: f32,
+
+    /// The volume of the chorus, from 0 to 1.
+    pub wet: f32,
+}
+
+impl ChorusParams {
+    /// Returns the parameters of a chorus type.
+    pub fn from_type(chorus_type: ChorusType) -> Self {
+        let (delay, depth, rate, feedback) = match chorus_type {
+            ChorusType::Chorus1 => (0.012, 0.001, 0.4, 0.0),
+            ChorusType::Chorus2 => (0.010, 0.003, 0.9, 0.04),
+            ChorusType::Chorus3 => (0.010, 0.003, 0.4, 0.06),
+            ChorusType::Chorus4 => (0.008, 0.0025, 0.9, 0.12),
+            ChorusType::FeedbackChorus => (0.014, 0.004, 0.3, 0.5),
+            ChorusType::Flanger => (0.002, 0.0015, 0.15, 0.85),
+            ChorusType::ShortDelay => (0.03, 0.0, 0.0, 0.0),
+            ChorusType::ShortDelayFeedback => (0.03, 0.0, 0.0, 0.6),
+        };
+
+        ChorusParams {
+            delay,
+            depth,
+            rate,
+            feedback,
+            wet: 0.5,
+        }
+    }
+}
+
+impl Default for ChorusParams {
+    /// The default GS chorus, Chorus 3.
+    fn default() -> Self {
+        Self::from_type(ChorusType::Chorus3)
+    }
+}
+
+/// A single chorus parameter, as changed by the GS chorus SysEx messages.
+/// The values use the same units as `ChorusParams`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChorusParameter {
+    Delay(f32),
+    Depth(f32),
+    Rate(f32),
+    Feedback(f32),
+    Wet(f32),
+}
+
+/// A stereo modulated delay chorus, used as a send effect.
+///
+/// The two sides are modulated by the same LFO with a quarter period
+/// offset, which widens the stereo image.
+pub struct Chorus {
+    params: ChorusParams,
+    channels: ChannelCount,
+    sample_rate: f32,
+
+    buffer: Vec<f32>,
+    pos: usize,
+    phase: f32,
+    feedback: [f32; 2],
+}
+
+impl Chorus {
+    /// Creates a new chorus with the given parameters.
+    pub fn new(params: ChorusParams, stream_params: AudioStreamParams) -> Self {
+        let sample_rate = stream_params.sample_rate as f32;
+        Chorus {
+            params,
+            channels: stream_params.channels,
+            sample_rate,
+            buffer: vec![0.0; (MAX_CHORUS_DELAY * sample_rate) as usize + 2],
+            pos: 0,
+            phase: 0.0,
+            feedback: [0.0; 2],
+        }
+    }
+
+    /// Returns the current parameters of the chorus.
+    pub fn params(&self) -> ChorusParams {
+        self.params
+    }
+
+    /// Changes the parameters of the chorus.
+    pub fn set_params(&mut self, params: ChorusParams) {
+        self.params = params;
+    }
+
+    /// Changes a single parameter of the chorus.
+    pub fn set_parameter(&mut self, parameter: ChorusParameter) {
+        match parameter {
+            ChorusParameter::Delay(delay) => self.params.delay = delay,
+            ChorusParameter::Depth(depth) => self.params.depth = depth,
+            ChorusParameter::Rate(rate) => self.params.rate = rate,
+            ChorusParameter::Feedback(feedback) => self.params.feedback = feedback,
+            ChorusParameter::Wet(wet) => self.params.wet = wet,
+        }
+    }
+
+    /// Changes the chorus type, keeping the volume of the chorus.
+    pub fn set_type(&mut self, chorus_type: ChorusType) {
+        self.set_params(ChorusParams {
+            wet: self.params.wet,
+            ..ChorusParams::from_type(chorus_type)
+        });
+    }
+
+    /// Clears the delay line.
+    pub fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.feedback = [0.0; 2];
+    }
+
+    /// Applies the chorus to the send input, and adds the result to the output.
+    /// Both buffers are interleaved in the channel count of the chorus.
+    pub fn process(&mut self, input: &[f32], output: &mut [f32]) {
+        let params = self.params;
+        let len = self.buffer.len();
+        let max_delay = (len - 2) as f32;
+
+        let depth = (params.depth.max(0.0) * self.sample_rate).min(max_delay - 1.0);
+        let delay = (params.delay.max(0.0) * self.sample_rate).clamp(1.0, max_delay - depth);
+        let feedback = params.feedback.clamp(0.0, 0.95);
+        let phase_step = params.rate.max(0.0) / self.sample_rate;
+
+        let channel_count = self.channels.count() as usize;
+        for (input, output) in input
+            .chunks_exact(channel_count)
+            .zip(output.chunks_exact_mut(channel_count))
+        {
+            let sum: f32 = input.iter().sum();
+            let input = sum / channel_count as f32;
+
+            // The feedback of both sides is mixed into the delay line
+            let feedback_sum = (self.feedback[0] + self.feedback[1]) / channel_count as f32;
+            self.buffer[self.pos] = input + feedback_sum * feedback;
+
+            for (i, out) in output.iter_mut().enumerate() {
+                let lfo = (2.0 * PI * (self.phase + i as f32 * 0.25)).sin();
+                let value = self.read(delay + depth * (lfo + 1.0) / 2.0);
+                self.feedback[i] = value;
+                *out += value * params.wet;
+            }
+
+            self.pos = (self.pos + 1) % len;
+            self.phase = (self.phase + phase_step).fract();
+        }
+    }
+
+    /// Reads the delay line with linear interpolation.
+    #[inline(always)]
+    fn read(&self, delay: f32) -> f32 {
+        let len = self.buffer.len();
+        let whole = delay as usize;
+        let fraction = delay - whole as f32;
+
+        let first = self.buffer[(self.pos + len - whole) % len];
+        let second = self.buffer[(self.pos + len - whole - 1) % len];
+        first + (second - first) * fraction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{effects::test_helpers::stream_params, helpers::Xorshift32};
+
+    fn params(delay: f32, depth: f32, rate: f32) -> ChorusParams {
+        ChorusParams {
+            delay,
+            depth,
+            rate,
+            feedback: 0.0,
+            wet: 1.0,
+        }
+    }
+
+    /// Returns the delays, in samples, at which the impulses sent every
+    /// `interval` samples come out of the left side of the chorus.
+    fn impulse_delays(chorus: &mut Chorus, interval: usize, count: usize) -> Vec<f32> {
+        let mut input = vec![0.0; interval * count * 2];
+        for i in 0..count {
+            input[i * interval * 2] = 1.0;
+            input[i * interval * 2 + 1] = 1.0;
+        }
+        let mut output = vec![0.0; input.len()];
+        chorus.process(&input, &mut output);
+
+        // The interpolation spreads each impulse over two samples, their
+        // weighted position is the fractional delay
+        (0..count)
+            .map(|i| {
+                let (sum, weighted) = (0..interval).fold((0.0, 0.0), |(sum, weighted), j| {
+                    let s = output[(i * interval + j) * 2];
+                    (sum + s, weighted + s * j as f32)
+                });
+                weighted / sum
+            })
+            .collect()
+    }
+
+    fn correlation(a: &[f32], b: &[f32]) -> f32 {
+        let dot = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+        dot(a, b) / (dot(a, a) * dot(b, b)).sqrt()
+    }
+
+    #[test]
+    fn test_impulse_tail() {
+        let mut chorus = Chorus::new(
+            ChorusParams::from_type(ChorusType::ShortDelayFeedback),
+            stream_params(),
+        );
+
+        let mut input = vec![0.0; 9600];
+        input[0] = 1.0;
+        input[1] = 1.0;
+        let mut output = vec![0.0; 9600];
+        chorus.process(&input, &mut output);
+
+        // The impulse comes out after the delay, and repeats with the feedback
+        let delay = (0.03 * 48000.0) as usize;
+        assert!(output[..(delay - 1) * 2].iter().all(|&s| s == 0.0));
+        assert!(output[delay * 2..(delay + 2) * 2].iter().any(|&s| s != 0.0));
+        assert!(output[(delay * 2 + 2) * 2..].iter().any(|&s| s != 0.0));
+
+        // Reset clears the delay line
+        chorus.reset();
+        let mut output = vec![0.0; 9600];
+        chorus.process(&vec![0.0; 9600], &mut output);
+        assert!(output.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_lfo_modulation() {
+        // Without depth, every impulse comes out after the same delay
+        let mut chorus = Chorus::new(params(0.01, 0.0, 2.0), stream_params());
+        let delays = impulse_delays(&mut chorus, 2400, 20);
+        assert!(
+            delays.iter().all(|&d| (d - 480.0).abs() < 0.01),
+            "{delays:?}"
+        );
+
+        // The LFO sweeps the delay over the depth
+        let mut chorus = Chorus::new(params(0.01, 0.004, 2.0), stream_params());
+        let delays = impulse_delays(&mut chorus, 2400, 20);
+        assert!(
+            delays.iter().all(|&d| (479.0..=673.0).contains(&d)),
+            "{delays:?}"
+        );
+        let min = delays.iter().fold(f32::MAX, |min, &d| min.min(d));
+        let max = delays.iter().fold(0.0f32, |max, &d| max.max(d));
+        assert!(max - min > 150.0, "{delays:?}");
+
+        // One LFO period is 10 impulses apart at 2 Hz
+        for (a, b) in delays.iter().zip(&delays[10..]) {
+            assert!((a - b).abs() < 1.0, "{delays:?}");
+        }
+    }
+
+    #[test]
+    fn test_stereo_decorrelation() {
+        let mut rng = Xorshift32::new(1);
+        let input: Vec<f32> = (0..48000)
+            .flat_map(|_| {
+                let s = rng.next_f32() * 2.0 - 1.0;
+                [s, s]
+            })
+            .collect();
+        let sides = |params| {
+            let mut chorus = Chorus::new(params, stream_params());
+            let mut output = vec![0.0; input.len()];
+            chorus.process(&input, &mut output);
+            let left: Vec<f32> = output.iter().step_by(2).copied().collect();
+            let right: Vec<f32> = output.iter().skip(1).step_by(2).copied().collect();
+            (left, right)
+        };
+
+        // The same delay on both sides without modulation
+        let (left, right) = sides(params(0.01, 0.0, 0.0));
+        assert_eq!(left, right);
+
+        // The LFO offset between the sides makes them differ
+        let (left, right) = sides(ChorusParams {
+            wet: 1.0,
+            ..Default::default()
+        });
+        let correlation = correlation(&left[4800..], &right[4800..]);
+        assert!(correlation < 0.5, "{correlation}");
+    }
+
+    #[test]
+    fn test_delay_clamping() {
+        let mut chorus = Chorus::new(params(0.01, 0.0, 0.0), stream_params());
+
+        // Delays past the delay line are limited to its length
+        chorus.set_parameter(ChorusParameter::Delay(1.0));
+        assert_eq!(chorus.params().delay, 1.0);
+        let max = MAX_CHORUS_DELAY * 48000.0;
+        assert_eq!(impulse_delays(&mut chorus, 4800, 2), [max, max]);
+
+        // Negative and zero delays are limited to one sample
+        chorus.reset();
+        chorus.set_parameter(ChorusParameter::Delay(-1.0));
+        assert_eq!(impulse_delays(&mut chorus, 4800, 2), [1.0, 1.0]);
+        chorus.set_parameter(ChorusParameter::Delay(0.0));
+        assert_eq!(impulse_delays(&mut chorus, 4800, 2), [1.0, 1.0]);
+
+        // The depth is limited so the modulated delay stays in the line
+        chorus.set_params(params(0.04, 1.0, 2.0));
+        let delays = impulse_delays(&mut chorus, 2400, 20);
+        assert!(
+            delays.iter().all(|&d| (1.0..=max).contains(&d)),
+            "{delays:?}"
+        );
+    }
+
+    #[test]
+    fn test_set_type() {
+        let mut chorus = Chorus::new(Default::default(), stream_params());
+        chorus.set_parameter(ChorusParameter::Wet(0.2));
+        assert_eq!(chorus.params().wet, 0.2);
+
+        chorus.set_type(ChorusType::Flanger);
+        assert_eq!(
+            chorus.params(),
+            ChorusParams {
+                wet: 0.2,
+                ..ChorusParams::from_type(ChorusType::Flanger)
+            }
+        );
+        assert_ne!(
+            ChorusParams::from_type(ChorusType::Flanger).delay,
+            ChorusParams::default().delay
+        );
+
+        chorus.set_parameter(ChorusParameter::Feedback(0.3));
+        assert_eq!(chorus.params().feedback, 0.3);
+    }
+}
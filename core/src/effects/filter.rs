@@ -55,6 +55,16 @@ impl BiQuadFilter {
         self.filter.replace_coefficients(coeffs);
     }
 
+    pub fn set_params(
+        &mut self,
+        fil_type: FilterType,
+        freq: f32,
+        sample_rate: f32,
+        q: Option<f32>,
+    ) {
+        self.set_coefficients(Self::get_coeffs(fil_type, freq, sample_rate, q));
+    }
+
     pub fn process(&mut self, input: f32) -> f32 {
         self.filter.run(input)
     }
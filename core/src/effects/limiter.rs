@@ -1,4 +1,9 @@
-use std::marker::PhantomData;
+use std::{collections::VecDeque, marker::PhantomData};
+
+use crate::{
+    helpers::{db_to_amp, windowed_sinc_kernel, SincWindow},
+    AudioStreamParams,
+};
 
 struct SingleChannelLimiter {
     loudness: f32,
@@ -39,7 +44,9 @@ impl SingleChannelLimiter {
 
 /// A multi-channel audio limiter.
 ///
-/// Can be useful to prevent clipping on loud audio.
+/// Can be useful to prevent clipping on loud audio. Halves the volume
+/// and limits the channels separately, see `LookaheadLimiter` for a
+/// limiter with a set ceiling.
 pub struct VolumeLimiter {
     channels: Vec<SingleChannelLimiter>,
     channel_count: usize,
@@ -100,3 +107,313 @@ impl VolumeLimiter {
         }
     }
 }
+
+/// The oversampling factor of the true peak detection.
+const TRUE_PEAK_OVERSAMPLING: usize = 4;
+
+/// The number of samples the true peak interpolation reads around
+/// each position.
+const TRUE_PEAK_TAPS: usize = 8;
+
+/// Parameters of the look-ahead limiter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LimiterParams {
+    /// The highest level of the output, in dBFS.
+    pub ceiling: f32,
+
+    /// How far ahead the limiter looks for peaks, in seconds. The gain is
+    /// lowered over this time before a peak, and the audio is delayed by it.
+    pub lookahead: f32,
+
+    /// How fast the gain recovers after a peak, in seconds.
+    pub release: f32,
+
+    /// If set to true, the peaks between the samples are detected with 4x
+    /// oversampling, so the output also stays below the ceiling once it is
+    /// converted to analog. Delays the audio by a few more samples.
+    pub true_peak: bool,
+}
+
+impl Default for LimiterParams {
+    fn default() -> Self {
+        LimiterParams {
+            ceiling: -1.0,
+            lookahead: 0.005,
+            release: 0.1,
+            true_peak: true,
+        }
+    }
+}
+
+/// A brickwall limiter that keeps the audio below a ceiling.
+///
+/// The peaks are detected ahead of time, and the gain is lowered smoothly
+/// before they are reached. All channels share the same gain, so the
+/// stereo image doesn't shift when one side is limited.
+pub struct LookaheadLimiter {
+    channel_count: usize,
+    ceiling: f32,
+    release: f32,
+    lookahead: usize,
+
+    /// The interpolation kernels of the true peak detection, one for each
+    /// position between two samples
+    true_peak_kernels: Option<Vec<[f32; TRUE_PEAK_TAPS]>>,
+    history: Vec<f32>,
+
+    /// The required gains of the look-ahead window that can still be the
+    /// lowest, with the index of their frame. The index wraps around, so
+    /// only the distance between two indexes is meaningful.
+    minimum: VecDeque<(usize, f32)>,
+    frame: usize,
+    envelope: f32,
+
+    /// The gain is averaged over the look-ahead window
+    average: Vec<f32>,
+    average_sum: f64,
+    average_pos: usize,
+
+    delay: Vec<f32>,
+    delay_pos: usize,
+}
+
+impl LookaheadLimiter {
+    /// Creates a new limiter with the given parameters.
+    pub fn new(params: LimiterParams, stream_params: AudioStreamParams) -> Self {
+        let sample_rate = stream_params.sample_rate as f32;
+        let channel_count = stream_params.channels.count() as usize;
+        let lookahead = ((params.lookahead * sample_rate) as usize).max(1);
+
+        let true_peak_kernels = params.true_peak.then(|| {
+            (1..TRUE_PEAK_OVERSAMPLING)
+                .map(|phase| true_peak_kernel(phase as f64 / TRUE_PEAK_OVERSAMPLING as f64))
+                .collect()
+        });
+
+        // The true peaks are found half of the interpolation taps late
+        let latency = lookahead - 1
+            + if params.true_peak {
+                TRUE_PEAK_TAPS / 2
+            } else {
+                0
+            };
+
+        LookaheadLimiter {
+            channel_count,
+            ceiling: db_to_amp(params.ceiling),
+            release: (-1.0 / (params.release.max(0.0001) * sample_rate)).exp(),
+            lookahead,
+            true_peak_kernels,
+            history: vec![0.0; TRUE_PEAK_TAPS * channel_count],
+            minimum: VecDeque::with_capacity(lookahead + 1),
+            frame: 0,
+            envelope: 1.0,
+            average: vec![1.0; lookahead],
+            average_sum: lookahead as f64,
+            average_pos: 0,
+            delay: vec![0.0; latency * channel_count],
+            delay_pos: 0,
+        }
+    }
+
+    /// Returns the delay of the output, in samples per channel.
+    pub fn latency(&self) -> usize {
+        self.delay.len() / self.channel_count
+    }
+
+    /// Clears the delayed audio and returns the gain to 1.
+    pub fn reset(&mut self) {
+        self.history.fill(0.0);
+        self.minimum.clear();
+        self.envelope = 1.0;
+        self.average.fill(1.0);
+        self.average_sum = self.average.len() as f64;
+        self.delay.fill(0.0);
+    }
+
+    /// Applies the limiter to the interleaved audio in place.
+    pub fn limit(&mut self, buffer: &mut [f32]) {
+        for frame in buffer.chunks_exact_mut(self.channel_count) {
+            let peak = self.detect_peak(frame);
+            let required = if peak > self.ceiling {
+                self.ceiling / peak
+            } else {
+                1.0
+            };
+
+            // The lowest required gain of the look-ahead window
+            while matches!(self.minimum.back(), Some(&(_, gain)) if gain >= required) {
+                self.minimum.pop_back();
+            }
+            self.minimum.push_back((self.frame, required));
+            while matches!(self.minimum.front(), Some(&(frame, _)) if self.frame.wrapping_sub(frame) >= self.lookahead)
+            {
+                self.minimum.pop_front();
+            }
+            let held = self.minimum.front().map_or(1.0, |&(_, gain)| gain);
+            self.frame = self.frame.wrapping_add(1);
+
+            // Drops instantly, the averaging below smooths the attack
+            self.envelope = if held < self.envelope {
+                held
+            } else {
+                held + (self.envelope - held) * self.release
+            };
+
+            // Every gain in the window is at most the one required by the
+            // sample leaving the delay, so neither is their average
+            self.average_sum += (self.envelope - self.average[self.average_pos]) as f64;
+            self.average[self.average_pos] = self.envelope;
+            self.average_pos = (self.average_pos + 1) % self.average.len();
+            let gain = (self.average_sum / self.average.len() as f64) as f32;
+
+            if self.delay.is_empty() {
+                for sample in frame.iter_mut() {
+                    *sample *= gain;
+                }
+            } else {
+                let delayed = &mut self.delay[self.delay_pos..self.delay_pos + self.channel_count];
+                for (sample, delayed) in frame.iter_mut().zip(delayed.iter_mut()) {
+                    let input = *sample;
+                    *sample = *delayed * gain;
+                    *delayed = input;
+                }
+                self.delay_pos = (self.delay_pos + self.channel_count) % self.delay.len();
+            }
+        }
+    }
+
+    /// Returns the loudest peak of all channels. With true peak detection,
+    /// this is the peak around the sample half of the taps ago.
+    #[inline(always)]
+    fn detect_peak(&mut self, frame: &[f32]) -> f32 {
+        let Some(kernels) = self.true_peak_kernels.as_ref() else {
+            return frame.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        };
+
+        let mut peak = 0.0f32;
+        for (sample, history) in frame
+            .iter()
+            .zip(self.history.chunks_exact_mut(TRUE_PEAK_TAPS))
+        {
+            history.copy_within(1.., 0);
+            history[TRUE_PEAK_TAPS - 1] = *sample;
+
+            peak = peak.max(history[TRUE_PEAK_TAPS / 2 - 1].abs());
+            for kernel in kernels {
+                let value: f32 = kernel.iter().zip(history.iter()).map(|(k, h)| k * h).sum();
+                peak = peak.max(value.abs());
+            }
+        }
+        peak
+    }
+}
+
+/// Returns a Hann windowed sinc kernel that interpolates the position `t`
+/// after the sample in the middle of the taps.
+fn true_peak_kernel(t: f64) -> [f32; TRUE_PEAK_TAPS] {
+    let mut kernel = [0.0; TRUE_PEAK_TAPS];
+    windowed_sinc_kernel(&mut kernel, t, SincWindow::Hann);
+    kernel.map(|c| c as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+    use crate::effects::test_helpers::{sine, stream_params};
+
+    fn params(true_peak: bool) -> LimiterParams {
+        LimiterParams {
+            true_peak,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_brickwall() {
+        let mut limiter = LookaheadLimiter::new(params(false), stream_params());
+        let mut buffer = sine(440.0, 0.5, 4.0, 4.0);
+
+        // Sudden peaks are caught as well
+        for i in (1000..buffer.len()).step_by(3001) {
+            buffer[i] = 20.0;
+        }
+        limiter.limit(&mut buffer);
+
+        let ceiling = db_to_amp(-1.0);
+        assert!(buffer.iter().all(|s| s.abs() <= ceiling + 1e-6));
+        assert!(buffer.iter().any(|s| s.abs() > ceiling * 0.9));
+    }
+
+    #[test]
+    fn test_linked_gain() {
+        let mut limiter = LookaheadLimiter::new(params(false), stream_params());
+        let mut buffer = sine(440.0, 0.5, 4.0, 1.0);
+        limiter.limit(&mut buffer);
+
+        // The quiet side is reduced by the same gain as the loud one
+        for frame in buffer.chunks_exact(2) {
+            assert!((frame[0] - frame[1] * 4.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_latency() {
+        for true_peak in [false, true] {
+            let mut limiter = LookaheadLimiter::new(params(true_peak), stream_params());
+            let mut buffer = vec![0.0; 2000];
+            buffer[200] = 0.5;
+            buffer[201] = 0.5;
+            limiter.limit(&mut buffer);
+
+            let latency = limiter.latency();
+            assert_eq!(latency, 239 + if true_peak { 4 } else { 0 });
+            let position = buffer.iter().position(|&s| s != 0.0).unwrap();
+            assert_eq!(position, (100 + latency) * 2);
+            assert_eq!(buffer[position], 0.5);
+        }
+    }
+
+    #[test]
+    fn test_true_peak() {
+        // A sine at a quarter of the sample rate with a phase of 45 degrees
+        // has samples at 0.707 of its peak
+        let level = 0.85 / (PI / 4.0).sin();
+        let signal: Vec<f32> = (0..24000)
+            .flat_map(|i| {
+                let s = (PI / 2.0 * i as f32 + PI / 4.0).sin() * level;
+                [s, s]
+            })
+            .collect();
+        let ceiling = db_to_amp(-1.0);
+        assert!(signal.iter().all(|s| s.abs() < ceiling));
+
+        // Without true peak detection the samples are left alone
+        let mut buffer = signal.clone();
+        LookaheadLimiter::new(params(false), stream_params()).limit(&mut buffer);
+        assert!(buffer[4800..].iter().any(|s| s.abs() > 0.84));
+
+        // With it the peaks between the samples are brought below the ceiling
+        let mut buffer = signal;
+        LookaheadLimiter::new(params(true), stream_params()).limit(&mut buffer);
+        let gain = buffer[4800..]
+            .iter()
+            .fold(0.0f32, |peak, s| peak.max(s.abs()))
+            / 0.85;
+        assert!(level * gain <= ceiling * 1.01);
+    }
+
+    #[test]
+    fn test_frame_wrap() {
+        let mut limiter = LookaheadLimiter::new(params(false), stream_params());
+        limiter.frame = usize::MAX - 100;
+
+        let mut buffer = sine(440.0, 0.5, 4.0, 4.0);
+        limiter.limit(&mut buffer);
+        let ceiling = db_to_amp(-1.0);
+        assert!(buffer.iter().all(|s| s.abs() <= ceiling + 1e-6));
+        assert!(limiter.minimum.len() <= limiter.lookahead);
+    }
+}
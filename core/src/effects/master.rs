@@ -0,0 +1,444 @@
+use std::f32::consts::PI;
+
+use biquad::*;
+
+use super::{LimiterParams, LookaheadLimiter, VolumeLimiter};
+use crate::{helpers::db_to_amp, AudioStreamParams, ChannelCount};
+
+/// The cutoff frequency of the DC blocker, in Hz.
+const DC_BLOCKER_FREQ: f32 = 10.0;
+
+/// The filter shape of an equalizer band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EqBandType {
+    Peak,
+    LowShelf,
+    HighShelf,
+    LowPass,
+    HighPass,
+}
+
+/// A single band of the parametric equalizer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EqBand {
+    pub band_type: EqBandType,
+
+    /// The center or corner frequency of the band, in Hz.
+    pub freq: f32,
+
+    /// The gain of the band, in dB. Not used by the low and high pass bands.
+    pub gain: f32,
+
+    /// The Q factor of the band. Higher values make the band narrower.
+    pub q: f32,
+}
+
+/// Parameters of the master bus compressor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompressorParams {
+    /// The level above which the audio is compressed, in dBFS.
+    pub threshold: f32,
+
+    /// The compression ratio above the threshold, for example 4.0 for 4:1.
+    pub ratio: f32,
+
+    /// How fast the compressor reacts to louder audio, in seconds.
+    pub attack: f32,
+
+    /// How fast the compressor recovers after the audio gets quieter, in seconds.
+    pub release: f32,
+
+    /// The gain applied after the compression, in dB.
+    pub makeup_gain: f32,
+}
+
+impl Default for CompressorParams {
+    fn default() -> Self {
+        CompressorParams {
+            threshold: -12.0,
+            ratio: 4.0,
+            attack: 0.005,
+            release: 0.1,
+            makeup_gain: 0.0,
+        }
+    }
+}
+
+/// A stage of the master bus effect chain.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MasterEffect {
+    /// A parametric equalizer, with the bands applied in order.
+    Equalizer(Vec<EqBand>),
+
+    /// A stereo linked compressor.
+    Compressor(CompressorParams),
+
+    /// The `LookaheadLimiter`, which keeps the audio below a ceiling.
+    /// See the `LimiterParams` documentation for more information.
+    Limiter(LimiterParams),
+
+    /// The `VolumeLimiter`, which keeps the audio from clipping by
+    /// following its loudness.
+    VolumeLimiter,
+
+    /// Changes the stereo width, from 0 (mono) to 1 (unchanged) and
+    /// above (wider). Has no effect on mono audio.
+    StereoWidth(f32),
+
+    /// Removes the DC offset of the audio with a 10 Hz high pass filter.
+    DcBlocker,
+}
+
+struct Equalizer {
+    /// One filter per channel for each band, grouped by band
+    filters: Vec<DirectForm1<f32>>,
+    channel_count: usize,
+}
+
+impl Equalizer {
+    fn new(bands: &[EqBand], stream_params: AudioStreamParams) -> Self {
+        let sample_rate = stream_params.sample_rate as f32;
+        let channel_count = stream_params.channels.count() as usize;
+
+        let mut filters = Vec::with_capacity(bands.len() * channel_count);
+        for band in bands {
+            let filter_type = match band.band_type {
+                EqBandType::Peak => Type::PeakingEQ(band.gain),
+                EqBandType::LowShelf => Type::LowShelf(band.gain),
+                EqBandType::HighShelf => Type::HighShelf(band.gain),
+                EqBandType::LowPass => Type::LowPass,
+                EqBandType::HighPass => Type::HighPass,
+            };
+
+            // Out of range frequencies would make the coefficients invalid
+            let freq = band.freq.clamp(1.0, sample_rate / 2.0 - 1.0);
+            let coeffs = Coefficients::<f32>::from_params(
+                filter_type,
+                sample_rate.hz(),
+                freq.hz(),
+                band.q.max(0.01),
+            )
+            .unwrap();
+
+            for _ in 0..channel_count {
+                filters.push(DirectForm1::<f32>::new(coeffs));
+            }
+        }
+
+        Equalizer {
+            filters,
+            channel_count,
+        }
+    }
+
+    fn process(&mut self, buffer: &mut [f32]) {
+        for frame in buffer.chunks_exact_mut(self.channel_count) {
+            for band in self.filters.chunks_exact_mut(self.channel_count) {
+                for (sample, filter) in frame.iter_mut().zip(band.iter_mut()) {
+                    *sample = filter.run(*sample);
+                }
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        for filter in self.filters.iter_mut() {
+            filter.reset_state();
+        }
+    }
+}
+
+struct Compressor {
+    params: CompressorParams,
+    channel_count: usize,
+    attack: f32,
+    release: f32,
+    envelope: f32,
+}
+
+impl Compressor {
+    fn new(params: CompressorParams, stream_params: AudioStreamParams) -> Self {
+        let sample_rate = stream_params.sample_rate as f32;
+        let coefficient = |time: f32| (-1.0 / (time.max(0.0001) * sample_rate)).exp();
+
+        Compressor {
+            params,
+            channel_count: stream_params.channels.count() as usize,
+            attack: coefficient(params.attack),
+            release: coefficient(params.release),
+            envelope: 0.0,
+        }
+    }
+
+    fn process(&mut self, buffer: &mut [f32]) {
+        let slope = 1.0 - 1.0 / self.params.ratio.max(1.0);
+
+        for frame in buffer.chunks_exact_mut(self.channel_count) {
+            // The loudest channel controls the gain of all of them
+            let level = frame.iter().fold(0.0f32, |level, s| level.max(s.abs()));
+            let coefficient = if level > self.envelope {
+                self.attack
+            } else {
+                self.release
+            };
+            self.envelope = level + (self.envelope - level) * coefficient;
+
+            let over = 20.0 * self.envelope.max(1e-6).log10() - self.params.threshold;
+            let gain = db_to_amp(self.params.makeup_gain - over.max(0.0) * slope);
+            for sample in frame.iter_mut() {
+                *sample *= gain;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.envelope = 0.0;
+    }
+}
+
+struct DcBlocker {
+    coefficient: f32,
+    channel_count: usize,
+
+    /// The last input and output of each channel
+    last: Vec<(f32, f32)>,
+}
+
+impl DcBlocker {
+    fn new(stream_params: AudioStreamParams) -> Self {
+        let channel_count = stream_params.channels.count() as usize;
+        DcBlocker {
+            coefficient: 1.0 - 2.0 * PI * DC_BLOCKER_FREQ / stream_params.sample_rate as f32,
+            channel_count,
+            last: vec![(0.0, 0.0); channel_count],
+        }
+    }
+
+    fn process(&mut self, buffer: &mut [f32]) {
+        for frame in buffer.chunks_exact_mut(self.channel_count) {
+            for (sample, (last_in, last_out)) in frame.iter_mut().zip(self.last.iter_mut()) {
+                let out = *sample - *last_in + self.coefficient * *last_out;
+                *last_in = *sample;
+                *last_out = out;
+                *sample = out;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.last.fill((0.0, 0.0));
+    }
+}
+
+enum MasterStage {
+    Equalizer(Equalizer),
+    Compressor(Compressor),
+    Limiter(LookaheadLimiter),
+    VolumeLimiter(VolumeLimiter),
+    StereoWidth(f32),
+    DcBlocker(DcBlocker),
+}
+
+/// The master bus effect chain, applied to the mixed output of all
+/// channels. The same chain gives the same result in the realtime and
+/// render crates.
+pub struct MasterBus {
+    stages: Vec<MasterStage>,
+    stream_params: AudioStreamParams,
+}
+
+impl MasterBus {
+    /// Creates a new master bus with the given effects, applied in order.
+    pub fn new(effects: &[MasterEffect], stream_params: AudioStreamParams) -> Self {
+        let stages = effects
+            .iter()
+            .map(|effect| match effect {
+                MasterEffect::Equalizer(bands) => {
+                    MasterStage::Equalizer(Equalizer::new(bands, stream_params))
+                }
+                MasterEffect::Compressor(params) => {
+                    MasterStage::Compressor(Compressor::new(*params, stream_params))
+                }
+                MasterEffect::Limiter(params) => {
+                    MasterStage::Limiter(LookaheadLimiter::new(*params, stream_params))
+                }
+                MasterEffect::VolumeLimiter => {
+                    MasterStage::VolumeLimiter(VolumeLimiter::new(stream_params.channels.count()))
+                }
+                MasterEffect::StereoWidth(width) => MasterStage::StereoWidth(*width),
+                MasterEffect::DcBlocker => MasterStage::DcBlocker(DcBlocker::new(stream_params)),
+            })
+            .collect();
+
+        MasterBus {
+            stages,
+            stream_params,
+        }
+    }
+
+    /// Applies the effect chain to the interleaved audio in place.
+    pub fn process(&mut self, buffer: &mut [f32]) {
+        let channels = self.stream_params.channels;
+        for stage in self.stages.iter_mut() {
+            match stage {
+                MasterStage::Equalizer(equalizer) => equalizer.process(buffer),
+                MasterStage::Compressor(compressor) => compressor.process(buffer),
+                MasterStage::Limiter(limiter) => limiter.limit(buffer),
+                MasterStage::VolumeLimiter(limiter) => limiter.limit(buffer),
+                MasterStage::StereoWidth(width) => {
+                    if channels == ChannelCount::Stereo {
+                        for frame in buffer.chunks_exact_mut(2) {
+                            let mid = (frame[0] + frame[1]) / 2.0;
+                            let side = (frame[0] - frame[1]) / 2.0 * *width;
+                            frame[0] = mid + side;
+                            frame[1] = mid - side;
+                        }
+                    }
+                }
+                MasterStage::DcBlocker(blocker) => blocker.process(buffer),
+            }
+        }
+    }
+
+    /// Returns the delay of the output caused by the limiters of the
+    /// chain, in samples per channel.
+    pub fn latency(&self) -> usize {
+        self.stages
+            .iter()
+            .map(|stage| match stage {
+                MasterStage::Limiter(limiter) => limiter.latency(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Clears the state of the effects, such as the filter history
+    /// and the gain of the compressor and limiter.
+    pub fn reset(&mut self) {
+        for stage in self.stages.iter_mut() {
+            match stage {
+                MasterStage::Equalizer(equalizer) => equalizer.reset(),
+                MasterStage::Compressor(compressor) => compressor.reset(),
+                MasterStage::Limiter(limiter) => limiter.reset(),
+                MasterStage::VolumeLimiter(limiter) => {
+                    *limiter = VolumeLimiter::new(self.stream_params.channels.count())
+                }
+                MasterStage::StereoWidth(_) => {}
+                MasterStage::DcBlocker(blocker) => blocker.reset(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::test_helpers::{sine, stream_params};
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+    }
+
+    #[test]
+    fn test_dc_blocker() {
+        let mut bus = MasterBus::new(&[MasterEffect::DcBlocker], stream_params());
+        let mut buffer: Vec<f32> = sine(440.0, 1.0, 0.5, 0.5).iter().map(|s| s + 0.5).collect();
+        bus.process(&mut buffer);
+
+        // The offset is gone after the filter settles, the tone stays
+        let end = &buffer[48000..];
+        let mean = end.iter().sum::<f32>() / end.len() as f32;
+        assert!(mean.abs() < 0.001);
+        assert!((peak(end) - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_stereo_width() {
+        let mut bus = MasterBus::new(&[MasterEffect::StereoWidth(0.0)], stream_params());
+        let mut buffer = vec![1.0, 0.0, 0.2, 0.6, -0.4, 0.4];
+        bus.process(&mut buffer);
+        assert_eq!(buffer, vec![0.5, 0.5, 0.4, 0.4, 0.0, 0.0]);
+
+        // A width of 1 leaves the audio unchanged
+        let mut bus = MasterBus::new(&[MasterEffect::StereoWidth(1.0)], stream_params());
+        let mut buffer = vec![1.0, 0.0, 0.25, 0.75];
+        bus.process(&mut buffer);
+        assert_eq!(buffer, vec![1.0, 0.0, 0.25, 0.75]);
+    }
+
+    #[test]
+    fn test_compressor() {
+        let params = CompressorParams {
+            threshold: -12.0,
+            ratio: 4.0,
+            ..Default::default()
+        };
+        let compress = |level: f32| {
+            let mut bus = MasterBus::new(&[MasterEffect::Compressor(params)], stream_params());
+            let mut buffer = sine(440.0, 1.0, level, level);
+            bus.process(&mut buffer);
+            peak(&buffer[48000..])
+        };
+
+        // Below the threshold the level is unchanged
+        let quiet = db_to_amp(-24.0);
+        assert!((compress(quiet) - quiet).abs() < 0.001);
+
+        // 12 dB above the threshold is reduced to 3 dB above it. The
+        // envelope follows the peaks, so the gain is close to that.
+        let loud = compress(1.0);
+        assert!(loud < db_to_amp(-6.0));
+        assert!(loud > db_to_amp(-12.0));
+    }
+
+    #[test]
+    fn test_equalizer() {
+        let band = |band_type, freq, gain| EqBand {
+            band_type,
+            freq,
+            gain,
+            q: 0.707,
+        };
+        let level = |bands: Vec<EqBand>, freq: f32| {
+            let mut bus = MasterBus::new(&[MasterEffect::Equalizer(bands)], stream_params());
+            let mut buffer = sine(freq, 1.0, 0.25, 0.25);
+            bus.process(&mut buffer);
+            peak(&buffer[48000..])
+        };
+
+        // A 6 dB peak doubles the level at its frequency only
+        let bands = vec![band(EqBandType::Peak, 1000.0, 6.0)];
+        assert!((level(bands.clone(), 1000.0) - 0.5).abs() < 0.01);
+        assert!((level(bands, 10000.0) - 0.25).abs() < 0.01);
+
+        // A high pass removes the low frequencies
+        let bands = vec![band(EqBandType::HighPass, 1000.0, 0.0)];
+        assert!(level(bands.clone(), 50.0) < 0.01);
+        assert!((level(bands, 10000.0) - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_reset() {
+        let effects = [
+            MasterEffect::Equalizer(vec![EqBand {
+                band_type: EqBandType::LowPass,
+                freq: 500.0,
+                gain: 0.0,
+                q: 0.707,
+            }]),
+            MasterEffect::Compressor(Default::default()),
+            MasterEffect::DcBlocker,
+        ];
+        let mut bus = MasterBus::new(&effects, stream_params());
+        let mut buffer = sine(440.0, 1.0, 1.0, 1.0);
+        bus.process(&mut buffer);
+
+        // After a reset the bus behaves like a new one
+        bus.reset();
+        let mut reset = sine(220.0, 1.0, 0.5, 0.5);
+        bus.process(&mut reset);
+        let mut new = sine(220.0, 1.0, 0.5, 0.5);
+        MasterBus::new(&effects, stream_params()).process(&mut new);
+        assert_eq!(reset, new);
+    }
+}
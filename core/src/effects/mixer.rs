@@ -0,0 +1,208 @@
+use std::ops::Range;
+
+use crate::{
+    channel::{ChannelAudioEvent, SendLevels},
+    channel_group::SynthEvent,
+    helpers::sum_simd,
+    AudioStreamParams,
+};
+
+use super::{Chorus, ChorusParams, MasterBus, MasterEffect, Reverb, ReverbParams};
+
+/// Mixes the channels of a synthesizer and sends them to a shared reverb
+/// and chorus by their send levels. The output of the send effects is
+/// added to the mix, which then goes through the master bus.
+///
+/// Used by `ChannelGroup` and by the mixer of the realtime synthesizer.
+pub struct EffectMixer {
+    stream_params: AudioStreamParams,
+
+    reverb: Option<Reverb>,
+    reverb_params: Option<ReverbParams>,
+    reverb_input: Vec<f32>,
+
+    chorus: Option<Chorus>,
+    chorus_params: Option<ChorusParams>,
+    chorus_input: Vec<f32>,
+
+    master_bus: MasterBus,
+
+    /// Effect events to be applied at a sample offset of the next block
+    timed_events: Vec<(u32, SynthEvent)>,
+}
+
+impl EffectMixer {
+    /// Creates a new mixer. The reverb and chorus are only created if their
+    /// parameters are given.
+    pub fn new(
+        reverb: Option<ReverbParams>,
+        chorus: Option<ChorusParams>,
+        master_effects: &[MasterEffect],
+        stream_params: AudioStreamParams,
+    ) -> Self {
+        Self {
+            stream_params,
+            reverb: reverb.map(|params| Reverb::new(params, stream_params)),
+            reverb_params: reverb,
+            reverb_input: Vec::new(),
+            chorus: chorus.map(|params| Chorus::new(params, stream_params)),
+            chorus_params: chorus,
+            chorus_input: Vec::new(),
+            master_bus: MasterBus::new(master_effects, stream_params),
+            timed_events: Vec::new(),
+        }
+    }
+
+    /// Applies an effect event. A system reset returns the send effects to
+    /// their initial parameters and clears the state of the master bus.
+    /// Events that don't change the effects are ignored.
+    pub fn process_event(&mut self, event: SynthEvent) {
+        match event {
+            SynthEvent::AllChannels(ChannelAudioEvent::SystemReset) => {
+                if let (Some(reverb), Some(params)) = (self.reverb.as_mut(), self.reverb_params) {
+                    reverb.set_params(params);
+                }
+                if let (Some(chorus), Some(params)) = (self.chorus.as_mut(), self.chorus_params) {
+                    chorus.set_params(params);
+                }
+                self.master_bus.reset();
+            }
+            SynthEvent::ReverbType(reverb_type) => {
+                if let Some(reverb) = self.reverb.as_mut() {
+                    reverb.set_type(reverb_type);
+                }
+            }
+            SynthEvent::ChorusType(chorus_type) => {
+                if let Some(chorus) = self.chorus.as_mut() {
+                    chorus.set_type(chorus_type);
+                }
+            }
+            SynthEvent::ChorusParameter(parameter) => {
+                if let Some(chorus) = self.chorus.as_mut() {
+                    chorus.set_parameter(parameter);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Applies an effect event `offset` samples (per audio channel) into the
+    /// next block processed by the mixer. Events past the end of the block
+    /// are applied in the following blocks.
+    pub fn process_event_at(&mut self, event: SynthEvent, offset: u32) {
+        self.timed_events.push((offset, event));
+    }
+
+    /// Adds the output of a channel to `out`, and to the inputs of the send
+    /// effects by its send levels. `out` must have the same length for all
+    /// the channels of a block.
+    pub fn mix_channel(&mut self, out: &mut [f32], samples: &[f32], sends: &[SendLevels]) {
+        let len = samples.len().min(out.len());
+        self.resize_inputs(out.len());
+
+        let channels = self.stream_params.channels.count() as usize;
+        for (i, levels) in sends.iter().enumerate() {
+            let start = (levels.offset * channels).min(len);
+            let end = sends
+                .get(i + 1)
+                .map(|next| (next.offset * channels).min(len))
+                .unwrap_or(len);
+
+            if self.reverb.is_some() {
+                add_send(
+                    &mut self.reverb_input[start..end],
+                    &samples[start..end],
+                    levels.reverb,
+                );
+            }
+            if self.chorus.is_some() {
+                add_send(
+                    &mut self.chorus_input[start..end],
+                    &samples[start..end],
+                    levels.chorus,
+                );
+            }
+        }
+
+        sum_simd(&samples[..len], &mut out[..len]);
+    }
+
+    /// Adds the send effects to the mixed channels in `buffer` and applies
+    /// the master bus. The processing is split at the offsets of the timed
+    /// events, and the send inputs are cleared for the next block.
+    pub fn process(&mut self, buffer: &mut [f32]) {
+        self.resize_inputs(buffer.len());
+
+        let channels = self.stream_params.channels.count() as usize;
+        let len = buffer.len() / channels;
+
+        let mut events = std::mem::take(&mut self.timed_events);
+        events.sort_by_key(|(offset, _)| *offset);
+        let count = events.partition_point(|(offset, _)| (*offset as usize) < len);
+
+        let mut pos = 0;
+        for (offset, event) in events.drain(..count) {
+            let offset = offset as usize;
+            if offset > pos {
+                self.process_range(buffer, pos * channels..offset * channels);
+                pos = offset;
+            }
+            self.process_event(event);
+        }
+        self.process_range(buffer, pos * channels..buffer.len());
+
+        // The events past the end of the block are kept for the next blocks
+        for (offset, _) in events.iter_mut() {
+            *offset -= len as u32;
+        }
+        self.timed_events = events;
+
+        self.reverb_input.fill(0.0);
+        self.chorus_input.fill(0.0);
+    }
+
+    /// Returns the delay of the output caused by the master bus effects,
+    /// in samples per channel. See `MasterBus::latency` for more information.
+    pub fn latency(&self) -> usize {
+        self.master_bus.latency()
+    }
+
+    /// Applies the send effects and the master bus to a part of the buffer.
+    fn process_range(&mut self, buffer: &mut [f32], range: Range<usize>) {
+        if let Some(reverb) = self.reverb.as_mut() {
+            reverb.process(
+                &self.reverb_input[range.clone()],
+                &mut buffer[range.clone()],
+            );
+        }
+        if let Some(chorus) = self.chorus.as_mut() {
+            chorus.process(
+                &self.chorus_input[range.clone()],
+                &mut buffer[range.clone()],
+            );
+        }
+
+        self.master_bus.process(&mut buffer[range]);
+    }
+
+    /// Matches the length of the send inputs to the block, clearing them
+    /// if it changed.
+    fn resize_inputs(&mut self, len: usize) {
+        if self.reverb.is_some() && self.reverb_input.len() != len {
+            self.reverb_input.clear();
+            self.reverb_input.resize(len, 0.0);
+        }
+        if self.chorus.is_some() && self.chorus_input.len() != len {
+            self.chorus_input.clear();
+            self.chorus_input.resize(len, 0.0);
+        }
+    }
+}
+
+fn add_send(input: &mut [f32], samples: &[f32], send: f32) {
+    if send > 0.0 {
+        for (input, sample) in input.iter_mut().zip(samples.iter()) {
+            *input += sample * send;
+        }
+    }
+}
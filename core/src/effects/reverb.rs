@@ -0,0 +1,370 @@
+use crate::{AudioStreamParams, ChannelCount};
+
+// The Freeverb tunings, in samples at 44.1kHz
+const COMB_TUNINGS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNINGS: [usize; 4] = [556, 441, 341, 225];
+const STEREO_SPREAD: usize = 23;
+
+const FIXED_GAIN: f32 = 0.015;
+const SCALE_WET: f32 = 3.0;
+const SCALE_DAMPING: f32 = 0.4;
+const SCALE_ROOM: f32 = 0.28;
+const OFFSET_ROOM: f32 = 0.7;
+const ALLPASS_FEEDBACK: f32 = 0.5;
+
+/// The longest supported pre-delay, in seconds.
+pub const MAX_REVERB_PRE_DELAY: f32 = 0.5;
+
+/// The reverb types of the GS and XG standards. The reverb is always a
+/// Freeverb style algorithm, the types only change its parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReverbType {
+    Room1,
+    Room2,
+    Room3,
+    Hall1,
+    Hall2,
+    Plate,
+
+    /// The GS delay types are approximated with a long pre-delay
+    Delay,
+    PanningDelay,
+
+    /// XG only
+    Stage1,
+
+    /// XG only
+    Stage2,
+}
+
+/// Parameters of the reverb send effect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReverbParams {
+    /// The size of the room, from 0 to 1. Larger rooms reverberate longer.
+    pub room_size: f32,
+
+    /// How fast the high frequencies fade out, from 0 to 1.
+    pub damping: f32,
+
+    /// The volume of the reverb, from 0 to 1.
+    pub wet: f32,
+
+    /// The stereo width of the reverb, from 0 to 1.
+    pub width: f32,
+
+    /// The delay before the reverb starts, in seconds. Can be up to
+    /// `MAX_REVERB_PRE_DELAY`.
+    pub pre_delay: f32,
+}
+
+impl ReverbParams {
+    /// Returns the parameters of a reverb type.
+    pub fn from_type(reverb_type: ReverbType) -> Self {
+        let (room_size, damping, width, pre_delay) = match reverb_type {
+            ReverbType::Room1 => (0.5, 0.6, 1.0, 0.005),
+            ReverbType::Room2 => (0.6, 0.5, 1.0, 0.008),
+            ReverbType::Room3 => (0.7, 0.45, 1.0, 0.01),
+            ReverbType::Hall1 => (0.8, 0.4, 1.0, 0.02),
+            ReverbType::Hall2 => (0.88, 0.3, 1.0, 0.025),
+            ReverbType::Plate => (0.75, 0.15, 1.0, 0.0),
+            ReverbType::Delay => (0.5, 0.5, 0.5, 0.25),
+            ReverbType::PanningDelay => (0.5, 0.5, 1.0, 0.25),
+            ReverbType::Stage1 => (0.82, 0.35, 1.0, 0.015),
+            ReverbType::Stage2 => (0.86, 0.3, 1.0, 0.02),
+        };
+
+        ReverbParams {
+            room_size,
+            damping,
+            wet: 1.0 / SCALE_WET,
+            width,
+            pre_delay,
+        }
+    }
+}
+
+impl Default for ReverbParams {
+    /// The default GS reverb, Hall 2.
+    fn default() -> Self {
+        Self::from_type(ReverbType::Hall2)
+    }
+}
+
+struct Comb {
+    buffer: Vec<f32>,
+    pos: usize,
+    filter_store: f32,
+}
+
+impl Comb {
+    fn new(length: usize) -> Self {
+        Comb {
+            buffer: vec![0.0; length.max(1)],
+            pos: 0,
+            filter_store: 0.0,
+        }
+    }
+
+    #[inline(always)]
+    fn process(&mut self, input: f32, feedback: f32, damp1: f32, damp2: f32) -> f32 {
+        let output = self.buffer[self.pos];
+        self.filter_store = output * damp2 + self.filter_store * damp1;
+        if self.filter_store.abs() < f32::MIN_POSITIVE {
+            self.filter_store = 0.0;
+        }
+
+        self.buffer[self.pos] = input + self.filter_store * feedback;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        output
+    }
+}
+
+struct Allpass {
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl Allpass {
+    fn new(length: usize) -> Self {
+        Allpass {
+            buffer: vec![0.0; length.max(1)],
+            pos: 0,
+        }
+    }
+
+    #[inline(always)]
+    fn process(&mut self, input: f32) -> f32 {
+        let buffered = self.buffer[self.pos];
+        self.buffer[self.pos] = input + buffered * ALLPASS_FEEDBACK;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        buffered - input
+    }
+}
+
+/// The comb and allpass filters of one side of the reverb.
+struct ReverbSide {
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+impl ReverbSide {
+    fn new(sample_rate: u32, spread: usize) -> Self {
+        let scale = |length: usize| (length + spread) * sample_rate as usize / 44100;
+        ReverbSide {
+            combs: COMB_TUNINGS.iter().map(|&l| Comb::new(scale(l))).collect(),
+            allpasses: ALLPASS_TUNINGS
+                .iter()
+                .map(|&l| Allpass::new(scale(l)))
+                .collect(),
+        }
+    }
+
+    #[inline(always)]
+    fn process(&mut self, input: f32, feedback: f32, damp1: f32, damp2: f32) -> f32 {
+        let mut out = 0.0;
+        for comb in self.combs.iter_mut() {
+            out += comb.process(input, feedback, damp1, damp2);
+        }
+        for allpass in self.allpasses.iter_mut() {
+            out = allpass.process(out);
+        }
+        out
+    }
+}
+
+/// A Freeverb style algorithmic reverb, used as a send effect.
+pub struct Reverb {
+    params: ReverbParams,
+    channels: ChannelCount,
+    sample_rate: u32,
+
+    left: ReverbSide,
+    right: ReverbSide,
+
+    pre_delay: Vec<f32>,
+    pre_delay_pos: usize,
+    pre_delay_length: usize,
+}
+
+impl Reverb {
+    /// Creates a new reverb with the given parameters.
+    pub fn new(params: ReverbParams, stream_params: AudioStreamParams) -> Self {
+        let sample_rate = stream_params.sample_rate;
+        let max_pre_delay = (MAX_REVERB_PRE_DELAY * sample_rate as f32) as usize + 1;
+
+        let mut reverb = Reverb {
+            params,
+            channels: stream_params.channels,
+            sample_rate,
+            left: ReverbSide::new(sample_rate, 0),
+            right: ReverbSide::new(sample_rate, STEREO_SPREAD),
+            pre_delay: vec![0.0; max_pre_delay],
+            pre_delay_pos: 0,
+            pre_delay_length: 0,
+        };
+        reverb.set_params(params);
+        reverb
+    }
+
+    /// Returns the current parameters of the reverb.
+    pub fn params(&self) -> ReverbParams {
+        self.params
+    }
+
+    /// Changes the parameters of the reverb.
+    pub fn set_params(&mut self, params: ReverbParams) {
+        self.params = params;
+        self.pre_delay_length = ((params.pre_delay.clamp(0.0, MAX_REVERB_PRE_DELAY)
+            * self.sample_rate as f32) as usize)
+            .min(self.pre_delay.len() - 1);
+    }
+
+    /// Changes the reverb type, keeping the volume of the reverb.
+    pub fn set_type(&mut self, reverb_type: ReverbType) {
+        self.set_params(ReverbParams {
+            wet: self.params.wet,
+            ..ReverbParams::from_type(reverb_type)
+        });
+    }
+
+    /// Clears the reverb tail.
+    pub fn reset(&mut self) {
+        let sample_rate = self.sample_rate;
+        self.left = ReverbSide::new(sample_rate, 0);
+        self.right = ReverbSide::new(sample_rate, STEREO_SPREAD);
+        self.pre_delay.fill(0.0);
+    }
+
+    /// Applies the reverb to the send input, and adds the result to the output.
+    /// Both buffers are interleaved in the channel count of the reverb.
+    pub fn process(&mut self, input: &[f32], output: &mut [f32]) {
+        let params = &self.params;
+        let feedback = params.room_size.clamp(0.0, 1.0) * SCALE_ROOM + OFFSET_ROOM;
+        let damp1 = params.damping.clamp(0.0, 1.0) * SCALE_DAMPING;
+        let damp2 = 1.0 - damp1;
+        let wet = params.wet * SCALE_WET;
+        let width = params.width.clamp(0.0, 1.0);
+        let wet1 = wet * (width / 2.0 + 0.5);
+        let wet2 = wet * ((1.0 - width) / 2.0);
+
+        let channel_count = self.channels.count() as usize;
+        for (input, output) in input
+            .chunks_exact(channel_count)
+            .zip(output.chunks_exact_mut(channel_count))
+        {
+            let sum: f32 = input.iter().sum();
+            let input = self.delay(sum / channel_count as f32 * 2.0 * FIXED_GAIN);
+
+            let left = self.left.process(input, feedback, damp1, damp2);
+            match self.channels {
+                ChannelCount::Mono => {
+                    output[0] += left * wet;
+                }
+                ChannelCount::Stereo => {
+                    let right = self.right.process(input, feedback, damp1, damp2);
+                    output[0] += left * wet1 + right * wet2;
+                    output[1] += right * wet1 + left * wet2;
+                }
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn delay(&mut self, input: f32) -> f32 {
+        if self.pre_delay_length == 0 {
+            return input;
+        }
+
+        let len = self.pre_delay.len();
+        let read = (self.pre_delay_pos + len - self.pre_delay_length) % len;
+        let output = self.pre_delay[read];
+        self.pre_delay[self.pre_delay_pos] = input;
+        self.pre_delay_pos = (self.pre_delay_pos + 1) % len;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::test_helpers::stream_params;
+
+    fn energy(samples: &[f32]) -> f32 {
+        samples.iter().map(|s| s * s).sum()
+    }
+
+    #[test]
+    fn test_impulse_tail() {
+        let mut reverb = Reverb::new(Default::default(), stream_params());
+
+        // One second of audio with an impulse at the start
+        let mut input = vec![0.0; 96000];
+        input[0] = 1.0;
+        input[1] = 1.0;
+        let mut output = vec![0.0; 96000];
+        reverb.process(&input, &mut output);
+
+        // Nothing comes out before the pre-delay
+        let pre_delay = (ReverbParams::default().pre_delay * 48000.0) as usize;
+        assert!(output[..pre_delay * 2].iter().all(|&s| s == 0.0));
+
+        // The tail is still ringing well after the impulse and fades out
+        let start = energy(&output[..48000]);
+        let end = energy(&output[48000..]);
+        assert!(start > 0.0);
+        assert!(end > 0.0);
+        assert!(end < start);
+
+        // Reset clears the tail
+        reverb.reset();
+        let mut output = vec![0.0; 9600];
+        reverb.process(&vec![0.0; 9600], &mut output);
+        assert!(output.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_zero_send_is_dry() {
+        let mut reverb = Reverb::new(Default::default(), stream_params());
+
+        // The channels add nothing to the input with a send of 0
+        let input = vec![0.0; 9600];
+        let dry: Vec<f32> = (0..9600).map(|i| (i as f32 * 0.01).sin()).collect();
+        let mut output = dry.clone();
+        reverb.process(&input, &mut output);
+        assert_eq!(output, dry);
+    }
+
+    #[test]
+    fn test_set_type() {
+        let mut reverb = Reverb::new(Default::default(), stream_params());
+        reverb.set_params(ReverbParams {
+            wet: 0.2,
+            ..Default::default()
+        });
+
+        reverb.set_type(ReverbType::Room1);
+        assert_eq!(
+            reverb.params(),
+            ReverbParams {
+                wet: 0.2,
+                ..ReverbParams::from_type(ReverbType::Room1)
+            }
+        );
+        assert_ne!(
+            ReverbParams::from_type(ReverbType::Room1).room_size,
+            ReverbParams::default().room_size
+        );
+
+        // A longer room rings for longer
+        let tail = |reverb_type| {
+            let mut reverb = Reverb::new(ReverbParams::from_type(reverb_type), stream_params());
+            let mut input = vec![0.0; 96000];
+            input[0] = 1.0;
+            input[1] = 1.0;
+            let mut output = vec![0.0; 96000];
+            reverb.process(&input, &mut output);
+            energy(&output[48000..]) / energy(&output[..48000])
+        };
+        assert!(tail(ReverbType::Hall2) > tail(ReverbType::Room1));
+    }
+}
@@ -0,0 +1,20 @@
+//! Helpers shared by the tests of the effects.
+
+use std::f32::consts::PI;
+
+use crate::{AudioStreamParams, ChannelCount};
+
+pub fn stream_params() -> AudioStreamParams {
+    AudioStreamParams::new(48000, ChannelCount::Stereo)
+}
+
+/// A stereo sine wave at 48 kHz, lasting `seconds`, with the given levels
+/// on the left and right channels.
+pub fn sine(freq: f32, seconds: f32, left: f32, right: f32) -> Vec<f32> {
+    (0..(seconds * 48000.0) as usize)
+        .flat_map(|i| {
+            let s = (2.0 * PI * freq * i as f32 / 48000.0).sin();
+            [s * left, s * right]
+        })
+        .collect()
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
 mod frequencies;
 pub use frequencies::*;
@@ -22,6 +22,74 @@ pub fn db_to_amp(db: f32) -> f32 {
     10f32.powf(db / 20.0)
 }
 
+/// A small, deterministic pseudo-random number generator (xorshift32),
+/// so that renders with random elements are reproducible.
+pub(crate) struct Xorshift32 {
+    state: u32,
+}
+
+impl Xorshift32 {
+    pub fn new(seed: u32) -> Self {
+        // The state can't be zero, otherwise it would stay zero forever
+        Xorshift32 { state: seed.max(1) }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    /// Returns a random value from 0 (inclusive) to 1 (exclusive).
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
+
+/// The window function of a windowed sinc kernel.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum SincWindow {
+    Hann,
+    Blackman,
+}
+
+/// Fills `kernel` with a windowed sinc kernel that interpolates the position
+/// `t` (0 to 1) after the tap before the middle of the kernel. The kernel is
+/// normalized so that constant signals keep their level.
+pub(crate) fn windowed_sinc_kernel(kernel: &mut [f64], t: f64, window: SincWindow) {
+    let half = (kernel.len() / 2) as f64;
+
+    for (tap, c) in kernel.iter_mut().enumerate() {
+        // The distance of the tap from the interpolated position
+        let x = (tap as f64 - (half - 1.0)) - t;
+        let sinc = if x == 0.0 {
+            1.0
+        } else {
+            (PI * x).sin() / (PI * x)
+        };
+
+        let w = x / half;
+        let window = if w.abs() >= 1.0 {
+            0.0
+        } else {
+            match window {
+                SincWindow::Hann => 0.5 + 0.5 * (PI * w).cos(),
+                SincWindow::Blackman => 0.42 + 0.5 * (PI * w).cos() + 0.08 * (2.0 * PI * w).cos(),
+            }
+        };
+
+        *c = sinc * window;
+    }
+
+    let sum: f64 = kernel.iter().sum();
+    for c in kernel.iter_mut() {
+        *c /= sum;
+    }
+}
+
 /// Checks if two `Arc<T>` vecs are equal based on `Arc::ptr_eq`.
 pub fn are_arc_vecs_equal<T: ?Sized>(old: &[Arc<T>], new: &[Arc<T>]) -> bool {
     // First, check if the lengths are the same
@@ -38,3 +106,29 @@ pub fn are_arc_vecs_equal<T: ?Sized>(old: &[Arc<T>], new: &[Arc<T>]) -> bool {
 
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xorshift() {
+        // The same seed always gives the same values
+        let values: Vec<_> = (0..100).map(|_| Xorshift32::new(60).next_u32()).collect();
+        assert!(values.iter().all(|&v| v == values[0]));
+
+        let mut rng = Xorshift32::new(60);
+        let values: Vec<_> = (0..10000).map(|_| rng.next_f32()).collect();
+        assert!(values.iter().all(|v| (0.0..1.0).contains(v)));
+
+        // The values are spread over the range
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        assert!((mean - 0.5).abs() < 0.02);
+        assert!(values.iter().any(|&v| v < 0.01));
+        assert!(values.iter().any(|&v| v > 0.99));
+
+        // A seed of zero doesn't get stuck at zero
+        let mut rng = Xorshift32::new(0);
+        assert_ne!(rng.next_u32(), 0);
+    }
+}
@@ -5,6 +5,8 @@ pub mod buffered_renderer;
 
 pub mod channel;
 
+mod control;
+
 pub mod voice;
 
 mod audio_pipe;
@@ -15,6 +17,8 @@ pub use audio_stream::*;
 
 pub mod soundfont;
 
+pub mod tuning;
+
 pub mod effects;
 
 pub mod helpers;
@@ -6,7 +6,11 @@ use symphonia::core::{audio::AudioBufferRef, meta::MetadataOptions};
 use symphonia::core::{audio::Signal, io::MediaSourceStream};
 use symphonia::core::{codecs::DecoderOptions, errors::Error};
 
-use crate::{AudioStreamParams, ChannelCount};
+use super::SampleFormat;
+use crate::{
+    voice::{SampleBuffer, StreamCache},
+    AudioStreamParams, ChannelCount,
+};
 use thiserror::Error;
 use xsynth_soundfonts::resample::resample_vecs;
 
@@ -113,6 +117,27 @@ pub(super) fn load_audio_file(
     Ok((built, sample_rate))
 }
 
+/// Moves the channels of a sample that are longer than `preload`
+/// to the stream cache. All of them are stored in the given format.
+pub(super) fn stream_sample(
+    sample: &[Arc<[f32]>],
+    cache: &StreamCache,
+    preload: usize,
+    format: SampleFormat,
+) -> Result<Arc<[SampleBuffer]>, AudioLoadError> {
+    sample
+        .iter()
+        .map(|channel| {
+            if channel.len() > preload {
+                let streamed = cache.store(channel, preload, format)?;
+                Ok(SampleBuffer::Stream(Arc::new(streamed)))
+            } else {
+                Ok(SampleBuffer::new(channel, format))
+            }
+        })
+        .collect()
+}
+
 struct BuilderVecs {
     vecs: Vec<Vec<f32>>,
 }
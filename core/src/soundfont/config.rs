@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 /// Type of the audio sample interpolation algorithm.
 #[derive(Clone, PartialEq, Eq, Copy, Debug)]
 pub enum Interpolator {
@@ -10,10 +12,46 @@ pub enum Interpolator {
     ///
     /// See more info about this method [here](https://en.wikipedia.org/wiki/Linear_interpolation)
     Linear,
+
+    /// 4-point cubic Hermite (Catmull-Rom) interpolation
+    ///
+    /// See more info about this method [here](https://en.wikipedia.org/wiki/Cubic_Hermite_spline)
+    Hermite,
+
+    /// 4-point, 3rd order Lagrange interpolation
+    ///
+    /// See more info about this method [here](https://en.wikipedia.org/wiki/Lagrange_polynomial)
+    Lagrange,
+
+    /// Windowed sinc interpolation with the given number of taps, which is
+    /// rounded down to an even number between 8 and 32. Has the highest
+    /// quality but is the slowest, so it is mostly useful for offline renders.
+    ///
+    /// See more info about this method [here](https://en.wikipedia.org/wiki/Whittaker%E2%80%93Shannon_interpolation_formula)
+    Sinc(u8),
+}
+
+/// The format that the audio samples are stored in memory as.
+#[derive(Clone, PartialEq, Eq, Copy, Debug)]
+pub enum SampleFormat {
+    /// 32-bit floats, with no loss of quality
+    F32,
+
+    /// 16-bit integers, using half of the memory. Samples that go above
+    /// full scale are scaled down to fit.
+    I16,
+
+    /// 24-bit integers, using three quarters of the memory. Samples that
+    /// go above full scale are scaled down to fit.
+    I24,
+
+    /// 16-bit half precision floats, using half of the memory with more
+    /// precision than `I16` for quiet samples
+    F16,
 }
 
 /// Options for initializing/loading a new sample soundfont.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct SoundfontInitOptions {
     /// The bank number (0-128) to extract and use from the soundfont.
     /// `None` means to use all available banks (bank 0 for SFZ).
@@ -45,6 +83,31 @@ pub struct SoundfontInitOptions {
     ///
     /// Default: `Nearest`
     pub interpolator: Interpolator,
+
+    /// The format to store the samples of the new soundfont in. See the
+    /// documentation of the `SampleFormat` enum for available options.
+    ///
+    /// Default: `F32`
+    pub sample_format: SampleFormat,
+
+    /// If set, the samples of SFZ soundfonts are streamed from the disk
+    /// instead of being kept in memory. Only the given number of samples
+    /// (at the output sample rate) at the start of each sample are preloaded,
+    /// and they need to cover the time it takes to read the rest from the disk.
+    ///
+    /// The samples are decoded into a temporary file in the sample format
+    /// when the soundfont is loaded, which is deleted when it is dropped.
+    /// Looped samples are always kept in memory.
+    ///
+    /// Default: `None`
+    pub streaming_preload: Option<usize>,
+
+    /// The directory of the temporary file of the streamed samples. `None`
+    /// means to use the temporary directory of the system, which may be
+    /// kept in memory on some systems.
+    ///
+    /// Default: `None`
+    pub streaming_cache_dir: Option<PathBuf>,
 }
 
 impl Default for SoundfontInitOptions {
@@ -55,6 +118,9 @@ impl Default for SoundfontInitOptions {
             linear_release: false,
             use_effects: true,
             interpolator: Interpolator::Nearest,
+            sample_format: SampleFormat::F32,
+            streaming_preload: None,
+            streaming_cache_dir: None,
         }
     }
 }
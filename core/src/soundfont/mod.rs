@@ -9,14 +9,20 @@ use std::{
 use biquad::Q_BUTTERWORTH_F32;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use thiserror::Error;
-use xsynth_soundfonts::{convert_sample_index, FilterType, LoopMode};
+use xsynth_soundfonts::{
+    convert_sample_index,
+    modulator::{Modulator, ModulatorDestination},
+    sf2::Sf2SampleData,
+    sfz::{CcRange, LfoParams, ModEnvelopeParams, RegionParams},
+    FilterType, LoopMode, OffMode,
+};
 
-use self::audio::load_audio_file;
 pub use self::audio::AudioLoadError;
+use self::audio::{load_audio_file, stream_sample};
 
 use super::{
     voice::VoiceControlData,
-    voice::{EnvelopeParameters, Voice},
+    voice::{EnvelopeParameters, ExclusiveClass, ReleaseType, SampleBuffer, StreamCache, Voice},
 };
 use crate::{helpers::db_to_amp, voice::EnvelopeDescriptor, AudioStreamParams, ChannelCount};
 
@@ -24,15 +30,95 @@ pub use xsynth_soundfonts::{sf2::Sf2ParseError, sfz::SfzParseError};
 
 mod audio;
 mod config;
+mod modulators;
 mod utils;
 mod voice_spawners;
+use modulators::*;
 use utils::*;
 use voice_spawners::*;
 
-pub use config::{Interpolator, SoundfontInitOptions};
+pub use config::{Interpolator, SampleFormat, SoundfontInitOptions};
+
+pub use xsynth_soundfonts::TriggerMode;
 
 pub trait VoiceSpawner: Sync + Send {
     fn spawn_voice(&self, control: &VoiceControlData) -> Box<dyn Voice>;
+
+    /// The note event that should spawn the voice. Spawners returned by
+    /// `get_release_voice_spawners_at` that don't use `ReleaseKey` are
+    /// treated as `Release`.
+    fn trigger(&self) -> TriggerMode {
+        TriggerMode::Attack
+    }
+
+    /// Returns true if the voice should be spawned for the given round robin
+    /// position and random value. Used to alternate between the voices of a note.
+    fn is_selected(&self, _selection: VoiceSelection) -> bool {
+        true
+    }
+
+    /// The exclusive class of the spawned voices.
+    fn exclusive_class(&self) -> ExclusiveClass {
+        ExclusiveClass::NONE
+    }
+
+    /// A bitmask of the keys that act as keyswitches for the voice.
+    fn keyswitch_keys(&self) -> u128 {
+        0
+    }
+
+    /// Returns true if the keyswitch conditions of the voice are met.
+    fn matches_keyswitch(&self, _keyswitch: KeyswitchState) -> bool {
+        true
+    }
+
+    /// Returns true if the current MIDI controller values of the channel
+    /// meet the conditions of the voice.
+    fn matches_controllers(&self, _cc: &[u8; 128]) -> bool {
+        true
+    }
+}
+
+/// A voice spawner that is triggered by MIDI controller movement instead
+/// of note events, e.g. for pedal noises.
+pub struct ControllerVoiceSpawner {
+    /// The key that the voices are played on
+    pub key: u8,
+
+    /// The voices are spawned when a controller moves into one of the ranges
+    pub triggers: Arc<[CcRange]>,
+
+    pub spawner: Box<dyn VoiceSpawner>,
+}
+
+/// Values used to pick between alternative voices of a note.
+#[derive(Debug, Clone, Copy)]
+pub struct VoiceSelection {
+    /// The number of previous notes of the key
+    pub sequence: u32,
+
+    /// A random value from 0 (inclusive) to 1 (exclusive)
+    pub random: f32,
+
+    /// The keyswitch state of the channel when the note started
+    pub keyswitch: KeyswitchState,
+}
+
+/// The keyswitch state of a channel.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeyswitchState {
+    /// The last keyswitch key that was pressed
+    pub last: Option<u8>,
+
+    /// A bitmask of the keys that are held down
+    pub held: u128,
+}
+
+impl KeyswitchState {
+    /// Returns true if the given key is held down.
+    pub fn is_held(&self, key: u8) -> bool {
+        key < 128 && self.held & (1 << key) != 0
+    }
 }
 
 pub trait SoundfontBase: Sync + Send + std::fmt::Debug {
@@ -52,6 +138,11 @@ pub trait SoundfontBase: Sync + Send + std::fmt::Debug {
         key: u8,
         vel: u8,
     ) -> Vec<Box<dyn VoiceSpawner>>;
+
+    /// Returns the voice spawners of a preset that are triggered by MIDI controllers.
+    fn get_controller_voice_spawners(&self, _bank: u8, _preset: u8) -> Vec<ControllerVoiceSpawner> {
+        Vec::new()
+    }
 }
 
 #[derive(Clone)]
@@ -62,6 +153,77 @@ pub(super) struct LoopParams {
     pub end: u32,
 }
 
+/// The round robin and random range of a region, like the SFZ
+/// `seq_length`, `seq_position`, `lorand` and `hirand` opcodes.
+#[derive(Clone, Copy)]
+pub(super) struct SelectionParams {
+    pub seq_length: u32,
+    pub seq_position: u32,
+    pub lorand: f32,
+    pub hirand: f32,
+}
+
+impl SelectionParams {
+    const ALWAYS: SelectionParams = SelectionParams {
+        seq_length: 1,
+        seq_position: 0,
+        lorand: 0.0,
+        hirand: 1.0,
+    };
+
+    pub fn is_selected(&self, selection: VoiceSelection) -> bool {
+        selection.sequence % self.seq_length == self.seq_position
+            && selection.random >= self.lorand
+            && (selection.random < self.hirand || self.hirand >= 1.0)
+    }
+}
+
+/// The keyswitch conditions of a region, like the SFZ `sw_*` opcodes.
+#[derive(Clone, Copy)]
+pub(super) struct KeyswitchParams {
+    pub keys: u128,
+    pub last: Option<u8>,
+    pub down: Option<u8>,
+    pub up: Option<u8>,
+    pub default: Option<u8>,
+}
+
+impl KeyswitchParams {
+    const NONE: KeyswitchParams = KeyswitchParams {
+        keys: 0,
+        last: None,
+        down: None,
+        up: None,
+        default: None,
+    };
+
+    pub fn matches(&self, keyswitch: KeyswitchState) -> bool {
+        if let Some(last) = self.last {
+            if keyswitch.last.or(self.default) != Some(last) {
+                return false;
+            }
+        }
+        if let Some(down) = self.down {
+            if !keyswitch.is_held(down) {
+                return false;
+            }
+        }
+        if let Some(up) = self.up {
+            if keyswitch.is_held(up) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(super) struct ModEnvelope {
+    pub envelope: EnvelopeParameters,
+    pub to_pitch: f32,
+    pub to_filter: f32,
+}
+
 struct SampleVoiceSpawnerParams {
     volume: f32,
     pan: f32,
@@ -71,14 +233,31 @@ struct SampleVoiceSpawnerParams {
     filter_type: FilterType,
     loop_params: LoopParams,
     envelope: Arc<EnvelopeParameters>,
-    sample: Arc<[Arc<[f32]>]>,
+    sample: Arc<[SampleBuffer]>,
     interpolator: Interpolator,
+    modulators: Arc<[Modulator]>,
+    lfos: Arc<[LfoParams]>,
+    mod_envelopes: Arc<[ModEnvelope]>,
+    trigger: TriggerMode,
+    selection: SelectionParams,
+    exclusive_class: ExclusiveClass,
+    keyswitch: KeyswitchParams,
+    cc_conditions: Arc<[CcRange]>,
+}
+
+struct ControllerSpawnerParams {
+    key: u8,
+    vel: u8,
+    triggers: Arc<[CcRange]>,
+    params: Arc<SampleVoiceSpawnerParams>,
 }
 
 pub(super) struct SoundfontInstrument {
     bank: u8,
     preset: u8,
     spawner_params_list: Vec<Vec<Arc<SampleVoiceSpawnerParams>>>,
+    release_spawner_params_list: Vec<Vec<Arc<SampleVoiceSpawnerParams>>>,
+    controller_spawner_params_list: Vec<ControllerSpawnerParams>,
 }
 
 /// Represents a sample soundfont to be used within XSynth.
@@ -111,6 +290,23 @@ pub(super) struct SoundfontInstrument {
 /// - `ampeg_decay`
 /// - `ampeg_sustain`
 /// - `ampeg_release`
+/// - `seq_length` & `seq_position`
+/// - `lorand` & `hirand`
+/// - `trigger` (`attack`, `release`, `release_key`, `first` & `legato`)
+/// - `sw_lokey`, `sw_hikey`, `sw_last`, `sw_down`, `sw_up` & `sw_default`
+/// - `xfin_lovel`, `xfin_hivel`, `xfout_lovel`, `xfout_hivel` & `xf_velcurve`
+/// - `xfin_lokey`, `xfin_hikey`, `xfout_lokey`, `xfout_hikey` & `xf_keycurve`
+/// - `amp_veltrack`, `amp_velcurve_N`, `amp_keytrack` & `amp_keycenter`
+/// - `loccN` & `hiccN`
+/// - `on_loccN` & `on_hiccN`
+/// - `group`, `off_by`, `off_mode` (`fast`, `normal` & `time`) & `off_time`
+/// - `pitchlfo_delay`, `pitchlfo_freq` & `pitchlfo_depth`
+/// - `amplfo_delay`, `amplfo_freq` & `amplfo_depth`
+/// - `fillfo_delay`, `fillfo_freq` & `fillfo_depth`
+/// - `pitcheg_delay`, `pitcheg_start`, `pitcheg_attack`, `pitcheg_hold`,
+///   `pitcheg_decay`, `pitcheg_sustain`, `pitcheg_release` & `pitcheg_depth`
+/// - `fileg_delay`, `fileg_start`, `fileg_attack`, `fileg_hold`,
+///   `fileg_decay`, `fileg_sustain`, `fileg_release` & `fileg_depth`
 ///
 /// ## SF2 specification support
 /// ### Generators
@@ -135,14 +331,92 @@ pub(super) struct SoundfontInstrument {
 /// - `sampleID`
 /// - `sampleModes`
 /// - `overridingRootKey`
+/// - `delayModLFO` & `freqModLFO`
+/// - `modLfoToPitch`, `modLfoToFilterFc` & `modLfoToVolume`
+/// - `delayVibLFO` & `freqVibLFO`
+/// - `vibLfoToPitch`
+/// - `delayModEnv`, `attackModEnv`, `holdModEnv`, `decayModEnv`,
+///   `sustainModEnv` & `releaseModEnv`
+/// - `modEnvToPitch` & `modEnvToFilterFc`
+/// - `exclusiveClass`
 ///
 /// ### Modulators
-/// None
+/// Default, instrument and preset modulators are supported, using the
+/// following sources:
+/// - Note-on velocity and key number
+/// - MIDI continuous controllers
+/// - Pitch wheel and pitch wheel sensitivity
+///
+/// and the following destinations:
+/// - `initialAttenuation`
+/// - `fineTune` & `coarseTune`
+/// - `initialFilterFc`
+/// - `initialFilterQ`
+/// - `pan`
+/// - `modLfoToPitch`, `modLfoToFilterFc` & `modLfoToVolume`
+/// - `vibLfoToPitch`
+///
+/// The default modulators for CC7, CC10, CC11 and the pitch wheel are
+/// handled by the channel itself, so they are not applied per voice.
 pub struct SampleSoundfont {
     instruments: Vec<SoundfontInstrument>,
     stream_params: AudioStreamParams,
 }
 
+/// Converts the modulation envelopes of a region, skipping the ones that
+/// don't modulate anything.
+fn create_mod_envelopes(
+    envelopes: &[ModEnvelopeParams],
+    sample_rate: u32,
+    options: &SoundfontInitOptions,
+) -> Arc<[ModEnvelope]> {
+    // Modulation envelopes are always linear
+    let options = SoundfontInitOptions {
+        linear_release: true,
+        ..options.clone()
+    };
+
+    envelopes
+        .iter()
+        .map(|env| ModEnvelope {
+            envelope: envelope_descriptor_from_region_params(&env.envelope)
+                .to_envelope_params(sample_rate, &options),
+            to_pitch: env.to_pitch,
+            to_filter: if options.use_effects {
+                env.to_filter
+            } else {
+                0.0
+            },
+        })
+        .filter(|env| env.to_pitch != 0.0 || env.to_filter != 0.0)
+        .collect()
+}
+
+/// Returns a bitmask of the keys that act as keyswitches for a region.
+/// If no keyswitch range is set, only the `sw_last` key is a keyswitch.
+fn keyswitch_keys(region: &RegionParams) -> u128 {
+    if region.sw_lokey.is_some() || region.sw_hikey.is_some() {
+        let lo = region.sw_lokey.unwrap_or(0).min(127);
+        let hi = region.sw_hikey.unwrap_or(127).min(127);
+        (lo..=hi).fold(0, |keys, key| keys | 1 << key)
+    } else {
+        match region.sw_last {
+            Some(key) if key < 128 => 1 << key,
+            _ => 0,
+        }
+    }
+}
+
+/// Identifies the shared data of an SF2 sample, so regions using the same
+/// sample only convert it once.
+fn sample_data_ptr(sample: &Sf2SampleData) -> *const () {
+    match sample {
+        Sf2SampleData::I16(data) => Arc::as_ptr(data) as *const (),
+        Sf2SampleData::I24(data) => Arc::as_ptr(data) as *const (),
+        Sf2SampleData::F32(data) => Arc::as_ptr(data) as *const (),
+    }
+}
+
 /// Errors that can be generated when loading an SFZ soundfont.
 #[derive(Debug, Error)]
 pub enum LoadSfzError {
@@ -222,12 +496,41 @@ impl SampleSoundfont {
             .map(sample_cache_from_region_params)
             .collect();
 
+        // Looped samples are kept in memory even when streaming, as the
+        // loop would otherwise be read from the disk on every repetition
+        let looped_samples: HashSet<_> = regions
+            .iter()
+            .filter(|region| {
+                matches!(
+                    region.loop_mode,
+                    LoopMode::LoopContinuous | LoopMode::LoopSustain
+                ) && region.loop_start != region.loop_end
+            })
+            .map(sample_cache_from_region_params)
+            .collect();
+        let stream_cache = match options.streaming_preload {
+            Some(preload) => Some((
+                StreamCache::new(options.streaming_cache_dir.as_deref())?,
+                preload,
+            )),
+            None => None,
+        };
+
         // Parse and convert them in parallel
         let samples: Result<HashMap<_, _>, _> = unique_sample_params
             .into_par_iter()
             .map(|params| -> Result<(_, _), LoadSfzError> {
-                let sample = load_audio_file(&params.path, stream_params)?;
-                Ok((params, sample))
+                let (sample, sample_rate) = load_audio_file(&params.path, stream_params)?;
+                let sample = match &stream_cache {
+                    Some((cache, preload)) if !looped_samples.contains(&params) => {
+                        stream_sample(&sample, cache, *preload, options.sample_format)?
+                    }
+                    _ => sample
+                        .iter()
+                        .map(|s| SampleBuffer::new(s, options.sample_format))
+                        .collect(),
+                };
+                Ok((params, (sample, sample_rate)))
             })
             .collect();
         let samples = samples?;
@@ -245,7 +548,7 @@ impl SampleSoundfont {
                 unique_envelope_params.push((
                     envelope_descriptor,
                     Arc::new(
-                        envelope_descriptor.to_envelope_params(stream_params.sample_rate, options),
+                        envelope_descriptor.to_envelope_params(stream_params.sample_rate, &options),
                     ),
                 ));
             }
@@ -253,8 +556,11 @@ impl SampleSoundfont {
 
         // Generate region params
         let mut spawner_params_list = Vec::<Vec<Arc<SampleVoiceSpawnerParams>>>::new();
+        let mut release_spawner_params_list = Vec::<Vec<Arc<SampleVoiceSpawnerParams>>>::new();
+        let mut controller_spawner_params_list = Vec::new();
         for _ in 0..(128 * 128) {
             spawner_params_list.push(Vec::new());
+            release_spawner_params_list.push(Vec::new());
         }
 
         // Write region params
@@ -262,13 +568,50 @@ impl SampleSoundfont {
             let params = sample_cache_from_region_params(&region);
             let envelope = envelope_descriptor_from_region_params(&region.ampeg_envelope);
 
-            // Key value -1 is used for CC triggered regions which are not supported by XSynth
-            if region.keyrange.contains(&-1) {
+            // Regions triggered by controllers are played on their pitch keycenter
+            // with full velocity. Otherwise, key value -1 is used for regions that
+            // can't be triggered by notes.
+            let cc_triggers: Arc<[CcRange]> = region.cc_triggers.clone().into();
+            let (keyrange, velrange) = if !cc_triggers.is_empty() {
+                let key = region.pitch_keycenter.max(0);
+                (key..=key, 127..=127)
+            } else if region.keyrange.contains(&-1) {
                 continue;
-            }
+            } else {
+                (region.keyrange.clone(), region.velrange.clone())
+            };
+            let cc_conditions: Arc<[CcRange]> = region.cc_conditions.clone().into();
+            let modulators: Arc<[Modulator]> = region
+                .modulators
+                .iter()
+                .filter(|m| {
+                    options.use_effects
+                        || !matches!(
+                            m.destination,
+                            ModulatorDestination::FilterCutoff
+                                | ModulatorDestination::LfoToFilter(_)
+                        )
+                })
+                .copied()
+                .collect();
 
-            for key in region.keyrange.clone() {
-                for vel in region.velrange.clone() {
+            let mod_envelopes =
+                create_mod_envelopes(&region.mod_envelopes, stream_params.sample_rate, &options);
+            let lfos: Arc<[LfoParams]> = region
+                .lfos
+                .iter()
+                .map(|lfo| LfoParams {
+                    to_filter: if options.use_effects {
+                        lfo.to_filter
+                    } else {
+                        0.0
+                    },
+                    ..*lfo
+                })
+                .collect();
+
+            for key in keyrange.clone() {
+                for vel in velrange.clone() {
                     let index = key_vel_to_index(key as u8, vel);
                     let speed_mult =
                         get_speed_mult_from_keys(key as u8, region.pitch_keycenter as u8)
@@ -298,7 +641,8 @@ impl SampleSoundfont {
                     }
 
                     let pan = ((region.pan as f32 / 100.0) + 1.0) / 2.0;
-                    let volume = db_to_amp(region.volume as f32);
+                    let volume =
+                        db_to_amp(region.volume as f32) * region_gain(&region, key as u8, vel);
 
                     let sample_rate = samples[&params].1;
 
@@ -318,8 +662,9 @@ impl SampleSoundfont {
                             sample_rate,
                             stream_params.sample_rate,
                         ),
+                        // The SFZ loop end is the last sample of the loop
                         end: convert_sample_index(
-                            region.loop_end,
+                            region.loop_end.saturating_add(1),
                             sample_rate,
                             stream_params.sample_rate,
                         ),
@@ -342,9 +687,52 @@ impl SampleSoundfont {
                         interpolator: options.interpolator,
                         loop_params,
                         sample: region_samples,
+                        modulators: modulators.clone(),
+                        lfos: lfos.clone(),
+                        mod_envelopes: mod_envelopes.clone(),
+                        trigger: region.trigger,
+                        selection: SelectionParams {
+                            seq_length: region.seq_length.max(1) as u32,
+                            seq_position: (region.seq_position.max(1) as u32 - 1)
+                                % region.seq_length.max(1) as u32,
+                            lorand: region.lorand,
+                            hirand: region.hirand,
+                        },
+                        exclusive_class: ExclusiveClass {
+                            group: region.group,
+                            off_by: region.off_by,
+                            off_release: match region.off_mode {
+                                OffMode::Fast => ReleaseType::Kill,
+                                OffMode::Normal => ReleaseType::Standard,
+                                OffMode::Time => ReleaseType::FadeOut(region.off_time),
+                            },
+                        },
+                        keyswitch: KeyswitchParams {
+                            keys: keyswitch_keys(&region),
+                            last: region.sw_last,
+                            down: region.sw_down,
+                            up: region.sw_up,
+                            default: region.sw_default,
+                        },
+                        cc_conditions: cc_conditions.clone(),
                     });
 
-                    spawner_params_list[index].push(spawner_params.clone());
+                    if !cc_triggers.is_empty() {
+                        controller_spawner_params_list.push(ControllerSpawnerParams {
+                            key: key as u8,
+                            vel,
+                            triggers: cc_triggers.clone(),
+                            params: spawner_params,
+                        });
+                        continue;
+                    }
+
+                    match region.trigger {
+                        TriggerMode::Release | TriggerMode::ReleaseKey => {
+                            release_spawner_params_list[index].push(spawner_params.clone())
+                        }
+                        _ => spawner_params_list[index].push(spawner_params.clone()),
+                    }
                 }
             }
         }
@@ -354,6 +742,8 @@ impl SampleSoundfont {
                 bank: options.bank.unwrap_or(0),
                 preset: options.preset.unwrap_or(0),
                 spawner_params_list,
+                release_spawner_params_list,
+                controller_spawner_params_list,
             }],
             stream_params,
         })
@@ -377,6 +767,9 @@ impl SampleSoundfont {
 
         let mut instruments = Vec::new();
 
+        // The regions share the sample data, so each sample is only converted once
+        let mut converted_samples = HashMap::<*const (), SampleBuffer>::new();
+
         for preset in presets {
             if let Some(bank) = options.bank {
                 if bank != preset.bank as u8 {
@@ -397,20 +790,72 @@ impl SampleSoundfont {
             for region in preset.regions {
                 let envelope_params = Arc::new(
                     envelope_descriptor_from_region_params(&region.ampeg_envelope)
-                        .to_envelope_params(stream_params.sample_rate, options),
+                        .to_envelope_params(stream_params.sample_rate, &options),
                 );
+                let samples: Arc<[SampleBuffer]> = region
+                    .sample
+                    .iter()
+                    .map(|s| {
+                        converted_samples
+                            .entry(sample_data_ptr(s))
+                            .or_insert_with(|| SampleBuffer::from_sf2(s, options.sample_format))
+                            .clone()
+                    })
+                    .collect();
+                let mod_envelopes = create_mod_envelopes(
+                    &region.mod_envelopes,
+                    stream_params.sample_rate,
+                    &options,
+                );
+
+                // Modulators with static sources only need to be calculated once
+                // for each key and velocity, the rest are evaluated by the voice
+                let (static_modulators, dynamic_modulators): (Vec<_>, Vec<_>) = region
+                    .modulators
+                    .iter()
+                    .filter(|m| !is_channel_modulator(m))
+                    .filter(|m| {
+                        options.use_effects
+                            || !matches!(
+                                m.destination,
+                                ModulatorDestination::FilterCutoff
+                                    | ModulatorDestination::FilterResonance
+                                    | ModulatorDestination::LfoToFilter(_)
+                            )
+                    })
+                    .partition(|m| m.is_static());
+                let dynamic_modulators: Arc<[Modulator]> = dynamic_modulators.into();
+                let default_control = VoiceControlData::new_defaults();
 
                 for key in region.keyrange.clone() {
                     for vel in region.velrange.clone() {
                         let index = key_vel_to_index(key, vel);
+                        let modulate = |destination| {
+                            evaluate_modulators(
+                                &static_modulators,
+                                destination,
+                                key,
+                                vel,
+                                &default_control,
+                            )
+                        };
+
                         let speed_mult = get_speed_mult_from_keys(key, region.root_key)
                             * cents_factor(
-                                region.fine_tune as f32 + region.coarse_tune as f32 * 100.0,
+                                region.fine_tune as f32
+                                    + region.coarse_tune as f32 * 100.0
+                                    + modulate(ModulatorDestination::Pitch),
                             );
 
                         let mut cutoff = None;
                         if options.use_effects {
-                            if let Some(cutoff_t) = region.cutoff {
+                            let cents = modulate(ModulatorDestination::FilterCutoff);
+                            let cutoff_t = match region.cutoff {
+                                Some(cutoff_t) => Some(cutoff_t * cents_factor(cents)),
+                                None if cents < 0.0 => Some(SF2_MAX_CUTOFF * cents_factor(cents)),
+                                None => None,
+                            };
+                            if let Some(cutoff_t) = cutoff_t {
                                 if cutoff_t >= 1.0 {
                                     cutoff = Some(cutoff_t.clamp(
                                         1.0,
@@ -420,7 +865,31 @@ impl SampleSoundfont {
                             }
                         }
 
-                        let pan = ((region.pan as f32 / 500.0) + 1.0) / 2.0;
+                        let pan = region.pan as f32 + modulate(ModulatorDestination::Pan);
+                        let pan = (((pan / 500.0) + 1.0) / 2.0).clamp(0.0, 1.0);
+
+                        let volume = region.volume
+                            * db_to_amp(-modulate(ModulatorDestination::Attenuation) / 10.0);
+                        let resonance = region.resonance
+                            + modulate(ModulatorDestination::FilterResonance) / 10.0;
+
+                        let lfos = region
+                            .lfos
+                            .iter()
+                            .enumerate()
+                            .map(|(i, lfo)| LfoParams {
+                                to_pitch: lfo.to_pitch
+                                    + modulate(ModulatorDestination::LfoToPitch(i)),
+                                to_filter: if options.use_effects {
+                                    lfo.to_filter + modulate(ModulatorDestination::LfoToFilter(i))
+                                } else {
+                                    0.0
+                                },
+                                to_volume: lfo.to_volume
+                                    + modulate(ModulatorDestination::LfoToVolume(i)) / 10.0,
+                                ..*lfo
+                            })
+                            .collect();
 
                         let loop_params = LoopParams {
                             mode: if region.loop_start == region.loop_end {
@@ -433,7 +902,7 @@ impl SampleSoundfont {
                             end: region.loop_end,
                         };
 
-                        let mut region_samples = region.sample.clone();
+                        let mut region_samples = samples.clone();
                         if stream_params.channels == ChannelCount::Stereo
                             && region_samples.len() == 1
                         {
@@ -443,15 +912,27 @@ impl SampleSoundfont {
 
                         let spawner_params = Arc::new(SampleVoiceSpawnerParams {
                             pan,
-                            volume: region.volume,
+                            volume,
                             envelope: envelope_params.clone(),
                             speed_mult,
                             cutoff,
-                            resonance: db_to_amp(region.resonance) * Q_BUTTERWORTH_F32,
+                            resonance: db_to_amp(resonance) * Q_BUTTERWORTH_F32,
                             filter_type: FilterType::LowPass,
                             interpolator: options.interpolator,
                             loop_params,
                             sample: region_samples,
+                            modulators: dynamic_modulators.clone(),
+                            lfos,
+                            mod_envelopes: mod_envelopes.clone(),
+                            trigger: TriggerMode::Attack,
+                            selection: SelectionParams::ALWAYS,
+                            exclusive_class: ExclusiveClass {
+                                group: region.exclusive_class as u32,
+                                off_by: region.exclusive_class as u32,
+                                off_release: ReleaseType::Kill,
+                            },
+                            keyswitch: KeyswitchParams::NONE,
+                            cc_conditions: Arc::new([]),
                         });
 
                         spawner_params_list[index].push(spawner_params.clone());
@@ -463,6 +944,8 @@ impl SampleSoundfont {
                 bank: preset.bank as u8,
                 preset: preset.preset as u8,
                 spawner_params_list,
+                release_spawner_params_list: Vec::new(),
+                controller_spawner_params_list: Vec::new(),
             };
             instruments.push(new);
         }
@@ -480,17 +963,14 @@ impl std::fmt::Debug for SampleSoundfont {
     }
 }
 
-impl SoundfontBase for SampleSoundfont {
-    fn stream_params(&self) -> &'_ AudioStreamParams {
-        &self.stream_params
-    }
-
-    fn get_attack_voice_spawners_at(
+impl SampleSoundfont {
+    fn get_voice_spawners_at(
         &self,
         bank: u8,
         preset: u8,
         key: u8,
         vel: u8,
+        params_list: impl Fn(&SoundfontInstrument) -> &Vec<Vec<Arc<SampleVoiceSpawnerParams>>>,
     ) -> Vec<Box<dyn VoiceSpawner>> {
         use simdeez::*; // nuts
 
@@ -500,22 +980,22 @@ impl SoundfontBase for SampleSoundfont {
             fn get(
                 key: u8,
                 vel: u8,
-                sf: &SoundfontInstrument,
+                params_list: &[Vec<Arc<SampleVoiceSpawnerParams>>],
                 stream_params: &AudioStreamParams,
             ) -> Vec<Box<dyn VoiceSpawner>> {
-                if sf.spawner_params_list.is_empty() {
+                if params_list.is_empty() {
                     return Vec::new();
                 }
 
                 let index = key_vel_to_index(key, vel);
                 let mut vec = Vec::<Box<dyn VoiceSpawner>>::new();
-                for spawner in &sf.spawner_params_list[index] {
+                for spawner in &params_list[index] {
                     match stream_params.channels {
                         ChannelCount::Stereo => vec.push(Box::new(
-                            StereoSampledVoiceSpawner::<S>::new(spawner, vel, *stream_params),
+                            StereoSampledVoiceSpawner::<S>::new(spawner, key, vel, *stream_params),
                         )),
                         ChannelCount::Mono => vec.push(Box::new(
-                            MonoSampledVoiceSpawner::<S>::new(spawner, vel, *stream_params),
+                            MonoSampledVoiceSpawner::<S>::new(spawner, key, vel, *stream_params),
                         )),
                     }
                 }
@@ -523,28 +1003,92 @@ impl SoundfontBase for SampleSoundfont {
             }
         );
 
-        let empty = SoundfontInstrument {
-            bank: 0,
-            preset: 0,
-            spawner_params_list: Vec::new(),
-        };
-
-        let instrument = self
+        match self
             .instruments
             .iter()
             .find(|i| i.bank == bank && i.preset == preset)
-            .unwrap_or(&empty);
+        {
+            Some(instrument) => get(key, vel, params_list(instrument), self.stream_params()),
+            None => Vec::new(),
+        }
+    }
+}
 
-        get(key, vel, instrument, self.stream_params())
+impl SoundfontBase for SampleSoundfont {
+    fn stream_params(&self) -> &'_ AudioStreamParams {
+        &self.stream_params
+    }
+
+    fn get_attack_voice_spawners_at(
+        &self,
+        bank: u8,
+        preset: u8,
+        key: u8,
+        vel: u8,
+    ) -> Vec<Box<dyn VoiceSpawner>> {
+        self.get_voice_spawners_at(bank, preset, key, vel, |i| &i.spawner_params_list)
     }
 
     fn get_release_voice_spawners_at(
         &self,
-        _bank: u8,
-        _preset: u8,
-        _key: u8,
-        _vel: u8,
+        bank: u8,
+        preset: u8,
+        key: u8,
+        vel: u8,
     ) -> Vec<Box<dyn VoiceSpawner>> {
-        vec![]
+        self.get_voice_spawners_at(bank, preset, key, vel, |i| &i.release_spawner_params_list)
+    }
+
+    fn get_controller_voice_spawners(&self, bank: u8, preset: u8) -> Vec<ControllerVoiceSpawner> {
+        use simdeez::*; // nuts
+
+        use simdeez::prelude::*;
+
+        simd_runtime_generate!(
+            fn get(
+                params_list: &[ControllerSpawnerParams],
+                stream_params: &AudioStreamParams,
+            ) -> Vec<ControllerVoiceSpawner> {
+                let mut vec = Vec::new();
+                for params in params_list {
+                    let (key, vel) = (params.key, params.vel);
+                    let spawner: Box<dyn VoiceSpawner> = match stream_params.channels {
+                        ChannelCount::Stereo => Box::new(StereoSampledVoiceSpawner::<S>::new(
+                            &params.params,
+                            key,
+                            vel,
+                            *stream_params,
+                        )),
+                        ChannelCount::Mono => Box::new(MonoSampledVoiceSpawner::<S>::new(
+                            &params.params,
+                            key,
+                            vel,
+                            *stream_params,
+                        )),
+                    };
+                    vec.push(ControllerVoiceSpawner {
+                        key,
+                        triggers: params.triggers.clone(),
+                        spawner,
+                    });
+                }
+                vec
+            }
+        );
+
+        match self
+            .instruments
+            .iter()
+            .find(|i| i.bank == bank && i.preset == preset)
+        {
+            Some(instrument) => get(
+                &instrument.controller_spawner_params_list,
+                self.stream_params(),
+            ),
+            None => Vec::new(),
+        }
     }
 }
+
+#[cfg(test)]
+mod tests;
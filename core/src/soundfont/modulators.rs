@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use xsynth_soundfonts::modulator::{Modulator, ModulatorDestination, ModulatorSource};
+
+use super::utils::cents_factor;
+use crate::{helpers::db_to_amp, voice::VoiceControlData};
+
+/// The cutoff frequency (13500 cents) at and above which
+/// the SF2 spec considers the filter to be disabled.
+pub(super) const SF2_MAX_CUTOFF: f32 = 19912.127;
+
+/// Returns true if the modulator is already implemented by the controller
+/// handling of the channel, and should not be applied to the voice.
+pub(super) fn is_channel_modulator(modulator: &Modulator) -> bool {
+    matches!(
+        (modulator.source.source, modulator.destination),
+        (
+            ModulatorSource::Controller(7) | ModulatorSource::Controller(11),
+            ModulatorDestination::Attenuation
+        ) | (ModulatorSource::Controller(10), ModulatorDestination::Pan)
+            | (ModulatorSource::PitchWheel, ModulatorDestination::Pitch)
+    )
+}
+
+/// The modulation wheel pitch depth that the soundfont modulators are made for,
+/// in cents. The vibrato of the modulation wheel is scaled by the modulation
+/// depth range of the channel (RPN 5) relative to it.
+const DEFAULT_MODULATION_DEPTH_RANGE: f32 = 50.0;
+
+fn is_modulation_wheel_vibrato(modulator: &Modulator) -> bool {
+    matches!(modulator.destination, ModulatorDestination::LfoToPitch(_))
+        && (modulator.source.source == ModulatorSource::Controller(1)
+            || modulator.amount_source.source == ModulatorSource::Controller(1))
+}
+
+/// Returns the normalized (0-1) value of a modulator source.
+fn source_value(source: ModulatorSource, key: u8, vel: u8, control: &VoiceControlData) -> f32 {
+    match source {
+        ModulatorSource::NoController => 1.0,
+        ModulatorSource::Velocity => vel as f32 / 128.0,
+        ModulatorSource::Key => key as f32 / 128.0,
+        ModulatorSource::PolyPressure => control.poly_pressure as f32 / 128.0,
+        ModulatorSource::ChannelPressure => control.channel_pressure as f32 / 128.0,
+        ModulatorSource::PitchWheel => (control.pitch_wheel + 1.0) / 2.0,
+        ModulatorSource::PitchWheelSensitivity => control.pitch_wheel_sensitivity / 128.0,
+        ModulatorSource::Controller(cc) => {
+            control.cc.get(cc as usize).copied().unwrap_or(0) as f32 / 128.0
+        }
+    }
+}
+
+/// Sums the outputs of the modulators that affect the given destination.
+pub(super) fn evaluate_modulators(
+    modulators: &[Modulator],
+    destination: ModulatorDestination,
+    key: u8,
+    vel: u8,
+    control: &VoiceControlData,
+) -> f32 {
+    modulators
+        .iter()
+        .filter(|m| m.destination == destination)
+        .map(|m| {
+            let out = m.output(
+                source_value(m.source.source, key, vel, control),
+                source_value(m.amount_source.source, key, vel, control),
+            );
+            if is_modulation_wheel_vibrato(m) {
+                out * control.modulation_depth_range / DEFAULT_MODULATION_DEPTH_RANGE
+            } else {
+                out
+            }
+        })
+        .sum()
+}
+
+/// The modulators of a voice whose output can change while the voice is playing.
+#[derive(Clone)]
+pub(super) struct VoiceModulators {
+    modulators: Arc<[Modulator]>,
+    key: u8,
+    vel: u8,
+}
+
+impl VoiceModulators {
+    pub fn new(modulators: Arc<[Modulator]>, key: u8, vel: u8) -> Self {
+        Self {
+            modulators,
+            key,
+            vel,
+        }
+    }
+
+    pub fn affects(&self, destination: ModulatorDestination) -> bool {
+        self.modulators.iter().any(|m| m.destination == destination)
+    }
+
+    /// Returns true if the output of the modulators of the destination
+    /// can change while the voice is playing.
+    pub fn is_live(&self, destination: ModulatorDestination) -> bool {
+        self.modulators
+            .iter()
+            .any(|m| m.destination == destination && !m.is_static())
+    }
+
+    pub fn evaluate(&self, destination: ModulatorDestination, control: &VoiceControlData) -> f32 {
+        evaluate_modulators(&self.modulators, destination, self.key, self.vel, control)
+    }
+
+    /// Applies the filter modulators to the given cutoff frequency and resonance.
+    /// Returns `None` if the voice shouldn't be filtered.
+    pub fn modulate_filter(
+        &self,
+        cutoff: Option<f32>,
+        resonance: f32,
+        control: &VoiceControlData,
+        sample_rate: f32,
+    ) -> Option<(f32, f32)> {
+        let cents = self.evaluate(ModulatorDestination::FilterCutoff, control);
+        let cutoff = match cutoff {
+            Some(cutoff) => cutoff,
+            None if cents < 0.0 => SF2_MAX_CUTOFF,
+            None => return None,
+        };
+
+        let cutoff = (cutoff * cents_factor(cents)).clamp(1.0, sample_rate / 2.0 - 100.0);
+        let resonance = resonance
+            * db_to_amp(self.evaluate(ModulatorDestination::FilterResonance, control) / 10.0);
+
+        Some((cutoff, resonance))
+    }
+}
@@ -0,0 +1,367 @@
+use std::{fs, path::Path};
+
+use super::*;
+use crate::{
+    channel::{ChannelAudioEvent, ChannelConfigEvent, ChannelEvent, ControlEvent, VoiceChannel},
+    AudioPipe,
+};
+
+/// Writes a short 16 bit mono WAV file at 48 kHz.
+fn write_wav(path: &Path, samples: &[i16]) {
+    let data_len = samples.len() as u32 * 2;
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&48000u32.to_le_bytes());
+    wav.extend_from_slice(&96000u32.to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    fs::write(path, wav).unwrap();
+}
+
+/// Writes an SFZ file with a constant `sample.wav` next to it to a new
+/// temporary folder, and passes its path to `f`.
+fn with_sfz<T>(name: &str, sfz: &str, f: impl FnOnce(PathBuf) -> T) -> T {
+    let dir = std::env::temp_dir().join(format!("xsynth-soundfont-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    write_wav(&dir.join("sample.wav"), &[16384; 4800]);
+    fs::write(dir.join("test.sfz"), sfz).unwrap();
+
+    let result = f(dir.join("test.sfz"));
+    fs::remove_dir_all(&dir).unwrap();
+    result
+}
+
+/// Loads an SFZ file at 48 kHz mono.
+fn load_sfz(name: &str, sfz: &str) -> SampleSoundfont {
+    with_sfz(name, sfz, |path| {
+        SampleSoundfont::new_sfz(
+            path,
+            AudioStreamParams::new(48000, ChannelCount::Mono),
+            Default::default(),
+        )
+        .unwrap()
+    })
+}
+
+/// Parses an SFZ file with a single region.
+fn parse_region(name: &str, opcodes: &str) -> RegionParams {
+    let sfz = format!("<region> sample=sample.wav {opcodes}");
+    with_sfz(name, &sfz, |path| {
+        xsynth_soundfonts::sfz::parse_soundfont(path).unwrap()
+    })
+    .remove(0)
+}
+
+/// Creates a channel that plays the soundfont.
+fn channel(soundfont: SampleSoundfont) -> VoiceChannel {
+    let stream_params = *soundfont.stream_params();
+    let mut channel = VoiceChannel::new(Default::default(), stream_params, None);
+    channel.process_event(ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(
+        vec![Arc::new(soundfont)],
+    )));
+    channel
+}
+
+/// Sends the event to the channel and returns its voice count after
+/// rendering a few samples.
+fn voice_count_after(channel: &mut VoiceChannel, event: ChannelAudioEvent) -> u64 {
+    channel.process_event(ChannelEvent::Audio(event));
+    channel.read_samples(&mut [0.0; 256]);
+    channel.get_channel_stats().voice_count()
+}
+
+fn triggers(spawners: &[Box<dyn VoiceSpawner>]) -> Vec<TriggerMode> {
+    spawners.iter().map(|s| s.trigger()).collect()
+}
+
+#[test]
+fn test_release_regions() {
+    let soundfont = load_sfz(
+        "release",
+        "<region> sample=sample.wav key=60
+        <region> sample=sample.wav key=60 trigger=release
+        <region> sample=sample.wav key=60 trigger=release_key
+        <region> sample=sample.wav key=60 trigger=first
+        <region> sample=sample.wav key=60 trigger=legato
+        <region> sample=sample.wav key=62 trigger=release",
+    );
+
+    // The first and legato regions are played on note on events
+    let attack = soundfont.get_attack_voice_spawners_at(0, 0, 60, 100);
+    assert_eq!(
+        triggers(&attack),
+        [TriggerMode::Attack, TriggerMode::First, TriggerMode::Legato]
+    );
+
+    let release = soundfont.get_release_voice_spawners_at(0, 0, 60, 100);
+    assert_eq!(
+        triggers(&release),
+        [TriggerMode::Release, TriggerMode::ReleaseKey]
+    );
+
+    // A key with only release regions has no attack voices
+    assert!(soundfont
+        .get_attack_voice_spawners_at(0, 0, 62, 100)
+        .is_empty());
+    assert_eq!(
+        triggers(&soundfont.get_release_voice_spawners_at(0, 0, 62, 100)),
+        [TriggerMode::Release]
+    );
+    assert!(soundfont
+        .get_release_voice_spawners_at(0, 0, 64, 100)
+        .is_empty());
+}
+
+fn selection(sequence: u32, random: f32) -> VoiceSelection {
+    VoiceSelection {
+        sequence,
+        random,
+        keyswitch: KeyswitchState::default(),
+    }
+}
+
+/// Returns the indexes of the spawners that are selected.
+fn selected(spawners: &[Box<dyn VoiceSpawner>], selection: VoiceSelection) -> Vec<usize> {
+    (0..spawners.len())
+        .filter(|&i| spawners[i].is_selected(selection))
+        .collect()
+}
+
+#[test]
+fn test_round_robin() {
+    let soundfont = load_sfz(
+        "round-robin",
+        "<group> key=60 seq_length=3
+        <region> sample=sample.wav seq_position=1
+        <region> sample=sample.wav seq_position=2
+        <region> sample=sample.wav seq_position=3",
+    );
+    let spawners = soundfont.get_attack_voice_spawners_at(0, 0, 60, 100);
+
+    // Each note plays the next region of the sequence
+    for sequence in 0..6 {
+        assert_eq!(
+            selected(&spawners, selection(sequence, 0.5)),
+            [sequence as usize % 3]
+        );
+    }
+}
+
+#[test]
+fn test_random_selection() {
+    let soundfont = load_sfz(
+        "random",
+        "<group> key=60
+        <region> sample=sample.wav hirand=0.5
+        <region> sample=sample.wav lorand=0.5
+        <region> sample=sample.wav",
+    );
+    let spawners = soundfont.get_attack_voice_spawners_at(0, 0, 60, 100);
+
+    // The ranges include their low value, and a high value of 1 includes
+    // every value up to it
+    assert_eq!(selected(&spawners, selection(0, 0.0)), [0, 2]);
+    assert_eq!(selected(&spawners, selection(0, 0.49)), [0, 2]);
+    assert_eq!(selected(&spawners, selection(0, 0.5)), [1, 2]);
+    assert_eq!(selected(&spawners, selection(0, 0.9999)), [1, 2]);
+}
+
+#[test]
+fn test_exclusive_class() {
+    let soundfont = load_sfz(
+        "exclusive-class",
+        "<region> sample=sample.wav key=60 group=1 off_by=2
+        <region> sample=sample.wav key=61 group=1 off_by=2 off_mode=normal
+        <region> sample=sample.wav key=62 group=1 off_by=2 off_mode=time off_time=0.5
+        <region> sample=sample.wav key=63",
+    );
+    let class = |key| soundfont.get_attack_voice_spawners_at(0, 0, key, 100)[0].exclusive_class();
+
+    assert!((class(60).group, class(60).off_by) == (1, 2));
+    assert!(class(60).off_release == ReleaseType::Kill);
+    assert!(class(61).off_release == ReleaseType::Standard);
+    assert!(class(62).off_release == ReleaseType::FadeOut(0.5));
+    assert!(class(63) == ExclusiveClass::NONE);
+}
+
+#[test]
+fn test_exclusive_class_choke() {
+    let soundfont = load_sfz(
+        "exclusive-class-choke",
+        "<region> sample=sample.wav key=60 group=1 off_by=2
+        <region> sample=sample.wav key=62 group=2",
+    );
+    let mut channel = channel(soundfont);
+    let mut note_on =
+        |key| voice_count_after(&mut channel, ChannelAudioEvent::NoteOn { key, vel: 100 });
+
+    // A voice doesn't turn off its own group
+    assert_eq!(note_on(60), 1);
+    assert_eq!(note_on(60), 2);
+
+    // The open voices are killed by the closed one
+    assert_eq!(note_on(62), 1);
+}
+
+fn keyswitch(last: Option<u8>, held: &[u8]) -> KeyswitchState {
+    KeyswitchState {
+        last,
+        held: held.iter().fold(0, |held, key| held | 1 << key),
+    }
+}
+
+#[test]
+fn test_keyswitch() {
+    let soundfont = load_sfz(
+        "keyswitch",
+        "<region> sample=sample.wav key=62 sw_last=30
+        <region> sample=sample.wav key=62 sw_down=26 sw_up=27
+        <group> key=60 sw_lokey=24 sw_hikey=25 sw_default=24
+        <region> sample=sample.wav sw_last=24
+        <region> sample=sample.wav sw_last=25",
+    );
+    let spawners = soundfont.get_attack_voice_spawners_at(0, 0, 60, 100);
+    let matching = |state| {
+        (0..spawners.len())
+            .filter(|&i| spawners[i].matches_keyswitch(state))
+            .collect::<Vec<_>>()
+    };
+
+    // The keyswitch range takes priority over the keys of sw_last
+    assert!(spawners.iter().all(|s| s.keyswitch_keys() == 0b11 << 24));
+
+    // The default articulation is used until a keyswitch is pressed
+    assert_eq!(matching(keyswitch(None, &[])), [0]);
+    assert_eq!(matching(keyswitch(Some(25), &[])), [1]);
+    assert_eq!(matching(keyswitch(Some(24), &[25])), [0]);
+
+    let spawners = soundfont.get_attack_voice_spawners_at(0, 0, 62, 100);
+    assert_eq!(spawners[0].keyswitch_keys(), 1 << 30);
+    assert!(!spawners[0].matches_keyswitch(keyswitch(None, &[])));
+    assert!(spawners[0].matches_keyswitch(keyswitch(Some(30), &[])));
+
+    // sw_down and sw_up only look at the held keys
+    assert_eq!(spawners[1].keyswitch_keys(), 0);
+    assert!(!spawners[1].matches_keyswitch(keyswitch(None, &[])));
+    assert!(spawners[1].matches_keyswitch(keyswitch(None, &[26])));
+    assert!(!spawners[1].matches_keyswitch(keyswitch(None, &[26, 27])));
+}
+
+#[test]
+fn test_cc_conditions() {
+    let soundfont = load_sfz(
+        "cc-conditions",
+        "<region> sample=sample.wav key=60 locc1=64
+        <region> sample=sample.wav key=60 hicc1=63",
+    );
+    let spawners = soundfont.get_attack_voice_spawners_at(0, 0, 60, 100);
+    let mut cc = [0; 128];
+    assert!(!spawners[0].matches_controllers(&cc));
+    assert!(spawners[1].matches_controllers(&cc));
+    cc[1] = 64;
+    assert!(spawners[0].matches_controllers(&cc));
+    assert!(!spawners[1].matches_controllers(&cc));
+
+    // Only the region that matches the modulation wheel plays
+    let mut channel = channel(soundfont);
+    let cc = |value| ChannelAudioEvent::Control(ControlEvent::Raw(1, value));
+    assert_eq!(voice_count_after(&mut channel, cc(64)), 0);
+    let note_on = ChannelAudioEvent::NoteOn { key: 60, vel: 100 };
+    assert_eq!(voice_count_after(&mut channel, note_on), 1);
+}
+
+#[test]
+fn test_cc_triggers() {
+    let soundfont = load_sfz(
+        "cc-triggers",
+        "<region> sample=sample.wav key=60
+        <region> sample=sample.wav pitch_keycenter=30 on_locc20=64 on_hicc20=127",
+    );
+
+    // Regions with controller triggers aren't played by notes
+    assert_eq!(
+        soundfont.get_attack_voice_spawners_at(0, 0, 60, 100).len(),
+        1
+    );
+    let spawners = soundfont.get_controller_voice_spawners(0, 0);
+    assert_eq!(spawners.len(), 1);
+    assert_eq!(spawners[0].key, 30);
+    assert_eq!(
+        *spawners[0].triggers,
+        [CcRange {
+            cc: 20,
+            lo: 64,
+            hi: 127
+        }]
+    );
+
+    // The voices start when the controller moves into the range
+    let mut channel = channel(soundfont);
+    let mut cc = |value| {
+        let event = ChannelAudioEvent::Control(ControlEvent::Raw(20, value));
+        voice_count_after(&mut channel, event)
+    };
+    assert_eq!(cc(63), 0);
+    assert_eq!(cc(64), 1);
+    assert_eq!(cc(127), 1);
+    assert_eq!(cc(0), 1);
+    assert_eq!(cc(100), 2);
+}
+
+#[test]
+fn test_region_velocity_gain() {
+    // The default velocity tracking follows the squared velocity
+    let region = parse_region("default-gain", "");
+    for vel in [0, 1, 64, 100, 127] {
+        let expected = (vel as f32 / 127.0).powi(2);
+        assert!((region_gain(&region, 60, vel) - expected).abs() < 1e-6);
+    }
+
+    let region = parse_region("velcurve", "amp_velcurve_64=0.5");
+    assert_eq!(region_gain(&region, 60, 32), 0.25);
+    assert_eq!(region_gain(&region, 60, 64), 0.5);
+
+    // Negative velocity tracking makes soft notes louder
+    let region = parse_region("negative-veltrack", "amp_veltrack=-100");
+    assert_eq!(region_gain(&region, 60, 0), 1.0);
+    assert_eq!(region_gain(&region, 60, 127), 0.0);
+    let region = parse_region("half-negative-veltrack", "amp_veltrack=-50");
+    assert_eq!(region_gain(&region, 60, 0), 1.0);
+    assert_eq!(region_gain(&region, 60, 127), 0.5);
+
+    let region = parse_region("no-veltrack", "amp_veltrack=0");
+    assert_eq!(region_gain(&region, 60, 1), 1.0);
+}
+
+#[test]
+fn test_region_crossfade_gain() {
+    let region = parse_region(
+        "crossfade",
+        "amp_veltrack=0 xfin_lovel=20 xfin_hivel=40 xfin_lokey=48 xfin_hikey=60 \
+        xfout_lokey=72 xfout_hikey=84 xf_keycurve=gain",
+    );
+    assert_eq!(region_gain(&region, 60, 20), 0.0);
+    assert!((region_gain(&region, 60, 30) - 0.5f32.sqrt()).abs() < 1e-6);
+    assert_eq!(region_gain(&region, 54, 127), 0.5);
+    assert_eq!(region_gain(&region, 66, 127), 1.0);
+    assert_eq!(region_gain(&region, 78, 127), 0.5);
+    assert!((region_gain(&region, 54, 30) - 0.5 * 0.5f32.sqrt()).abs() < 1e-6);
+
+    // Key tracking is in dB per key from the key center
+    let region = parse_region(
+        "keytrack",
+        "amp_veltrack=0 amp_keytrack=-6 amp_keycenter=60",
+    );
+    assert!((region_gain(&region, 61, 127) - db_to_amp(-6.0)).abs() < 1e-6);
+    assert!((region_gain(&region, 58, 127) - db_to_amp(12.0)).abs() < 1e-6);
+}
@@ -1,6 +1,9 @@
-use crate::{helpers::FREQS, voice::EnvelopeDescriptor};
+use crate::{
+    helpers::{db_to_amp, FREQS},
+    voice::EnvelopeDescriptor,
+};
 use std::path::PathBuf;
-use xsynth_soundfonts::sfz::{AmpegEnvelopeParams, RegionParams};
+use xsynth_soundfonts::sfz::{AmpegEnvelopeParams, CrossfadeCurve, RegionParams};
 
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub(super) struct SampleCache {
@@ -45,3 +48,161 @@ pub(super) fn envelope_descriptor_from_region_params(
         release: env.ampeg_release,
     }
 }
+
+/// Returns the gain of a crossfade at `value`. The gain rises from 0 to 1
+/// between `in_lo` and `in_hi`, and falls back to 0 between `out_lo` and `out_hi`.
+fn crossfade_gain(
+    value: u8,
+    (in_lo, in_hi): (u8, u8),
+    (out_lo, out_hi): (u8, u8),
+    curve: CrossfadeCurve,
+) -> f32 {
+    let fade_in = if value >= in_hi {
+        1.0
+    } else if value <= in_lo {
+        0.0
+    } else {
+        (value - in_lo) as f32 / (in_hi - in_lo) as f32
+    };
+    let fade_out = if value <= out_lo {
+        1.0
+    } else if value >= out_hi {
+        0.0
+    } else {
+        (out_hi - value) as f32 / (out_hi - out_lo) as f32
+    };
+
+    let gain = fade_in * fade_out;
+    match curve {
+        CrossfadeCurve::Gain => gain,
+        CrossfadeCurve::Power => gain.sqrt(),
+    }
+}
+
+/// Returns the amplitude of a velocity using the velocity curve of a region.
+/// The curve is interpolated between its points, defaulting to a quadratic
+/// curve if it has none.
+fn velocity_curve(curve: &[(u8, f32)], vel: u8) -> f32 {
+    if curve.is_empty() {
+        return (vel as f32 / 127.0).powi(2);
+    }
+
+    // The curve starts at 0 and ends at 1 unless its points override them
+    let mut prev = (0, 0.0);
+    for &point in curve.iter().chain(std::iter::once(&(127, 1.0))) {
+        if point.0 >= vel {
+            if point.0 == prev.0 {
+                return point.1;
+            }
+            let t = (vel - prev.0) as f32 / (point.0 - prev.0) as f32;
+            return prev.1 + (point.1 - prev.1) * t;
+        }
+        prev = point;
+    }
+    prev.1
+}
+
+/// Returns the amplitude of a region for a key and velocity, using its
+/// crossfades, velocity tracking and key tracking.
+pub(super) fn region_gain(region: &RegionParams, key: u8, vel: u8) -> f32 {
+    let vel_curve = velocity_curve(&region.amp_velcurve, vel);
+    let veltrack = region.amp_veltrack / 100.0;
+    let vel_gain = if veltrack >= 0.0 {
+        1.0 - veltrack + veltrack * vel_curve
+    } else {
+        1.0 + veltrack * vel_curve
+    };
+
+    let keytrack_db = (key as f32 - region.amp_keycenter as f32) * region.amp_keytrack;
+
+    let vel_xfade = crossfade_gain(
+        vel,
+        (region.xfin_lovel, region.xfin_hivel),
+        (region.xfout_lovel, region.xfout_hivel),
+        region.xf_velcurve,
+    );
+    let key_xfade = crossfade_gain(
+        key,
+        (region.xfin_lokey, region.xfin_hikey),
+        (region.xfout_lokey, region.xfout_hikey),
+        region.xf_keycurve,
+    );
+
+    vel_gain * db_to_amp(keytrack_db) * vel_xfade * key_xfade
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_velocity_curve() {
+        for vel in 0..=127 {
+            let expected = (vel as f32 / 127.0).powi(2);
+            assert!((velocity_curve(&[], vel) - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_velocity_curve_points() {
+        // The curve is interpolated from (0, 0) to the points and up to (127, 1)
+        let curve = [(64, 0.25)];
+        assert_eq!(velocity_curve(&curve, 0), 0.0);
+        assert_eq!(velocity_curve(&curve, 32), 0.125);
+        assert_eq!(velocity_curve(&curve, 64), 0.25);
+        assert!((velocity_curve(&curve, 96) - (0.25 + 0.75 * 32.0 / 63.0)).abs() < 1e-6);
+        assert_eq!(velocity_curve(&curve, 127), 1.0);
+
+        // The points can override the ends of the curve
+        let curve = [(0, 0.5), (127, 0.75)];
+        assert_eq!(velocity_curve(&curve, 0), 0.5);
+        assert!((velocity_curve(&curve, 127) - 0.75).abs() < 1e-6);
+        assert!(velocity_curve(&curve, 64) > 0.5 && velocity_curve(&curve, 64) < 0.75);
+    }
+
+    #[test]
+    fn test_crossfade_gain() {
+        let no_fade_out = (127, 127);
+        assert_eq!(
+            crossfade_gain(64, (0, 0), no_fade_out, CrossfadeCurve::Gain),
+            1.0
+        );
+        assert_eq!(
+            crossfade_gain(20, (20, 40), no_fade_out, CrossfadeCurve::Gain),
+            0.0
+        );
+        assert_eq!(
+            crossfade_gain(30, (20, 40), no_fade_out, CrossfadeCurve::Gain),
+            0.5
+        );
+        assert_eq!(
+            crossfade_gain(40, (20, 40), no_fade_out, CrossfadeCurve::Gain),
+            1.0
+        );
+        assert_eq!(
+            crossfade_gain(90, (0, 0), (80, 100), CrossfadeCurve::Gain),
+            0.5
+        );
+        assert_eq!(
+            crossfade_gain(100, (0, 0), (80, 100), CrossfadeCurve::Gain),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_crossfade_sums() {
+        // Two layers crossfading over the same range keep a constant gain
+        // or a constant power depending on the curve
+        for value in 20..=40 {
+            let fade_in = |curve| crossfade_gain(value, (20, 40), (127, 127), curve);
+            let fade_out = |curve| crossfade_gain(value, (0, 0), (20, 40), curve);
+
+            let gain = fade_in(CrossfadeCurve::Gain) + fade_out(CrossfadeCurve::Gain);
+            assert!((gain - 1.0).abs() < 1e-6);
+
+            let power =
+                fade_in(CrossfadeCurve::Power).powi(2) + fade_out(CrossfadeCurve::Power).powi(2);
+            assert!((power - 1.0).abs() < 1e-6);
+        }
+    }
+}
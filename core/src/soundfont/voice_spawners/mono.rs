@@ -4,35 +4,58 @@ use simdeez::Simd;
 
 use crate::{
     effects::BiQuadFilter,
+    helpers::db_to_amp,
+    soundfont::utils::cents_factor,
     voice::{
-        BufferSampler, SIMDMonoVoiceCutoff, SIMDSample, SIMDSampleGrabber, SIMDSampleMono,
-        SIMDVoiceGenerator,
+        BufferSampler, ModulatedCutoffParams, SIMDMonoVoiceCutoff, SIMDMonoVoiceModulatedCutoff,
+        SIMDSample, SIMDSampleGrabber, SIMDSampleMono, SIMDVoiceGenerator,
     },
     AudioStreamParams,
 };
 use crate::{
     voice::VoiceControlData,
     voice::{
-        BufferSamplers, EnvelopeParameters, SIMDConstant, SIMDLinearSampleGrabber, SIMDMonoVoice,
-        SIMDMonoVoiceSampler, SIMDNearestSampleGrabber, SIMDVoiceControl, SIMDVoiceEnvelope,
-        SampleReader, SampleReaderLoop, SampleReaderLoopSustain, SampleReaderNoLoop, Voice,
-        VoiceBase, VoiceCombineSIMD,
+        BufferSamplers, EnvelopeParameters, ExclusiveClass, SIMDConstant, SIMDHermiteSampleGrabber,
+        SIMDLagrangeSampleGrabber, SIMDLinearSampleGrabber, SIMDMonoVoice, SIMDMonoVoiceSampler,
+        SIMDNearestSampleGrabber, SIMDSincSampleGrabber, SIMDVoiceControl, SIMDVoiceEnvelope,
+        SIMDVoiceGlide, SIMDVoiceLfo, SIMDVoiceModEnvelopes, SampleBuffer, SampleReader,
+        SampleReaderLoop, SampleReaderLoopSustain, SampleReaderNoLoop, Voice, VoiceBase,
+        VoiceCombineSIMD,
     },
 };
 
-use xsynth_soundfonts::LoopMode;
+use xsynth_soundfonts::{
+    modulator::ModulatorDestination,
+    sfz::{CcRange, LfoParams},
+    FilterType, LoopMode,
+};
 
-use crate::soundfont::{Interpolator, LoopParams, SampleVoiceSpawnerParams, VoiceSpawner};
+use crate::soundfont::{
+    modulators::VoiceModulators, Interpolator, KeyswitchParams, KeyswitchState, LoopParams,
+    ModEnvelope, SampleVoiceSpawnerParams, SelectionParams, TriggerMode, VoiceSelection,
+    VoiceSpawner,
+};
 
 pub struct MonoSampledVoiceSpawner<S: 'static + Simd + Send + Sync> {
     speed_mult: f32,
     filter: Option<BiQuadFilter>,
+    cutoff: Option<f32>,
+    resonance: f32,
+    filter_type: FilterType,
     loop_params: LoopParams,
     amp: f32,
     volume_envelope_params: Arc<EnvelopeParameters>,
-    samples: Arc<[Arc<[f32]>]>,
+    samples: Arc<[SampleBuffer]>,
     interpolator: Interpolator,
     vel: u8,
+    modulators: VoiceModulators,
+    lfos: Arc<[LfoParams]>,
+    mod_envelopes: Arc<[ModEnvelope]>,
+    trigger: TriggerMode,
+    selection: SelectionParams,
+    exclusive_class: ExclusiveClass,
+    keyswitch: KeyswitchParams,
+    cc_conditions: Arc<[CcRange]>,
     stream_params: AudioStreamParams,
     _s: PhantomData<S>,
 }
@@ -40,11 +63,10 @@ pub struct MonoSampledVoiceSpawner<S: 'static + Simd + Send + Sync> {
 impl<S: Simd + Send + Sync> MonoSampledVoiceSpawner<S> {
     pub fn new(
         params: &SampleVoiceSpawnerParams,
+        key: u8,
         vel: u8,
         stream_params: AudioStreamParams,
     ) -> Self {
-        let amp = (vel as f32 / 127.0).powi(2) * params.volume;
-
         let filter = params.cutoff.map(|cutoff| {
             BiQuadFilter::new(
                 params.filter_type,
@@ -57,27 +79,36 @@ impl<S: Simd + Send + Sync> MonoSampledVoiceSpawner<S> {
         Self {
             speed_mult: params.speed_mult,
             filter,
+            cutoff: params.cutoff,
+            resonance: params.resonance,
+            filter_type: params.filter_type,
             loop_params: params.loop_params.clone(),
-            amp,
+            amp: params.volume,
             volume_envelope_params: params.envelope.clone(),
             samples: params.sample.clone(),
             interpolator: params.interpolator,
             vel,
+            modulators: VoiceModulators::new(params.modulators.clone(), key, vel),
+            lfos: params.lfos.clone(),
+            mod_envelopes: params.mod_envelopes.clone(),
+            trigger: params.trigger,
+            selection: params.selection,
+            exclusive_class: params.exclusive_class,
+            keyswitch: params.keyswitch,
+            cc_conditions: params.cc_conditions.clone(),
             stream_params,
             _s: PhantomData,
         }
     }
 
     fn begin_voice(&self, control: &VoiceControlData) -> Box<dyn Voice> {
-        // Currently there's only the f32 buffer samples, more could be added in the future.
-        #[allow(clippy::redundant_closure)]
-        self.make_sample_reader(control, |s| BufferSamplers::new_f32(s))
+        self.make_sample_reader(control, BufferSamplers::new)
     }
 
     fn make_sample_reader<BS: 'static + BufferSampler>(
         &self,
         control: &VoiceControlData,
-        make_bs: impl Fn(Arc<[f32]>) -> BS,
+        make_bs: impl Fn(SampleBuffer) -> BS,
     ) -> Box<dyn Voice> {
         match self.loop_params.mode {
             LoopMode::LoopContinuous => self.make_sample_grabber(control, move |s| {
@@ -95,7 +126,7 @@ impl<S: Simd + Send + Sync> MonoSampledVoiceSpawner<S> {
     fn make_sample_grabber<SR: 'static + SampleReader>(
         &self,
         control: &VoiceControlData,
-        make_bs: impl Fn(Arc<[f32]>) -> SR,
+        make_bs: impl Fn(SampleBuffer) -> SR,
     ) -> Box<dyn Voice> {
         match self.interpolator {
             Interpolator::Nearest => {
@@ -104,13 +135,22 @@ impl<S: Simd + Send + Sync> MonoSampledVoiceSpawner<S> {
             Interpolator::Linear => {
                 self.generate_sampler(control, |s| SIMDLinearSampleGrabber::new(make_bs(s)))
             }
+            Interpolator::Hermite => {
+                self.generate_sampler(control, |s| SIMDHermiteSampleGrabber::new(make_bs(s)))
+            }
+            Interpolator::Lagrange => {
+                self.generate_sampler(control, |s| SIMDLagrangeSampleGrabber::new(make_bs(s)))
+            }
+            Interpolator::Sinc(taps) => self.generate_sampler(control, |s| {
+                SIMDSincSampleGrabber::new(make_bs(s), taps as usize)
+            }),
         }
     }
 
     fn generate_sampler<SG: 'static + SIMDSampleGrabber<S>>(
         &self,
         control: &VoiceControlData,
-        make_sampler: impl Fn(Arc<[f32]>) -> SG,
+        make_sampler: impl Fn(SampleBuffer) -> SG,
     ) -> Box<dyn Voice> {
         let sample = make_sampler(self.samples[0].clone());
 
@@ -120,13 +160,29 @@ impl<S: Simd + Send + Sync> MonoSampledVoiceSpawner<S> {
         self.apply_voice_params(sampler, control)
     }
 
-    fn apply_velocity<Gen, Sample>(&self, gen: Gen) -> impl SIMDVoiceGenerator<S, Sample>
+    fn apply_velocity<Gen, Sample>(
+        &self,
+        gen: Gen,
+        control: &VoiceControlData,
+    ) -> impl SIMDVoiceGenerator<S, Sample>
     where
         Sample: SIMDSample<S>,
         SIMDSampleMono<S>: Mul<Sample, Output = Sample>,
         Gen: SIMDVoiceGenerator<S, Sample>,
     {
-        let amp = SIMDConstant::<S>::new(self.amp);
+        let amp = self.amp;
+        let modulators = self.modulators.clone();
+        let amp = SIMDVoiceControl::new(control, move |vc| {
+            amp * db_to_amp(-modulators.evaluate(ModulatorDestination::Attenuation, vc) / 10.0)
+        });
+        let lfo = self.create_lfo(
+            control,
+            |lfo| lfo.to_volume,
+            ModulatorDestination::LfoToVolume,
+            0.1,
+            db_to_amp,
+        );
+        let gen = VoiceCombineSIMD::mult(lfo, gen);
         let amp = VoiceCombineSIMD::mult(amp, gen);
         amp
     }
@@ -136,11 +192,123 @@ impl<S: Simd + Send + Sync> MonoSampledVoiceSpawner<S> {
         control: &VoiceControlData,
     ) -> impl SIMDVoiceGenerator<S, SIMDSampleMono<S>> {
         let pitch_fac = SIMDConstant::<S>::new(self.speed_mult);
-        let pitch_multiplier = SIMDVoiceControl::new(control, |vc| vc.voice_pitch_multiplier);
+        let modulators = self.modulators.clone();
+        let pitch_multiplier = SIMDVoiceControl::new(control, move |vc| {
+            vc.voice_pitch_multiplier
+                * cents_factor(modulators.evaluate(ModulatorDestination::Pitch, vc))
+        });
         let pitch_fac = VoiceCombineSIMD::mult(pitch_fac, pitch_multiplier);
+        let glide = SIMDVoiceGlide::new(control, self.stream_params.sample_rate as f32);
+        let pitch_fac = VoiceCombineSIMD::mult(pitch_fac, glide);
+        let lfo = self.create_vibrato(control);
+        let pitch_fac = VoiceCombineSIMD::mult(pitch_fac, lfo);
+        let envelope = self.create_mod_envelope(|env| env.to_pitch);
+        let pitch_fac = VoiceCombineSIMD::mult(pitch_fac, envelope);
         pitch_fac
     }
 
+    /// Creates a generator that outputs the cents factor of the sum of the
+    /// modulation envelopes, using `depth` as the depth of each envelope.
+    fn create_mod_envelope(
+        &self,
+        depth: fn(&ModEnvelope) -> f32,
+    ) -> impl SIMDVoiceGenerator<S, SIMDSampleMono<S>> {
+        let envelopes = self
+            .mod_envelopes
+            .iter()
+            .filter(|env| depth(env) != 0.0)
+            .map(|env| (env.envelope, depth(env)));
+
+        SIMDVoiceModEnvelopes::new(
+            envelopes,
+            self.stream_params.sample_rate as f32,
+            cents_factor,
+        )
+    }
+
+    /// Returns true if any of the LFOs has a non-zero depth for the given
+    /// parameter, or has its depth changed by a modulator.
+    fn is_lfo_used(
+        &self,
+        depth: fn(&LfoParams) -> f32,
+        destination: fn(usize) -> ModulatorDestination,
+    ) -> bool {
+        self.lfos
+            .iter()
+            .enumerate()
+            .any(|(i, lfo)| depth(lfo) != 0.0 || self.modulators.affects(destination(i)))
+    }
+
+    /// Creates a generator that outputs the sum of the LFOs of the voice, using
+    /// `depth` as the depth of each LFO, converted with `convert`.
+    ///
+    /// The outputs of the modulators of `destination` are multiplied by
+    /// `modulator_scale` and added to the depth.
+    fn create_lfo<C>(
+        &self,
+        control: &VoiceControlData,
+        depth: fn(&LfoParams) -> f32,
+        destination: fn(usize) -> ModulatorDestination,
+        modulator_scale: f32,
+        convert: C,
+    ) -> impl SIMDVoiceGenerator<S, SIMDSampleMono<S>>
+    where
+        C: 'static + Fn(f32) -> f32 + Send + Sync,
+    {
+        let lfos: &[LfoParams] = if self.is_lfo_used(depth, destination) {
+            &self.lfos
+        } else {
+            &[]
+        };
+
+        let params = self.lfos.clone();
+        let modulators = self.modulators.clone();
+        SIMDVoiceLfo::new(
+            lfos,
+            self.stream_params.sample_rate as f32,
+            control,
+            move |i, vc| {
+                depth(&params[i]) + modulators.evaluate(destination(i), vc) * modulator_scale
+            },
+            convert,
+        )
+    }
+
+    /// Creates the generator of the pitch LFOs, with the rate, depth and
+    /// delay changed by the vibrato controls of the channel.
+    fn create_vibrato(
+        &self,
+        control: &VoiceControlData,
+    ) -> impl SIMDVoiceGenerator<S, SIMDSampleMono<S>> {
+        let vibrato = control.vibrato;
+        let lfos: Vec<LfoParams> =
+            if self.is_lfo_used(|lfo| lfo.to_pitch, ModulatorDestination::LfoToPitch) {
+                self.lfos
+                    .iter()
+                    .map(|lfo| LfoParams {
+                        freq: lfo.freq * vibrato.rate,
+                        delay: lfo.delay * vibrato.delay,
+                        ..*lfo
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+
+        let params = self.lfos.clone();
+        let modulators = self.modulators.clone();
+        SIMDVoiceLfo::new(
+            &lfos,
+            self.stream_params.sample_rate as f32,
+            control,
+            move |i, vc| {
+                (params[i].to_pitch + modulators.evaluate(ModulatorDestination::LfoToPitch(i), vc))
+                    * vc.vibrato.depth
+            },
+            cents_factor,
+        )
+    }
+
     fn apply_envelope<Gen, Sample>(
         &self,
         gen: Gen,
@@ -170,12 +338,41 @@ impl<S: Simd + Send + Sync> MonoSampledVoiceSpawner<S> {
         amp
     }
 
+    fn is_filter_modulated(&self) -> bool {
+        self.modulators.affects(ModulatorDestination::FilterCutoff)
+            || self
+                .modulators
+                .affects(ModulatorDestination::FilterResonance)
+    }
+
+    /// Returns the cutoff frequency and resonance of the voice's filter
+    fn filter_params(&self, control: &VoiceControlData) -> Option<(f32, f32)> {
+        if !self.is_filter_modulated() {
+            return self.cutoff.map(|cutoff| (cutoff, self.resonance));
+        }
+
+        let sample_rate = self.stream_params.sample_rate as f32;
+        self.modulators
+            .modulate_filter(self.cutoff, self.resonance, control, sample_rate)
+    }
+
+    fn create_filter(&self, control: &VoiceControlData) -> Option<BiQuadFilter> {
+        if !self.is_filter_modulated() {
+            return self.filter.clone();
+        }
+
+        let sample_rate = self.stream_params.sample_rate as f32;
+        self.filter_params(control).map(|(cutoff, resonance)| {
+            BiQuadFilter::new(self.filter_type, cutoff, sample_rate, Some(resonance))
+        })
+    }
+
     fn convert_to_voice<Gen>(&self, gen: Gen) -> Box<dyn Voice>
     where
         Gen: 'static + SIMDVoiceGenerator<S, SIMDSampleMono<S>>,
     {
         let flattened = SIMDMonoVoice::new(gen);
-        let base = VoiceBase::new(self.vel, flattened);
+        let base = VoiceBase::new(self.vel, self.exclusive_class, flattened);
 
         Box::new(base)
     }
@@ -184,17 +381,56 @@ impl<S: Simd + Send + Sync> MonoSampledVoiceSpawner<S> {
     where
         Gen: 'static + SIMDVoiceGenerator<S, SIMDSampleMono<S>>,
     {
-        let gen = self.apply_velocity(gen);
+        let gen = self.apply_velocity(gen, control);
         let gen = self.apply_envelope(gen, control);
 
-        self.apply_cutoff_effect(gen)
+        self.apply_cutoff_effect(control, gen)
     }
 
     fn apply_cutoff_effect(
         &self,
+        control: &VoiceControlData,
         gen: impl 'static + SIMDVoiceGenerator<S, SIMDSampleMono<S>>,
     ) -> Box<dyn Voice> {
-        if let Some(filter) = &self.filter {
+        let cutoff_modulated = self
+            .is_lfo_used(|lfo| lfo.to_filter, ModulatorDestination::LfoToFilter)
+            || self.mod_envelopes.iter().any(|env| env.to_filter != 0.0)
+            || self.modulators.is_live(ModulatorDestination::FilterCutoff);
+
+        if cutoff_modulated {
+            if let Some((cutoff, resonance)) = self.filter_params(control) {
+                let lfo = self.create_lfo(
+                    control,
+                    |lfo| lfo.to_filter,
+                    ModulatorDestination::LfoToFilter,
+                    1.0,
+                    cents_factor,
+                );
+                let envelope = self.create_mod_envelope(|env| env.to_filter);
+
+                // The cutoff already includes the modulators at the start of
+                // the voice, only their changes since then are applied here
+                let modulators = self.modulators.clone();
+                let start_cents = modulators.evaluate(ModulatorDestination::FilterCutoff, control);
+                let controls = SIMDVoiceControl::new(control, move |vc| {
+                    let cents = modulators.evaluate(ModulatorDestination::FilterCutoff, vc);
+                    cents_factor(cents - start_cents)
+                });
+
+                let modulator =
+                    VoiceCombineSIMD::mult(VoiceCombineSIMD::mult(lfo, envelope), controls);
+                let params = ModulatedCutoffParams {
+                    filter_type: self.filter_type,
+                    cutoff,
+                    resonance,
+                    sample_rate: self.stream_params.sample_rate as f32,
+                };
+                let gen = SIMDMonoVoiceModulatedCutoff::new(gen, modulator, params);
+                return self.convert_to_voice(gen);
+            }
+        }
+
+        if let Some(filter) = &self.create_filter(control) {
             let gen = SIMDMonoVoiceCutoff::new(gen, filter);
             self.convert_to_voice(gen)
         } else {
@@ -207,4 +443,113 @@ impl<S: 'static + Sync + Send + Simd> VoiceSpawner for MonoSampledVoiceSpawner<S
     fn spawn_voice(&self, control: &VoiceControlData) -> Box<dyn Voice> {
         self.begin_voice(control)
     }
+
+    fn trigger(&self) -> TriggerMode {
+        self.trigger
+    }
+
+    fn is_selected(&self, selection: VoiceSelection) -> bool {
+        self.selection.is_selected(selection)
+    }
+
+    fn exclusive_class(&self) -> ExclusiveClass {
+        self.exclusive_class
+    }
+
+    fn keyswitch_keys(&self) -> u128 {
+        self.keyswitch.keys
+    }
+
+    fn matches_keyswitch(&self, keyswitch: KeyswitchState) -> bool {
+        self.keyswitch.matches(keyswitch)
+    }
+
+    fn matches_controllers(&self, cc: &[u8; 128]) -> bool {
+        CcRange::all_match(&self.cc_conditions, cc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use simdeez::scalar::Scalar;
+    use xsynth_soundfonts::modulator::{Modulator, ModulatorInput, ModulatorSource};
+
+    use super::*;
+    use crate::{voice::EnvelopeDescriptor, ChannelCount};
+
+    fn render_peak(voice: &mut Box<dyn Voice>) -> f32 {
+        let mut buffer = vec![0.0; 4800];
+        voice.render_to(&mut buffer);
+
+        // Skips the response of the filter to the start of the block
+        buffer[2400..].iter().fold(0.0, |peak, s| s.abs().max(peak))
+    }
+
+    #[test]
+    fn test_pressure_modulates_cutoff_while_held() {
+        let stream_params = AudioStreamParams::new(48000, ChannelCount::Mono);
+
+        // A 12 kHz tone, far above the cutoff
+        let sample: Arc<[f32]> = (0..48000).map(|i| [1.0, 0.0, -1.0, 0.0][i % 4]).collect();
+
+        let envelope = EnvelopeDescriptor {
+            start_percent: 1.0,
+            delay: 0.0,
+            attack: 0.0,
+            hold: 0.0,
+            decay: 0.0,
+            sustain_percent: 1.0,
+            release: 0.1,
+        }
+        .to_envelope_params(stream_params.sample_rate, &Default::default());
+
+        let params = SampleVoiceSpawnerParams {
+            volume: 1.0,
+            pan: 0.0,
+            speed_mult: 1.0,
+            cutoff: Some(500.0),
+            resonance: 0.707,
+            filter_type: FilterType::LowPass,
+            loop_params: LoopParams {
+                mode: LoopMode::NoLoop,
+                offset: 0,
+                start: 0,
+                end: 0,
+            },
+            envelope: Arc::new(envelope),
+            sample: Arc::new([SampleBuffer::F32(sample)]),
+            interpolator: Interpolator::Nearest,
+            modulators: Arc::new([Modulator {
+                source: ModulatorInput::linear(ModulatorSource::ChannelPressure),
+                amount_source: ModulatorInput::NONE,
+                destination: ModulatorDestination::FilterCutoff,
+                amount: 6000.0,
+                absolute: false,
+            }]),
+            lfos: Arc::new([]),
+            mod_envelopes: Arc::new([]),
+            trigger: TriggerMode::Attack,
+            selection: SelectionParams::ALWAYS,
+            exclusive_class: ExclusiveClass::NONE,
+            keyswitch: KeyswitchParams::NONE,
+            cc_conditions: Arc::new([]),
+        };
+
+        let spawner = MonoSampledVoiceSpawner::<Scalar>::new(&params, 60, 127, stream_params);
+        let mut control = VoiceControlData::new_defaults();
+        let mut voice = spawner.spawn_voice(&control);
+
+        let filtered = render_peak(&mut voice);
+        assert!(filtered < 0.05, "{filtered}");
+
+        control.channel_pressure = 127;
+        voice.process_controls(&control);
+        let opened = render_peak(&mut voice);
+        assert!(opened > 0.5, "{opened}");
+
+        control.channel_pressure = 0;
+        voice.process_controls(&control);
+        let closed = render_peak(&mut voice);
+        assert!(closed < 0.05, "{closed}");
+    }
 }
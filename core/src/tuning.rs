@@ -0,0 +1,458 @@
+use std::{fs, io, path::Path};
+
+use thiserror::Error;
+
+/// The frequency of A4 (key 69) in Hz.
+const A4_FREQUENCY: f64 = 440.0;
+
+/// The 12-TET frequency of middle C (key 60) in Hz.
+const C4_FREQUENCY: f64 = 261.625_565_300_598_6;
+
+/// Errors that can be generated when loading a Scala tuning.
+#[derive(Debug, Error)]
+pub enum ScalaError {
+    #[error("IO Error")]
+    IOError(#[from] io::Error),
+
+    #[error("Invalid scale file: {0}")]
+    InvalidScale(String),
+
+    #[error("Invalid keyboard mapping file: {0}")]
+    InvalidMapping(String),
+}
+
+/// A map from the 128 MIDI keys to the pitches they play.
+///
+/// Pitches are in semitones on the MIDI key scale, where 69.0 is 440Hz.
+/// The default 12-TET tuning maps every key to its own number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+    pitches: [f32; 128],
+}
+
+impl Tuning {
+    /// Creates the standard 12-tone equal temperament tuning.
+    pub fn equal_temperament() -> Self {
+        Tuning {
+            pitches: std::array::from_fn(|key| key as f32),
+        }
+    }
+
+    /// Creates a tuning from the frequency of each key in Hz.
+    pub fn from_frequencies(frequencies: &[f32; 128]) -> Self {
+        Tuning {
+            pitches: frequencies.map(frequency_to_pitch),
+        }
+    }
+
+    /// Returns the pitch of a key in semitones.
+    pub fn pitch(&self, key: u8) -> f32 {
+        self.pitches[key as usize & 0x7F]
+    }
+
+    /// Sets the pitch of a key in semitones.
+    pub fn set_pitch(&mut self, key: u8, pitch: f32) {
+        self.pitches[key as usize & 0x7F] = pitch;
+    }
+
+    /// Returns the frequency of a key in Hz.
+    pub fn frequency(&self, key: u8) -> f32 {
+        A4_FREQUENCY as f32 * 2.0f32.powf((self.pitch(key) - 69.0) / 12.0)
+    }
+
+    /// Returns how far the pitch of a key is from its 12-TET pitch,
+    /// in semitones.
+    pub fn offset(&self, key: u8) -> f32 {
+        self.pitch(key) - (key & 0x7F) as f32
+    }
+
+    /// Creates a tuning from the contents of a Scala scale (`.scl`) file
+    /// and an optional keyboard mapping (`.kbm`) file.
+    ///
+    /// Without a keyboard mapping, the first degree of the scale is mapped
+    /// to middle C (key 60) at its 12-TET frequency and the following keys
+    /// play the next degrees of the scale.
+    pub fn from_scala(scl: &str, kbm: Option<&str>) -> Result<Self, ScalaError> {
+        let scale = Scale::parse(scl)?;
+        let mapping = match kbm {
+            Some(kbm) => KeyboardMapping::parse(kbm)?,
+            None => KeyboardMapping::default(),
+        };
+
+        let reference_cents = mapping
+            .cents(mapping.reference_key, &scale)
+            .ok_or_else(|| ScalaError::InvalidMapping("the reference key is not mapped".into()))?;
+        let reference_pitch = 69.0 + 12.0 * (mapping.reference_frequency / A4_FREQUENCY).log2();
+
+        let mut tuning = Tuning::equal_temperament();
+        for key in mapping.first_key.max(0)..=mapping.last_key.min(127) {
+            // Unmapped keys keep their 12-TET pitch
+            if let Some(cents) = mapping.cents(key, &scale) {
+                let pitch = reference_pitch + (cents - reference_cents) / 100.0;
+                tuning.set_pitch(key as u8, pitch as f32);
+            }
+        }
+
+        Ok(tuning)
+    }
+
+    /// Loads a tuning from a Scala scale (`.scl`) file and an optional
+    /// keyboard mapping (`.kbm`) file.
+    /// See `from_scala` for more information.
+    pub fn load_scala(scl: impl AsRef<Path>, kbm: Option<&Path>) -> Result<Self, ScalaError> {
+        let scl = fs::read_to_string(scl)?;
+        let kbm = kbm.map(fs::read_to_string).transpose()?;
+        Self::from_scala(&scl, kbm.as_deref())
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::equal_temperament()
+    }
+}
+
+fn frequency_to_pitch(frequency: f32) -> f32 {
+    69.0 + 12.0 * (frequency / A4_FREQUENCY as f32).log2()
+}
+
+/// Returns the lines of a Scala file, without the comments.
+fn scala_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines().filter(|line| !line.starts_with('!'))
+}
+
+/// The degrees of a Scala scale, in cents. The first degree (0 cents) is
+/// implied, the last one is the period of the scale.
+struct Scale {
+    degrees: Vec<f64>,
+}
+
+impl Scale {
+    fn parse(text: &str) -> Result<Self, ScalaError> {
+        let error = |msg: &str| ScalaError::InvalidScale(msg.into());
+
+        // The first line is the description of the scale and can be empty
+        let mut lines = scala_lines(text).skip(1);
+
+        let count = lines
+            .next()
+            .and_then(|line| line.split_whitespace().next())
+            .and_then(|count| count.parse::<usize>().ok())
+            .ok_or_else(|| error("missing the note count"))?;
+        if count == 0 {
+            return Err(error("the scale has no notes"));
+        }
+
+        let degrees = lines
+            .take(count)
+            .map(|line| {
+                let value = line.split_whitespace().next().unwrap_or("");
+                parse_scala_pitch(value).ok_or_else(|| error(&format!("invalid pitch {value:?}")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if degrees.len() != count {
+            return Err(error("the scale has less notes than its note count"));
+        }
+
+        Ok(Scale { degrees })
+    }
+
+    /// Returns the pitch of a scale degree in cents, relative to the
+    /// first degree.
+    fn cents(&self, degree: i32) -> f64 {
+        let len = self.degrees.len() as i32;
+        let period = self.degrees[self.degrees.len() - 1];
+        let octave = degree.div_euclid(len) as f64;
+        match degree.rem_euclid(len) {
+            0 => octave * period,
+            step => octave * period + self.degrees[step as usize - 1],
+        }
+    }
+}
+
+/// Parses a Scala pitch, which is in cents if it contains a period and
+/// a ratio or an integer otherwise.
+fn parse_scala_pitch(value: &str) -> Option<f64> {
+    if value.contains('.') {
+        return value.parse().ok();
+    }
+
+    let (numerator, denominator) = value.split_once('/').unwrap_or((value, "1"));
+    let numerator: f64 = numerator.parse::<u64>().ok()? as f64;
+    let denominator: f64 = denominator.parse::<u64>().ok()? as f64;
+    if numerator == 0.0 || denominator == 0.0 {
+        return None;
+    }
+    Some(1200.0 * (numerator / denominator).log2())
+}
+
+/// The mapping of the MIDI keys to the degrees of a scale.
+struct KeyboardMapping {
+    first_key: i32,
+    last_key: i32,
+    middle_key: i32,
+    reference_key: i32,
+    reference_frequency: f64,
+    octave_degree: i32,
+
+    /// The degree of each key of the mapping pattern, `None` if the
+    /// key is unmapped. An empty pattern maps each key to the next degree.
+    map: Vec<Option<i32>>,
+}
+
+impl KeyboardMapping {
+    fn parse(text: &str) -> Result<Self, ScalaError> {
+        let error = |msg: &str| ScalaError::InvalidMapping(msg.into());
+
+        let mut values = scala_lines(text).filter_map(|line| line.split_whitespace().next());
+        let mut next_number = |name: &str| {
+            values
+                .next()
+                .and_then(|value| value.parse::<i32>().ok())
+                .ok_or_else(|| error(&format!("missing or invalid {name}")))
+        };
+
+        let size = next_number("map size")?;
+        let first_key = next_number("first key")?;
+        let last_key = next_number("last key")?;
+        let middle_key = next_number("middle key")?;
+        let reference_key = next_number("reference key")?;
+
+        let reference_frequency = values
+            .next()
+            .and_then(|value| value.parse::<f64>().ok())
+            .filter(|frequency| *frequency > 0.0)
+            .ok_or_else(|| error("missing or invalid reference frequency"))?;
+
+        let octave_degree = values
+            .next()
+            .and_then(|value| value.parse::<i32>().ok())
+            .ok_or_else(|| error("missing or invalid octave degree"))?;
+
+        // Missing entries at the end of the pattern are unmapped
+        let mut map = values
+            .take(size.max(0) as usize)
+            .map(|value| match value {
+                "x" | "X" => Ok(None),
+                value => value
+                    .parse::<i32>()
+                    .map(Some)
+                    .map_err(|_| error(&format!("invalid map entry {value:?}"))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        map.resize(size.max(0) as usize, None);
+
+        Ok(KeyboardMapping {
+            first_key,
+            last_key,
+            middle_key,
+            reference_key,
+            reference_frequency,
+            octave_degree,
+            map,
+        })
+    }
+
+    /// Returns the pitch of a key in cents relative to the first degree
+    /// of the scale, or `None` if the key is unmapped.
+    fn cents(&self, key: i32, scale: &Scale) -> Option<f64> {
+        let offset = key - self.middle_key;
+        if self.map.is_empty() {
+            return Some(scale.cents(offset));
+        }
+
+        let size = self.map.len() as i32;
+        let degree = self.map[offset.rem_euclid(size) as usize]?;
+        let octave = match self.octave_degree {
+            0 => scale.cents(scale.degrees.len() as i32),
+            degree => scale.cents(degree),
+        };
+        Some(scale.cents(degree) + offset.div_euclid(size) as f64 * octave)
+    }
+}
+
+impl Default for KeyboardMapping {
+    fn default() -> Self {
+        KeyboardMapping {
+            first_key: 0,
+            last_key: 127,
+            middle_key: 60,
+            reference_key: 60,
+            reference_frequency: C4_FREQUENCY,
+            octave_degree: 0,
+            map: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWELVE_TET: &str = "! 12tet.scl
+!
+12 tone equal temperament
+ 12
+!
+ 100.0
+ 200.
+ 300.0
+ 400.0
+ 500.0
+ 600.0
+ 700.0
+ 800.0
+ 900.0
+ 1000.0
+ 1100.0
+ 2/1
+";
+
+    /// A 5-limit just intonation major scale.
+    const JUST_MAJOR: &str = "Just major
+7
+9/8
+5/4 major third
+4/3
+3/2
+5/3
+15/8
+2
+";
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{a} != {b}");
+    }
+
+    #[test]
+    fn test_parse_scala_pitch() {
+        assert_eq!(parse_scala_pitch("100.0"), Some(100.0));
+        assert_eq!(parse_scala_pitch("-50.5"), Some(-50.5));
+        assert_eq!(parse_scala_pitch("700."), Some(700.0));
+        assert_eq!(parse_scala_pitch("2/1"), Some(1200.0));
+        assert_eq!(parse_scala_pitch("2"), Some(1200.0));
+        assert!((parse_scala_pitch("3/2").unwrap() - 701.955).abs() < 1e-3);
+
+        assert_eq!(parse_scala_pitch(""), None);
+        assert_eq!(parse_scala_pitch("abc"), None);
+        assert_eq!(parse_scala_pitch("3/"), None);
+        assert_eq!(parse_scala_pitch("3/0"), None);
+        assert_eq!(parse_scala_pitch("0/1"), None);
+        assert_eq!(parse_scala_pitch("-3/2"), None);
+    }
+
+    #[test]
+    fn test_parse_scale() {
+        let scale = Scale::parse(TWELVE_TET).unwrap();
+        assert_eq!(scale.degrees.len(), 12);
+        assert_eq!(scale.degrees[1], 200.0);
+        assert_eq!(scale.cents(0), 0.0);
+        assert_eq!(scale.cents(13), 1300.0);
+        assert_eq!(scale.cents(-1), -100.0);
+
+        // Text after the pitch is ignored
+        let scale = Scale::parse(JUST_MAJOR).unwrap();
+        assert!((scale.cents(2) - 386.314).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_invalid_scale() {
+        let is_invalid = |scl: &str| matches!(Scale::parse(scl), Err(ScalaError::InvalidScale(_)));
+
+        assert!(is_invalid(""));
+        assert!(is_invalid("description\n"));
+        assert!(is_invalid("description\nmany\n"));
+        assert!(is_invalid("description\n0\n"));
+        assert!(is_invalid("description\n2\n100.0\n"));
+        assert!(is_invalid("description\n2\n100.0\nfoo\n"));
+    }
+
+    #[test]
+    fn test_equal_temperament_scale() {
+        let tuning = Tuning::from_scala(TWELVE_TET, None).unwrap();
+        for key in 0..128 {
+            assert_close(tuning.pitch(key), key as f32);
+            assert_close(tuning.offset(key), 0.0);
+        }
+        assert_close(tuning.frequency(69), 440.0);
+        assert_close(tuning.frequency(60), 261.6256);
+    }
+
+    #[test]
+    fn test_scale_without_mapping() {
+        let tuning = Tuning::from_scala(JUST_MAJOR, None).unwrap();
+
+        // Middle C keeps its frequency, the following keys play the next
+        // degrees of the scale
+        assert_close(tuning.frequency(60), 261.6256);
+        assert_close(tuning.frequency(62), 261.6256 * 5.0 / 4.0);
+        assert_close(tuning.frequency(63), 261.6256 * 4.0 / 3.0);
+        assert_close(tuning.frequency(67), 261.6256 * 2.0);
+        assert_close(tuning.frequency(53), 261.6256 / 2.0);
+    }
+
+    #[test]
+    fn test_scale_with_mapping() {
+        // Maps the white keys to the just major scale, with A4 at 432 Hz
+        let kbm = "! white keys
+12
+0
+127
+60
+69
+432.0
+7
+! the mapping
+0
+x
+1
+x
+2
+3
+x
+4
+x
+5
+x
+6
+";
+        let tuning = Tuning::from_scala(JUST_MAJOR, Some(kbm)).unwrap();
+
+        assert_close(tuning.frequency(69), 432.0);
+        assert_close(tuning.frequency(60), 432.0 * 3.0 / 5.0);
+        assert_close(tuning.frequency(64), 432.0 * 3.0 / 4.0);
+        assert_close(tuning.frequency(72), 432.0 * 6.0 / 5.0);
+        assert_close(tuning.frequency(57), 432.0 / 2.0);
+
+        // Unmapped keys keep their 12-TET pitch
+        assert_close(tuning.pitch(61), 61.0);
+    }
+
+    #[test]
+    fn test_invalid_mapping() {
+        let is_invalid = |kbm: &str| {
+            matches!(
+                Tuning::from_scala(JUST_MAJOR, Some(kbm)),
+                Err(ScalaError::InvalidMapping(_))
+            )
+        };
+
+        assert!(is_invalid(""));
+        assert!(is_invalid("12\n0\n127\n60\n69\n"));
+        assert!(is_invalid("12\n0\n127\n60\n69\n-1.0\n7\n"));
+        assert!(is_invalid("1\n0\n127\n60\n69\n440.0\n7\ny\n"));
+
+        // The reference key must be mapped
+        assert!(is_invalid("1\n0\n127\n60\n69\n440.0\n7\nx\n"));
+    }
+
+    #[test]
+    fn test_from_frequencies() {
+        let mut frequencies = std::array::from_fn(|key| Tuning::default().frequency(key as u8));
+        frequencies[60] = 270.0;
+
+        let tuning = Tuning::from_frequencies(&frequencies);
+        assert_close(tuning.frequency(60), 270.0);
+        assert_close(tuning.pitch(61), 61.0);
+    }
+}
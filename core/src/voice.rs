@@ -33,16 +33,63 @@ pub(crate) use control::*;
 mod cutoff;
 pub(crate) use cutoff::*;
 
+mod lfo;
+pub(crate) use lfo::*;
+
 /// Options to modify the envelope of a voice.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 pub struct EnvelopeControlData {
     /// Controls the attack. Can take values from 0 to 128
     /// according to the MIDI CC spec.
     pub attack: Option<u8>,
 
+    /// Controls the decay. Can take values from 0 to 128
+    /// according to the MIDI CC spec.
+    pub decay: Option<u8>,
+
     /// Controls the release. Can take values from 0 to 128
     /// according to the MIDI CC spec.
     pub release: Option<u8>,
+
+    /// Multiplier of the release time, used for half-pedalling
+    pub release_scale: f32,
+}
+
+/// Changes to the vibrato of a voice, applied to the LFOs that
+/// modulate its pitch.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VibratoControlData {
+    /// Multiplier of the LFO frequency
+    pub rate: f32,
+
+    /// Multiplier of the LFO pitch depth
+    pub depth: f32,
+
+    /// Multiplier of the LFO delay
+    pub delay: f32,
+}
+
+impl Default for VibratoControlData {
+    fn default() -> Self {
+        VibratoControlData {
+            rate: 1.0,
+            depth: 1.0,
+            delay: 1.0,
+        }
+    }
+}
+
+/// The pitch glide of the voices of a key, used for portamento.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct GlideControlData {
+    /// The pitch offset that new voices start from, in semitones
+    pub start: f32,
+
+    /// The pitch offset that the voices glide to, in semitones
+    pub target: f32,
+
+    /// The length of the glide in seconds
+    pub time: f32,
 }
 
 /// How a voice should be released.
@@ -53,6 +100,35 @@ pub enum ReleaseType {
 
     /// Kills the voice with a fadeout of 1ms.
     Kill,
+
+    /// Kills the voice with a fadeout of the given length in seconds.
+    FadeOut(f32),
+}
+
+/// The exclusive class of a voice. Voices can turn off the voices
+/// of other classes when they start, e.g. a closed hi-hat choking
+/// an open one.
+#[derive(Copy, Clone, PartialEq)]
+pub struct ExclusiveClass {
+    /// The group of the voice. When the voice starts, the voices that
+    /// are turned off by this group are stopped. 0 means no group.
+    pub group: u32,
+
+    /// The group that turns off the voice. 0 means the voice can't
+    /// be turned off.
+    pub off_by: u32,
+
+    /// How the voice is stopped when it is turned off
+    pub off_release: ReleaseType,
+}
+
+impl ExclusiveClass {
+    /// An exclusive class that doesn't interact with other voices.
+    pub const NONE: ExclusiveClass = ExclusiveClass {
+        group: 0,
+        off_by: 0,
+        off_release: ReleaseType::Kill,
+    };
 }
 
 /// Options to control the parameters of a voice.
@@ -63,6 +139,30 @@ pub struct VoiceControlData {
 
     /// Envelope control
     pub envelope: EnvelopeControlData,
+
+    /// Current values of the channel's MIDI controllers (CC 0-127)
+    pub cc: [u8; 128],
+
+    /// Position of the pitch wheel, from -1 to 1
+    pub pitch_wheel: f32,
+
+    /// Pitch wheel sensitivity in semitones
+    pub pitch_wheel_sensitivity: f32,
+
+    /// Pitch glide of the voices
+    pub glide: GlideControlData,
+
+    /// Channel aftertouch value (0-127)
+    pub channel_pressure: u8,
+
+    /// Polyphonic aftertouch value of the voice's key (0-127)
+    pub poly_pressure: u8,
+
+    /// The pitch depth of the modulation wheel at its maximum, in cents
+    pub modulation_depth_range: f32,
+
+    /// Vibrato changes set by the channel
+    pub vibrato: VibratoControlData,
 }
 
 impl VoiceControlData {
@@ -71,12 +171,32 @@ impl VoiceControlData {
             voice_pitch_multiplier: 1.0,
             envelope: EnvelopeControlData {
                 attack: None,
+                decay: None,
                 release: None,
+                release_scale: 1.0,
             },
+            cc: default_cc_values(),
+            pitch_wheel: 0.0,
+            pitch_wheel_sensitivity: 2.0,
+            glide: GlideControlData::default(),
+            channel_pressure: 0,
+            poly_pressure: 0,
+            modulation_depth_range: 50.0,
+            vibrato: VibratoControlData::default(),
         }
     }
 }
 
+/// The initial controller values of a channel, as specified by the
+/// "Reset All Controllers" recommended practice.
+fn default_cc_values() -> [u8; 128] {
+    let mut cc = [0; 128];
+    cc[7] = 100;
+    cc[10] = 64;
+    cc[11] = 127;
+    cc
+}
+
 pub trait VoiceGeneratorBase: Sync + Send {
     fn ended(&self) -> bool;
     fn signal_release(&mut self, rel_type: ReleaseType);
@@ -92,4 +212,6 @@ pub trait Voice: VoiceSampleGenerator + Send + Sync {
     fn is_killed(&self) -> bool;
 
     fn velocity(&self) -> u8;
+
+    fn exclusive_class(&self) -> ExclusiveClass;
 }
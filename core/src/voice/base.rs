@@ -1,6 +1,6 @@
 use crate::voice::{ReleaseType, VoiceControlData};
 
-use super::{Voice, VoiceGeneratorBase, VoiceSampleGenerator};
+use super::{ExclusiveClass, Voice, VoiceGeneratorBase, VoiceSampleGenerator};
 
 /// A struct that tracks the highest level voice functionality.
 pub struct VoiceBase<T: Send + Sync + VoiceSampleGenerator> {
@@ -8,15 +8,17 @@ pub struct VoiceBase<T: Send + Sync + VoiceSampleGenerator> {
     releasing: bool,
     killed: bool,
     velocity: u8,
+    exclusive_class: ExclusiveClass,
 }
 
 impl<T: Send + Sync + VoiceSampleGenerator> VoiceBase<T> {
-    pub fn new(velocity: u8, sample_generator: T) -> VoiceBase<T> {
+    pub fn new(velocity: u8, exclusive_class: ExclusiveClass, sample_generator: T) -> VoiceBase<T> {
         VoiceBase {
             sample_generator,
             releasing: false,
             killed: false,
             velocity,
+            exclusive_class,
         }
     }
 }
@@ -34,7 +36,7 @@ where
     fn signal_release(&mut self, rel_type: ReleaseType) {
         match rel_type {
             ReleaseType::Standard => self.releasing = true,
-            ReleaseType::Kill => self.killed = true,
+            ReleaseType::Kill | ReleaseType::FadeOut(_) => self.killed = true,
         }
         self.sample_generator.signal_release(rel_type)
     }
@@ -73,4 +75,9 @@ where
     fn velocity(&self) -> u8 {
         self.velocity
     }
+
+    #[inline(always)]
+    fn exclusive_class(&self) -> ExclusiveClass {
+        self.exclusive_class
+    }
 }
@@ -4,16 +4,13 @@ use crate::voice::{ReleaseType, VoiceControlData};
 
 use super::{SIMDSampleMono, SIMDVoiceGenerator, VoiceGeneratorBase};
 
-pub struct SIMDVoiceControl<S: Simd> {
+pub struct SIMDVoiceControl<S: Simd, F: Fn(&VoiceControlData) -> f32> {
     values: S::Vf32,
-    update: fn(&VoiceControlData) -> f32,
+    update: F,
 }
 
-impl<S: Simd> SIMDVoiceControl<S> {
-    pub fn new(
-        control: &VoiceControlData,
-        update: fn(&VoiceControlData) -> f32,
-    ) -> SIMDVoiceControl<S> {
+impl<S: Simd, F: Fn(&VoiceControlData) -> f32> SIMDVoiceControl<S, F> {
+    pub fn new(control: &VoiceControlData, update: F) -> SIMDVoiceControl<S, F> {
         simd_invoke!(S, {
             SIMDVoiceControl {
                 values: S::Vf32::set1((update)(control)),
@@ -23,7 +20,9 @@ impl<S: Simd> SIMDVoiceControl<S> {
     }
 }
 
-impl<S: Simd> VoiceGeneratorBase for SIMDVoiceControl<S> {
+impl<S: Simd, F: Fn(&VoiceControlData) -> f32 + Send + Sync> VoiceGeneratorBase
+    for SIMDVoiceControl<S, F>
+{
     #[inline(always)]
     fn ended(&self) -> bool {
         false
@@ -40,9 +39,128 @@ impl<S: Simd> VoiceGeneratorBase for SIMDVoiceControl<S> {
     }
 }
 
-impl<S: Simd> SIMDVoiceGenerator<S, SIMDSampleMono<S>> for SIMDVoiceControl<S> {
+impl<S: Simd, F: Fn(&VoiceControlData) -> f32 + Send + Sync>
+    SIMDVoiceGenerator<S, SIMDSampleMono<S>> for SIMDVoiceControl<S, F>
+{
     #[inline(always)]
     fn next_sample(&mut self) -> SIMDSampleMono<S> {
         SIMDSampleMono(self.values)
     }
 }
+
+/// Glides the pitch of a voice between offsets, outputting the pitch multiplier.
+///
+/// The voice starts at the glide start offset, and glides to a new target
+/// whenever the target in the voice controls changes.
+pub struct SIMDVoiceGlide<S: Simd> {
+    sample_rate: f32,
+    offset: f32,
+    target: f32,
+    step: f32,
+    values: S::Vf32,
+}
+
+impl<S: Simd> SIMDVoiceGlide<S> {
+    pub fn new(control: &VoiceControlData, sample_rate: f32) -> SIMDVoiceGlide<S> {
+        simd_invoke!(S, {
+            let glide = control.glide;
+            let mut generator = SIMDVoiceGlide {
+                sample_rate,
+                offset: glide.start,
+                target: glide.start,
+                step: 0.0,
+                values: S::Vf32::set1(Self::multiplier(glide.start)),
+            };
+            generator.set_target(glide.target, glide.time);
+            generator
+        })
+    }
+
+    fn multiplier(offset: f32) -> f32 {
+        2.0f32.powf(offset / 12.0)
+    }
+
+    fn set_target(&mut self, target: f32, time: f32) {
+        self.target = target;
+        let samples = time * self.sample_rate;
+        if samples >= 1.0 {
+            self.step = (target - self.offset) / samples;
+        } else {
+            simd_invoke!(S, {
+                self.offset = target;
+                self.values = S::Vf32::set1(Self::multiplier(target));
+            })
+        }
+    }
+}
+
+impl<S: Simd> VoiceGeneratorBase for SIMDVoiceGlide<S> {
+    #[inline(always)]
+    fn ended(&self) -> bool {
+        false
+    }
+
+    #[inline(always)]
+    fn signal_release(&mut self, _rel_type: ReleaseType) {}
+
+    #[inline(always)]
+    fn process_controls(&mut self, control: &VoiceControlData) {
+        if control.glide.target != self.target {
+            self.set_target(control.glide.target, control.glide.time);
+        }
+    }
+}
+
+impl<S: Simd> SIMDVoiceGenerator<S, SIMDSampleMono<S>> for SIMDVoiceGlide<S> {
+    #[inline(always)]
+    fn next_sample(&mut self) -> SIMDSampleMono<S> {
+        simd_invoke!(S, {
+            let values = self.values;
+            if self.offset != self.target {
+                let offset = self.offset + self.step * S::Vf32::WIDTH as f32;
+                self.offset = if (self.step > 0.0) == (offset > self.target) {
+                    self.target
+                } else {
+                    offset
+                };
+                self.values = S::Vf32::set1(Self::multiplier(self.offset));
+            }
+
+            SIMDSampleMono(values)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use simdeez::simd_runtime_generate;
+
+    use super::*;
+    use crate::voice::GlideControlData;
+
+    #[test]
+    fn test_glide() {
+        simd_runtime_generate!(
+            fn run() {
+                let mut control = VoiceControlData::new_defaults();
+                control.glide = GlideControlData {
+                    start: -12.0,
+                    target: 0.0,
+                    time: 2.0,
+                };
+                let sample_rate = S::Vf32::WIDTH as f32;
+
+                let mut glide = SIMDVoiceGlide::<S>::new(&control, sample_rate);
+                let values: Vec<f32> = (0..4).map(|_| glide.next_sample().0[0]).collect();
+                assert_eq!(values, vec![0.5, 2f32.powf(-0.5), 1.0, 1.0]);
+
+                control.glide.target = 12.0;
+                control.glide.time = 0.0;
+                glide.process_controls(&control);
+                assert_eq!(glide.next_sample().0[0], 2.0);
+            }
+        );
+
+        run();
+    }
+}
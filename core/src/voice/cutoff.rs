@@ -3,7 +3,7 @@ use std::marker::PhantomData;
 use simdeez::prelude::*;
 
 use crate::{
-    effects::BiQuadFilter,
+    effects::{BiQuadFilter, FilterType},
     voice::{ReleaseType, SIMDVoiceGenerator, VoiceControlData},
 };
 
@@ -131,3 +131,204 @@ where
         })
     }
 }
+
+/// The parameters of a filter whose cutoff frequency is modulated
+/// by a generator while the voice is playing.
+#[derive(Clone, Copy)]
+pub struct ModulatedCutoffParams {
+    pub filter_type: FilterType,
+    pub cutoff: f32,
+    pub resonance: f32,
+    pub sample_rate: f32,
+}
+
+impl ModulatedCutoffParams {
+    fn create_filter(&self) -> BiQuadFilter {
+        BiQuadFilter::new(
+            self.filter_type,
+            self.cutoff,
+            self.sample_rate,
+            Some(self.resonance),
+        )
+    }
+
+    /// Returns the cutoff frequency multiplied by the first value
+    /// of the modulator's output, or `None` if it didn't change.
+    #[inline(always)]
+    fn next_cutoff<S: Simd>(&self, mult: S::Vf32, last_mult: &mut f32) -> Option<f32> {
+        let mult = mult[0];
+        if mult == *last_mult {
+            return None;
+        }
+
+        *last_mult = mult;
+        Some((self.cutoff * mult).clamp(1.0, self.sample_rate / 2.0 - 100.0))
+    }
+}
+
+pub struct SIMDMonoVoiceModulatedCutoff<S, V, M>
+where
+    S: Simd,
+    V: SIMDVoiceGenerator<S, SIMDSampleMono<S>>,
+    M: SIMDVoiceGenerator<S, SIMDSampleMono<S>>,
+{
+    v: V,
+    modulator: M,
+    params: ModulatedCutoffParams,
+    last_mult: f32,
+    cutoff: BiQuadFilter,
+    _s: PhantomData<S>,
+}
+
+impl<S, V, M> SIMDMonoVoiceModulatedCutoff<S, V, M>
+where
+    S: Simd,
+    V: SIMDVoiceGenerator<S, SIMDSampleMono<S>>,
+    M: SIMDVoiceGenerator<S, SIMDSampleMono<S>>,
+{
+    /// Creates a new filtered generator. The output of `modulator` is used
+    /// as a multiplier for the cutoff frequency.
+    pub fn new(v: V, modulator: M, params: ModulatedCutoffParams) -> Self {
+        SIMDMonoVoiceModulatedCutoff {
+            v,
+            modulator,
+            params,
+            last_mult: 1.0,
+            cutoff: params.create_filter(),
+            _s: PhantomData,
+        }
+    }
+}
+
+impl<S, V, M> VoiceGeneratorBase for SIMDMonoVoiceModulatedCutoff<S, V, M>
+where
+    S: Simd,
+    V: SIMDVoiceGenerator<S, SIMDSampleMono<S>>,
+    M: SIMDVoiceGenerator<S, SIMDSampleMono<S>>,
+{
+    #[inline(always)]
+    fn ended(&self) -> bool {
+        self.v.ended()
+    }
+
+    #[inline(always)]
+    fn signal_release(&mut self, rel_type: ReleaseType) {
+        self.v.signal_release(rel_type);
+        self.modulator.signal_release(rel_type);
+    }
+
+    #[inline(always)]
+    fn process_controls(&mut self, control: &VoiceControlData) {
+        self.v.process_controls(control);
+        self.modulator.process_controls(control);
+    }
+}
+
+impl<S, V, M> SIMDVoiceGenerator<S, SIMDSampleMono<S>> for SIMDMonoVoiceModulatedCutoff<S, V, M>
+where
+    S: Simd,
+    V: SIMDVoiceGenerator<S, SIMDSampleMono<S>>,
+    M: SIMDVoiceGenerator<S, SIMDSampleMono<S>>,
+{
+    #[inline(always)]
+    fn next_sample(&mut self) -> SIMDSampleMono<S> {
+        simd_invoke!(S, {
+            let mult = self.modulator.next_sample().0;
+            if let Some(cutoff) = self.params.next_cutoff::<S>(mult, &mut self.last_mult) {
+                let p = &self.params;
+                self.cutoff
+                    .set_params(p.filter_type, cutoff, p.sample_rate, Some(p.resonance));
+            }
+
+            let mut next_sample = self.v.next_sample();
+            next_sample.0 = self.cutoff.process_simd::<S>(next_sample.0);
+            next_sample
+        })
+    }
+}
+
+pub struct SIMDStereoVoiceModulatedCutoff<S, V, M>
+where
+    S: Simd,
+    V: SIMDVoiceGenerator<S, SIMDSampleStereo<S>>,
+    M: SIMDVoiceGenerator<S, SIMDSampleMono<S>>,
+{
+    v: V,
+    modulator: M,
+    params: ModulatedCutoffParams,
+    last_mult: f32,
+    cutoff1: BiQuadFilter,
+    cutoff2: BiQuadFilter,
+    _s: PhantomData<S>,
+}
+
+impl<S, V, M> SIMDStereoVoiceModulatedCutoff<S, V, M>
+where
+    S: Simd,
+    V: SIMDVoiceGenerator<S, SIMDSampleStereo<S>>,
+    M: SIMDVoiceGenerator<S, SIMDSampleMono<S>>,
+{
+    /// Creates a new filtered generator. The output of `modulator` is used
+    /// as a multiplier for the cutoff frequency.
+    pub fn new(v: V, modulator: M, params: ModulatedCutoffParams) -> Self {
+        SIMDStereoVoiceModulatedCutoff {
+            v,
+            modulator,
+            params,
+            last_mult: 1.0,
+            cutoff1: params.create_filter(),
+            cutoff2: params.create_filter(),
+            _s: PhantomData,
+        }
+    }
+}
+
+impl<S, V, M> VoiceGeneratorBase for SIMDStereoVoiceModulatedCutoff<S, V, M>
+where
+    S: Simd,
+    V: SIMDVoiceGenerator<S, SIMDSampleStereo<S>>,
+    M: SIMDVoiceGenerator<S, SIMDSampleMono<S>>,
+{
+    #[inline(always)]
+    fn ended(&self) -> bool {
+        self.v.ended()
+    }
+
+    #[inline(always)]
+    fn signal_release(&mut self, rel_type: ReleaseType) {
+        self.v.signal_release(rel_type);
+        self.modulator.signal_release(rel_type);
+    }
+
+    #[inline(always)]
+    fn process_controls(&mut self, control: &VoiceControlData) {
+        self.v.process_controls(control);
+        self.modulator.process_controls(control);
+    }
+}
+
+impl<S, V, M> SIMDVoiceGenerator<S, SIMDSampleStereo<S>> for SIMDStereoVoiceModulatedCutoff<S, V, M>
+where
+    S: Simd,
+    V: SIMDVoiceGenerator<S, SIMDSampleStereo<S>>,
+    M: SIMDVoiceGenerator<S, SIMDSampleMono<S>>,
+{
+    #[inline(always)]
+    fn next_sample(&mut self) -> SIMDSampleStereo<S> {
+        simd_invoke!(S, {
+            let mult = self.modulator.next_sample().0;
+            if let Some(cutoff) = self.params.next_cutoff::<S>(mult, &mut self.last_mult) {
+                let p = &self.params;
+                self.cutoff1
+                    .set_params(p.filter_type, cutoff, p.sample_rate, Some(p.resonance));
+                self.cutoff2
+                    .set_params(p.filter_type, cutoff, p.sample_rate, Some(p.resonance));
+            }
+
+            let mut next_sample = self.v.next_sample();
+            next_sample.0 = self.cutoff1.process_simd::<S>(next_sample.0);
+            next_sample.1 = self.cutoff2.process_simd::<S>(next_sample.1);
+            next_sample
+        })
+    }
+}
@@ -176,7 +176,7 @@ impl<T: Simd> StageTime<T> {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum EnvelopePart {
     Lerp {
         target: f32,   // Target value by the end of the envelope part
@@ -219,7 +219,7 @@ impl EnvelopeDescriptor {
     pub fn to_envelope_params(
         &self,
         samplerate: u32,
-        options: SoundfontInitOptions,
+        options: &SoundfontInitOptions,
     ) -> EnvelopeParameters {
         let samplerate = samplerate as f32;
 
@@ -254,7 +254,7 @@ impl EnvelopeDescriptor {
 /// The raw envelope parameters used to generate the envelope.
 /// Is a separate struct to EnvelopeDescriptor for performance reasons.
 /// Use EnvelopeDescriptor to generate the EnvelopeParameters struct.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct EnvelopeParameters {
     start: f32,
     pub parts: [EnvelopePart; 7],
@@ -451,6 +451,18 @@ impl<T: Simd> SIMDVoiceEnvelope<T> {
                 ),
             );
         }
+        if let Some(decay) = envelope.decay {
+            if let EnvelopePart::Lerp { target, duration } = params.parts[3] {
+                let duration = duration as f32 / sample_rate;
+                params.modify_stage_data(
+                    3,
+                    EnvelopePart::lerp(
+                        target,
+                        (calculate_curve(decay, duration) * sample_rate) as u32,
+                    ),
+                );
+            }
+        }
         if let Some(release) = envelope.release {
             let duration = params.get_stage_duration(EnvelopeStage::Release) as f32 / sample_rate;
             params.modify_stage_data(
@@ -460,15 +472,28 @@ impl<T: Simd> SIMDVoiceEnvelope<T> {
                 ),
             );
         }
+        if envelope.release_scale != 1.0 {
+            let duration = params.get_stage_duration(EnvelopeStage::Release) as f32;
+            params.modify_stage_data(
+                5,
+                EnvelopePart::lerp_to_zero_curve((duration * envelope.release_scale) as u32),
+            );
+        }
 
         params
     }
 
     pub fn modify_envelope(&mut self, envelope: EnvelopeControlData) {
         if !self.killed {
-            self.params =
+            let params =
                 Self::get_modified_envelope(self.original_params, envelope, self.sample_rate);
-            self.update_stage();
+
+            // Restarting the current stage changes its timing, so only
+            // do it if the envelope was actually modified
+            if params != self.params {
+                self.params = params;
+                self.update_stage();
+            }
         }
     }
 }
@@ -481,11 +506,14 @@ impl<T: Simd> VoiceGeneratorBase for SIMDVoiceEnvelope<T> {
 
     #[inline(always)]
     fn signal_release(&mut self, rel_type: ReleaseType) {
-        if rel_type == ReleaseType::Kill {
-            self.params.modify_stage_data(
-                5,
-                EnvelopePart::lerp(0.0, (0.001 * self.sample_rate) as u32),
-            );
+        let fade_out = match rel_type {
+            ReleaseType::Standard => None,
+            ReleaseType::Kill => Some(0.001),
+            ReleaseType::FadeOut(time) => Some(time),
+        };
+        if let Some(time) = fade_out {
+            self.params
+                .modify_stage_data(5, EnvelopePart::lerp(0.0, (time * self.sample_rate) as u32));
             self.update_stage();
             self.killed = true;
         }
@@ -550,6 +578,85 @@ impl<T: Simd> SIMDVoiceGenerator<T, SIMDSampleMono<T>> for SIMDVoiceEnvelope<T>
     }
 }
 
+/// Sums a list of envelopes, each scaled by a depth, and converts the
+/// sum with `convert`. Used to modulate voice parameters over time.
+///
+/// The sum is only calculated once for each SIMD array, using the
+/// value of the envelopes at the start of the array.
+pub struct SIMDVoiceModEnvelopes<T: Simd, C: Fn(f32) -> f32> {
+    envelopes: Vec<(SIMDVoiceEnvelope<T>, f32)>,
+    convert: C,
+    value: f32,
+    values: T::Vf32,
+}
+
+impl<T: Simd, C: Fn(f32) -> f32> SIMDVoiceModEnvelopes<T, C> {
+    /// Creates a new generator from a list of envelope parameters
+    /// and their depths.
+    pub fn new(
+        envelopes: impl Iterator<Item = (EnvelopeParameters, f32)>,
+        sample_rate: f32,
+        convert: C,
+    ) -> Self {
+        simd_invoke!(T, {
+            let envelopes = envelopes
+                .map(|(params, depth)| {
+                    (
+                        SIMDVoiceEnvelope::new(params, params, true, sample_rate),
+                        depth,
+                    )
+                })
+                .collect();
+
+            SIMDVoiceModEnvelopes {
+                envelopes,
+                values: T::Vf32::set1((convert)(0.0)),
+                convert,
+                value: 0.0,
+            }
+        })
+    }
+}
+
+impl<T: Simd, C: Fn(f32) -> f32 + Send + Sync> VoiceGeneratorBase for SIMDVoiceModEnvelopes<T, C> {
+    #[inline(always)]
+    fn ended(&self) -> bool {
+        // The amplitude envelope decides when the voice ends
+        false
+    }
+
+    #[inline(always)]
+    fn signal_release(&mut self, rel_type: ReleaseType) {
+        for (envelope, _) in self.envelopes.iter_mut() {
+            envelope.signal_release(rel_type);
+        }
+    }
+
+    #[inline(always)]
+    fn process_controls(&mut self, _control: &VoiceControlData) {}
+}
+
+impl<T: Simd, C: Fn(f32) -> f32 + Send + Sync> SIMDVoiceGenerator<T, SIMDSampleMono<T>>
+    for SIMDVoiceModEnvelopes<T, C>
+{
+    #[inline(always)]
+    fn next_sample(&mut self) -> SIMDSampleMono<T> {
+        simd_invoke!(T, {
+            let mut value = 0.0;
+            for (envelope, depth) in self.envelopes.iter_mut() {
+                value += envelope.next_sample().0[0] * *depth;
+            }
+
+            if value != self.value {
+                self.value = value;
+                self.values = T::Vf32::set1((self.convert)(value));
+            }
+
+            SIMDSampleMono(self.values)
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use simdeez::simd_runtime_generate;
@@ -678,7 +785,7 @@ mod tests {
                     sustain_percent: 0.4,
                     release: 16.0,
                 };
-                let params = descriptor.to_envelope_params(1, Default::default());
+                let params = descriptor.to_envelope_params(1, &Default::default());
 
                 let mut env = SIMDVoiceEnvelope::<S>::new(params, params, true, 1.0);
 
@@ -0,0 +1,203 @@
+use simdeez::prelude::*;
+
+use xsynth_soundfonts::sfz::LfoParams;
+
+use crate::voice::{ReleaseType, VoiceControlData};
+
+use super::{SIMDSampleMono, SIMDVoiceGenerator, VoiceGeneratorBase};
+
+/// A triangle oscillator that starts at 0 and rises to 1 after
+/// a quarter of a cycle.
+struct LfoOscillator {
+    delay: u32,
+    phase: f32,
+    step: f32,
+}
+
+impl LfoOscillator {
+    fn new(params: &LfoParams, sample_rate: f32) -> Self {
+        LfoOscillator {
+            delay: (params.delay.max(0.0) * sample_rate) as u32,
+            phase: 0.0,
+            step: params.freq.max(0.0) / sample_rate,
+        }
+    }
+
+    fn value(&self) -> f32 {
+        if self.phase < 0.25 {
+            self.phase * 4.0
+        } else if self.phase < 0.75 {
+            2.0 - self.phase * 4.0
+        } else {
+            self.phase * 4.0 - 4.0
+        }
+    }
+
+    /// Returns the current value and advances the oscillator by the given amount of samples.
+    fn next(&mut self, samples: u32) -> f32 {
+        if self.delay > 0 {
+            self.delay = self.delay.saturating_sub(samples);
+            return 0.0;
+        }
+
+        let value = self.value();
+        self.phase = (self.phase + self.step * samples as f32).fract();
+        value
+    }
+}
+
+struct LfoState {
+    oscillator: LfoOscillator,
+    depth: f32,
+}
+
+/// Sums the outputs of a list of LFOs, each scaled by a depth that can be
+/// changed by the voice controls, and converts the sum with `convert`.
+///
+/// LFOs are slow enough that they are only evaluated once for each SIMD
+/// array, and the conversion is only repeated when the sum changes.
+pub struct SIMDVoiceLfo<S, F, C>
+where
+    S: Simd,
+    F: Fn(usize, &VoiceControlData) -> f32,
+    C: Fn(f32) -> f32,
+{
+    lfos: Vec<LfoState>,
+    update_depth: F,
+    convert: C,
+    value: f32,
+    values: S::Vf32,
+}
+
+impl<S, F, C> SIMDVoiceLfo<S, F, C>
+where
+    S: Simd,
+    F: Fn(usize, &VoiceControlData) -> f32,
+    C: Fn(f32) -> f32,
+{
+    /// Creates a new LFO generator. `update_depth` receives the index of
+    /// the LFO in `lfos` and returns its depth.
+    pub fn new(
+        lfos: &[LfoParams],
+        sample_rate: f32,
+        control: &VoiceControlData,
+        update_depth: F,
+        convert: C,
+    ) -> Self {
+        simd_invoke!(S, {
+            let lfos = lfos
+                .iter()
+                .enumerate()
+                .map(|(i, params)| LfoState {
+                    oscillator: LfoOscillator::new(params, sample_rate),
+                    depth: (update_depth)(i, control),
+                })
+                .collect();
+
+            SIMDVoiceLfo {
+                lfos,
+                update_depth,
+                values: S::Vf32::set1((convert)(0.0)),
+                convert,
+                value: 0.0,
+            }
+        })
+    }
+}
+
+impl<S, F, C> VoiceGeneratorBase for SIMDVoiceLfo<S, F, C>
+where
+    S: Simd,
+    F: Fn(usize, &VoiceControlData) -> f32 + Send + Sync,
+    C: Fn(f32) -> f32 + Send + Sync,
+{
+    #[inline(always)]
+    fn ended(&self) -> bool {
+        false
+    }
+
+    #[inline(always)]
+    fn signal_release(&mut self, _rel_type: ReleaseType) {}
+
+    #[inline(always)]
+    fn process_controls(&mut self, control: &VoiceControlData) {
+        for (i, lfo) in self.lfos.iter_mut().enumerate() {
+            lfo.depth = (self.update_depth)(i, control);
+        }
+    }
+}
+
+impl<S, F, C> SIMDVoiceGenerator<S, SIMDSampleMono<S>> for SIMDVoiceLfo<S, F, C>
+where
+    S: Simd,
+    F: Fn(usize, &VoiceControlData) -> f32 + Send + Sync,
+    C: Fn(f32) -> f32 + Send + Sync,
+{
+    #[inline(always)]
+    fn next_sample(&mut self) -> SIMDSampleMono<S> {
+        simd_invoke!(S, {
+            let mut value = 0.0;
+            for lfo in self.lfos.iter_mut() {
+                let lfo_value = lfo.oscillator.next(S::Vf32::WIDTH as u32);
+                value += lfo_value * lfo.depth;
+            }
+
+            if value != self.value {
+                self.value = value;
+                self.values = S::Vf32::set1((self.convert)(value));
+            }
+
+            SIMDSampleMono(self.values)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use simdeez::simd_runtime_generate;
+
+    use super::*;
+
+    #[test]
+    fn test_lfo_oscillator() {
+        let params = LfoParams {
+            delay: 0.5,
+            freq: 1.0,
+            ..Default::default()
+        };
+        let mut osc = LfoOscillator::new(&params, 8.0);
+
+        let values: Vec<f32> = (0..12).map(|_| osc.next(1)).collect();
+        assert_eq!(
+            values,
+            vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.5, 1.0, 0.5, 0.0, -0.5, -1.0, -0.5]
+        );
+    }
+
+    #[test]
+    fn test_lfo_depth() {
+        simd_runtime_generate!(
+            fn run() {
+                let params = LfoParams {
+                    freq: 1.0,
+                    ..Default::default()
+                };
+                let control = VoiceControlData::new_defaults();
+                let sample_rate = S::Vf32::WIDTH as f32 * 4.0;
+
+                let mut lfo = SIMDVoiceLfo::<S, _, _>::new(
+                    &[params],
+                    sample_rate,
+                    &control,
+                    |_, _| 2.0,
+                    |v| v + 1.0,
+                );
+
+                let values: Vec<f32> = (0..4).map(|_| lfo.next_sample().0[0]).collect();
+                assert_eq!(values, vec![1.0, 3.0, 1.0, -1.0]);
+            }
+        );
+
+        run();
+    }
+}
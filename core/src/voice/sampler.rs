@@ -1,18 +1,34 @@
 use std::{marker::PhantomData, sync::Arc};
 
 use simdeez::prelude::*;
+use xsynth_soundfonts::sf2::Sf2SampleData;
 
-use crate::soundfont::LoopParams;
+use crate::soundfont::{LoopParams, SampleFormat};
 use crate::voice::{ReleaseType, VoiceControlData};
 
 use super::{SIMDSampleMono, SIMDSampleStereo, SIMDVoiceGenerator, VoiceGeneratorBase};
 
+mod formats;
+pub use formats::*;
+
+mod hermite;
+pub use hermite::*;
+
+mod lagrange;
+pub use lagrange::*;
+
 mod linear;
 pub use linear::*;
 
 mod nearest;
 pub use nearest::*;
 
+mod sinc;
+pub use sinc::*;
+
+mod stream;
+pub use stream::*;
+
 // I believe some terminology reference is relevant for this one.
 //
 // BufferSampler: Something that grabs a sample based on an index
@@ -28,6 +44,12 @@ pub use nearest::*;
 pub trait BufferSampler: Send + Sync {
     fn get(&self, pos: usize) -> f32;
     fn length(&self) -> usize;
+
+    /// The factor that converts the values returned by `get` to the
+    /// -1 to 1 range. The grabbers apply it to the interpolated values.
+    fn scale(&self) -> f32 {
+        1.0
+    }
 }
 
 pub trait SIMDSampleGrabber<S: Simd>: Send + Sync {
@@ -59,17 +81,86 @@ impl BufferSampler for F32BufferSampler {
     }
 }
 
+// The audio data of a sample channel
+
+#[derive(Clone)]
+pub enum SampleBuffer {
+    F32(Arc<[f32]>),
+    /// The integer values and the scale that converts them back
+    I16(Arc<[i16]>, f32),
+    I24(Arc<[[u8; 3]]>, f32),
+    F16(Arc<[u16]>),
+    Stream(Arc<StreamedSample>),
+}
+
+impl SampleBuffer {
+    /// Stores a sample in the given format.
+    pub fn new(sample: &Arc<[f32]>, format: SampleFormat) -> Self {
+        match format {
+            SampleFormat::F32 => SampleBuffer::F32(sample.clone()),
+            SampleFormat::I16 => {
+                let (values, scale) = I16BufferSampler::convert(sample);
+                SampleBuffer::I16(values, scale)
+            }
+            SampleFormat::I24 => {
+                let (values, scale) = I24BufferSampler::convert(sample);
+                SampleBuffer::I24(values, scale)
+            }
+            SampleFormat::F16 => SampleBuffer::F16(F16BufferSampler::convert(sample)),
+        }
+    }
+
+    /// Stores an SF2 sample in the given format. The integer values of the
+    /// file are kept as they are when the format holds them without loss,
+    /// otherwise the sample is converted through floats.
+    pub fn from_sf2(sample: &Sf2SampleData, format: SampleFormat) -> Self {
+        match (sample, format) {
+            (Sf2SampleData::I16(values), SampleFormat::I16 | SampleFormat::I24) => {
+                SampleBuffer::I16(values.clone(), 1.0 / I16_SCALE)
+            }
+            (Sf2SampleData::I24(values), SampleFormat::I24) => {
+                SampleBuffer::I24(values.clone(), 1.0 / I24_SCALE)
+            }
+            (sample, format) => Self::new(&sample.to_f32(), format),
+        }
+    }
+}
+
 // Generalized enum sampler
 
 pub enum BufferSamplers {
     F32(F32BufferSampler),
+    I16(I16BufferSampler),
+    I24(I24BufferSampler),
+    F16(F16BufferSampler),
+    Stream(StreamBufferSampler),
 }
 
 impl BufferSamplers {
+    #[inline(always)]
+    pub fn new(sample: SampleBuffer) -> BufferSamplers {
+        match sample {
+            SampleBuffer::F32(sample) => Self::new_f32(sample),
+            SampleBuffer::I16(sample, scale) => {
+                BufferSamplers::I16(I16BufferSampler::new(sample, scale))
+            }
+            SampleBuffer::I24(sample, scale) => {
+                BufferSamplers::I24(I24BufferSampler::new(sample, scale))
+            }
+            SampleBuffer::F16(sample) => BufferSamplers::F16(F16BufferSampler::new(sample)),
+            SampleBuffer::Stream(sample) => Self::new_stream(sample),
+        }
+    }
+
     #[inline(always)]
     pub fn new_f32(sample: Arc<[f32]>) -> BufferSamplers {
         BufferSamplers::F32(F32BufferSampler(sample))
     }
+
+    #[inline(always)]
+    pub fn new_stream(sample: Arc<StreamedSample>) -> BufferSamplers {
+        BufferSamplers::Stream(StreamBufferSampler::new(sample))
+    }
 }
 
 impl BufferSampler for BufferSamplers {
@@ -77,12 +168,30 @@ impl BufferSampler for BufferSamplers {
     fn get(&self, pos: usize) -> f32 {
         match self {
             BufferSamplers::F32(sampler) => sampler.get(pos),
+            BufferSamplers::I16(sampler) => sampler.get(pos),
+            BufferSamplers::I24(sampler) => sampler.get(pos),
+            BufferSamplers::F16(sampler) => sampler.get(pos),
+            BufferSamplers::Stream(sampler) => sampler.get(pos),
         }
     }
 
     fn length(&self) -> usize {
         match self {
             BufferSamplers::F32(sampler) => sampler.length(),
+            BufferSamplers::I16(sampler) => sampler.length(),
+            BufferSamplers::I24(sampler) => sampler.length(),
+            BufferSamplers::F16(sampler) => sampler.length(),
+            BufferSamplers::Stream(sampler) => sampler.length(),
+        }
+    }
+
+    fn scale(&self) -> f32 {
+        match self {
+            BufferSamplers::F32(sampler) => sampler.scale(),
+            BufferSamplers::I16(sampler) => sampler.scale(),
+            BufferSamplers::I24(sampler) => sampler.scale(),
+            BufferSamplers::F16(sampler) => sampler.scale(),
+            BufferSamplers::Stream(sampler) => sampler.scale(),
         }
     }
 }
@@ -93,6 +202,9 @@ pub trait SampleReader: Send + Sync {
     fn get(&mut self, pos: usize) -> f32;
     fn is_past_end(&self, pos: usize) -> bool;
     fn signal_release(&mut self);
+
+    /// See `BufferSampler::scale`.
+    fn scale(&self) -> f32;
 }
 
 pub struct SampleReaderNoLoop<Sampler: BufferSampler> {
@@ -119,13 +231,28 @@ impl<Sampler: BufferSampler> SampleReader for SampleReaderNoLoop<Sampler> {
 
     fn is_past_end(&self, pos: usize) -> bool {
         if let Some(len) = self.length {
-            pos - self.offset >= len
+            pos + self.offset >= len
         } else {
             false
         }
     }
 
     fn signal_release(&mut self) {}
+
+    fn scale(&self) -> f32 {
+        self.buffer.scale()
+    }
+}
+
+/// Wraps a position into the loop if it is past its end. The loop plays
+/// the samples from `start` up to, but not including, `end`.
+#[inline(always)]
+fn wrap_loop_position(pos: usize, start: usize, end: usize) -> usize {
+    if pos >= end && end > start {
+        (pos - start) % (end - start) + start
+    } else {
+        pos
+    }
 }
 
 pub struct SampleReaderLoop<Sampler: BufferSampler> {
@@ -148,14 +275,7 @@ impl<Sampler: BufferSampler> SampleReaderLoop<Sampler> {
 
 impl<Sampler: BufferSampler> SampleReader for SampleReaderLoop<Sampler> {
     fn get(&mut self, pos: usize) -> f32 {
-        let mut pos = pos + self.offset;
-        let end = self.loop_end;
-        let start = self.loop_start;
-
-        if pos > end {
-            pos = (pos - end - 1) % (end - start) + start;
-        }
-
+        let pos = wrap_loop_position(pos + self.offset, self.loop_start, self.loop_end);
         self.buffer.get(pos)
     }
 
@@ -164,21 +284,28 @@ impl<Sampler: BufferSampler> SampleReader for SampleReaderLoop<Sampler> {
     }
 
     fn signal_release(&mut self) {}
+
+    fn scale(&self) -> f32 {
+        self.buffer.scale()
+    }
 }
 
 pub struct SampleReaderLoopSustain<Sampler: BufferSampler> {
     buffer: Sampler,
-    length: Option<usize>,
+    length: usize,
     offset: usize,
     loop_start: usize,
     loop_end: usize,
+
+    /// The furthest position that was read before the release. The sample
+    /// continues from the loop position of it after the release.
     last: usize,
     is_released: bool,
 }
 
 impl<Sampler: BufferSampler> SampleReaderLoopSustain<Sampler> {
     pub fn new(buffer: Sampler, loop_params: LoopParams) -> Self {
-        let length = Some(buffer.length());
+        let length = buffer.length();
         Self {
             buffer,
             length,
@@ -189,37 +316,49 @@ impl<Sampler: BufferSampler> SampleReaderLoopSustain<Sampler> {
             is_released: false,
         }
     }
+
+    /// Returns the buffer position of a position that includes the offset.
+    fn buffer_position(&self, pos: usize) -> usize {
+        if self.is_released && pos > self.last {
+            wrap_loop_position(self.last, self.loop_start, self.loop_end) + (pos - self.last)
+        } else {
+            wrap_loop_position(pos, self.loop_start, self.loop_end)
+        }
+    }
 }
 
 impl<Sampler: BufferSampler> SampleReader for SampleReaderLoopSustain<Sampler> {
     fn get(&mut self, pos: usize) -> f32 {
-        let mut pos = pos + self.offset;
-        let end = self.loop_end;
-        let start = self.loop_start;
-
+        let pos = pos + self.offset;
         if !self.is_released {
-            self.last = pos;
-            if pos > end {
-                pos = (pos - end - 1) % (end - start) + start;
-            }
-        } else {
-            pos = pos - self.last + self.loop_end;
+            self.last = self.last.max(pos);
         }
-
-        self.buffer.get(pos)
+        self.buffer.get(self.buffer_position(pos))
     }
 
     fn is_past_end(&self, pos: usize) -> bool {
-        if let Some(len) = self.length {
-            pos - self.last - self.offset >= len
-        } else {
-            false
-        }
+        self.is_released && self.buffer_position(pos + self.offset) >= self.length
     }
 
     fn signal_release(&mut self) {
         self.is_released = true;
     }
+
+    fn scale(&self) -> f32 {
+        self.buffer.scale()
+    }
+}
+
+/// Reads the sample at an offset from an index, used by the interpolators
+/// that read multiple samples. Positions before the start are silent.
+#[inline(always)]
+fn read_tap(reader: &mut impl SampleReader, index: i32, offset: i32) -> f32 {
+    let pos = index + offset;
+    if pos < 0 {
+        0.0
+    } else {
+        reader.get(pos as usize)
+    }
 }
 
 // Sample grabbers enum
@@ -227,6 +366,9 @@ impl<Sampler: BufferSampler> SampleReader for SampleReaderLoopSustain<Sampler> {
 pub enum SIMDSampleGrabbers<S: Simd, Reader: SampleReader> {
     Nearest(SIMDNearestSampleGrabber<S, Reader>),
     Linear(SIMDLinearSampleGrabber<S, Reader>),
+    Hermite(SIMDHermiteSampleGrabber<S, Reader>),
+    Lagrange(SIMDLagrangeSampleGrabber<S, Reader>),
+    Sinc(SIMDSincSampleGrabber<S, Reader>),
 }
 
 impl<S: Simd, Reader: SampleReader> SIMDSampleGrabbers<S, Reader> {
@@ -237,6 +379,18 @@ impl<S: Simd, Reader: SampleReader> SIMDSampleGrabbers<S, Reader> {
     pub fn linear(reader: Reader) -> Self {
         SIMDSampleGrabbers::Linear(SIMDLinearSampleGrabber::new(reader))
     }
+
+    pub fn hermite(reader: Reader) -> Self {
+        SIMDSampleGrabbers::Hermite(SIMDHermiteSampleGrabber::new(reader))
+    }
+
+    pub fn lagrange(reader: Reader) -> Self {
+        SIMDSampleGrabbers::Lagrange(SIMDLagrangeSampleGrabber::new(reader))
+    }
+
+    pub fn sinc(reader: Reader, taps: usize) -> Self {
+        SIMDSampleGrabbers::Sinc(SIMDSincSampleGrabber::new(reader, taps))
+    }
 }
 
 impl<S: Simd, Reader: SampleReader> SIMDSampleGrabber<S> for SIMDSampleGrabbers<S, Reader> {
@@ -245,6 +399,9 @@ impl<S: Simd, Reader: SampleReader> SIMDSampleGrabber<S> for SIMDSampleGrabbers<
         match self {
             SIMDSampleGrabbers::Linear(grabber) => grabber.get(indexes, fractional),
             SIMDSampleGrabbers::Nearest(grabber) => grabber.get(indexes, fractional),
+            SIMDSampleGrabbers::Hermite(grabber) => grabber.get(indexes, fractional),
+            SIMDSampleGrabbers::Lagrange(grabber) => grabber.get(indexes, fractional),
+            SIMDSampleGrabbers::Sinc(grabber) => grabber.get(indexes, fractional),
         }
     }
 
@@ -253,6 +410,9 @@ impl<S: Simd, Reader: SampleReader> SIMDSampleGrabber<S> for SIMDSampleGrabbers<
         match self {
             SIMDSampleGrabbers::Linear(grabber) => grabber.is_past_end(pos),
             SIMDSampleGrabbers::Nearest(grabber) => grabber.is_past_end(pos),
+            SIMDSampleGrabbers::Hermite(grabber) => grabber.is_past_end(pos),
+            SIMDSampleGrabbers::Lagrange(grabber) => grabber.is_past_end(pos),
+            SIMDSampleGrabbers::Sinc(grabber) => grabber.is_past_end(pos),
         }
     }
 
@@ -261,6 +421,9 @@ impl<S: Simd, Reader: SampleReader> SIMDSampleGrabber<S> for SIMDSampleGrabbers<
         match self {
             SIMDSampleGrabbers::Linear(grabber) => grabber.signal_release(),
             SIMDSampleGrabbers::Nearest(grabber) => grabber.signal_release(),
+            SIMDSampleGrabbers::Hermite(grabber) => grabber.signal_release(),
+            SIMDSampleGrabbers::Lagrange(grabber) => grabber.signal_release(),
+            SIMDSampleGrabbers::Sinc(grabber) => grabber.signal_release(),
         }
     }
 }
@@ -448,3 +611,157 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use simdeez::scalar::Scalar;
+    use xsynth_soundfonts::LoopMode;
+
+    use super::*;
+
+    const LOOP_START: u32 = 64;
+    const LOOP_END: u32 = 128;
+    const TAIL: f32 = 0.25;
+
+    /// A sine that loops seamlessly between the loop points, followed by
+    /// a constant tail.
+    fn loop_sample() -> BufferSamplers {
+        let period = (LOOP_END - LOOP_START) as f32;
+        let sample: Arc<[f32]> = (0..LOOP_END + 64)
+            .map(|i| {
+                if i < LOOP_END {
+                    (i as f32 / period * 2.0 * std::f32::consts::PI).sin()
+                } else {
+                    TAIL
+                }
+            })
+            .collect();
+        BufferSamplers::new_f32(sample)
+    }
+
+    fn loop_params(mode: LoopMode) -> LoopParams {
+        LoopParams {
+            mode,
+            offset: 0,
+            start: LOOP_START,
+            end: LOOP_END,
+        }
+    }
+
+    fn get(grabber: &mut impl SIMDSampleGrabber<Scalar>, pos: f32) -> f32 {
+        grabber.get(
+            <Scalar as Simd>::Vi32::set1(pos as i32),
+            <Scalar as Simd>::Vf32::set1(pos.fract()),
+        )[0]
+    }
+
+    /// Checks that the positions around the loop end read the continuation
+    /// of the sine, without a jump or a repeated sample.
+    fn assert_seamless_loop(grabber: &mut impl SIMDSampleGrabber<Scalar>) {
+        let period = (LOOP_END - LOOP_START) as f32;
+        let end = LOOP_END as f32;
+        for pos in [
+            end - 2.5,
+            end - 1.0,
+            end - 0.5,
+            end,
+            end + 0.5,
+            end + 1.25,
+            end + 3.0,
+        ] {
+            let expected = (pos / period * 2.0 * std::f32::consts::PI).sin();
+            let value = get(grabber, pos);
+            assert!(
+                (value - expected).abs() < 2e-3,
+                "{pos}: {value} != {expected}"
+            );
+        }
+        assert!(!grabber.is_past_end(end as f64 * 4.0));
+    }
+
+    fn loop_grabbers<Reader: SampleReader>(
+        reader: impl Fn() -> Reader,
+    ) -> Vec<SIMDSampleGrabbers<Scalar, Reader>> {
+        vec![
+            SIMDSampleGrabbers::hermite(reader()),
+            SIMDSampleGrabbers::lagrange(reader()),
+            SIMDSampleGrabbers::sinc(reader(), 8),
+            SIMDSampleGrabbers::sinc(reader(), 32),
+        ]
+    }
+
+    #[test]
+    fn test_loop_boundary() {
+        let reader = || SampleReaderLoop::new(loop_sample(), loop_params(LoopMode::LoopContinuous));
+        for mut grabber in loop_grabbers(reader) {
+            assert_seamless_loop(&mut grabber);
+        }
+    }
+
+    #[test]
+    fn test_loop_sustain_boundary() {
+        let reader =
+            || SampleReaderLoopSustain::new(loop_sample(), loop_params(LoopMode::LoopSustain));
+        for mut grabber in loop_grabbers(reader) {
+            assert_seamless_loop(&mut grabber);
+
+            // After the release, the loop plays to its end and continues
+            // into the tail
+            grabber.signal_release();
+            let end = LOOP_END as f32;
+            let tail = get(&mut grabber, end + 100.5);
+            assert!((tail - TAIL).abs() < 1e-3, "{tail}");
+            assert!(grabber.is_past_end(end as f64 + 200.0));
+        }
+    }
+
+    #[test]
+    fn test_wrap_loop_position() {
+        assert_eq!(wrap_loop_position(127, 64, 128), 127);
+        assert_eq!(wrap_loop_position(128, 64, 128), 64);
+        assert_eq!(wrap_loop_position(200, 64, 128), 72);
+
+        // Positions before the loop and empty loops are unchanged
+        assert_eq!(wrap_loop_position(10, 64, 128), 10);
+        assert_eq!(wrap_loop_position(200, 64, 64), 200);
+    }
+
+    #[test]
+    fn test_sf2_sample_formats() {
+        let i16_data: Arc<[i16]> = Arc::new([0, 16384, -32767]);
+        let i24_data: Arc<[[u8; 3]]> = Arc::new([[0, 0, 0], [0, 0, 0x40], [1, 0, 0x80]]);
+        let i16_sample = Sf2SampleData::I16(i16_data.clone());
+        let i24_sample = Sf2SampleData::I24(i24_data.clone());
+
+        // The integer values of the file are shared, not converted
+        match SampleBuffer::from_sf2(&i16_sample, SampleFormat::I16) {
+            SampleBuffer::I16(values, _) => assert!(Arc::ptr_eq(&values, &i16_data)),
+            _ => panic!("16 bit sample was converted"),
+        }
+        match SampleBuffer::from_sf2(&i16_sample, SampleFormat::I24) {
+            SampleBuffer::I16(values, _) => assert!(Arc::ptr_eq(&values, &i16_data)),
+            _ => panic!("16 bit sample was converted"),
+        }
+        match SampleBuffer::from_sf2(&i24_sample, SampleFormat::I24) {
+            SampleBuffer::I24(values, _) => assert!(Arc::ptr_eq(&values, &i24_data)),
+            _ => panic!("24 bit sample was converted"),
+        }
+
+        // The other formats get the same values
+        let expected = [0.0, 0.5, -1.0];
+        for sample in [&i16_sample, &i24_sample] {
+            for format in [
+                SampleFormat::F32,
+                SampleFormat::I16,
+                SampleFormat::I24,
+                SampleFormat::F16,
+            ] {
+                let sampler = BufferSamplers::new(SampleBuffer::from_sf2(sample, format));
+                for (pos, &expected) in expected.iter().enumerate() {
+                    let value = sampler.get(pos) * sampler.scale();
+                    assert!((value - expected).abs() < 1e-3, "{value} != {expected}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use super::BufferSampler;
+
+pub(crate) const I16_SCALE: f32 = i16::MAX as f32;
+pub(crate) const I24_SCALE: f32 = 8_388_607.0;
+
+/// Returns the value that is mapped to the largest integer value: the
+/// peak of a sample that goes above full scale, otherwise 1.
+fn sample_peak(sample: &[f32]) -> f32 {
+    sample
+        .iter()
+        .filter(|s| s.is_finite())
+        .fold(1.0f32, |peak, s| peak.max(s.abs()))
+}
+
+// 16 bit integer sampler
+
+/// Returns the integer values of the samples, the grabbers scale them
+/// back to their original range.
+pub struct I16BufferSampler {
+    sample: Arc<[i16]>,
+    scale: f32,
+}
+
+impl I16BufferSampler {
+    /// Creates a new sampler from the values and scale returned by `convert`.
+    pub fn new(sample: Arc<[i16]>, scale: f32) -> Self {
+        Self { sample, scale }
+    }
+
+    /// Converts a sample to integers spanning the full 16 bit range.
+    /// Returns the values and the scale that converts them back.
+    pub fn convert(sample: &[f32]) -> (Arc<[i16]>, f32) {
+        let peak = sample_peak(sample);
+        let values = sample
+            .iter()
+            .map(|&s| (s / peak * I16_SCALE).round() as i16)
+            .collect();
+        (values, peak / I16_SCALE)
+    }
+}
+
+impl BufferSampler for I16BufferSampler {
+    #[inline(always)]
+    fn get(&self, pos: usize) -> f32 {
+        match self.sample.get(pos) {
+            Some(v) => *v as f32,
+            None => 0.0,
+        }
+    }
+
+    fn length(&self) -> usize {
+        self.sample.len()
+    }
+
+    fn scale(&self) -> f32 {
+        self.scale
+    }
+}
+
+// 24 bit integer sampler
+
+/// Stores the samples as packed little endian 24 bit integers. Like the
+/// 16 bit sampler, it returns integer values.
+pub struct I24BufferSampler {
+    sample: Arc<[[u8; 3]]>,
+    scale: f32,
+}
+
+impl I24BufferSampler {
+    /// Creates a new sampler from the values and scale returned by `convert`.
+    pub fn new(sample: Arc<[[u8; 3]]>, scale: f32) -> Self {
+        Self { sample, scale }
+    }
+
+    /// Converts a sample to integers spanning the full 24 bit range.
+    /// Returns the values and the scale that converts them back.
+    pub fn convert(sample: &[f32]) -> (Arc<[[u8; 3]]>, f32) {
+        let peak = sample_peak(sample);
+        let values = sample
+            .iter()
+            .map(|&s| {
+                let [b0, b1, b2, _] = ((s / peak * I24_SCALE).round() as i32).to_le_bytes();
+                [b0, b1, b2]
+            })
+            .collect();
+        (values, peak / I24_SCALE)
+    }
+}
+
+impl BufferSampler for I24BufferSampler {
+    #[inline(always)]
+    fn get(&self, pos: usize) -> f32 {
+        match self.sample.get(pos) {
+            // Shifting back down extends the sign
+            Some(&[b0, b1, b2]) => (i32::from_le_bytes([0, b0, b1, b2]) >> 8) as f32,
+            None => 0.0,
+        }
+    }
+
+    fn length(&self) -> usize {
+        self.sample.len()
+    }
+
+    fn scale(&self) -> f32 {
+        self.scale
+    }
+}
+
+// Half float sampler
+
+/// Stores the samples as IEEE 754 half precision floats.
+pub struct F16BufferSampler(Arc<[u16]>);
+
+impl F16BufferSampler {
+    pub fn new(sample: Arc<[u16]>) -> Self {
+        Self(sample)
+    }
+
+    pub fn convert(sample: &[f32]) -> Arc<[u16]> {
+        sample.iter().map(|&s| f32_to_f16(s)).collect()
+    }
+}
+
+impl BufferSampler for F16BufferSampler {
+    #[inline(always)]
+    fn get(&self, pos: usize) -> f32 {
+        match self.0.get(pos) {
+            Some(v) => f16_to_f32(*v),
+            None => 0.0,
+        }
+    }
+
+    fn length(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Converts a float to half precision, rounding to the nearest value.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    // Infinity and NaN
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        // Too large, rounds to infinity
+        sign | 0x7c00
+    } else if exponent <= 0 {
+        // Subnormal or zero
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let round = (mantissa >> (shift - 1)) & 1;
+        sign | ((mantissa >> shift) + round) as u16
+    } else {
+        // A carry from the rounding correctly moves on to the exponent
+        let half = ((exponent as u32) << 10) | (mantissa >> 13);
+        let round = (mantissa >> 12) & 1;
+        sign | (half + round) as u16
+    }
+}
+
+#[inline(always)]
+pub(super) fn f16_to_f32(value: u16) -> f32 {
+    let sign = ((value & 0x8000) as u32) << 16;
+    let exponent = ((value >> 10) & 0x1f) as u32;
+    let mantissa = (value & 0x3ff) as u32;
+
+    let bits = match exponent {
+        0 => {
+            // Subnormal or zero
+            let value = mantissa as f32 / 16_777_216.0;
+            return if sign != 0 { -value } else { value };
+        }
+        0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_formats() {
+        let sample: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.1).sin()).collect();
+
+        let (values, scale) = I16BufferSampler::convert(&sample);
+        let i16_sampler = I16BufferSampler::new(values, scale);
+        let (values, scale) = I24BufferSampler::convert(&sample);
+        let i24_sampler = I24BufferSampler::new(values, scale);
+        let f16_sampler = F16BufferSampler::new(F16BufferSampler::convert(&sample));
+
+        for (pos, &expected) in sample.iter().enumerate() {
+            let value = |sampler: &dyn BufferSampler| sampler.get(pos) * sampler.scale();
+            assert!((value(&i16_sampler) - expected).abs() <= 0.5 / I16_SCALE);
+            assert!((value(&i24_sampler) - expected).abs() <= 0.5 / I24_SCALE + f32::EPSILON);
+            assert!((value(&f16_sampler) - expected).abs() <= expected.abs() / 2048.0 + 1e-7);
+        }
+
+        assert_eq!(f16_to_f32(f32_to_f16(-1.0)), -1.0);
+        assert_eq!(f16_to_f32(f32_to_f16(0.5)), 0.5);
+        assert_eq!(i24_sampler.length(), sample.len());
+    }
+
+    #[test]
+    fn test_integer_formats_above_full_scale() {
+        let sample: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.1).sin() * 2.5).collect();
+
+        let (values, scale) = I16BufferSampler::convert(&sample);
+        let i16_sampler = I16BufferSampler::new(values, scale);
+        let (values, scale) = I24BufferSampler::convert(&sample);
+        let i24_sampler = I24BufferSampler::new(values, scale);
+
+        for (pos, &expected) in sample.iter().enumerate() {
+            let value = |sampler: &dyn BufferSampler| sampler.get(pos) * sampler.scale();
+            assert!((value(&i16_sampler) - expected).abs() <= 2.5 / I16_SCALE);
+            assert!((value(&i24_sampler) - expected).abs() <= 2.5 / I24_SCALE);
+        }
+
+        // Samples within full scale keep the -1 to 1 range
+        let (values, scale) = I16BufferSampler::convert(&[0.0, 0.5]);
+        assert_eq!(scale, 1.0 / I16_SCALE);
+        assert_eq!(*values, [0, 16384]);
+    }
+}
@@ -0,0 +1,57 @@
+use std::marker::PhantomData;
+
+use simdeez::prelude::*;
+
+use super::{read_tap, SIMDSampleGrabber, SampleReader};
+
+/// 4-point cubic Hermite (Catmull-Rom) interpolation.
+pub struct SIMDHermiteSampleGrabber<S: Simd, Reader: SampleReader> {
+    sampler_reader: Reader,
+    _s: PhantomData<S>,
+}
+
+impl<S: Simd, Reader: SampleReader> SIMDHermiteSampleGrabber<S, Reader> {
+    pub fn new(sampler_reader: Reader) -> Self {
+        SIMDHermiteSampleGrabber {
+            sampler_reader,
+            _s: PhantomData,
+        }
+    }
+}
+
+impl<S: Simd, Reader: SampleReader> SIMDSampleGrabber<S> for SIMDHermiteSampleGrabber<S, Reader> {
+    fn get(&mut self, indexes: S::Vi32, fractional: S::Vf32) -> S::Vf32 {
+        simd_invoke!(S, {
+            let t = fractional;
+            let mut prev = S::Vf32::zeroes();
+            let mut first = S::Vf32::zeroes();
+            let mut second = S::Vf32::zeroes();
+            let mut next = S::Vf32::zeroes();
+
+            for i in 0..S::Vf32::WIDTH {
+                let index = indexes[i];
+                prev[i] = read_tap(&mut self.sampler_reader, index, -1);
+                first[i] = read_tap(&mut self.sampler_reader, index, 0);
+                second[i] = read_tap(&mut self.sampler_reader, index, 1);
+                next[i] = read_tap(&mut self.sampler_reader, index, 2);
+            }
+
+            let half = S::Vf32::set1(0.5);
+            let c1 = half * (second - prev);
+            let c2 = prev - S::Vf32::set1(2.5) * first + S::Vf32::set1(2.0) * second - half * next;
+            let c3 = half * (next - prev) + S::Vf32::set1(1.5) * (first - second);
+
+            let scale = S::Vf32::set1(self.sampler_reader.scale());
+            (((c3 * t + c2) * t + c1) * t + first) * scale
+        })
+    }
+
+    fn is_past_end(&self, pos: f64) -> bool {
+        let pos = pos as usize;
+        self.sampler_reader.is_past_end(pos)
+    }
+
+    fn signal_release(&mut self) {
+        self.sampler_reader.signal_release();
+    }
+}
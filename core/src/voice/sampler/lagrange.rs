@@ -0,0 +1,64 @@
+use std::marker::PhantomData;
+
+use simdeez::prelude::*;
+
+use super::{read_tap, SIMDSampleGrabber, SampleReader};
+
+/// 4-point, 3rd order Lagrange interpolation.
+pub struct SIMDLagrangeSampleGrabber<S: Simd, Reader: SampleReader> {
+    sampler_reader: Reader,
+    _s: PhantomData<S>,
+}
+
+impl<S: Simd, Reader: SampleReader> SIMDLagrangeSampleGrabber<S, Reader> {
+    pub fn new(sampler_reader: Reader) -> Self {
+        SIMDLagrangeSampleGrabber {
+            sampler_reader,
+            _s: PhantomData,
+        }
+    }
+}
+
+impl<S: Simd, Reader: SampleReader> SIMDSampleGrabber<S> for SIMDLagrangeSampleGrabber<S, Reader> {
+    fn get(&mut self, indexes: S::Vi32, fractional: S::Vf32) -> S::Vf32 {
+        simd_invoke!(S, {
+            let t = fractional;
+            let mut prev = S::Vf32::zeroes();
+            let mut first = S::Vf32::zeroes();
+            let mut second = S::Vf32::zeroes();
+            let mut next = S::Vf32::zeroes();
+
+            for i in 0..S::Vf32::WIDTH {
+                let index = indexes[i];
+                prev[i] = read_tap(&mut self.sampler_reader, index, -1);
+                first[i] = read_tap(&mut self.sampler_reader, index, 0);
+                second[i] = read_tap(&mut self.sampler_reader, index, 1);
+                next[i] = read_tap(&mut self.sampler_reader, index, 2);
+            }
+
+            // The basis polynomials of the points at -1, 0, 1 and 2
+            let ones = S::Vf32::set1(1.0);
+            let twos = S::Vf32::set1(2.0);
+            let t_prev = t + ones;
+            let t_second = t - ones;
+            let t_next = t - twos;
+
+            let w_prev = t * t_second * t_next * S::Vf32::set1(-1.0 / 6.0);
+            let w_first = t_prev * t_second * t_next * S::Vf32::set1(0.5);
+            let w_second = t_prev * t * t_next * S::Vf32::set1(-0.5);
+            let w_next = t_prev * t * t_second * S::Vf32::set1(1.0 / 6.0);
+
+            let scale = S::Vf32::set1(self.sampler_reader.scale());
+            (prev * w_prev + first * w_first + second * w_second + next * w_next) * scale
+        })
+    }
+
+    fn is_past_end(&self, pos: f64) -> bool {
+        let pos = pos as usize;
+        self.sampler_reader.is_past_end(pos)
+    }
+
+    fn signal_release(&mut self) {
+        self.sampler_reader.signal_release();
+    }
+}
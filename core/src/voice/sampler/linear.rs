@@ -32,9 +32,10 @@ impl<S: Simd, Reader: SampleReader> SIMDSampleGrabber<S> for SIMDLinearSampleGra
                 values_second[i] = self.sampler_reader.get(index + 1);
             }
 
+            let scale = S::Vf32::set1(self.sampler_reader.scale());
             let blended = values_first * (ones - blend) + values_second * blend;
 
-            blended
+            blended * scale
         },)
     }
 
@@ -29,7 +29,7 @@ impl<S: Simd, Reader: SampleReader> SIMDSampleGrabber<S> for SIMDNearestSampleGr
                 *values.get_unchecked_mut(i) = self.sampler_reader.get(index);
             }
 
-            values
+            values * S::Vf32::set1(self.sampler_reader.scale())
         })
     }
 
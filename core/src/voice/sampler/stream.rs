@@ -0,0 +1,539 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use atomic_refcell::AtomicRefCell;
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
+
+use super::{formats::f16_to_f32, BufferSampler, BufferSamplers, SampleBuffer};
+use crate::soundfont::SampleFormat;
+
+/// The number of samples read from the disk at once.
+const STREAM_CHUNK_SIZE: usize = 8192;
+
+/// The number of chunks each voice keeps in memory. One slot holds the
+/// previous chunk for the interpolator, the rest are read ahead.
+const STREAM_SLOTS: usize = 4;
+
+/// The number of chunk requests that can wait for the I/O thread. When the
+/// queue is full, voices request the chunks again on their next sample.
+const STREAM_QUEUE_SIZE: usize = 1024;
+
+/// How often the I/O thread retries storing the chunks of which the slot
+/// was being read by a voice.
+const STREAM_RETRY_INTERVAL: Duration = Duration::from_millis(1);
+
+const NO_CHUNK: usize = usize::MAX;
+
+/// A temporary file that holds the tails of the streamed samples, in their
+/// sample format. The file is deleted once all of the samples using it
+/// are dropped.
+struct CacheFile {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl CacheFile {
+    fn new(dir: &Path) -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = dir.join(format!(
+            "xsynth-stream-{}-{}.tmp",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+
+        Ok(CacheFile {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Appends data to the file and returns its position in bytes.
+    fn append(&self, data: &[u8]) -> io::Result<u64> {
+        let mut file = self.file.lock().unwrap();
+        let position = file.seek(SeekFrom::End(0))?;
+        file.write_all(data)?;
+        Ok(position)
+    }
+
+    fn read(&self, position: u64, data: &mut [u8]) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start(position))?;
+        file.read_exact(data)
+    }
+}
+
+impl Drop for CacheFile {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+/// The number of bytes that a value takes in the cache file.
+fn value_size(format: SampleFormat) -> usize {
+    match format {
+        SampleFormat::F32 => 4,
+        SampleFormat::I16 | SampleFormat::F16 => 2,
+        SampleFormat::I24 => 3,
+    }
+}
+
+/// Converts values read from the cache file to floats. Integer values are
+/// kept unscaled, like in the in-memory samplers.
+fn decode_values(format: SampleFormat, data: &[u8], values: &mut Vec<f32>) {
+    let size = value_size(format);
+    let chunks = data.chunks_exact(size);
+    match format {
+        SampleFormat::F32 => {
+            values.extend(chunks.map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]])))
+        }
+        SampleFormat::I16 => values.extend(chunks.map(|b| i16::from_ne_bytes([b[0], b[1]]) as f32)),
+        SampleFormat::I24 => values.extend(
+            // Shifting back down extends the sign
+            chunks.map(|b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32),
+        ),
+        SampleFormat::F16 => {
+            values.extend(chunks.map(|b| f16_to_f32(u16::from_ne_bytes([b[0], b[1]]))))
+        }
+    }
+}
+
+/// Splits the values of a sample into the ones kept in memory and the
+/// bytes of the rest.
+fn split_values<T: bytemuck::Pod>(values: &[T], preload: usize) -> (Arc<[T]>, &[u8]) {
+    let (head, tail) = values.split_at(preload);
+    (head.into(), bytemuck::cast_slice(tail))
+}
+
+/// A chunk of a streamed sample, shared between a voice and the I/O thread.
+struct StreamSlot {
+    chunk: AtomicUsize,
+    data: AtomicRefCell<Vec<f32>>,
+}
+
+type StreamSlots = [StreamSlot; STREAM_SLOTS];
+
+struct StreamRequest {
+    slots: Arc<StreamSlots>,
+    chunk: usize,
+    format: SampleFormat,
+    position: u64,
+    length: usize,
+}
+
+impl StreamRequest {
+    /// Skips the voices that already ended
+    fn is_dropped(&self) -> bool {
+        Arc::strong_count(&self.slots) == 1
+    }
+
+    /// Moves a chunk into its slot. Voices only hold the borrow of a slot
+    /// while reading a single sample, so if it's borrowed the chunk is
+    /// returned to be stored later.
+    fn store(&self, values: Vec<f32>) -> Result<(), Vec<f32>> {
+        let slot = &self.slots[self.chunk % STREAM_SLOTS];
+        match slot.data.try_borrow_mut() {
+            Ok(mut data) => {
+                *data = values;
+                slot.chunk.store(self.chunk, Ordering::Release);
+                Ok(())
+            }
+            Err(_) => Err(values),
+        }
+    }
+}
+
+/// Reads the requested chunks from the cache file on the I/O thread.
+struct StreamReader {
+    file: Arc<CacheFile>,
+    receiver: Receiver<StreamRequest>,
+
+    /// Chunks that were read while their slot was borrowed by a voice
+    pending: Vec<(StreamRequest, Vec<f32>)>,
+    buffer: Vec<u8>,
+}
+
+impl StreamReader {
+    /// Reads chunks until all the streamed samples are dropped.
+    fn run(mut self) {
+        loop {
+            let request = if self.pending.is_empty() {
+                self.receiver
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                self.receiver.recv_timeout(STREAM_RETRY_INTERVAL)
+            };
+
+            match request {
+                Ok(request) => self.read(request),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            self.store_pending();
+        }
+    }
+
+    fn read(&mut self, request: StreamRequest) {
+        if request.is_dropped() {
+            return;
+        }
+
+        self.buffer.clear();
+        self.buffer
+            .resize(request.length * value_size(request.format), 0);
+        if self.file.read(request.position, &mut self.buffer).is_err() {
+            return;
+        }
+
+        let mut values = Vec::with_capacity(request.length);
+        decode_values(request.format, &self.buffer, &mut values);
+        if let Err(values) = request.store(values) {
+            self.pending.push((request, values));
+        }
+    }
+
+    fn store_pending(&mut self) {
+        for (request, values) in std::mem::take(&mut self.pending) {
+            if request.is_dropped() {
+                continue;
+            }
+            if let Err(values) = request.store(values) {
+                self.pending.push((request, values));
+            }
+        }
+    }
+}
+
+/// Stores the streamed samples of a soundfont on the disk, and reads
+/// them back on a background I/O thread while they are played.
+pub struct StreamCache {
+    file: Arc<CacheFile>,
+    sender: Sender<StreamRequest>,
+}
+
+impl StreamCache {
+    /// Creates the cache file in `dir`, or in the temporary directory of
+    /// the system if it's `None`.
+    pub fn new(dir: Option<&Path>) -> io::Result<Self> {
+        let (cache, reader) = Self::with_reader(dir)?;
+        thread::Builder::new()
+            .name("xsynth_sample_stream".to_string())
+            .spawn(move || reader.run())?;
+
+        Ok(cache)
+    }
+
+    fn with_reader(dir: Option<&Path>) -> io::Result<(Self, StreamReader)> {
+        let file = match dir {
+            Some(dir) => CacheFile::new(dir)?,
+            None => CacheFile::new(&std::env::temp_dir())?,
+        };
+        let file = Arc::new(file);
+        let (sender, receiver) = bounded::<StreamRequest>(STREAM_QUEUE_SIZE);
+
+        let reader = StreamReader {
+            file: file.clone(),
+            receiver,
+            pending: Vec::new(),
+            buffer: Vec::new(),
+        };
+        Ok((StreamCache { file, sender }, reader))
+    }
+
+    /// Keeps the first `preload` samples in memory and moves the rest
+    /// of the sample to the disk, both stored in the given format.
+    pub fn store(
+        &self,
+        sample: &Arc<[f32]>,
+        preload: usize,
+        format: SampleFormat,
+    ) -> io::Result<StreamedSample> {
+        let preload = preload.min(sample.len());
+        let (head, tail) = match SampleBuffer::new(sample, format) {
+            SampleBuffer::F32(values) => {
+                let (head, tail) = split_values(&values, preload);
+                (SampleBuffer::F32(head), self.file.append(tail)?)
+            }
+            SampleBuffer::I16(values, scale) => {
+                let (head, tail) = split_values(&values, preload);
+                (SampleBuffer::I16(head, scale), self.file.append(tail)?)
+            }
+            SampleBuffer::I24(values, scale) => {
+                let (head, tail) = split_values(&values, preload);
+                (SampleBuffer::I24(head, scale), self.file.append(tail)?)
+            }
+            SampleBuffer::F16(values) => {
+                let (head, tail) = split_values(&values, preload);
+                (SampleBuffer::F16(head), self.file.append(tail)?)
+            }
+            SampleBuffer::Stream(_) => unreachable!("samples are converted to a stored format"),
+        };
+
+        Ok(StreamedSample {
+            head: BufferSamplers::new(head),
+            length: sample.len(),
+            format,
+            position: tail,
+            sender: self.sender.clone(),
+            _file: self.file.clone(),
+        })
+    }
+}
+
+/// A sample of which only the start is kept in memory.
+pub struct StreamedSample {
+    head: BufferSamplers,
+    length: usize,
+    format: SampleFormat,
+
+    /// The position of the rest of the sample in the cache file, in bytes
+    position: u64,
+    sender: Sender<StreamRequest>,
+    _file: Arc<CacheFile>,
+}
+
+impl StreamedSample {
+    fn chunk_count(&self) -> usize {
+        (self.length - self.head.length()).div_ceil(STREAM_CHUNK_SIZE)
+    }
+}
+
+/// Plays a streamed sample. The start of the sample is played from memory
+/// while the next chunks are read from the disk. If a chunk isn't read in
+/// time, the sampler returns silence until it arrives.
+pub struct StreamBufferSampler {
+    sample: Arc<StreamedSample>,
+    slots: Arc<StreamSlots>,
+
+    /// The first chunk that hasn't been requested yet
+    next_request: AtomicUsize,
+}
+
+impl StreamBufferSampler {
+    pub fn new(sample: Arc<StreamedSample>) -> Self {
+        let sampler = StreamBufferSampler {
+            sample,
+            slots: Arc::new(std::array::from_fn(|_| StreamSlot {
+                chunk: AtomicUsize::new(NO_CHUNK),
+                data: AtomicRefCell::new(Vec::new()),
+            })),
+            next_request: AtomicUsize::new(0),
+        };
+
+        // Start reading while the head is playing
+        sampler.request_chunks(0);
+        sampler
+    }
+
+    /// Requests the chunks after the one being played, keeping the
+    /// previous chunk in memory. This runs on the audio thread, so the
+    /// requests are never blocked on: the ones that don't fit in the
+    /// queue are made again on the next call.
+    #[inline(always)]
+    fn request_chunks(&self, chunk: usize) {
+        let last = (chunk + STREAM_SLOTS - 1).min(self.sample.chunk_count());
+        let next = self.next_request.load(Ordering::Relaxed);
+        if next >= last {
+            return;
+        }
+
+        let tail_length = self.sample.length - self.sample.head.length();
+        let value_size = value_size(self.sample.format);
+        for chunk in next.max(chunk)..last {
+            let start = chunk * STREAM_CHUNK_SIZE;
+            let request = StreamRequest {
+                slots: self.slots.clone(),
+                chunk,
+                format: self.sample.format,
+                position: self.sample.position + (start * value_size) as u64,
+                length: STREAM_CHUNK_SIZE.min(tail_length - start),
+            };
+            if self.sample.sender.try_send(request).is_err() {
+                self.next_request.store(chunk, Ordering::Relaxed);
+                return;
+            }
+        }
+        self.next_request.store(last, Ordering::Relaxed);
+    }
+}
+
+impl BufferSampler for StreamBufferSampler {
+    #[inline(always)]
+    fn get(&self, pos: usize) -> f32 {
+        let head = &self.sample.head;
+        if pos < head.length() {
+            return head.get(pos);
+        }
+        if pos >= self.sample.length {
+            return 0.0;
+        }
+
+        let pos = pos - head.length();
+        let chunk = pos / STREAM_CHUNK_SIZE;
+        self.request_chunks(chunk);
+
+        let slot = &self.slots[chunk % STREAM_SLOTS];
+        match slot.data.try_borrow() {
+            Ok(data) if slot.chunk.load(Ordering::Acquire) == chunk => {
+                data.get(pos % STREAM_CHUNK_SIZE).copied().unwrap_or(0.0)
+            }
+            // The chunk is still being read
+            _ => 0.0,
+        }
+    }
+
+    fn length(&self) -> usize {
+        self.sample.length
+    }
+
+    fn scale(&self) -> f32 {
+        self.sample.head.scale()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache() -> (StreamCache, StreamReader) {
+        StreamCache::with_reader(None).unwrap()
+    }
+
+    fn stream(
+        cache: &StreamCache,
+        sample: &Arc<[f32]>,
+        format: SampleFormat,
+    ) -> StreamBufferSampler {
+        StreamBufferSampler::new(Arc::new(cache.store(sample, 1000, format).unwrap()))
+    }
+
+    /// Reads the queued chunks on the current thread.
+    fn read_queued(reader: &mut StreamReader) {
+        while let Ok(request) = reader.receiver.try_recv() {
+            reader.read(request);
+        }
+        reader.store_pending();
+    }
+
+    #[test]
+    fn test_stream_sampler() {
+        let sample: Arc<[f32]> = (0..50000).map(|i| i as f32 + 1.0).collect();
+        let (cache, mut reader) = cache();
+        let sampler = stream(&cache, &sample, SampleFormat::F32);
+        assert_eq!(sampler.length(), sample.len());
+
+        for (pos, &expected) in sample.iter().enumerate() {
+            let mut value = sampler.get(pos);
+            if value == 0.0 {
+                read_queued(&mut reader);
+                value = sampler.get(pos);
+            }
+            assert_eq!(value, expected);
+        }
+        assert_eq!(sampler.get(sample.len()), 0.0);
+    }
+
+    #[test]
+    fn test_stream_underrun() {
+        let sample: Arc<[f32]> = (0..50000).map(|i| i as f32 + 1.0).collect();
+        let (cache, mut reader) = cache();
+        let sampler = stream(&cache, &sample, SampleFormat::F32);
+
+        // The head plays from memory, the chunks after it are silent
+        // until they are read
+        assert_eq!(sampler.get(999), 1000.0);
+        assert_eq!(sampler.get(1000), 0.0);
+        read_queued(&mut reader);
+        assert_eq!(sampler.get(1000), 1001.0);
+
+        // Chunks past the read ahead are requested once they are reached
+        let far = 1000 + STREAM_CHUNK_SIZE * 4;
+        assert_eq!(sampler.get(far), 0.0);
+
+        // A chunk read while a voice borrows its slot is stored afterwards
+        let next = far + STREAM_CHUNK_SIZE;
+        let borrow = sampler.slots[5 % STREAM_SLOTS].data.borrow();
+        read_queued(&mut reader);
+        assert_eq!(reader.pending.len(), 1);
+        assert_eq!(sampler.get(far), far as f32 + 1.0);
+        drop(borrow);
+        reader.store_pending();
+        assert!(reader.pending.is_empty());
+        assert_eq!(sampler.get(next), next as f32 + 1.0);
+    }
+
+    #[test]
+    fn test_stream_queue_full() {
+        let sample: Arc<[f32]> = (0..1100).map(|i| i as f32 + 1.0).collect();
+        let (cache, mut reader) = cache();
+
+        // Each sampler requests its only chunk, filling the queue
+        let samplers: Vec<_> = (0..STREAM_QUEUE_SIZE + 1)
+            .map(|_| stream(&cache, &sample, SampleFormat::F32))
+            .collect();
+        let last = samplers.last().unwrap();
+        assert_eq!(last.next_request.load(Ordering::Relaxed), 0);
+
+        // The requests that didn't fit are made again when the chunks are played
+        read_queued(&mut reader);
+        assert_eq!(last.get(1000), 0.0);
+        read_queued(&mut reader);
+        for sampler in samplers.iter() {
+            if sampler.get(1000) == 0.0 {
+                read_queued(&mut reader);
+            }
+            assert_eq!(sampler.get(1000), 1001.0);
+        }
+    }
+
+    #[test]
+    fn test_stream_formats() {
+        let sample: Arc<[f32]> = (0..20000).map(|i| (i as f32 * 0.01).sin()).collect();
+
+        for (format, max_error) in [
+            (SampleFormat::F32, 0.0),
+            (SampleFormat::I16, 1e-4),
+            (SampleFormat::I24, 1e-6),
+            (SampleFormat::F16, 1e-3),
+        ] {
+            let (cache, mut reader) = cache();
+            let sampler = stream(&cache, &sample, format);
+
+            // The tail is stored in the sample format
+            let file_length = cache.file.file.lock().unwrap().metadata().unwrap().len();
+            assert_eq!(
+                file_length as usize,
+                (sample.len() - 1000) * value_size(format)
+            );
+
+            read_queued(&mut reader);
+            for (pos, &expected) in sample.iter().enumerate() {
+                if pos > 1000 && (pos - 1000) % STREAM_CHUNK_SIZE == 0 {
+                    sampler.get(pos);
+                    read_queued(&mut reader);
+                }
+                let value = sampler.get(pos) * sampler.scale();
+                assert!(
+                    (value - expected).abs() <= max_error,
+                    "{format:?} {pos}: {value} != {expected}"
+                );
+            }
+        }
+    }
+}
@@ -13,7 +13,7 @@ use xsynth_core::{
     soundfont::{SampleSoundfont, SoundfontBase},
 };
 
-use xsynth_realtime::{RealtimeEventSender, RealtimeSynth, XSynthRealtimeConfig};
+use xsynth_realtime::{RealtimeEventSender, RealtimeSynth, SynthEvent, XSynthRealtimeConfig};
 
 #[cfg(windows)]
 use winapi::{
@@ -141,6 +141,39 @@ pub extern "C" fn SendDirectDataNoBuf(dwMsg: u32) -> u32 {
     1
 }
 
+/// The start of the MIDIHDR struct, which holds the buffer of a long MIDI message.
+#[repr(C)]
+pub struct MidiHeader {
+    data: *const u8,
+    buffer_length: u32,
+}
+
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn SendDirectLongData(
+    IIMidiHdr: *const MidiHeader,
+    _IIMidiHdrSize: u32,
+) -> u32 {
+    let synth = &mut *std::ptr::addr_of_mut!(GLOBAL_SYNTH);
+    if let (Some(sender), Some(header)) = (synth.as_mut(), IIMidiHdr.as_ref()) {
+        if !header.data.is_null() {
+            let data = std::slice::from_raw_parts(header.data, header.buffer_length as usize);
+            sender.senders.send_event(SynthEvent::SysEx(data.to_vec()));
+        }
+    }
+    1
+}
+
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn SendDirectLongDataNoBuf(
+    IIMidiHdr: *const MidiHeader,
+    IIMidiHdrSize: u32,
+) -> u32 {
+    SendDirectLongData(IIMidiHdr, IIMidiHdrSize); //We don't have a buffer, just use SendDirectLongData
+    1
+}
+
 #[no_mangle]
 pub extern "C" fn IsKDMAPIAvailable() -> u32 {
     println!("IsKDMAPIAvailable");
@@ -162,18 +195,6 @@ pub extern "C" fn SendCustomEvent(_eventtype: u32, _chan: u32, _param: u32) -> u
     1
 }
 
-#[no_mangle]
-pub extern "C" fn SendDirectLongData() -> u32 {
-    println!("SendDirectLongData");
-    1
-}
-
-#[no_mangle]
-pub extern "C" fn SendDirectLongDataNoBuf() -> u32 {
-    println!("SendDirectLongDataNoBuf");
-    1
-}
-
 #[no_mangle]
 pub extern "C" fn PrepareLongData() -> u32 {
     println!("PrepareLongData");
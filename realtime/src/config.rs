@@ -1,5 +1,9 @@
 use std::ops::RangeInclusive;
-pub use xsynth_core::{channel::ChannelInitOptions, channel_group::ThreadCount};
+pub use xsynth_core::{
+    channel::ChannelInitOptions,
+    channel_group::ThreadCount,
+    effects::{ChorusParams, LimiterParams, MasterEffect, ReverbParams},
+};
 
 /// Options for initializing a new RealtimeSynth.
 pub struct XSynthRealtimeConfig {
@@ -9,6 +13,9 @@ pub struct XSynthRealtimeConfig {
 
     /// The length of the buffer reader in ms.
     ///
+    /// The events are played one render window after they are sent,
+    /// keeping their timing within it.
+    ///
     /// Default: `10.0`
     pub render_window_ms: f64,
 
@@ -36,6 +43,43 @@ pub struct XSynthRealtimeConfig {
     ///
     /// Default: `0..=0`
     pub ignore_range: RangeInclusive<u8>,
+
+    /// Parameters of the reverb send effect, which the channels are sent to
+    /// by their CC 91 value. `None` disables the reverb.
+    /// See the `ReverbParams` documentation for more information.
+    ///
+    /// The reverb is disabled by default, set this to
+    /// `Some(ReverbParams::default())` to enable it.
+    ///
+    /// Default: `None`
+    pub reverb: Option<ReverbParams>,
+
+    /// Parameters of the chorus send effect, which the channels are sent to
+    /// by their CC 93 value. `None` disables the chorus.
+    /// See the `ChorusParams` documentation for more information.
+    ///
+    /// The chorus is disabled by default, set this to
+    /// `Some(ChorusParams::default())` to enable it.
+    ///
+    /// Default: `None`
+    pub chorus: Option<ChorusParams>,
+
+    /// The master bus effects, applied in order to the mixed output of the
+    /// channels after the reverb and chorus. Uses the same effects as the
+    /// `master_effects` of the `ChannelGroupConfig`.
+    ///
+    /// Default: `[]`
+    pub master_effects: Vec<MasterEffect>,
+
+    /// Parameters of the limiter, which is applied after the master effects
+    /// to keep the output from clipping. `None` disables the limiter.
+    /// See the `LimiterParams` documentation for more information.
+    ///
+    /// The limiter delays the output by its look-ahead time, plus 4 samples
+    /// with true peak detection. This adds to the latency of the render window.
+    ///
+    /// Default: `Some(LimiterParams::default())`
+    pub limiter: Option<LimiterParams>,
 }
 
 impl Default for XSynthRealtimeConfig {
@@ -47,6 +91,10 @@ impl Default for XSynthRealtimeConfig {
             drums_channels: vec![9],
             multithreading: ThreadCount::None,
             ignore_range: 0..=0,
+            reverb: None,
+            chorus: None,
+            master_effects: Vec::new(),
+            limiter: Some(Default::default()),
         }
     }
 }
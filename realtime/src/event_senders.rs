@@ -8,7 +8,10 @@ use std::{
 
 use crossbeam_channel::Sender;
 
-use xsynth_core::channel::{ChannelAudioEvent, ChannelConfigEvent, ChannelEvent, ControlEvent};
+use xsynth_core::{
+    channel::{ChannelAudioEvent, ChannelConfigEvent, ChannelEvent, ControlEvent},
+    channel_group::{parse_sysex, MpeZones},
+};
 
 use crate::{util::ReadWriteAtomicU64, SynthEvent};
 
@@ -118,8 +121,10 @@ fn should_send_for_vel_and_nps(vel: u8, nps: u64, max: u64) -> bool {
     (vel as u64) * max / 127 > nps
 }
 
+/// Sends the events of a channel to its render thread, with the time they
+/// were sent at.
 struct EventSender {
-    sender: Sender<ChannelEvent>,
+    sender: Sender<(Instant, ChannelEvent)>,
     nps: RoughNpsTracker,
     max_nps: Arc<ReadWriteAtomicU64>,
     skipped_notes: [u64; 128],
@@ -129,7 +134,7 @@ struct EventSender {
 impl EventSender {
     pub fn new(
         max_nps: Arc<ReadWriteAtomicU64>,
-        sender: Sender<ChannelEvent>,
+        sender: Sender<(Instant, ChannelEvent)>,
         ignore_range: RangeInclusive<u8>,
     ) -> Self {
         EventSender {
@@ -152,7 +157,7 @@ impl EventSender {
 
                 let nps = self.nps.calculate_nps();
                 if should_send_for_vel_and_nps(*vel, nps, self.max_nps.read()) && !in_ignore_range {
-                    self.sender.send(ChannelEvent::Audio(event)).ok();
+                    self.send(ChannelEvent::Audio(event));
                     self.nps.add_note();
                 } else {
                     self.skipped_notes[*key as usize] += 1;
@@ -166,17 +171,21 @@ impl EventSender {
                 if self.skipped_notes[*key as usize] > 0 {
                     self.skipped_notes[*key as usize] -= 1;
                 } else {
-                    self.sender.send(ChannelEvent::Audio(event)).ok();
+                    self.send(ChannelEvent::Audio(event));
                 }
             }
             _ => {
-                self.sender.send(ChannelEvent::Audio(event)).ok();
+                self.send(ChannelEvent::Audio(event));
             }
         }
     }
 
     pub fn send_config(&mut self, event: ChannelConfigEvent) {
-        self.sender.send(ChannelEvent::Config(event)).ok();
+        self.send(ChannelEvent::Config(event));
+    }
+
+    fn send(&mut self, event: ChannelEvent) {
+        self.sender.send((Instant::now(), event)).ok();
     }
 
     // pub fn send(&mut self, event: ChannelEvent) {
@@ -213,31 +222,53 @@ impl Clone for EventSender {
 #[derive(Clone)]
 pub struct RealtimeEventSender {
     senders: Vec<EventSender>,
+    effects: Sender<(Instant, SynthEvent)>,
+    mpe: MpeZones,
 }
 
 impl RealtimeEventSender {
     pub(super) fn new(
-        senders: Vec<Sender<ChannelEvent>>,
+        senders: Vec<Sender<(Instant, ChannelEvent)>>,
+        effects: Sender<(Instant, SynthEvent)>,
         max_nps: Arc<ReadWriteAtomicU64>,
         ignore_range: RangeInclusive<u8>,
     ) -> RealtimeEventSender {
+        let channel_count = senders.len() as u32;
         RealtimeEventSender {
             senders: senders
                 .into_iter()
                 .map(|s| EventSender::new(max_nps.clone(), s, ignore_range.clone()))
                 .collect(),
+            effects,
+            mpe: MpeZones::new(channel_count),
         }
     }
 
     /// Sends a SynthEvent to the realtime synthesizer.
     ///
+    /// The events are timestamped when they are sent, and played at the same
+    /// relative times one rendered block later, so their timing doesn't
+    /// depend on the size of the render window.
+    ///
     /// See the `SynthEvent` documentation for more information.
     pub fn send_event(&mut self, event: SynthEvent) {
         match event {
             SynthEvent::Channel(channel, event) => {
-                self.senders[channel as usize].send_audio(event);
+                let senders = &mut self.senders;
+                self.mpe.process_event(channel, event, |channel, event| {
+                    senders[channel as usize].send_audio(event);
+                });
             }
             SynthEvent::AllChannels(event) => {
+                self.mpe.process_all_channels_event(&event);
+                if let ChannelAudioEvent::SystemReset = event {
+                    self.effects
+                        .send((
+                            Instant::now(),
+                            SynthEvent::AllChannels(ChannelAudioEvent::SystemReset),
+                        ))
+                        .ok();
+                }
                 for sender in self.senders.iter_mut() {
                     sender.send_audio(event.clone());
                 }
@@ -247,6 +278,21 @@ impl RealtimeEventSender {
                     sender.send_config(event.clone());
                 }
             }
+            SynthEvent::SingleChannelConfig(channel, event) => {
+                if let Some(sender) = self.senders.get_mut(channel as usize) {
+                    sender.send_config(event);
+                }
+            }
+            event @ (SynthEvent::ReverbType(_)
+            | SynthEvent::ChorusType(_)
+            | SynthEvent::ChorusParameter(_)) => {
+                self.effects.send((Instant::now(), event)).ok();
+            }
+            SynthEvent::SysEx(data) => {
+                for event in parse_sysex(&data, self.senders.len() as u32) {
+                    self.send_event(event);
+                }
+            }
         }
     }
 
@@ -291,6 +337,15 @@ impl RealtimeEventSender {
                     },
                 ));
             }
+            0xA => {
+                self.send_event(SynthEvent::Channel(
+                    channel,
+                    ChannelAudioEvent::PolyAftertouch {
+                        key: val1!(),
+                        value: val2!(),
+                    },
+                ));
+            }
             0xB => {
                 self.send_event(SynthEvent::Channel(
                     channel,
@@ -303,6 +358,12 @@ impl RealtimeEventSender {
                     ChannelAudioEvent::ProgramChange(val1!()),
                 ));
             }
+            0xD => {
+                self.send_event(SynthEvent::Channel(
+                    channel,
+                    ChannelAudioEvent::ChannelPressure(val1!()),
+                ));
+            }
             0xE => {
                 let value = (((val2!() as i16) << 7) | val1!() as i16) - 8192;
                 let value = value as f32 / 8192.0;
@@ -5,6 +5,7 @@ use std::{
         Arc, Mutex,
     },
     thread::{self},
+    time::Instant,
 };
 
 use cpal::{
@@ -15,9 +16,9 @@ use crossbeam_channel::{bounded, unbounded};
 
 use xsynth_core::{
     buffered_renderer::{BufferedRenderer, BufferedRendererStatsReader},
-    channel::VoiceChannel,
-    effects::VolumeLimiter,
-    helpers::{prepapre_cache_vec, sum_simd},
+    channel::{ChannelEvent, SendLevels, VoiceChannel},
+    effects::{EffectMixer, MasterEffect},
+    helpers::prepapre_cache_vec,
     AudioPipe, AudioStreamParams, FunctionAudioPipe,
 };
 
@@ -25,6 +26,31 @@ use crate::{
     util::ReadWriteAtomicU64, RealtimeEventSender, SynthEvent, ThreadCount, XSynthRealtimeConfig,
 };
 
+/// The buffers of a channel thread. The mixer sends them to the thread to
+/// be rendered to, and gets them back with the audio and send levels of
+/// the channel.
+struct ChannelOutput {
+    samples: Vec<f32>,
+    sends: Vec<SendLevels>,
+
+    /// The time the mixer started rendering the block
+    start: Instant,
+}
+
+/// Converts the time an event was sent to an offset in a block of `len`
+/// samples (per audio channel) that started rendering at `start`.
+///
+/// The events are played one block after they were sent, so the events
+/// sent while the previous block was rendered keep their relative timing
+/// within the block. Older events are played at the start of the block,
+/// and events sent after the start are played in the next blocks.
+fn event_offset(time: Instant, start: Instant, len: usize, sample_rate: u32) -> u32 {
+    let early = start.saturating_duration_since(time).as_secs_f64();
+    let late = time.saturating_duration_since(start).as_secs_f64();
+    let offset = len as f64 + (late - early) * sample_rate as f64;
+    offset.max(0.0) as u32
+}
+
 /// Holds the statistics for an instance of RealtimeSynth.
 #[derive(Debug, Clone)]
 struct RealtimeSynthStats {
@@ -148,7 +174,8 @@ impl RealtimeSynth {
             )),
         };
 
-        let (output_sender, output_receiver) = bounded::<Vec<f32>>(config.channel_count as usize);
+        let (output_sender, output_receiver) =
+            bounded::<ChannelOutput>(config.channel_count as usize);
 
         let mut thread_handles = vec![];
 
@@ -162,55 +189,82 @@ impl RealtimeSynth {
             let stats = channel.get_channel_stats();
             channel_stats.push(stats);
 
-            let (event_sender, event_receiver) = unbounded();
+            let (event_sender, event_receiver) = unbounded::<(Instant, ChannelEvent)>();
             senders.push(event_sender);
 
-            let (command_sender, command_receiver) = bounded::<Vec<f32>>(1);
+            let (command_sender, command_receiver) = bounded::<ChannelOutput>(1);
 
             command_senders.push(command_sender);
 
             let output_sender = output_sender.clone();
             let join_handle = thread::Builder::new()
                 .name("xsynth_channel_handler".to_string())
-                .spawn(move || loop {
-                    channel.push_events_iter(event_receiver.try_iter());
-                    let mut vec = match command_receiver.recv() {
-                        Ok(vec) => vec,
-                        Err(_) => break,
-                    };
-                    channel.push_events_iter(event_receiver.try_iter());
-                    channel.read_samples(&mut vec);
-                    output_sender.send(vec).unwrap();
+                .spawn(move || {
+                    while let Ok(mut output) = command_receiver.recv() {
+                        let len = output.samples.len() / stream_params.channels.count() as usize;
+                        for (time, event) in event_receiver.try_iter() {
+                            let offset = event_offset(time, output.start, len, sample_rate);
+                            channel.process_event_at(event, offset);
+                        }
+
+                        channel.read_samples(&mut output.samples);
+                        output.sends.clear();
+                        output.sends.extend_from_slice(channel.send_levels());
+                        output_sender.send(output).unwrap();
+                    }
                 })
                 .unwrap();
 
             thread_handles.push(join_handle);
         }
 
-        let mut vec_cache: VecDeque<Vec<f32>> = VecDeque::new();
+        let mut vec_cache: VecDeque<ChannelOutput> = VecDeque::new();
         for _ in 0..(config.channel_count) {
-            vec_cache.push_front(Vec::new());
+            vec_cache.push_front(ChannelOutput {
+                samples: Vec::new(),
+                sends: Vec::new(),
+                start: Instant::now(),
+            });
         }
 
         let stats = RealtimeSynthStats::new();
 
         let total_voice_count = stats.voice_count.clone();
 
+        let mut master_effects = config.master_effects.clone();
+        if let Some(params) = config.limiter {
+            master_effects.push(MasterEffect::Limiter(params));
+        }
+        let mut effects =
+            EffectMixer::new(config.reverb, config.chorus, &master_effects, stream_params);
+
+        // Send effect events are timed by the mixer like the channel events
+        let (effect_sender, effect_receiver) = unbounded::<(Instant, SynthEvent)>();
+
         let channel_count = config.channel_count;
         let render = FunctionAudioPipe::new(stream_params, move |out| {
+            let start = Instant::now();
+            let len = out.len() / stream_params.channels.count() as usize;
+            for (time, event) in effect_receiver.try_iter() {
+                effects.process_event_at(event, event_offset(time, start, len, sample_rate));
+            }
+
             for sender in command_senders.iter() {
                 let mut buf = vec_cache.pop_front().unwrap();
-                prepapre_cache_vec(&mut buf, out.len(), 0.0);
+                prepapre_cache_vec(&mut buf.samples, out.len(), 0.0);
+                buf.start = start;
 
                 sender.send(buf).unwrap();
             }
 
             for _ in 0..channel_count {
-                let buf = output_receiver.recv().unwrap();
-                sum_simd(&buf, out);
-                vec_cache.push_front(buf);
+                let output = output_receiver.recv().unwrap();
+                effects.mix_channel(out, &output.samples, &output.sends);
+                vec_cache.push_front(output);
             }
 
+            effects.process(out);
+
             let total_voices = channel_stats.iter().map(|c| c.voice_count()).sum();
             total_voice_count.store(total_voices, Ordering::SeqCst);
         });
@@ -229,15 +283,13 @@ impl RealtimeSynth {
             let err_fn = |err| eprintln!("an error occurred on stream: {err}");
             let mut output_vec = Vec::new();
 
-            let mut limiter = VolumeLimiter::new(stream_config.channels());
-
             device
                 .build_output_stream(
                     &stream_config.into(),
                     move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
                         output_vec.resize(data.len(), 0.0);
                         buffered.lock().unwrap().read(&mut output_vec);
-                        for (i, s) in limiter.limit_iter(output_vec.drain(0..)).enumerate() {
+                        for (i, s) in output_vec.drain(0..).enumerate() {
                             data[i] = ConvertSample::from_f32(s);
                         }
                     },
@@ -262,7 +314,12 @@ impl RealtimeSynth {
             data: Some(RealtimeSynthThreadSharedData {
                 buffered_renderer: buffered,
 
-                event_senders: RealtimeEventSender::new(senders, max_nps, config.ignore_range),
+                event_senders: RealtimeEventSender::new(
+                    senders,
+                    effect_sender,
+                    max_nps,
+                    config.ignore_range,
+                ),
                 stream,
             }),
             join_handles: thread_handles,
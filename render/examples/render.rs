@@ -73,8 +73,11 @@ fn main() {
             drums_channels: vec![9],
             audio_params: AudioStreamParams::new(48000, 2.into()),
             parallelism: Default::default(),
+            reverb: Some(Default::default()),
+            chorus: Some(Default::default()),
+            master_effects: Vec::new(),
         },
-        use_limiter: true,
+        limiter: Some(Default::default()),
         audio_format: XSynthRenderAudioFormat::Wav,
     };
 
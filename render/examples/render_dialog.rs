@@ -94,8 +94,11 @@ fn main() {
                     key: ThreadCount::None,
                 }
             },
+            reverb: Some(Default::default()),
+            chorus: Some(Default::default()),
+            master_effects: Vec::new(),
         },
-        use_limiter: true,
+        limiter: Some(Default::default()),
         audio_format: XSynthRenderAudioFormat::Wav,
     };
 
@@ -23,7 +23,10 @@ use midi_toolkit::{
     },
 };
 
-pub use xsynth_core::channel_group::ParallelismOptions;
+pub use xsynth_core::{
+    channel_group::ParallelismOptions,
+    effects::{ChorusParams, LimiterParams, MasterEffect, ReverbParams},
+};
 
 /// Statistics of an XSynthRender object.
 pub struct XSynthRenderStats {
@@ -96,8 +99,28 @@ impl<'a, ProgressCallback: FnMut(XSynthRenderStats)> XSynthRenderBuilder<'a, Pro
         self
     }
 
+    pub fn with_reverb(mut self, reverb: Option<ReverbParams>) -> Self {
+        self.config.group_options.reverb = reverb;
+        self
+    }
+
+    pub fn with_chorus(mut self, chorus: Option<ChorusParams>) -> Self {
+        self.config.group_options.chorus = chorus;
+        self
+    }
+
+    pub fn with_master_effects(mut self, effects: Vec<MasterEffect>) -> Self {
+        self.config.group_options.master_effects = effects;
+        self
+    }
+
     pub fn use_limiter(mut self, use_limiter: bool) -> Self {
-        self.config.use_limiter = use_limiter;
+        self.config.limiter = use_limiter.then(Default::default);
+        self
+    }
+
+    pub fn with_limiter(mut self, limiter: Option<LimiterParams>) -> Self {
+        self.config.limiter = limiter;
         self
     }
 
@@ -217,6 +240,24 @@ impl<'a, ProgressCallback: FnMut(XSynthRenderStats)> XSynthRenderBuilder<'a, Pro
                             ChannelAudioEvent::ProgramChange(e.program),
                         ));
                     }
+                    Event::PolyphonicKeyPressure(e) => {
+                        synth.send_event(SynthEvent::Channel(
+                            e.channel as u32,
+                            ChannelAudioEvent::PolyAftertouch {
+                                key: e.key,
+                                value: e.velocity,
+                            },
+                        ));
+                    }
+                    Event::ChannelPressure(e) => {
+                        synth.send_event(SynthEvent::Channel(
+                            e.channel as u32,
+                            ChannelAudioEvent::ChannelPressure(e.pressure),
+                        ));
+                    }
+                    Event::SystemExclusiveMessage(e) => {
+                        synth.send_event(SynthEvent::SysEx(e.data.clone()));
+                    }
                     _ => {}
                 }
             }
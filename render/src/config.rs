@@ -1,5 +1,6 @@
 pub use xsynth_core::{
-    channel_group::ChannelGroupConfig, soundfont::SoundfontInitOptions, AudioStreamParams,
+    channel_group::ChannelGroupConfig, effects::LimiterParams, soundfont::SoundfontInitOptions,
+    AudioStreamParams,
 };
 
 /// Supported audio formats of XSynthRender.
@@ -15,9 +16,11 @@ pub struct XSynthRenderConfig {
     /// See the `ChannelGroupConfig` documentation for more information.
     pub group_options: ChannelGroupConfig,
 
-    /// If set to true, the rendered audio will be limited to 0dB using
-    /// the `VolumeLimiter` effect from `core` to prevent clipping.
-    pub use_limiter: bool,
+    /// Parameters of the limiter, which is added to the end of the master
+    /// effects of the group options to keep the rendered audio from
+    /// clipping. `None` disables the limiter.
+    /// See the `LimiterParams` documentation for more information.
+    pub limiter: Option<LimiterParams>,
 
     /// Audio output format. Supported: WAV
     pub audio_format: XSynthRenderAudioFormat,
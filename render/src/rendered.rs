@@ -1,6 +1,6 @@
 use xsynth_core::{
     channel_group::{ChannelGroup, SynthEvent},
-    effects::VolumeLimiter,
+    effects::MasterEffect,
     AudioPipe, AudioStreamParams,
 };
 
@@ -8,9 +8,21 @@ use std::path::PathBuf;
 
 use crate::{config::XSynthRenderConfig, writer::AudioFileWriter};
 
+/// The number of samples (per audio channel) rendered at once. The events
+/// are applied at their exact sample inside of the blocks.
+const RENDER_BLOCK_SIZE: usize = 4096;
+
 struct BatchRenderElements {
     output_vec: Vec<f32>,
     missed_samples: f64,
+
+    /// The samples (per audio channel) of the elapsed time that were
+    /// not rendered yet
+    pending_samples: usize,
+
+    /// The samples at the start of the output that are still to be
+    /// dropped, so the latency of the master bus doesn't delay the file
+    skipped_samples: usize,
 }
 
 /// Represents an XSynth MIDI synthesizer that renders a MIDI to a file.
@@ -18,7 +30,6 @@ pub struct XSynthRender {
     config: XSynthRenderConfig,
     channel_group: ChannelGroup,
     audio_writer: AudioFileWriter,
-    limiter: Option<VolumeLimiter>,
     render_elements: BatchRenderElements,
 }
 
@@ -26,26 +37,27 @@ impl XSynthRender {
     /// Initializes a new XSynthRender object with the given configuration and
     /// audio output path.
     pub fn new(config: XSynthRenderConfig, out_path: PathBuf) -> Self {
-        let channel_group = ChannelGroup::new(config.group_options.clone());
+        let mut group_options = config.group_options.clone();
+        if let Some(params) = config.limiter {
+            group_options
+                .master_effects
+                .push(MasterEffect::Limiter(params));
+        }
+        let channel_group = ChannelGroup::new(group_options);
+        let skipped_samples =
+            channel_group.latency() * config.group_options.audio_params.channels.count() as usize;
 
         let audio_writer = AudioFileWriter::new(config.clone(), out_path);
 
-        let limiter = if config.use_limiter {
-            Some(VolumeLimiter::new(
-                config.group_options.audio_params.channels.count(),
-            ))
-        } else {
-            None
-        };
-
         Self {
             config,
             channel_group,
             audio_writer,
-            limiter,
             render_elements: BatchRenderElements {
                 output_vec: vec![0.0],
                 missed_samples: 0.0,
+                pending_samples: 0,
+                skipped_samples,
             },
         }
     }
@@ -58,47 +70,58 @@ impl XSynthRender {
     /// Sends a SynthEvent to the XSynthRender object.
     /// Please see the SynthEvent documentation for more information.
     pub fn send_event(&mut self, event: SynthEvent) {
-        self.channel_group.send_event(event);
+        let offset = self.render_elements.pending_samples as u32;
+        self.channel_group.send_event_at(event, offset);
     }
 
-    /// Renders audio samples of the specified time to the audio output file.
+    /// Advances the render by the specified time, in seconds.
     ///
-    /// The time should be the delta time of the last sent events.
+    /// The time should be the delta time of the last sent events. The audio
+    /// is written to the output file in blocks, with the events applied at
+    /// their exact sample inside of them.
     pub fn render_batch(&mut self, event_time: f64) {
-        if event_time > 10.0 {
-            // If the time is too large, split it up
-            let mut remaining_time = event_time;
-            loop {
-                if remaining_time > 10.0 {
-                    self.render_batch(10.0);
-                    remaining_time -= 10.0;
-                } else {
-                    self.render_batch(remaining_time);
-                    break;
-                }
-            }
-        } else {
-            let samples = self.config.group_options.audio_params.sample_rate as f64 * event_time
-                + self.render_elements.missed_samples;
-            self.render_elements.missed_samples = samples % 1.0;
-            let samples =
-                samples as usize * self.config.group_options.audio_params.channels.count() as usize;
-
-            self.render_elements.output_vec.resize(samples, 0.0);
-            self.channel_group
-                .read_samples(&mut self.render_elements.output_vec);
+        let samples = self.config.group_options.audio_params.sample_rate as f64 * event_time
+            + self.render_elements.missed_samples;
+        self.render_elements.missed_samples = samples % 1.0;
+        self.render_elements.pending_samples += samples as usize;
+
+        while self.render_elements.pending_samples >= RENDER_BLOCK_SIZE {
+            self.render_block(RENDER_BLOCK_SIZE);
+            self.render_elements.pending_samples -= RENDER_BLOCK_SIZE;
+        }
+    }
 
-            if let Some(limiter) = &mut self.limiter {
-                limiter.limit(&mut self.render_elements.output_vec);
-            }
+    /// Renders a block of samples (per audio channel) to the audio output file.
+    fn render_block(&mut self, samples: usize) {
+        let samples = samples * self.config.group_options.audio_params.channels.count() as usize;
 
-            self.audio_writer
-                .write_samples(&mut self.render_elements.output_vec);
+        self.render_elements.output_vec.resize(samples, 0.0);
+        self.channel_group
+            .read_samples(&mut self.render_elements.output_vec);
+
+        self.write_output();
+    }
+
+    /// Writes the rendered samples to the audio output file, without the
+    /// samples that are delayed by the master bus.
+    fn write_output(&mut self) {
+        let elements = &mut self.render_elements;
+        if elements.skipped_samples > 0 {
+            let skipped = elements.skipped_samples.min(elements.output_vec.len());
+            elements.output_vec.drain(..skipped);
+            elements.skipped_samples -= skipped;
         }
+
+        self.audio_writer.write_samples(&mut elements.output_vec);
     }
 
     /// Finishes the render and finalizes the audio file.
     pub fn finalize(mut self) {
+        if self.render_elements.pending_samples > 0 {
+            self.render_block(self.render_elements.pending_samples);
+            self.render_elements.pending_samples = 0;
+        }
+
         loop {
             self.render_elements.output_vec.resize(
                 self.config.group_options.audio_params.sample_rate as usize,
@@ -116,8 +139,7 @@ impl XSynthRender {
             if is_empty {
                 break;
             }
-            self.audio_writer
-                .write_samples(&mut self.render_elements.output_vec);
+            self.write_output();
         }
         self.audio_writer.finalize();
     }
@@ -1,3 +1,4 @@
+pub mod modulator;
 pub mod resample;
 pub mod sf2;
 pub mod sfz;
@@ -37,6 +38,41 @@ pub enum LoopMode {
     LoopSustain,
 }
 
+/// The note event that triggers a region.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum TriggerMode {
+    /// Play on every note on event
+    #[default]
+    Attack,
+
+    /// Play on note off events, or when the sustain pedal is lifted
+    /// if the note was held by it
+    Release,
+
+    /// Play on note off events, ignoring the sustain pedal
+    ReleaseKey,
+
+    /// Play on note on events, only if no other notes are held
+    First,
+
+    /// Play on note on events, only if other notes are held
+    Legato,
+}
+
+/// How a voice is stopped when another voice turns off its group.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum OffMode {
+    /// Stop the voice almost immediately
+    #[default]
+    Fast,
+
+    /// Release the voice using its amplitude envelope
+    Normal,
+
+    /// Fade out the voice over the region's off time
+    Time,
+}
+
 /// Converts the sample index of an audio sample array when
 /// it is resampled.
 pub fn convert_sample_index(idx: u32, old_sample_rate: u32, new_sample_rate: u32) -> u32 {
@@ -0,0 +1,333 @@
+/// The controller used as the input of a modulator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModulatorSource {
+    /// No controller. The input will be treated as a constant value of 1.
+    NoController,
+
+    /// The velocity of the note on event that started the voice
+    Velocity,
+
+    /// The key number of the note on event that started the voice
+    Key,
+
+    /// The polyphonic aftertouch value of the voice's key
+    PolyPressure,
+
+    /// The channel aftertouch value
+    ChannelPressure,
+
+    /// The position of the pitch wheel
+    PitchWheel,
+
+    /// The pitch wheel sensitivity (RPN 0)
+    PitchWheelSensitivity,
+
+    /// A MIDI continuous controller (0-127)
+    Controller(u8),
+}
+
+impl ModulatorSource {
+    /// Returns true if the value of the source can't change
+    /// during the lifetime of a voice.
+    pub fn is_static(&self) -> bool {
+        matches!(
+            self,
+            ModulatorSource::NoController | ModulatorSource::Velocity | ModulatorSource::Key
+        )
+    }
+}
+
+/// The shape of the curve used to map the value of a modulator source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ModulatorCurve {
+    /// The value moves linearly from the minimum to the maximum
+    #[default]
+    Linear,
+
+    /// The value follows a concave (logarithmic) curve
+    Concave,
+
+    /// The value follows a convex curve (mirrored concave curve)
+    Convex,
+
+    /// The value is at the minimum until half of the input range,
+    /// after which it jumps to the maximum
+    Switch,
+}
+
+impl ModulatorCurve {
+    fn apply(&self, value: f32) -> f32 {
+        match self {
+            ModulatorCurve::Linear => value,
+            ModulatorCurve::Concave => concave(value),
+            ModulatorCurve::Convex => 1.0 - concave(1.0 - value),
+            ModulatorCurve::Switch => {
+                if value >= 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+// The concave curve of the SF2 spec, which results in a 96dB range when
+// applied to the attenuation with an amount of 960cB.
+fn concave(value: f32) -> f32 {
+    if value <= 0.0 {
+        0.0
+    } else if value >= 1.0 {
+        1.0
+    } else {
+        (-200.0 / 960.0 * 2.0 * (1.0 - value).log10()).clamp(0.0, 1.0)
+    }
+}
+
+/// A modulator source along with the mapping applied to its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModulatorInput {
+    pub source: ModulatorSource,
+    pub curve: ModulatorCurve,
+
+    /// If true, the value will be mapped to the -1 to 1 range instead of 0 to 1.
+    pub bipolar: bool,
+
+    /// If true, the value will move from the maximum to the minimum.
+    pub negative: bool,
+}
+
+impl ModulatorInput {
+    /// An input that is always 1.
+    pub const NONE: ModulatorInput = ModulatorInput {
+        source: ModulatorSource::NoController,
+        curve: ModulatorCurve::Linear,
+        bipolar: false,
+        negative: false,
+    };
+
+    /// Creates a positive, unipolar, linear input of the given source.
+    pub fn linear(source: ModulatorSource) -> Self {
+        ModulatorInput {
+            source,
+            curve: ModulatorCurve::Linear,
+            bipolar: false,
+            negative: false,
+        }
+    }
+
+    /// Maps a normalized (0-1) value of the source using the curve,
+    /// polarity and direction of the input.
+    pub fn map(&self, value: f32) -> f32 {
+        if self.source == ModulatorSource::NoController {
+            return 1.0;
+        }
+
+        let value = if self.negative { 1.0 - value } else { value };
+
+        if self.bipolar {
+            if self.curve == ModulatorCurve::Switch {
+                return if value >= 0.5 { 1.0 } else { -1.0 };
+            }
+
+            let value = value * 2.0 - 1.0;
+            if value >= 0.0 {
+                self.curve.apply(value)
+            } else {
+                -self.curve.apply(-value)
+            }
+        } else {
+            self.curve.apply(value)
+        }
+    }
+}
+
+/// The voice parameter that the output of a modulator is added to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModulatorDestination {
+    /// Attenuation of the voice, in centibels
+    Attenuation,
+
+    /// Pitch of the voice, in cents
+    Pitch,
+
+    /// Cutoff frequency of the voice's filter, in cents
+    FilterCutoff,
+
+    /// Resonance of the voice's filter, in centibels
+    FilterResonance,
+
+    /// Pan of the voice, in 0.1% units (-500 is left, 500 is right)
+    Pan,
+
+    /// Pitch modulation depth of the LFO with the given index, in cents
+    LfoToPitch(usize),
+
+    /// Filter cutoff modulation depth of the LFO with the given index, in cents
+    LfoToFilter(usize),
+
+    /// Volume modulation depth of the LFO with the given index, in centibels
+    LfoToVolume(usize),
+}
+
+/// A modulator that routes the value of a controller to a voice parameter.
+///
+/// The output is calculated as `source * amount_source * amount` and is added
+/// to the destination parameter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Modulator {
+    pub source: ModulatorInput,
+    pub amount_source: ModulatorInput,
+    pub destination: ModulatorDestination,
+    pub amount: f32,
+
+    /// If true, the absolute value of the output will be used.
+    pub absolute: bool,
+}
+
+impl Modulator {
+    /// Returns true if the output of the modulator can't change
+    /// during the lifetime of a voice.
+    pub fn is_static(&self) -> bool {
+        self.source.source.is_static() && self.amount_source.source.is_static()
+    }
+
+    /// Calculates the output of the modulator from the normalized (0-1)
+    /// values of its source and amount source.
+    pub fn output(&self, source_value: f32, amount_source_value: f32) -> f32 {
+        let out = self.source.map(source_value)
+            * self.amount_source.map(amount_source_value)
+            * self.amount;
+
+        if self.absolute {
+            out.abs()
+        } else {
+            out
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(curve: ModulatorCurve, bipolar: bool, negative: bool) -> ModulatorInput {
+        ModulatorInput {
+            source: ModulatorSource::Controller(1),
+            curve,
+            bipolar,
+            negative,
+        }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.001, "{a} != {b}");
+    }
+
+    #[test]
+    fn test_curves() {
+        // SF2 8.2.4: the concave curve is -20/96 * log10((1 - x)^2)
+        assert_eq!(ModulatorCurve::Concave.apply(0.0), 0.0);
+        assert_close(ModulatorCurve::Concave.apply(0.5), 0.1254);
+        assert_close(ModulatorCurve::Concave.apply(0.9), 0.4167);
+        assert_eq!(ModulatorCurve::Concave.apply(1.0), 1.0);
+
+        // The convex curve is the concave curve with its ends swapped
+        assert_eq!(ModulatorCurve::Convex.apply(0.0), 0.0);
+        assert_close(ModulatorCurve::Convex.apply(0.5), 0.8746);
+        assert_eq!(ModulatorCurve::Convex.apply(1.0), 1.0);
+
+        assert_eq!(ModulatorCurve::Linear.apply(0.25), 0.25);
+        assert_eq!(ModulatorCurve::Switch.apply(0.49), 0.0);
+        assert_eq!(ModulatorCurve::Switch.apply(0.5), 1.0);
+    }
+
+    #[test]
+    fn test_concave_attenuation() {
+        // A negative concave velocity source with an amount of 960cB
+        // gives the attenuation of 40 * log10(127 / vel) dB
+        let modulator = Modulator {
+            source: ModulatorInput {
+                source: ModulatorSource::Velocity,
+                ..input(ModulatorCurve::Concave, false, true)
+            },
+            amount_source: ModulatorInput::NONE,
+            destination: ModulatorDestination::Attenuation,
+            amount: 960.0,
+            absolute: false,
+        };
+
+        assert_eq!(modulator.output(1.0, 0.0), 0.0);
+        assert!((modulator.output(64.0 / 127.0, 0.0) - 119.05).abs() < 0.01);
+        assert_eq!(modulator.output(0.0, 0.0), 960.0);
+    }
+
+    #[test]
+    fn test_polarity_and_direction() {
+        let unipolar = input(ModulatorCurve::Linear, false, false);
+        assert_eq!(unipolar.map(0.0), 0.0);
+        assert_eq!(unipolar.map(1.0), 1.0);
+
+        let negative = input(ModulatorCurve::Linear, false, true);
+        assert_eq!(negative.map(0.0), 1.0);
+        assert_eq!(negative.map(0.25), 0.75);
+
+        let bipolar = input(ModulatorCurve::Linear, true, false);
+        assert_eq!(bipolar.map(0.0), -1.0);
+        assert_eq!(bipolar.map(0.5), 0.0);
+        assert_eq!(bipolar.map(1.0), 1.0);
+
+        let bipolar_negative = input(ModulatorCurve::Linear, true, true);
+        assert_eq!(bipolar_negative.map(0.0), 1.0);
+        assert_eq!(bipolar_negative.map(1.0), -1.0);
+
+        // The bipolar curves are mirrored around the center
+        let bipolar_concave = input(ModulatorCurve::Concave, true, false);
+        assert_close(bipolar_concave.map(0.75), 0.1254);
+        assert_close(bipolar_concave.map(0.25), -0.1254);
+    }
+
+    #[test]
+    fn test_switch() {
+        let switch = input(ModulatorCurve::Switch, false, false);
+        assert_eq!(switch.map(0.4), 0.0);
+        assert_eq!(switch.map(0.6), 1.0);
+
+        let negative = input(ModulatorCurve::Switch, false, true);
+        assert_eq!(negative.map(0.4), 1.0);
+        assert_eq!(negative.map(0.6), 0.0);
+
+        // A bipolar switch jumps from -1 to 1
+        let bipolar = input(ModulatorCurve::Switch, true, false);
+        assert_eq!(bipolar.map(0.0), -1.0);
+        assert_eq!(bipolar.map(0.49), -1.0);
+        assert_eq!(bipolar.map(0.5), 1.0);
+
+        let bipolar_negative = input(ModulatorCurve::Switch, true, true);
+        assert_eq!(bipolar_negative.map(0.0), 1.0);
+        assert_eq!(bipolar_negative.map(1.0), -1.0);
+    }
+
+    #[test]
+    fn test_output() {
+        assert_eq!(ModulatorInput::NONE.map(0.0), 1.0);
+
+        let mut modulator = Modulator {
+            source: input(ModulatorCurve::Linear, true, false),
+            amount_source: ModulatorInput::linear(ModulatorSource::Controller(2)),
+            destination: ModulatorDestination::Pitch,
+            amount: 100.0,
+            absolute: false,
+        };
+        assert_eq!(modulator.output(0.0, 0.5), -50.0);
+        assert_eq!(modulator.output(1.0, 0.5), 50.0);
+        assert!(!modulator.is_static());
+
+        modulator.absolute = true;
+        assert_eq!(modulator.output(0.0, 0.5), 50.0);
+
+        modulator.source.source = ModulatorSource::Key;
+        modulator.amount_source = ModulatorInput::NONE;
+        assert!(modulator.is_static());
+    }
+}
@@ -1,13 +1,25 @@
-use crate::{sfz::AmpegEnvelopeParams, LoopMode};
+use crate::{
+    modulator::Modulator,
+    sfz::{AmpegEnvelopeParams, LfoParams, ModEnvelopeParams},
+    LoopMode,
+};
 use std::{fs::File, ops::RangeInclusive, path::PathBuf, sync::Arc};
 
 use thiserror::Error;
 
 mod instrument;
+mod modulator;
 mod preset;
 mod sample;
+pub use sample::Sf2SampleData;
 mod zone;
 
+/// Index of the modulation LFO in the LFOs of an SF2 region
+pub const SF2_MOD_LFO: usize = 0;
+
+/// Index of the vibrato LFO in the LFOs of an SF2 region
+pub const SF2_VIB_LFO: usize = 1;
+
 /// Errors that can be generated when loading an SF2 file.
 #[derive(Error, Debug, Clone)]
 pub enum Sf2ParseError {
@@ -21,7 +33,7 @@ pub enum Sf2ParseError {
 /// Structure that holds the generator and modulator parameters of an SF2 region.
 #[derive(Clone)]
 pub struct Sf2Region {
-    pub sample: Arc<[Arc<[f32]>]>,
+    pub sample: Arc<[Sf2SampleData]>,
     pub sample_rate: u32,
     pub velrange: RangeInclusive<u8>,
     pub keyrange: RangeInclusive<u8>,
@@ -37,6 +49,10 @@ pub struct Sf2Region {
     pub ampeg_envelope: AmpegEnvelopeParams,
     pub fine_tune: i16,
     pub coarse_tune: i16,
+    pub lfos: Vec<LfoParams>,
+    pub mod_envelopes: Vec<ModEnvelopeParams>,
+    pub exclusive_class: u16,
+    pub modulators: Vec<Modulator>,
 }
 
 /// Structure that holds the parameters of an SF2 preset.
@@ -0,0 +1,336 @@
+use super::{SF2_MOD_LFO, SF2_VIB_LFO};
+use crate::modulator::{
+    Modulator, ModulatorCurve, ModulatorDestination, ModulatorInput, ModulatorSource,
+};
+use soundfont::data::{
+    hydra::generator::GeneratorType,
+    modulator::{
+        ControllerPalette, GeneralPalette, ModulatorSource as Sf2ModulatorSource,
+        ModulatorTransform, SourceDirection, SourcePolarity, SourceType,
+    },
+    Modulator as Sf2Modulator,
+};
+
+/// Converts a modulator of an SF2 file. Returns `None` if the modulator
+/// uses an unknown or unsupported source or destination, in which case
+/// it should be ignored.
+pub fn convert_modulator(modulator: &Sf2Modulator) -> Option<Modulator> {
+    let (destination, scale) = match modulator.dest {
+        GeneratorType::InitialAttenuation => (ModulatorDestination::Attenuation, 1.0),
+        GeneratorType::FineTune => (ModulatorDestination::Pitch, 1.0),
+        GeneratorType::CoarseTune => (ModulatorDestination::Pitch, 100.0),
+        GeneratorType::InitialFilterFc => (ModulatorDestination::FilterCutoff, 1.0),
+        GeneratorType::InitialFilterQ => (ModulatorDestination::FilterResonance, 1.0),
+        GeneratorType::Pan => (ModulatorDestination::Pan, 1.0),
+        GeneratorType::ModLfoToPitch => (ModulatorDestination::LfoToPitch(SF2_MOD_LFO), 1.0),
+        GeneratorType::VibLfoToPitch => (ModulatorDestination::LfoToPitch(SF2_VIB_LFO), 1.0),
+        GeneratorType::ModLfoToFilterFc => (ModulatorDestination::LfoToFilter(SF2_MOD_LFO), 1.0),
+        GeneratorType::ModLfoToVolume => (ModulatorDestination::LfoToVolume(SF2_MOD_LFO), 1.0),
+        _ => return None,
+    };
+
+    let source = convert_source(&modulator.src)?;
+    let amount_source = convert_source(&modulator.amt_src)?;
+
+    // An empty source results in an output of 0, which means the
+    // modulator has no effect
+    if source.source == ModulatorSource::NoController {
+        return None;
+    }
+
+    Some(Modulator {
+        source,
+        amount_source,
+        destination,
+        amount: modulator.amount as f32 * scale,
+        absolute: modulator.transform == ModulatorTransform::Absolute,
+    })
+}
+
+fn convert_source(source: &Sf2ModulatorSource) -> Option<ModulatorInput> {
+    let curve = match source.ty {
+        SourceType::Linear => ModulatorCurve::Linear,
+        SourceType::Concave => ModulatorCurve::Concave,
+        SourceType::Convex => ModulatorCurve::Convex,
+        SourceType::Switch => ModulatorCurve::Switch,
+        SourceType::Unknown(_) => return None,
+    };
+
+    let source_type = match source.controller_palette {
+        ControllerPalette::General(palette) => match palette {
+            GeneralPalette::NoController => ModulatorSource::NoController,
+            GeneralPalette::NoteOnVelocity => ModulatorSource::Velocity,
+            GeneralPalette::NoteOnKeyNumber => ModulatorSource::Key,
+            GeneralPalette::PolyPressure => ModulatorSource::PolyPressure,
+            GeneralPalette::ChannelPressure => ModulatorSource::ChannelPressure,
+            GeneralPalette::PitchWheel => ModulatorSource::PitchWheel,
+            GeneralPalette::PitchWheelSensitivity => ModulatorSource::PitchWheelSensitivity,
+            GeneralPalette::Link | GeneralPalette::Unknown(_) => return None,
+        },
+        // Bank select, data entry, RPN/NRPN and channel mode messages
+        // are not valid modulator sources
+        ControllerPalette::Midi(cc) => match cc {
+            0 | 6 | 32 | 38 | 98..=101 | 120..=127 => return None,
+            cc => ModulatorSource::Controller(cc),
+        },
+    };
+
+    Some(ModulatorInput {
+        source: source_type,
+        curve,
+        bipolar: source.polarity == SourcePolarity::Bipolar,
+        negative: source.direction == SourceDirection::Negative,
+    })
+}
+
+/// Returns the default modulators defined in section 8.4 of the SF2 spec
+/// that have a supported destination.
+pub fn default_modulators() -> Vec<Modulator> {
+    let negative_concave = |source| ModulatorInput {
+        source,
+        curve: ModulatorCurve::Concave,
+        bipolar: false,
+        negative: true,
+    };
+
+    vec![
+        // 8.4.1 MIDI Note-On Velocity to Initial Attenuation
+        Modulator {
+            source: negative_concave(ModulatorSource::Velocity),
+            amount_source: ModulatorInput::NONE,
+            destination: ModulatorDestination::Attenuation,
+            amount: 960.0,
+            absolute: false,
+        },
+        // 8.4.2 MIDI Note-On Velocity to Filter Cutoff
+        // The amount source is a switch, like most SF2 synths do, so that the
+        // filter is only closed for velocities below 64.
+        Modulator {
+            source: ModulatorInput {
+                negative: true,
+                ..ModulatorInput::linear(ModulatorSource::Velocity)
+            },
+            amount_source: ModulatorInput {
+                source: ModulatorSource::Velocity,
+                curve: ModulatorCurve::Switch,
+                bipolar: false,
+                negative: true,
+            },
+            destination: ModulatorDestination::FilterCutoff,
+            amount: -2400.0,
+            absolute: false,
+        },
+        // 8.4.3 MIDI Channel Pressure to Vibrato LFO Pitch Depth
+        Modulator {
+            source: ModulatorInput::linear(ModulatorSource::ChannelPressure),
+            amount_source: ModulatorInput::NONE,
+            destination: ModulatorDestination::LfoToPitch(SF2_VIB_LFO),
+            amount: 50.0,
+            absolute: false,
+        },
+        // 8.4.4 MIDI Continuous Controller 1 to Vibrato LFO Pitch Depth
+        Modulator {
+            source: ModulatorInput::linear(ModulatorSource::Controller(1)),
+            amount_source: ModulatorInput::NONE,
+            destination: ModulatorDestination::LfoToPitch(SF2_VIB_LFO),
+            amount: 50.0,
+            absolute: false,
+        },
+        // 8.4.5 MIDI Continuous Controller 7 to Initial Attenuation
+        Modulator {
+            source: negative_concave(ModulatorSource::Controller(7)),
+            amount_source: ModulatorInput::NONE,
+            destination: ModulatorDestination::Attenuation,
+            amount: 960.0,
+            absolute: false,
+        },
+        // 8.4.6 MIDI Continuous Controller 10 to Pan Position
+        Modulator {
+            source: ModulatorInput {
+                bipolar: true,
+                ..ModulatorInput::linear(ModulatorSource::Controller(10))
+            },
+            amount_source: ModulatorInput::NONE,
+            destination: ModulatorDestination::Pan,
+            amount: 500.0,
+            absolute: false,
+        },
+        // 8.4.7 MIDI Continuous Controller 11 to Initial Attenuation
+        Modulator {
+            source: negative_concave(ModulatorSource::Controller(11)),
+            amount_source: ModulatorInput::NONE,
+            destination: ModulatorDestination::Attenuation,
+            amount: 960.0,
+            absolute: false,
+        },
+        // 8.4.10 Pitch Wheel to Initial Pitch Controlled by Pitch Wheel Sensitivity
+        Modulator {
+            source: ModulatorInput {
+                bipolar: true,
+                ..ModulatorInput::linear(ModulatorSource::PitchWheel)
+            },
+            amount_source: ModulatorInput::linear(ModulatorSource::PitchWheelSensitivity),
+            destination: ModulatorDestination::Pitch,
+            amount: 12700.0,
+            absolute: false,
+        },
+    ]
+}
+
+/// Merges two modulator lists. Modulators in `overrides` that are identical
+/// to one in `base` (same sources, destination and transform) replace it,
+/// the rest are appended.
+pub fn merge_modulators(base: &[Modulator], overrides: &[Modulator]) -> Vec<Modulator> {
+    let mut out = base.to_vec();
+
+    for modulator in overrides {
+        match out.iter_mut().find(|m| is_identical(m, modulator)) {
+            Some(m) => *m = *modulator,
+            None => out.push(*modulator),
+        }
+    }
+
+    out
+}
+
+fn is_identical(a: &Modulator, b: &Modulator) -> bool {
+    a.source == b.source
+        && a.amount_source == b.amount_source
+        && a.destination == b.destination
+        && a.absolute == b.absolute
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(modulators: &[Modulator], destination: ModulatorDestination) -> Vec<Modulator> {
+        modulators
+            .iter()
+            .filter(|m| m.destination == destination)
+            .copied()
+            .collect()
+    }
+
+    fn sf2_modulator(src: u16, dest: GeneratorType, amount: i16, amt_src: u16) -> Sf2Modulator {
+        Sf2Modulator {
+            src: src.into(),
+            dest,
+            amount,
+            amt_src: amt_src.into(),
+            transform: ModulatorTransform::Linear,
+        }
+    }
+
+    #[test]
+    fn test_convert_modulator() {
+        // 8.4.1 as stored in a file: negative unipolar concave velocity
+        let modulator = convert_modulator(&sf2_modulator(
+            0x0502,
+            GeneratorType::InitialAttenuation,
+            960,
+            0,
+        ))
+        .unwrap();
+        assert_eq!(
+            modulator.source,
+            ModulatorInput {
+                source: ModulatorSource::Velocity,
+                curve: ModulatorCurve::Concave,
+                bipolar: false,
+                negative: true,
+            }
+        );
+        assert_eq!(modulator.amount_source, ModulatorInput::NONE);
+        assert_eq!(modulator.destination, ModulatorDestination::Attenuation);
+        assert_eq!(modulator, default_modulators()[0]);
+
+        // Bipolar linear CC 10, coarse tune is converted to cents
+        let modulator =
+            convert_modulator(&sf2_modulator(0x028A, GeneratorType::CoarseTune, 2, 0)).unwrap();
+        assert_eq!(
+            modulator.source,
+            ModulatorInput {
+                bipolar: true,
+                ..ModulatorInput::linear(ModulatorSource::Controller(10))
+            }
+        );
+        assert_eq!(modulator.amount, 200.0);
+
+        // Invalid controllers, unknown types and destinations are ignored
+        let invalid = [
+            sf2_modulator(0x0086, GeneratorType::Pan, 100, 0),
+            sf2_modulator(0x1002, GeneratorType::Pan, 100, 0),
+            sf2_modulator(0x0002, GeneratorType::StartAddrsOffset, 100, 0),
+            sf2_modulator(0x0000, GeneratorType::Pan, 100, 0x0002),
+        ];
+        for modulator in invalid.iter() {
+            assert!(convert_modulator(modulator).is_none());
+        }
+    }
+
+    #[test]
+    fn test_default_modulators() {
+        let modulators = default_modulators();
+
+        // The velocity and volume controllers follow the concave curve
+        let attenuation = find(&modulators, ModulatorDestination::Attenuation);
+        assert_eq!(attenuation.len(), 3);
+        for modulator in attenuation.iter() {
+            assert_eq!(modulator.output(1.0, 0.0), 0.0);
+            assert!((modulator.output(64.0 / 127.0, 0.0) - 119.05).abs() < 0.01);
+        }
+
+        // The filter is only closed below a velocity of 64
+        let [cutoff] = find(&modulators, ModulatorDestination::FilterCutoff)[..] else {
+            panic!("expected one cutoff modulator");
+        };
+        assert_eq!(cutoff.output(1.0, 1.0), 0.0);
+        assert_eq!(cutoff.output(0.75, 0.75), 0.0);
+        assert_eq!(cutoff.output(0.25, 0.25), -1800.0);
+
+        // The pan is centered at CC 10 = 64
+        let [pan] = find(&modulators, ModulatorDestination::Pan)[..] else {
+            panic!("expected one pan modulator");
+        };
+        assert_eq!(pan.output(0.0, 0.0), -500.0);
+        assert_eq!(pan.output(0.5, 0.0), 0.0);
+        assert_eq!(pan.output(1.0, 0.0), 500.0);
+
+        // A pitch wheel sensitivity of 2 semitones bends by up to 200 cents
+        let [pitch] = find(&modulators, ModulatorDestination::Pitch)[..] else {
+            panic!("expected one pitch modulator");
+        };
+        assert!((pitch.output(1.0, 2.0 / 127.0) - 200.0).abs() < 0.01);
+        assert!((pitch.output(0.0, 2.0 / 127.0) + 200.0).abs() < 0.01);
+
+        let vibrato = find(&modulators, ModulatorDestination::LfoToPitch(SF2_VIB_LFO));
+        assert_eq!(vibrato.len(), 2);
+        assert!(vibrato.iter().all(|m| m.output(1.0, 0.0) == 50.0));
+    }
+
+    #[test]
+    fn test_merge_modulators() {
+        let base = default_modulators();
+
+        // An identical modulator replaces the default one
+        let replaced = Modulator {
+            amount: 480.0,
+            ..base[0]
+        };
+
+        // One with a different transform is a separate modulator
+        let added = Modulator {
+            absolute: true,
+            ..base[1]
+        };
+
+        let merged = merge_modulators(&base, &[replaced, added]);
+        assert_eq!(merged.len(), base.len() + 1);
+        assert_eq!(merged[0], replaced);
+        assert_eq!(merged[1..base.len()], base[1..]);
+        assert_eq!(merged[base.len()], added);
+
+        assert_eq!(merge_modulators(&base, &[]), base);
+    }
+}
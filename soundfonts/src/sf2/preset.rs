@@ -1,5 +1,15 @@
-use super::{instrument::Sf2Instrument, sample::Sf2Sample, zone::Sf2Zone, Sf2Preset, Sf2Region};
-use crate::{convert_sample_index, sfz::AmpegEnvelopeParams, LoopMode};
+use super::{
+    instrument::Sf2Instrument,
+    modulator::{default_modulators, merge_modulators},
+    sample::Sf2Sample,
+    zone::Sf2Zone,
+    Sf2Preset, Sf2Region,
+};
+use crate::{
+    convert_sample_index,
+    sfz::{AmpegEnvelopeParams, LfoParams, ModEnvelopeParams},
+    LoopMode,
+};
 use soundfont::Preset;
 use std::{ops::RangeInclusive, sync::Arc};
 
@@ -34,6 +44,7 @@ impl Sf2ParsedPreset {
         sample_rate: u32,
     ) -> Vec<Sf2Preset> {
         let mut out: Vec<Sf2Preset> = Vec::new();
+        let default_modulators = default_modulators();
 
         for preset in presets {
             let mut new_preset = Sf2Preset {
@@ -127,6 +138,72 @@ impl Sf2ParsedPreset {
                                     ampeg_release: subzone.env_release.unwrap_or(0.0)
                                         * zone.env_release.unwrap_or(1.0),
                                 },
+                                lfos: vec![
+                                    LfoParams {
+                                        delay: subzone.mod_lfo_delay.unwrap_or(0.0)
+                                            * zone.mod_lfo_delay.unwrap_or(1.0),
+                                        freq: lfo_freq(subzone.mod_lfo_freq, zone.mod_lfo_freq),
+                                        to_pitch: sum_generators(
+                                            subzone.mod_lfo_to_pitch,
+                                            zone.mod_lfo_to_pitch,
+                                        ),
+                                        to_filter: sum_generators(
+                                            subzone.mod_lfo_to_filter,
+                                            zone.mod_lfo_to_filter,
+                                        ),
+                                        // Centibels to dB
+                                        to_volume: sum_generators(
+                                            subzone.mod_lfo_to_volume,
+                                            zone.mod_lfo_to_volume,
+                                        ) / 10.0,
+                                    },
+                                    LfoParams {
+                                        delay: subzone.vib_lfo_delay.unwrap_or(0.0)
+                                            * zone.vib_lfo_delay.unwrap_or(1.0),
+                                        freq: lfo_freq(subzone.vib_lfo_freq, zone.vib_lfo_freq),
+                                        to_pitch: sum_generators(
+                                            subzone.vib_lfo_to_pitch,
+                                            zone.vib_lfo_to_pitch,
+                                        ),
+                                        ..Default::default()
+                                    },
+                                ],
+                                mod_envelopes: vec![ModEnvelopeParams {
+                                    envelope: AmpegEnvelopeParams {
+                                        ampeg_start: 0.0,
+                                        ampeg_delay: subzone.mod_env_delay.unwrap_or(0.0)
+                                            * zone.mod_env_delay.unwrap_or(1.0),
+                                        ampeg_attack: subzone.mod_env_attack.unwrap_or(0.0)
+                                            * zone.mod_env_attack.unwrap_or(1.0),
+                                        ampeg_hold: subzone.mod_env_hold.unwrap_or(0.0)
+                                            * zone.mod_env_hold.unwrap_or(1.0),
+                                        ampeg_decay: subzone.mod_env_decay.unwrap_or(0.0)
+                                            * zone.mod_env_decay.unwrap_or(1.0),
+                                        ampeg_sustain: zone
+                                            .mod_env_sustain
+                                            .unwrap_or(subzone.mod_env_sustain.unwrap_or(100.0)),
+                                        ampeg_release: subzone.mod_env_release.unwrap_or(0.0)
+                                            * zone.mod_env_release.unwrap_or(1.0),
+                                    },
+                                    to_pitch: sum_generators(
+                                        subzone.mod_env_to_pitch,
+                                        zone.mod_env_to_pitch,
+                                    ),
+                                    to_filter: sum_generators(
+                                        subzone.mod_env_to_filter,
+                                        zone.mod_env_to_filter,
+                                    ),
+                                }],
+                                // Only valid at the instrument level
+                                exclusive_class: subzone.exclusive_class.unwrap_or(0).max(0) as u16,
+                                // Instrument modulators override the default ones,
+                                // preset modulators are added on top of them
+                                modulators: {
+                                    let mut modulators =
+                                        merge_modulators(&default_modulators, &subzone.modulators);
+                                    modulators.extend_from_slice(&zone.modulators);
+                                    modulators
+                                },
                             };
 
                             regions.push((new_region, sample.clone()));
@@ -199,6 +276,14 @@ impl Sf2ParsedPreset {
     }
 }
 
+fn sum_generators(instrument: Option<i16>, preset: Option<i16>) -> f32 {
+    instrument.unwrap_or(0) as f32 + preset.unwrap_or(0) as f32
+}
+
+fn lfo_freq(instrument: Option<i16>, preset: Option<i16>) -> f32 {
+    8.176 * 2f32.powf(sum_generators(instrument, preset) / 1200.0)
+}
+
 fn combine_ranges<T: Ord + Copy>(
     r1: RangeInclusive<T>,
     r2: RangeInclusive<T>,
@@ -6,9 +6,51 @@ use soundfont::data::{
 };
 use std::{fs::File, sync::Arc};
 
+/// The audio data of an SF2 sample.
+///
+/// The 16 and 24 bit values of the file are kept as they are, and samples
+/// are only converted to floats when they need to be resampled.
+#[derive(Clone, Debug)]
+pub enum Sf2SampleData {
+    /// 16 bit values, as stored in the file.
+    I16(Arc<[i16]>),
+    /// 24 bit values, as packed little endian integers.
+    I24(Arc<[[u8; 3]]>),
+    /// Values resampled to the output sample rate, from -1 to 1.
+    F32(Arc<[f32]>),
+}
+
+impl Sf2SampleData {
+    /// The number of values in the sample.
+    pub fn len(&self) -> usize {
+        match self {
+            Sf2SampleData::I16(data) => data.len(),
+            Sf2SampleData::I24(data) => data.len(),
+            Sf2SampleData::F32(data) => data.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Converts the sample to floats from -1 to 1.
+    pub fn to_f32(&self) -> Arc<[f32]> {
+        match self {
+            Sf2SampleData::I16(data) => data.iter().map(|&s| s as f32 / i16::MAX as f32).collect(),
+            Sf2SampleData::I24(data) => data.iter().map(|&s| i24_to_f32(s)).collect(),
+            Sf2SampleData::F32(data) => data.clone(),
+        }
+    }
+}
+
+fn i24_to_f32([b0, b1, b2]: [u8; 3]) -> f32 {
+    (i32::from_le_bytes([0, b0, b1, b2]) >> 8) as f32 / 8_388_607.0
+}
+
 #[derive(Clone, Debug)]
 pub struct Sf2Sample {
-    pub data: Arc<[f32]>,
+    pub data: Sf2SampleData,
     pub link_type: i8,
     pub loop_start: u32,
     pub loop_end: u32,
@@ -31,9 +73,7 @@ impl Sf2Sample {
             return Err(Sf2ParseError::FailedToParseFile);
         };
 
-        let mut samples = Vec::new();
-
-        if let Some(sm24) = data.sm24 {
+        let samples = if let Some(sm24) = data.sm24 {
             // SF2 is 24-bit
             let extra = sm24
                 .read_contents(file)
@@ -42,38 +82,47 @@ impl Sf2Sample {
                 return Err(Sf2ParseError::FailedToParseFile);
             }
 
-            for i in 0..extra.len() {
-                let n0 = 0;
-                let n1 = extra[i];
-                let n2 = smpl[i * 2];
-                let n3 = smpl[i * 2 + 1];
-                let sample = i32::from_le_bytes([n0, n1, n2, n3]);
-                let conv = sample as f32 / i32::MAX as f32;
-                samples.push(conv);
-            }
+            let samples: Vec<[u8; 3]> = extra
+                .iter()
+                .zip(smpl.chunks(2))
+                .map(|(&n0, n)| [n0, n[0], n[1]])
+                .collect();
+            Sf2SampleData::I24(samples.into())
         } else {
             // SF2 is 16-bit
-            for i in smpl.chunks(2) {
-                let n0 = i[0];
-                let n1 = i[1];
-                let sample = i16::from_le_bytes([n0, n1]);
-                let conv = sample as f32 / i16::MAX as f32;
-                samples.push(conv);
-            }
-        }
+            let samples: Vec<i16> = smpl
+                .chunks(2)
+                .map(|n| i16::from_le_bytes([n[0], n[1]]))
+                .collect();
+            Sf2SampleData::I16(samples.into())
+        };
 
         let mut out: Vec<Sf2Sample> = Vec::new();
 
         for h in headers {
             let start = h.start;
             let end = h.end;
-            let sample: Vec<f32> = samples[start as usize..end as usize].into();
+            let range = start as usize..end as usize;
+            if range.end > samples.len() || range.start > range.end {
+                return Err(Sf2ParseError::FailedToParseFile);
+            }
+
+            let sample = match &samples {
+                Sf2SampleData::I16(data) => Sf2SampleData::I16(data[range].into()),
+                Sf2SampleData::I24(data) => Sf2SampleData::I24(data[range].into()),
+                Sf2SampleData::F32(data) => Sf2SampleData::F32(data[range].into()),
+            };
 
             let new = Sf2Sample {
-                data: if h.sample_rate != sample_rate || !sample.is_empty() {
-                    resample_vec(sample, h.sample_rate as f32, sample_rate as f32)
+                data: if h.sample_rate != sample_rate && !sample.is_empty() {
+                    let sample = sample.to_f32().to_vec();
+                    Sf2SampleData::F32(resample_vec(
+                        sample,
+                        h.sample_rate as f32,
+                        sample_rate as f32,
+                    ))
                 } else {
-                    sample.into()
+                    sample
                 },
                 link_type: match h.sample_type {
                     SampleLink::LeftSample => -1,
@@ -1,4 +1,5 @@
-use crate::LoopMode;
+use super::modulator::{convert_modulator, merge_modulators};
+use crate::{modulator::Modulator, LoopMode};
 use soundfont::{data::hydra::generator::GeneratorType, Zone};
 use std::ops::RangeInclusive;
 
@@ -27,6 +28,24 @@ pub struct Sf2Zone {
     pub fine_tune: Option<i16>,
     pub coarse_tune: Option<i16>,
     pub root_override: Option<i16>,
+    pub mod_lfo_delay: Option<f32>,
+    pub mod_lfo_freq: Option<i16>,
+    pub mod_lfo_to_pitch: Option<i16>,
+    pub mod_lfo_to_filter: Option<i16>,
+    pub mod_lfo_to_volume: Option<i16>,
+    pub vib_lfo_delay: Option<f32>,
+    pub vib_lfo_freq: Option<i16>,
+    pub vib_lfo_to_pitch: Option<i16>,
+    pub mod_env_delay: Option<f32>,
+    pub mod_env_attack: Option<f32>,
+    pub mod_env_hold: Option<f32>,
+    pub mod_env_decay: Option<f32>,
+    pub mod_env_sustain: Option<f32>,
+    pub mod_env_release: Option<f32>,
+    pub mod_env_to_pitch: Option<i16>,
+    pub mod_env_to_filter: Option<i16>,
+    pub exclusive_class: Option<i16>,
+    pub modulators: Vec<Modulator>,
 }
 
 impl Sf2Zone {
@@ -85,6 +104,38 @@ impl Sf2Zone {
                         region.env_release =
                             gen.amount.as_i16().map(|v| 2f32.powf(*v as f32 / 1200.0))
                     }
+                    GeneratorType::DelayModEnv => {
+                        region.mod_env_delay =
+                            gen.amount.as_i16().map(|v| 2f32.powf(*v as f32 / 1200.0))
+                    }
+                    GeneratorType::AttackModEnv => {
+                        region.mod_env_attack =
+                            gen.amount.as_i16().map(|v| 2f32.powf(*v as f32 / 1200.0))
+                    }
+                    GeneratorType::HoldModEnv => {
+                        region.mod_env_hold =
+                            gen.amount.as_i16().map(|v| 2f32.powf(*v as f32 / 1200.0))
+                    }
+                    GeneratorType::DecayModEnv => {
+                        region.mod_env_decay =
+                            gen.amount.as_i16().map(|v| 2f32.powf(*v as f32 / 1200.0))
+                    }
+                    GeneratorType::SustainModEnv => {
+                        region.mod_env_sustain = gen
+                            .amount
+                            .as_i16()
+                            .map(|v| (100.0 - *v as f32 / 10.0).clamp(0.0, 100.0))
+                    }
+                    GeneratorType::ReleaseModEnv => {
+                        region.mod_env_release =
+                            gen.amount.as_i16().map(|v| 2f32.powf(*v as f32 / 1200.0))
+                    }
+                    GeneratorType::ModEnvToPitch => {
+                        region.mod_env_to_pitch = gen.amount.as_i16().copied()
+                    }
+                    GeneratorType::ModEnvToFilterFc => {
+                        region.mod_env_to_filter = gen.amount.as_i16().copied()
+                    }
                     GeneratorType::KeyRange => {
                         let range = gen.amount.as_range().copied();
                         region.keyrange = range.map(|v| v.low..=v.high)
@@ -107,6 +158,31 @@ impl Sf2Zone {
                             _ => LoopMode::NoLoop,
                         })
                     }
+                    GeneratorType::DelayModLFO => {
+                        region.mod_lfo_delay =
+                            gen.amount.as_i16().map(|v| 2f32.powf(*v as f32 / 1200.0))
+                    }
+                    GeneratorType::FreqModLFO => region.mod_lfo_freq = gen.amount.as_i16().copied(),
+                    GeneratorType::ModLfoToPitch => {
+                        region.mod_lfo_to_pitch = gen.amount.as_i16().copied()
+                    }
+                    GeneratorType::ModLfoToFilterFc => {
+                        region.mod_lfo_to_filter = gen.amount.as_i16().copied()
+                    }
+                    GeneratorType::ModLfoToVolume => {
+                        region.mod_lfo_to_volume = gen.amount.as_i16().copied()
+                    }
+                    GeneratorType::DelayVibLFO => {
+                        region.vib_lfo_delay =
+                            gen.amount.as_i16().map(|v| 2f32.powf(*v as f32 / 1200.0))
+                    }
+                    GeneratorType::FreqVibLFO => region.vib_lfo_freq = gen.amount.as_i16().copied(),
+                    GeneratorType::VibLfoToPitch => {
+                        region.vib_lfo_to_pitch = gen.amount.as_i16().copied()
+                    }
+                    GeneratorType::ExclusiveClass => {
+                        region.exclusive_class = gen.amount.as_i16().copied()
+                    }
                     GeneratorType::OverridingRootKey => {
                         region.root_override = gen.amount.as_i16().copied()
                     }
@@ -114,6 +190,10 @@ impl Sf2Zone {
                 }
             }
 
+            let modulators: Vec<Modulator> =
+                zone.mod_list.iter().filter_map(convert_modulator).collect();
+            region.modulators = merge_modulators(&region.modulators, &modulators);
+
             if i == 0 && region.index.is_none() {
                 global_region = region;
             } else {
@@ -4,9 +4,14 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use self::parse::{parse_tokens_resolved, SfzAmpegEnvelope, SfzGroupType, SfzOpcode, SfzToken};
+use self::parse::{
+    parse_tokens_resolved, SfzAmpegEnvelope, SfzGroupType, SfzLfo, SfzOpcode, SfzToken,
+};
 
-use crate::{FilterType, LoopMode};
+use crate::{
+    modulator::{Modulator, ModulatorDestination, ModulatorInput, ModulatorSource},
+    FilterType, LoopMode, OffMode, TriggerMode,
+};
 
 mod grammar;
 mod parse;
@@ -52,6 +57,129 @@ impl AmpegEnvelopeParams {
     }
 }
 
+/// Structure that holds the parameters of a low frequency oscillator.
+///
+/// The oscillator has a triangle shape, starting at 0 and rising
+/// to its maximum after a quarter of a cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LfoParams {
+    /// Time before the oscillator starts, in seconds
+    pub delay: f32,
+
+    /// Frequency of the oscillator, in Hz
+    pub freq: f32,
+
+    /// Depth of the pitch modulation, in cents
+    pub to_pitch: f32,
+
+    /// Depth of the filter cutoff modulation, in cents
+    pub to_filter: f32,
+
+    /// Depth of the volume modulation, in dB
+    pub to_volume: f32,
+}
+
+/// Structure that holds the parameters of an envelope that modulates
+/// the pitch and filter cutoff of a voice.
+///
+/// The envelope moves from 0 to 1 during the attack, the depths are
+/// the amount of modulation applied at its peak.
+#[derive(Debug, Clone)]
+pub struct ModEnvelopeParams {
+    pub envelope: AmpegEnvelopeParams,
+
+    /// Depth of the pitch modulation, in cents
+    pub to_pitch: f32,
+
+    /// Depth of the filter cutoff modulation, in cents
+    pub to_filter: f32,
+}
+
+impl Default for ModEnvelopeParams {
+    fn default() -> Self {
+        ModEnvelopeParams {
+            envelope: AmpegEnvelopeParams {
+                ampeg_attack: 0.0,
+                ampeg_sustain: 0.0,
+                ampeg_release: 0.0,
+                ..Default::default()
+            },
+            to_pitch: 0.0,
+            to_filter: 0.0,
+        }
+    }
+}
+
+/// The shape of a crossfade between regions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CrossfadeCurve {
+    /// Linear gain, the sum of the amplitudes of the regions stays constant
+    Gain,
+
+    /// Constant power, the sum of the powers of the regions stays constant
+    #[default]
+    Power,
+}
+
+/// A range of values of a MIDI controller, like the SFZ `loccN` and `hiccN` opcodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CcRange {
+    /// The controller number
+    pub cc: u8,
+
+    /// The lowest value of the range
+    pub lo: u8,
+
+    /// The highest value of the range
+    pub hi: u8,
+}
+
+impl CcRange {
+    /// Returns true if the value is within the range.
+    pub fn contains(&self, value: u8) -> bool {
+        (self.lo..=self.hi).contains(&value)
+    }
+
+    /// Returns true if all the ranges contain the current value of their controller.
+    pub fn all_match(ranges: &[CcRange], cc: &[u8; 128]) -> bool {
+        ranges
+            .iter()
+            .all(|range| range.contains(cc[range.cc as usize & 0x7F]))
+    }
+}
+
+/// Sets the low or high value of the range of a controller in the list,
+/// adding a full range for it if it doesn't exist yet.
+fn set_cc_range(ranges: &mut Vec<CcRange>, cc: u8, value: u8, high: bool) {
+    let range = match ranges.iter_mut().position(|r| r.cc == cc) {
+        Some(i) => &mut ranges[i],
+        None => {
+            ranges.push(CcRange { cc, lo: 0, hi: 127 });
+            ranges.last_mut().unwrap()
+        }
+    };
+    if high {
+        range.hi = value;
+    } else {
+        range.lo = value;
+    }
+}
+
+/// Index of the `pitcheg` envelope in the modulation envelopes of an SFZ region
+pub const SFZ_PITCH_EG: usize = 0;
+
+/// Index of the `fileg` envelope in the modulation envelopes of an SFZ region
+pub const SFZ_FIL_EG: usize = 1;
+
+/// Index of the `pitchlfo` oscillator in the LFOs of an SFZ region
+pub const SFZ_PITCH_LFO: usize = 0;
+
+/// Index of the `amplfo` oscillator in the LFOs of an SFZ region
+pub const SFZ_AMP_LFO: usize = 1;
+
+/// Index of the `fillfo` oscillator in the LFOs of an SFZ region
+pub const SFZ_FIL_LFO: usize = 2;
+
 #[derive(Debug, Clone)]
 pub(crate) struct RegionParamsBuilder {
     lovel: u8,
@@ -64,6 +192,7 @@ pub(crate) struct RegionParamsBuilder {
     sample: Option<String>,
     default_path: Option<String>,
     loop_mode: LoopMode,
+    trigger: TriggerMode,
     loop_start: u32,
     loop_end: u32,
     offset: u32,
@@ -75,6 +204,39 @@ pub(crate) struct RegionParamsBuilder {
     filter_type: FilterType,
     ampeg_envelope: AmpegEnvelopeParams,
     tune: i16,
+    seq_length: u8,
+    seq_position: u8,
+    lorand: f32,
+    hirand: f32,
+    sw_lokey: Option<u8>,
+    sw_hikey: Option<u8>,
+    sw_last: Option<u8>,
+    sw_down: Option<u8>,
+    sw_up: Option<u8>,
+    sw_default: Option<u8>,
+    xfin_lovel: u8,
+    xfin_hivel: u8,
+    xfout_lovel: u8,
+    xfout_hivel: u8,
+    xfin_lokey: u8,
+    xfin_hikey: u8,
+    xfout_lokey: u8,
+    xfout_hikey: u8,
+    xf_velcurve: CrossfadeCurve,
+    xf_keycurve: CrossfadeCurve,
+    amp_veltrack: f32,
+    amp_velcurve: Vec<(u8, f32)>,
+    amp_keytrack: f32,
+    amp_keycenter: i8,
+    cc_conditions: Vec<CcRange>,
+    cc_triggers: Vec<CcRange>,
+    group: u32,
+    off_by: u32,
+    off_mode: OffMode,
+    off_time: f32,
+    lfos: [LfoParams; 3],
+    mod_envelopes: [ModEnvelopeParams; 2],
+    modulators: Vec<Modulator>,
 }
 
 impl Default for RegionParamsBuilder {
@@ -90,6 +252,7 @@ impl Default for RegionParamsBuilder {
             sample: None,
             default_path: None,
             loop_mode: LoopMode::NoLoop,
+            trigger: TriggerMode::Attack,
             loop_start: 0,
             loop_end: 0,
             offset: 0,
@@ -101,6 +264,39 @@ impl Default for RegionParamsBuilder {
             filter_type: FilterType::default(),
             ampeg_envelope: AmpegEnvelopeParams::default(),
             tune: 0,
+            seq_length: 1,
+            seq_position: 1,
+            lorand: 0.0,
+            hirand: 1.0,
+            sw_lokey: None,
+            sw_hikey: None,
+            sw_last: None,
+            sw_down: None,
+            sw_up: None,
+            sw_default: None,
+            xfin_lovel: 0,
+            xfin_hivel: 0,
+            xfout_lovel: 127,
+            xfout_hivel: 127,
+            xfin_lokey: 0,
+            xfin_hikey: 0,
+            xfout_lokey: 127,
+            xfout_hikey: 127,
+            xf_velcurve: CrossfadeCurve::Power,
+            xf_keycurve: CrossfadeCurve::Power,
+            amp_veltrack: 100.0,
+            amp_velcurve: Vec::new(),
+            amp_keytrack: 0.0,
+            amp_keycenter: 60,
+            cc_conditions: Vec::new(),
+            cc_triggers: Vec::new(),
+            group: 0,
+            off_by: 0,
+            off_mode: OffMode::Fast,
+            off_time: 0.006,
+            lfos: Default::default(),
+            mod_envelopes: Default::default(),
+            modulators: Vec::new(),
         }
     }
 }
@@ -122,10 +318,21 @@ impl RegionParamsBuilder {
             SfzOpcode::Volume(val) => self.volume = val,
             SfzOpcode::Sample(val) => self.sample = Some(val),
             SfzOpcode::LoopMode(val) => self.loop_mode = val,
+            SfzOpcode::Trigger(val) => self.trigger = val,
             SfzOpcode::LoopStart(val) => self.loop_start = val,
             SfzOpcode::LoopEnd(val) => self.loop_end = val,
             SfzOpcode::Offset(val) => self.offset = val,
             SfzOpcode::Cutoff(val) => self.cutoff = Some(val),
+            SfzOpcode::CutoffChanaft(val) => self.set_modulator(
+                ModulatorSource::ChannelPressure,
+                ModulatorDestination::FilterCutoff,
+                val,
+            ),
+            SfzOpcode::CutoffPolyaft(val) => self.set_modulator(
+                ModulatorSource::PolyPressure,
+                ModulatorDestination::FilterCutoff,
+                val,
+            ),
             SfzOpcode::Resonance(val) => self.resonance = val,
             SfzOpcode::FilVeltrack(val) => self.fil_veltrack = val,
             SfzOpcode::FilKeytrack(val) => self.fil_keytrack = val,
@@ -134,9 +341,119 @@ impl RegionParamsBuilder {
             SfzOpcode::DefaultPath(val) => self.default_path = Some(val),
             SfzOpcode::AmpegEnvelope(flag) => self.ampeg_envelope.update_from_flag(flag),
             SfzOpcode::Tune(val) => self.tune = val,
+            SfzOpcode::SeqLength(val) => self.seq_length = val,
+            SfzOpcode::SeqPosition(val) => self.seq_position = val,
+            SfzOpcode::Lorand(val) => self.lorand = val,
+            SfzOpcode::Hirand(val) => self.hirand = val,
+            SfzOpcode::SwLokey(val) => self.sw_lokey = u8::try_from(val).ok(),
+            SfzOpcode::SwHikey(val) => self.sw_hikey = u8::try_from(val).ok(),
+            SfzOpcode::SwLast(val) => self.sw_last = u8::try_from(val).ok(),
+            SfzOpcode::SwDown(val) => self.sw_down = u8::try_from(val).ok(),
+            SfzOpcode::SwUp(val) => self.sw_up = u8::try_from(val).ok(),
+            SfzOpcode::SwDefault(val) => self.sw_default = u8::try_from(val).ok(),
+            SfzOpcode::XfinLovel(val) => self.xfin_lovel = val,
+            SfzOpcode::XfinHivel(val) => self.xfin_hivel = val,
+            SfzOpcode::XfoutLovel(val) => self.xfout_lovel = val,
+            SfzOpcode::XfoutHivel(val) => self.xfout_hivel = val,
+            SfzOpcode::XfinLokey(val) => self.xfin_lokey = val.max(0) as u8,
+            SfzOpcode::XfinHikey(val) => self.xfin_hikey = val.max(0) as u8,
+            SfzOpcode::XfoutLokey(val) => self.xfout_lokey = val.max(0) as u8,
+            SfzOpcode::XfoutHikey(val) => self.xfout_hikey = val.max(0) as u8,
+            SfzOpcode::XfVelcurve(val) => self.xf_velcurve = val,
+            SfzOpcode::XfKeycurve(val) => self.xf_keycurve = val,
+            SfzOpcode::AmpVeltrack(val) => self.amp_veltrack = val,
+            SfzOpcode::AmpVelcurve(vel, val) => {
+                match self.amp_velcurve.iter_mut().find(|(v, _)| *v == vel) {
+                    Some(point) => point.1 = val,
+                    None => self.amp_velcurve.push((vel, val)),
+                }
+            }
+            SfzOpcode::AmpKeytrack(val) => self.amp_keytrack = val,
+            SfzOpcode::AmpKeycenter(val) => self.amp_keycenter = val,
+            SfzOpcode::Locc(cc, val) => set_cc_range(&mut self.cc_conditions, cc, val, false),
+            SfzOpcode::Hicc(cc, val) => set_cc_range(&mut self.cc_conditions, cc, val, true),
+            SfzOpcode::OnLocc(cc, val) => set_cc_range(&mut self.cc_triggers, cc, val, false),
+            SfzOpcode::OnHicc(cc, val) => set_cc_range(&mut self.cc_triggers, cc, val, true),
+            SfzOpcode::Group(val) => self.group = val,
+            SfzOpcode::OffBy(val) => self.off_by = val,
+            SfzOpcode::OffMode(val) => self.off_mode = val,
+            SfzOpcode::OffTime(val) => self.off_time = val,
+            SfzOpcode::Lfo(flag) => self.update_lfo_from_flag(flag),
+            SfzOpcode::PitchegEnvelope(flag) => self.mod_envelopes[SFZ_PITCH_EG]
+                .envelope
+                .update_from_flag(flag),
+            SfzOpcode::PitchegDepth(val) => self.mod_envelopes[SFZ_PITCH_EG].to_pitch = val,
+            SfzOpcode::FilegEnvelope(flag) => self.mod_envelopes[SFZ_FIL_EG]
+                .envelope
+                .update_from_flag(flag),
+            SfzOpcode::FilegDepth(val) => self.mod_envelopes[SFZ_FIL_EG].to_filter = val,
+        }
+    }
+
+    fn update_lfo_from_flag(&mut self, flag: SfzLfo) {
+        match flag {
+            SfzLfo::PitchlfoDelay(val) => self.lfos[SFZ_PITCH_LFO].delay = val,
+            SfzLfo::PitchlfoFreq(val) => self.lfos[SFZ_PITCH_LFO].freq = val,
+            SfzLfo::PitchlfoDepth(val) => self.lfos[SFZ_PITCH_LFO].to_pitch = val,
+            SfzLfo::AmplfoDelay(val) => self.lfos[SFZ_AMP_LFO].delay = val,
+            SfzLfo::AmplfoFreq(val) => self.lfos[SFZ_AMP_LFO].freq = val,
+            SfzLfo::AmplfoDepth(val) => self.lfos[SFZ_AMP_LFO].to_volume = val,
+            SfzLfo::FillfoDelay(val) => self.lfos[SFZ_FIL_LFO].delay = val,
+            SfzLfo::FillfoFreq(val) => self.lfos[SFZ_FIL_LFO].freq = val,
+            SfzLfo::FillfoDepth(val) => self.lfos[SFZ_FIL_LFO].to_filter = val,
+            SfzLfo::PitchlfoDepthChanaft(val) => self.set_modulator(
+                ModulatorSource::ChannelPressure,
+                ModulatorDestination::LfoToPitch(SFZ_PITCH_LFO),
+                val,
+            ),
+            SfzLfo::PitchlfoDepthPolyaft(val) => self.set_modulator(
+                ModulatorSource::PolyPressure,
+                ModulatorDestination::LfoToPitch(SFZ_PITCH_LFO),
+                val,
+            ),
+            // The LFO volume depth is in dB, the modulator output in centibels
+            SfzLfo::AmplfoDepthChanaft(val) => self.set_modulator(
+                ModulatorSource::ChannelPressure,
+                ModulatorDestination::LfoToVolume(SFZ_AMP_LFO),
+                val * 10.0,
+            ),
+            SfzLfo::AmplfoDepthPolyaft(val) => self.set_modulator(
+                ModulatorSource::PolyPressure,
+                ModulatorDestination::LfoToVolume(SFZ_AMP_LFO),
+                val * 10.0,
+            ),
+            SfzLfo::FillfoDepthChanaft(val) => self.set_modulator(
+                ModulatorSource::ChannelPressure,
+                ModulatorDestination::LfoToFilter(SFZ_FIL_LFO),
+                val,
+            ),
+            SfzLfo::FillfoDepthPolyaft(val) => self.set_modulator(
+                ModulatorSource::PolyPressure,
+                ModulatorDestination::LfoToFilter(SFZ_FIL_LFO),
+                val,
+            ),
         }
     }
 
+    /// Sets the amount of the modulator with the given source and destination,
+    /// replacing the one inherited from a parent header.
+    fn set_modulator(
+        &mut self,
+        source: ModulatorSource,
+        destination: ModulatorDestination,
+        amount: f32,
+    ) {
+        self.modulators
+            .retain(|m| m.source.source != source || m.destination != destination);
+        self.modulators.push(Modulator {
+            source: ModulatorInput::linear(source),
+            amount_source: ModulatorInput::NONE,
+            destination,
+            amount,
+            absolute: false,
+        });
+    }
+
     fn build(self, base_path: &Path) -> Option<RegionParams> {
         let relative_sample_path = if let Some(default_path) = self.default_path {
             PathBuf::from(default_path).join(self.sample?)
@@ -158,6 +475,7 @@ impl RegionParamsBuilder {
             pan: self.pan,
             sample_path,
             loop_mode: self.loop_mode,
+            trigger: self.trigger,
             loop_start: self.loop_start,
             loop_end: self.loop_end,
             offset: self.offset,
@@ -169,6 +487,43 @@ impl RegionParamsBuilder {
             filter_type: self.filter_type,
             ampeg_envelope: self.ampeg_envelope,
             tune: self.tune,
+            seq_length: self.seq_length,
+            seq_position: self.seq_position,
+            lorand: self.lorand,
+            hirand: self.hirand,
+            sw_lokey: self.sw_lokey,
+            sw_hikey: self.sw_hikey,
+            sw_last: self.sw_last,
+            sw_down: self.sw_down,
+            sw_up: self.sw_up,
+            sw_default: self.sw_default,
+            xfin_lovel: self.xfin_lovel,
+            xfin_hivel: self.xfin_hivel,
+            xfout_lovel: self.xfout_lovel,
+            xfout_hivel: self.xfout_hivel,
+            xfin_lokey: self.xfin_lokey,
+            xfin_hikey: self.xfin_hikey,
+            xfout_lokey: self.xfout_lokey,
+            xfout_hikey: self.xfout_hikey,
+            xf_velcurve: self.xf_velcurve,
+            xf_keycurve: self.xf_keycurve,
+            amp_veltrack: self.amp_veltrack,
+            amp_velcurve: {
+                let mut curve = self.amp_velcurve;
+                curve.sort_by_key(|(vel, _)| *vel);
+                curve
+            },
+            amp_keytrack: self.amp_keytrack,
+            amp_keycenter: self.amp_keycenter,
+            cc_conditions: self.cc_conditions,
+            cc_triggers: self.cc_triggers,
+            group: self.group,
+            off_by: self.off_by,
+            off_mode: self.off_mode,
+            off_time: self.off_time,
+            lfos: self.lfos.to_vec(),
+            mod_envelopes: self.mod_envelopes.to_vec(),
+            modulators: self.modulators,
         })
     }
 }
@@ -183,6 +538,7 @@ pub struct RegionParams {
     pub pan: i8,
     pub sample_path: PathBuf,
     pub loop_mode: LoopMode,
+    pub trigger: TriggerMode,
     pub loop_start: u32,
     pub loop_end: u32,
     pub offset: u32,
@@ -194,6 +550,41 @@ pub struct RegionParams {
     pub filter_type: FilterType,
     pub ampeg_envelope: AmpegEnvelopeParams,
     pub tune: i16,
+    pub seq_length: u8,
+    pub seq_position: u8,
+    pub lorand: f32,
+    pub hirand: f32,
+    pub sw_lokey: Option<u8>,
+    pub sw_hikey: Option<u8>,
+    pub sw_last: Option<u8>,
+    pub sw_down: Option<u8>,
+    pub sw_up: Option<u8>,
+    pub sw_default: Option<u8>,
+    pub xfin_lovel: u8,
+    pub xfin_hivel: u8,
+    pub xfout_lovel: u8,
+    pub xfout_hivel: u8,
+    pub xfin_lokey: u8,
+    pub xfin_hikey: u8,
+    pub xfout_lokey: u8,
+    pub xfout_hikey: u8,
+    pub xf_velcurve: CrossfadeCurve,
+    pub xf_keycurve: CrossfadeCurve,
+    pub amp_veltrack: f32,
+    /// Points of the velocity curve, sorted by velocity
+    pub amp_velcurve: Vec<(u8, f32)>,
+    pub amp_keytrack: f32,
+    pub amp_keycenter: i8,
+    pub cc_conditions: Vec<CcRange>,
+    pub cc_triggers: Vec<CcRange>,
+    pub group: u32,
+    pub off_by: u32,
+    pub off_mode: OffMode,
+    pub off_time: f32,
+    pub lfos: Vec<LfoParams>,
+    pub mod_envelopes: Vec<ModEnvelopeParams>,
+    /// Aftertouch modulators of the region
+    pub modulators: Vec<Modulator>,
 }
 
 fn get_group_level(group_type: SfzGroupType) -> Option<usize> {
@@ -281,3 +672,6 @@ pub fn parse_soundfont(sfz_path: impl Into<PathBuf>) -> Result<Vec<RegionParams>
 
     Ok(regions)
 }
+
+#[cfg(test)]
+mod tests;
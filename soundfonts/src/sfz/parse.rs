@@ -8,7 +8,8 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::{FilterType, LoopMode};
+use super::CrossfadeCurve;
+use crate::{FilterType, LoopMode, OffMode, TriggerMode};
 use encoding_rs::UTF_8;
 use encoding_rs_io::DecodeReaderBytesBuilder;
 
@@ -28,10 +29,13 @@ pub enum SfzOpcode {
     Pan(i8),
     Sample(String),
     LoopMode(LoopMode),
+    Trigger(TriggerMode),
     LoopStart(u32),
     LoopEnd(u32),
     Offset(u32),
     Cutoff(f32),
+    CutoffChanaft(f32),
+    CutoffPolyaft(f32),
     Resonance(f32),
     FilVeltrack(i16),
     FilKeycenter(i8),
@@ -39,7 +43,44 @@ pub enum SfzOpcode {
     FilterType(FilterType),
     DefaultPath(String),
     Tune(i16),
+    SeqLength(u8),
+    SeqPosition(u8),
+    Lorand(f32),
+    Hirand(f32),
+    SwLokey(i8),
+    SwHikey(i8),
+    SwLast(i8),
+    SwDown(i8),
+    SwUp(i8),
+    SwDefault(i8),
+    XfinLovel(u8),
+    XfinHivel(u8),
+    XfoutLovel(u8),
+    XfoutHivel(u8),
+    XfinLokey(i8),
+    XfinHikey(i8),
+    XfoutLokey(i8),
+    XfoutHikey(i8),
+    XfVelcurve(CrossfadeCurve),
+    XfKeycurve(CrossfadeCurve),
+    AmpVeltrack(f32),
+    AmpVelcurve(u8, f32),
+    AmpKeytrack(f32),
+    AmpKeycenter(i8),
+    Locc(u8, u8),
+    Hicc(u8, u8),
+    OnLocc(u8, u8),
+    OnHicc(u8, u8),
+    Group(u32),
+    OffBy(u32),
+    OffMode(OffMode),
+    OffTime(f32),
     AmpegEnvelope(SfzAmpegEnvelope),
+    Lfo(SfzLfo),
+    PitchegEnvelope(SfzAmpegEnvelope),
+    PitchegDepth(f32),
+    FilegEnvelope(SfzAmpegEnvelope),
+    FilegDepth(f32),
 }
 
 #[derive(Debug, Clone)]
@@ -54,6 +95,25 @@ pub enum SfzAmpegEnvelope {
     AmpegRelease(f32),
 }
 
+#[derive(Debug, Clone)]
+pub enum SfzLfo {
+    PitchlfoDelay(f32),
+    PitchlfoFreq(f32),
+    PitchlfoDepth(f32),
+    AmplfoDelay(f32),
+    AmplfoFreq(f32),
+    AmplfoDepth(f32),
+    FillfoDelay(f32),
+    FillfoFreq(f32),
+    FillfoDepth(f32),
+    PitchlfoDepthChanaft(f32),
+    PitchlfoDepthPolyaft(f32),
+    AmplfoDepthChanaft(f32),
+    AmplfoDepthPolyaft(f32),
+    FillfoDepthChanaft(f32),
+    FillfoDepthPolyaft(f32),
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SfzGroupType {
     Region,
@@ -210,6 +270,34 @@ fn parse_loop_mode(val: &str) -> Option<LoopMode> {
     }
 }
 
+fn parse_trigger_mode(val: &str) -> Option<TriggerMode> {
+    match val {
+        "attack" => Some(TriggerMode::Attack),
+        "release" => Some(TriggerMode::Release),
+        "release_key" => Some(TriggerMode::ReleaseKey),
+        "first" => Some(TriggerMode::First),
+        "legato" => Some(TriggerMode::Legato),
+        _ => None,
+    }
+}
+
+fn parse_off_mode(val: &str) -> Option<OffMode> {
+    match val {
+        "fast" => Some(OffMode::Fast),
+        "normal" => Some(OffMode::Normal),
+        "time" => Some(OffMode::Time),
+        _ => None,
+    }
+}
+
+fn parse_crossfade_curve(val: &str) -> Option<CrossfadeCurve> {
+    match val {
+        "gain" => Some(CrossfadeCurve::Gain),
+        "power" => Some(CrossfadeCurve::Power),
+        _ => None,
+    }
+}
+
 fn parse_sfz_opcode(
     opcode: Opcode,
     defines: &RefCell<HashMap<String, String>>,
@@ -230,6 +318,7 @@ fn parse_sfz_opcode(
     }
 
     use SfzAmpegEnvelope::*;
+    use SfzLfo::*;
     use SfzOpcode::*;
 
     let val = val.as_ref();
@@ -245,17 +334,47 @@ fn parse_sfz_opcode(
         "pitch_keycenter" => parse_key_number(val).map(PitchKeycenter),
         "key" => parse_key_number(val).map(Key),
         "cutoff" => parse_float_in_range(val, 1.0..=100000.0).map(Cutoff),
+        "cutoff_chanaft" => parse_float_in_range(val, -9600.0..=9600.0).map(CutoffChanaft),
+        "cutoff_polyaft" => parse_float_in_range(val, -9600.0..=9600.0).map(CutoffPolyaft),
         "resonance" => parse_float_in_range(val, 0.0..=40.0).map(Resonance),
         "fil_veltrack" => parse_i16_in_range(val, -9600..=9600).map(FilVeltrack),
         "fil_keytrack" => parse_i16_in_range(val, 0..=1200).map(FilKeytrack),
         "fil_keycenter" => parse_key_number(val).map(FilKeycenter),
         "fil_type" => parse_filter_kind(val).map(FilterType),
         "loop_mode" | "loopmode" => parse_loop_mode(val).map(LoopMode),
+        "trigger" => parse_trigger_mode(val).map(Trigger),
         "loop_start" | "loopstart" => parse_u32_in_range(val, 0..=u32::MAX).map(LoopStart),
         "loop_end" | "loopend" => parse_u32_in_range(val, 0..=u32::MAX).map(LoopEnd),
         "offset" => parse_u32_in_range(val, 0..=u32::MAX).map(Offset),
         "default_path" => Some(DefaultPath(val.replace('\\', "/"))),
         "tune" => parse_i16_in_range(val, -2400..=2400).map(Tune),
+        "seq_length" => parse_u8_in_range(val, 1..=100).map(SeqLength),
+        "seq_position" => parse_u8_in_range(val, 1..=100).map(SeqPosition),
+        "lorand" => parse_float_in_range(val, 0.0..=1.0).map(Lorand),
+        "hirand" => parse_float_in_range(val, 0.0..=1.0).map(Hirand),
+        "sw_lokey" => parse_key_number(val).map(SwLokey),
+        "sw_hikey" => parse_key_number(val).map(SwHikey),
+        "sw_last" => parse_key_number(val).map(SwLast),
+        "sw_down" => parse_key_number(val).map(SwDown),
+        "sw_up" => parse_key_number(val).map(SwUp),
+        "sw_default" => parse_key_number(val).map(SwDefault),
+        "xfin_lovel" => parse_u8_in_range(val, 0..=127).map(XfinLovel),
+        "xfin_hivel" => parse_u8_in_range(val, 0..=127).map(XfinHivel),
+        "xfout_lovel" => parse_u8_in_range(val, 0..=127).map(XfoutLovel),
+        "xfout_hivel" => parse_u8_in_range(val, 0..=127).map(XfoutHivel),
+        "xfin_lokey" => parse_key_number(val).map(XfinLokey),
+        "xfin_hikey" => parse_key_number(val).map(XfinHikey),
+        "xfout_lokey" => parse_key_number(val).map(XfoutLokey),
+        "xfout_hikey" => parse_key_number(val).map(XfoutHikey),
+        "xf_velcurve" => parse_crossfade_curve(val).map(XfVelcurve),
+        "xf_keycurve" => parse_crossfade_curve(val).map(XfKeycurve),
+        "amp_veltrack" => parse_float_in_range(val, -100.0..=100.0).map(AmpVeltrack),
+        "amp_keytrack" => parse_float_in_range(val, -96.0..=12.0).map(AmpKeytrack),
+        "amp_keycenter" => parse_key_number(val).map(AmpKeycenter),
+        "group" => parse_u32_in_range(val, 0..=u32::MAX).map(Group),
+        "off_by" => parse_u32_in_range(val, 0..=u32::MAX).map(OffBy),
+        "off_mode" => parse_off_mode(val).map(OffMode),
+        "off_time" => parse_float_in_range(val, 0.0..=100.0).map(OffTime),
 
         "ampeg_delay" => parse_float_in_range(val, 0.0..=100.0)
             .map(AmpegDelay)
@@ -279,12 +398,122 @@ fn parse_sfz_opcode(
             .map(AmpegRelease)
             .map(AmpegEnvelope),
 
+        "pitchlfo_delay" => parse_float_in_range(val, 0.0..=100.0)
+            .map(PitchlfoDelay)
+            .map(Lfo),
+        "pitchlfo_freq" => parse_float_in_range(val, 0.0..=20.0)
+            .map(PitchlfoFreq)
+            .map(Lfo),
+        "pitchlfo_depth" => parse_float_in_range(val, -1200.0..=1200.0)
+            .map(PitchlfoDepth)
+            .map(Lfo),
+        "amplfo_delay" => parse_float_in_range(val, 0.0..=100.0)
+            .map(AmplfoDelay)
+            .map(Lfo),
+        "amplfo_freq" => parse_float_in_range(val, 0.0..=20.0)
+            .map(AmplfoFreq)
+            .map(Lfo),
+        "amplfo_depth" => parse_float_in_range(val, -10.0..=10.0)
+            .map(AmplfoDepth)
+            .map(Lfo),
+        "fillfo_delay" => parse_float_in_range(val, 0.0..=100.0)
+            .map(FillfoDelay)
+            .map(Lfo),
+        "fillfo_freq" => parse_float_in_range(val, 0.0..=20.0)
+            .map(FillfoFreq)
+            .map(Lfo),
+        "fillfo_depth" => parse_float_in_range(val, -1200.0..=1200.0)
+            .map(FillfoDepth)
+            .map(Lfo),
+        "pitchlfo_depthchanaft" => parse_float_in_range(val, -1200.0..=1200.0)
+            .map(PitchlfoDepthChanaft)
+            .map(Lfo),
+        "pitchlfo_depthpolyaft" => parse_float_in_range(val, -1200.0..=1200.0)
+            .map(PitchlfoDepthPolyaft)
+            .map(Lfo),
+        "amplfo_depthchanaft" => parse_float_in_range(val, -10.0..=10.0)
+            .map(AmplfoDepthChanaft)
+            .map(Lfo),
+        "amplfo_depthpolyaft" => parse_float_in_range(val, -10.0..=10.0)
+            .map(AmplfoDepthPolyaft)
+            .map(Lfo),
+        "fillfo_depthchanaft" => parse_float_in_range(val, -1200.0..=1200.0)
+            .map(FillfoDepthChanaft)
+            .map(Lfo),
+        "fillfo_depthpolyaft" => parse_float_in_range(val, -1200.0..=1200.0)
+            .map(FillfoDepthPolyaft)
+            .map(Lfo),
+
+        "pitcheg_delay" => parse_float_in_range(val, 0.0..=100.0)
+            .map(AmpegDelay)
+            .map(PitchegEnvelope),
+        "pitcheg_start" => parse_float_in_range(val, 0.0..=100.0)
+            .map(AmpegStart)
+            .map(PitchegEnvelope),
+        "pitcheg_attack" => parse_float_in_range(val, 0.0..=100.0)
+            .map(AmpegAttack)
+            .map(PitchegEnvelope),
+        "pitcheg_hold" => parse_float_in_range(val, 0.0..=100.0)
+            .map(AmpegHold)
+            .map(PitchegEnvelope),
+        "pitcheg_decay" => parse_float_in_range(val, 0.0..=100.0)
+            .map(AmpegDecay)
+            .map(PitchegEnvelope),
+        "pitcheg_sustain" => parse_float_in_range(val, 0.0..=100.0)
+            .map(AmpegSustain)
+            .map(PitchegEnvelope),
+        "pitcheg_release" => parse_float_in_range(val, 0.0..=100.0)
+            .map(AmpegRelease)
+            .map(PitchegEnvelope),
+        "pitcheg_depth" => parse_float_in_range(val, -12000.0..=12000.0).map(PitchegDepth),
+
+        "fileg_delay" => parse_float_in_range(val, 0.0..=100.0)
+            .map(AmpegDelay)
+            .map(FilegEnvelope),
+        "fileg_start" => parse_float_in_range(val, 0.0..=100.0)
+            .map(AmpegStart)
+            .map(FilegEnvelope),
+        "fileg_attack" => parse_float_in_range(val, 0.0..=100.0)
+            .map(AmpegAttack)
+            .map(FilegEnvelope),
+        "fileg_hold" => parse_float_in_range(val, 0.0..=100.0)
+            .map(AmpegHold)
+            .map(FilegEnvelope),
+        "fileg_decay" => parse_float_in_range(val, 0.0..=100.0)
+            .map(AmpegDecay)
+            .map(FilegEnvelope),
+        "fileg_sustain" => parse_float_in_range(val, 0.0..=100.0)
+            .map(AmpegSustain)
+            .map(FilegEnvelope),
+        "fileg_release" => parse_float_in_range(val, 0.0..=100.0)
+            .map(AmpegRelease)
+            .map(FilegEnvelope),
+        "fileg_depth" => parse_float_in_range(val, -12000.0..=12000.0).map(FilegDepth),
+
         "sample" => Some(Sample(val.replace('\\', "/"))),
 
-        _ => None,
+        _ => parse_numbered_opcode(name, val),
     })
 }
 
+/// Parses the opcodes that end with a number, like `locc64` or `amp_velcurve_127`.
+fn parse_numbered_opcode(name: &str, val: &str) -> Option<SfzOpcode> {
+    let split = name.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let (name, num) = name.split_at(split);
+    let num = parse_u8_in_range(num, 0..=127)?;
+
+    match name {
+        "locc" => parse_u8_in_range(val, 0..=127).map(|val| SfzOpcode::Locc(num, val)),
+        "hicc" => parse_u8_in_range(val, 0..=127).map(|val| SfzOpcode::Hicc(num, val)),
+        "on_locc" => parse_u8_in_range(val, 0..=127).map(|val| SfzOpcode::OnLocc(num, val)),
+        "on_hicc" => parse_u8_in_range(val, 0..=127).map(|val| SfzOpcode::OnHicc(num, val)),
+        "amp_velcurve_" => {
+            parse_float_in_range(val, 0.0..=1.0).map(|val| SfzOpcode::AmpVelcurve(num, val))
+        }
+        _ => None,
+    }
+}
+
 fn parse_sfz_group(group: Group) -> Result<SfzGroupType, SfzValidationError> {
     Ok(match group.name.text {
         "region" => SfzGroupType::Region,
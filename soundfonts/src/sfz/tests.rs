@@ -0,0 +1,167 @@
+use std::fs;
+
+use super::*;
+
+/// Writes an SFZ file with an empty `sample.wav` next to it to a new
+/// temporary folder, and parses it.
+fn parse_sfz(name: &str, sfz: &str) -> Vec<RegionParams> {
+    let dir = std::env::temp_dir().join(format!("xsynth-sfz-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("sample.wav"), []).unwrap();
+    fs::write(dir.join("test.sfz"), sfz).unwrap();
+
+    let regions = parse_soundfont(dir.join("test.sfz")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    regions
+}
+
+#[test]
+fn test_trigger() {
+    let regions = parse_sfz(
+        "trigger",
+        "<region> sample=sample.wav
+        <group> trigger=release
+        <region> sample=sample.wav
+        <region> sample=sample.wav trigger=release_key
+        <region> sample=sample.wav trigger=first
+        <region> sample=sample.wav trigger=legato
+        <region> sample=sample.wav trigger=attack
+        <region> sample=sample.wav trigger=unknown",
+    );
+
+    let triggers: Vec<_> = regions.iter().map(|r| r.trigger).collect();
+    assert_eq!(
+        triggers,
+        [
+            TriggerMode::Attack,
+            TriggerMode::Release,
+            TriggerMode::ReleaseKey,
+            TriggerMode::First,
+            TriggerMode::Legato,
+            TriggerMode::Attack,
+            // Invalid values keep the one of the group
+            TriggerMode::Release,
+        ]
+    );
+}
+
+#[test]
+fn test_selection() {
+    let regions = parse_sfz(
+        "selection",
+        "<group> seq_length=2
+        <region> sample=sample.wav seq_position=1
+        <region> sample=sample.wav seq_position=2
+        <group> seq_length=1 lorand=0.5
+        <region> sample=sample.wav hirand=0.75
+        <region> sample=sample.wav",
+    );
+
+    let selection: Vec<_> = regions
+        .iter()
+        .map(|r| (r.seq_length, r.seq_position, r.lorand, r.hirand))
+        .collect();
+    assert_eq!(
+        selection,
+        [
+            (2, 1, 0.0, 1.0),
+            (2, 2, 0.0, 1.0),
+            (1, 1, 0.5, 0.75),
+            (1, 1, 0.5, 1.0),
+        ]
+    );
+}
+
+#[test]
+fn test_exclusive_class() {
+    let regions = parse_sfz(
+        "exclusive-class",
+        "<group> group=1 off_by=2
+        <region> sample=sample.wav
+        <region> sample=sample.wav off_mode=normal
+        <region> sample=sample.wav off_mode=time off_time=0.5
+        <region> sample=sample.wav off_mode=unknown group=2 off_by=0",
+    );
+
+    let classes: Vec<_> = regions
+        .iter()
+        .map(|r| (r.group, r.off_by, r.off_mode, r.off_time))
+        .collect();
+    assert_eq!(
+        classes,
+        [
+            (1, 2, OffMode::Fast, 0.006),
+            (1, 2, OffMode::Normal, 0.006),
+            (1, 2, OffMode::Time, 0.5),
+            (2, 0, OffMode::Fast, 0.006),
+        ]
+    );
+}
+
+#[test]
+fn test_keyswitch() {
+    let regions = parse_sfz(
+        "keyswitch",
+        "<group> sw_lokey=c1 sw_hikey=25 sw_default=24
+        <region> sample=sample.wav sw_last=c1
+        <region> sample=sample.wav sw_last=25
+        <region> sample=sample.wav sw_down=26 sw_up=27
+        <region> sample=sample.wav sw_last=-1",
+    );
+
+    let keyswitches: Vec<_> = regions
+        .iter()
+        .map(|r| {
+            (
+                r.sw_lokey,
+                r.sw_hikey,
+                r.sw_last,
+                r.sw_down,
+                r.sw_up,
+                r.sw_default,
+            )
+        })
+        .collect();
+    assert_eq!(
+        keyswitches,
+        [
+            (Some(24), Some(25), Some(24), None, None, Some(24)),
+            (Some(24), Some(25), Some(25), None, None, Some(24)),
+            (Some(24), Some(25), None, Some(26), Some(27), Some(24)),
+            (Some(24), Some(25), None, None, None, Some(24)),
+        ]
+    );
+}
+
+#[test]
+fn test_cc_ranges() {
+    let regions = parse_sfz(
+        "cc-ranges",
+        "<group> locc1=10
+        <region> sample=sample.wav hicc1=20 hicc7=100
+        <region> sample=sample.wav on_locc64=64 on_hicc64=127 on_hicc20=0",
+    );
+
+    let range = |cc, lo, hi| CcRange { cc, lo, hi };
+    assert_eq!(
+        regions[0].cc_conditions,
+        [range(1, 10, 20), range(7, 0, 100)]
+    );
+    assert!(regions[0].cc_triggers.is_empty());
+    assert_eq!(regions[1].cc_conditions, [range(1, 10, 127)]);
+    assert_eq!(
+        regions[1].cc_triggers,
+        [range(64, 64, 127), range(20, 0, 0)]
+    );
+
+    let mut cc = [0; 128];
+    assert!(CcRange::all_match(&[], &cc));
+    assert!(!CcRange::all_match(&regions[0].cc_conditions, &cc));
+    cc[1] = 10;
+    assert!(CcRange::all_match(&regions[0].cc_conditions, &cc));
+    cc[1] = 21;
+    assert!(!CcRange::all_match(&regions[0].cc_conditions, &cc));
+    cc[1] = 20;
+    cc[7] = 101;
+    assert!(!CcRange::all_match(&regions[0].cc_conditions, &cc));
+}